@@ -23,16 +23,8 @@ out struct {
 
 } v2f;
 
-// NOTE: Matrices Block | 128 bytes
-layout (std140) uniform Matrices {
-
-    mat4 view;       // 64
-    mat4 projection; // 64
-
-};
-
-uniform mat4 model;
-uniform mat3 normal_mat;
+#include "common/matrices.glsl"
+#include "common/object.glsl"
 
 void main()
 {
@@ -42,9 +34,10 @@ void main()
     vec4 worldPosition = model * vec4( Position, 1.0 );
     v2f.world_position = worldPosition.xyz;
 
+    // tangents keep the bitangent's handedness in w
     v2f.normal    = normal_mat * Normal; 
     v2f.tangent   = normal_mat * Tangent.xyz;
-    v2f.bitangent = cross( v2f.tangent, v2f.normal );
+    v2f.bitangent = cross( v2f.normal, v2f.tangent ) * ( Tangent.w < 0.0 ? -1.0 : 1.0 );
 
     v2f.uv    = UV;
     v2f.color = Color;
@@ -56,6 +49,8 @@ void main()
 
 #version 460 core
 
+#pragma multi_compile VERTEX_COLOR ENVIRONMENT_LIGHTING NORMAL_MAP PARALLAX_MAPPING
+
 in struct {
 
     vec3 local_position;
@@ -71,73 +66,37 @@ in struct {
 
 } v2f;
 
-// NOTE: Directional struct | 48 bytes
-struct Directional {
-    vec4 direction; // 16 | offset: 0
-    vec4 diffuse;   // 16 | offset: 16
-    vec4 specular;  // 16 | offset: 32
-};
-
-#define MAX_POINT_LIGHTS 4 // NOTE: Point lights | 256 bytes
-// NOTE: Point struct | 64 bytes
-struct Point {
-    vec4 position;   // 16 | offset: 0
-    vec4 diffuse;    // 16 | offset: 16
-    vec4 specular;   // 16 | offset: 32
-
-    float constant;  // 4  | offset: 48
-    float linear;    // 4  | offset: 52
-    float quadratic; // 4  | offset: 56
-
-    bool  is_active; // 4  | offset: 60
-};
-
-#define MAX_SPOT_LIGHTS 2 // NOTE: Spot lights | 192 bytes
-// NOTE: Spot struct | 96 bytes
-struct Spot {
-    vec4 position;      // 16 | offset: 0
-    vec4 direction;     // 16 | offset: 16
-    vec4 diffuse;       // 16 | offset: 32
-    vec4 specular;      // 16 | offset: 48
-
-    float inner_cutoff; // 4  | offset: 64
-    float outer_cutoff; // 4  | offset: 68
-    float constant;     // 4  | offset: 72
-    float linear;       // 4  | offset: 76
-    float quadratic;    // 4  | offset: 80
-
-    bool  is_active;    // 4  | offset: 84
-    // 88, padded to 96
-};
-
-// NOTE: Lights Block | 496 bytes
-layout (std140) uniform Lights {
-    Directional directional_light;                // 48  | offset: 0
-    Point       point_lights[ MAX_POINT_LIGHTS ]; // 256 | offset: 48 
-    Spot        spot_lights[ MAX_SPOT_LIGHTS ];   // 192 | offset: 304
-    // 496
-};
-
-// NOTE: Data Block | 40 bytes
-layout (std140) uniform Data {
-    vec4  camera_position; // 16 | offset: 0
-    vec4  fog_color;       // 16 | offset: 16
-    float near_clip;       // 4  | offset: 32
-    float far_clip;        // 4  | offset: 36
-    // 40, padded to 48
-};
+#include "common/lights.glsl"
+#include "common/data.glsl"
+
+#include "common/normal_mapping.glsl"
+
+#ifdef ENVIRONMENT_LIGHTING
+#include "common/ibl.glsl"
+#endif
+
+#ifdef PARALLAX_MAPPING
+#include "common/parallax.glsl"
+uniform vec2 height_sampler_scaler;
+#endif
+
+#ifdef NORMAL_MAP
+uniform sampler2D normal_sampler;
+uniform vec2      normal_sampler_scaler;
+uniform float     normal_strength;
+#endif
 
 uniform sampler2D albedo_sampler;
 uniform vec2 albedo_sampler_scaler;
 uniform sampler2D specular_sampler;
 uniform vec2 specular_sampler_scaler;
-uniform bool      use_vertex_color;
 uniform float     glossiness;
 
 vec3 DirectionalLight(
     vec3 dir, vec3 norm, vec3 cam_dir,
     vec3 albedo_texture, vec3 spec_texture,
-    vec3 diffuse, vec3 specular
+    vec3 diffuse, vec3 specular,
+    float shadow
 );
 
 vec3 PointLight(
@@ -145,7 +104,8 @@ vec3 PointLight(
     vec3 norm, vec3 cam_dir,
     vec3 albedo_texture, vec3 spec_texture,
     vec3 diffuse, vec3 specular,
-    float constant, float lin, float quad
+    float constant, float lin, float quad,
+    float shadow
 );
 
 vec3 SpotLight(
@@ -154,39 +114,75 @@ vec3 SpotLight(
     vec3 albedo_texture, vec3 spec_texture,
     vec3 diffuse, vec3 specular,
     float constant, float lin, float quad,
-    float inner, float outer
+    float inner, float outer,
+    float shadow
 );
 
+// 1 unless the height map shadows the fragment from a light in direction dir
+float SelfShadow( vec3 dir );
+
 float Spec( vec3 dir, vec3 norm, vec3 cam_dir );
 
+// a scaler left at zero by the material counts as 1
+vec2 Scaler( vec2 scaler ) {
+    return mix( scaler, vec2( 1.0 ), equal( scaler, vec2( 0.0 ) ) );
+}
+
+// same for strengths like normal_strength, so an unset one doesn't flatten the map
+float Scaler( float scaler ) {
+    return scaler == 0.0 ? 1.0 : scaler;
+}
+
+// set in main for SelfShadow
+mat3  tbn;
+vec2  heightUV;
+float parallaxDepth = 0.0;
+
 out vec4 FRAG_COLOR;
 void main()
 {
-    vec3 albedoTexture   = texture2D( albedo_sampler,   v2f.uv * albedo_sampler_scaler ).rgb;
-    vec3 specularTexture = texture2D( specular_sampler, v2f.uv * specular_sampler_scaler ).rgb;
-
     vec3 cameraDirectionRaw = camera_position.xyz - v2f.world_position;
+    tbn = TangentBasis( v2f.tangent, v2f.bitangent, v2f.normal );
+
+    // every texture is sampled at its scaler times uv, unset scalers count as 1
+    vec2 uv = v2f.uv;
+#ifdef PARALLAX_MAPPING
+    // march in the height map's uv space, then back to uv
+    vec2 heightScaler = Scaler( height_sampler_scaler );
+    heightUV = ParallaxOcclusion(
+        v2f.uv * heightScaler, normalize( transpose( tbn ) * cameraDirectionRaw ), parallaxDepth
+    );
+    uv = heightUV / heightScaler;
+#endif
+
+    vec3 albedoTexture   = texture2D( albedo_sampler,   uv * Scaler( albedo_sampler_scaler ) ).rgb;
+    vec3 specularTexture = texture2D( specular_sampler, uv * Scaler( specular_sampler_scaler ) ).rgb;
 
     float distance_to_camera = length( cameraDirectionRaw );
     float fog_mask = smoothstep( near_clip, far_clip, distance_to_camera );
 
     vec3 fog = fog_color.rgb * fog_mask;
 
-    if( use_vertex_color ) {
-        albedoTexture = v2f.color;
-    }
+#ifdef VERTEX_COLOR
+    albedoTexture = v2f.color;
+#endif
 
     vec3 cameraDirection = normalize( cameraDirectionRaw );
-    vec3 normal    = normalize( v2f.normal );
-    vec3 tangent   = normalize( v2f.tangent );
-    vec3 bitangent = normalize( v2f.bitangent );
+#ifdef NORMAL_MAP
+    vec3 normal = normalize( tbn * UnpackNormal(
+        texture2D( normal_sampler, uv * Scaler( normal_sampler_scaler ) ).rgb, Scaler( normal_strength )
+    ) );
+#else
+    vec3 normal = tbn[2];
+#endif
 
     vec3 color = vec3(0.0);
 
     color += DirectionalLight(
         normalize( directional_light.direction.xyz ), normal, cameraDirection,
         albedoTexture, specularTexture,
-        directional_light.diffuse.rgb, directional_light.specular.rgb
+        directional_light.diffuse.rgb, directional_light.specular.rgb,
+        SelfShadow( normalize( directional_light.direction.xyz ) )
     );
 
     for( int i = 0; i < MAX_POINT_LIGHTS; ++i ) {
@@ -196,7 +192,8 @@ void main()
             normal, cameraDirection,
             albedoTexture, specularTexture,
             point_lights[i].diffuse.rgb, point_lights[i].specular.rgb,
-            point_lights[i].constant, point_lights[i].linear, point_lights[i].quadratic
+            point_lights[i].constant, point_lights[i].linear, point_lights[i].quadratic,
+            SelfShadow( normalize( point_lights[i].position.xyz - v2f.world_position ) )
         );
     }
 
@@ -208,28 +205,47 @@ void main()
             albedoTexture, specularTexture,
             spot_lights[i].diffuse.rgb, spot_lights[i].specular.rgb,
             spot_lights[i].constant, spot_lights[i].linear, spot_lights[i].quadratic,
-            spot_lights[i].inner_cutoff, spot_lights[i].outer_cutoff
+            spot_lights[i].inner_cutoff, spot_lights[i].outer_cutoff,
+            SelfShadow( normalize( spot_lights[i].position.xyz - v2f.world_position ) )
         );
     }
 
+#ifdef ENVIRONMENT_LIGHTING
+    // glossiness is a Blinn-Phong exponent, this is the roughness it roughly matches
+    color += EnvironmentLighting(
+        normal, cameraDirection,
+        albedoTexture, vec3( 0.04 ) * specularTexture,
+        sqrt( 2.0 / ( max( glossiness, 1.0 ) + 2.0 ) ), 0.0
+    );
+#endif
+
     color = ( color * ( 1.0 - fog_mask ) ) + fog;
 
     FRAG_COLOR = vec4( color, 1.0 );
 
 }
 
+float SelfShadow( vec3 dir ) {
+#ifdef PARALLAX_MAPPING
+    return ParallaxShadow( heightUV, parallaxDepth, transpose( tbn ) * dir );
+#else
+    return 1.0;
+#endif
+}
+
 vec3 SpotLight(
     vec3 pos, vec3 dir, vec3 world_pos,
     vec3 norm, vec3 cam_dir,
     vec3 albedo_texture, vec3 spec_texture,
     vec3 diffuse, vec3 specular,
     float constant, float lin, float quad,
-    float inner, float outer
+    float inner, float outer,
+    float shadow
 )
 {
     vec3 lightColor = PointLight(
         pos, world_pos, norm, cam_dir, albedo_texture, spec_texture,
-        diffuse, specular, constant, lin, quad
+        diffuse, specular, constant, lin, quad, shadow
     );
     vec3  dir2    = normalize( pos - world_pos );
     float theta   = dot( dir2, normalize( -dir ) );
@@ -244,14 +260,15 @@ vec3 PointLight(
     vec3 norm, vec3 cam_dir,
     vec3 albedo_texture, vec3 spec_texture,
     vec3 diffuse, vec3 specular,
-    float constant, float lin, float quad
+    float constant, float lin, float quad,
+    float shadow
 )
 {
     vec3 lightDir = pos - world_pos;
     vec3 lightColor = DirectionalLight(
         normalize( lightDir ), norm, cam_dir,
         albedo_texture, spec_texture,
-        diffuse, specular
+        diffuse, specular, shadow
     );
     float dist = length( lightDir );
     float atten = 1.0 / ( constant + lin * dist + quad * ( dist * dist ) );
@@ -262,7 +279,8 @@ vec3 PointLight(
 vec3 DirectionalLight(
     vec3 dir, vec3 norm, vec3 cam_dir,
     vec3 albedo_texture, vec3 spec_texture,
-    vec3 diffuse, vec3 specular
+    vec3 diffuse, vec3 specular,
+    float shadow
 )
 {
     float cutoff = max( dot( norm, dir ), 0.0 );
@@ -270,7 +288,8 @@ vec3 DirectionalLight(
     vec3 diff = ( albedo_texture * diffuse  ) * cutoff;
     vec3 spec = (spec_texture * specular) * Spec( dir, norm, cam_dir );
 
-    return diff + amb + ( spec * cutoff );
+    // shadowed by the height map, the ambient still reaches it
+    return ( diff + ( spec * cutoff ) ) * shadow + amb;
 }
 
 float Spec( vec3 dir, vec3 norm, vec3 cam_dir ) {
@@ -0,0 +1,57 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+out vec2 uv;
+
+#include "common/cubemap.glsl"
+
+void main()
+{
+    vec2 position = FullscreenPosition();
+    uv = position * 0.5 + 0.5;
+    gl_Position = vec4( position, 0.0, 1.0 );
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+in vec2 uv;
+
+#include "common/ibl_sampling.glsl"
+
+uniform int sample_count;
+
+// x is n dot v, y is roughness,
+// output is the scale and bias applied to F0
+out vec2 FRAG_COLOR;
+void main()
+{
+    float n_dot_v   = max( uv.x, 0.0001 );
+    float roughness = uv.y;
+    uint  count     = uint( max( sample_count, 1 ) );
+
+    vec3 view   = vec3( sqrt( 1.0 - n_dot_v * n_dot_v ), 0.0, n_dot_v );
+    vec3 normal = vec3( 0.0, 0.0, 1.0 );
+
+    float scale = 0.0;
+    float bias  = 0.0;
+    for( uint i = 0u; i < count; ++i ) {
+        vec3 halfway   = ImportanceSampleGGX( Hammersley( i, count ), normal, roughness );
+        vec3 direction = normalize( 2.0 * dot( view, halfway ) * halfway - view );
+
+        float n_dot_l = max( direction.z, 0.0 );
+        float n_dot_h = max( halfway.z, 0.0 );
+        float v_dot_h = max( dot( view, halfway ), 0.0 );
+        if( n_dot_l <= 0.0 ) { continue; }
+
+        float visibility = GeometrySmith( n_dot_v, n_dot_l, roughness ) * v_dot_h / ( n_dot_h * n_dot_v + 0.0001 );
+        float fresnel    = pow( 1.0 - v_dot_h, 5.0 );
+
+        scale += ( 1.0 - fresnel ) * visibility;
+        bias  += fresnel * visibility;
+    }
+
+    FRAG_COLOR = vec2( scale, bias ) / float( count );
+}
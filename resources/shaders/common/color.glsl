@@ -0,0 +1,16 @@
+#pragma once
+
+// NOTE: sRGB transfer functions, lighting is done on linear colors
+
+vec3 SrgbToLinear( vec3 color ) {
+    vec3 low  = color / 12.92;
+    vec3 high = pow( ( color + 0.055 ) / 1.055, vec3( 2.4 ) );
+    return mix( low, high, step( vec3( 0.04045 ), color ) );
+}
+
+vec3 LinearToSrgb( vec3 color ) {
+    color = clamp( color, 0.0, 1.0 );
+    vec3 low  = color * 12.92;
+    vec3 high = 1.055 * pow( color, vec3( 1.0 / 2.4 ) ) - 0.055;
+    return mix( low, high, step( vec3( 0.0031308 ), color ) );
+}
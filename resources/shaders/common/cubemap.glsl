@@ -0,0 +1,25 @@
+#pragma once
+
+// NOTE: Cubemap face rendering, see Cubemap::render_to_faces
+
+// index of the face being rendered, GL_TEXTURE_CUBE_MAP_POSITIVE_X + face
+uniform int face;
+
+// fullscreen triangle corner for gl_VertexID, -1 to 1 over the screen
+vec2 FullscreenPosition() {
+    return vec2( ( gl_VertexID << 1 ) & 2, gl_VertexID & 2 ) * 2.0 - 1.0;
+}
+
+// direction a texel of a face points in, position is -1 to 1 across the face
+vec3 CubemapDirection( int cube_face, vec2 position ) {
+    float u = position.x;
+    float v = position.y;
+    switch( cube_face ) {
+        case 0:  return normalize( vec3(  1.0, -v, -u ) );
+        case 1:  return normalize( vec3( -1.0, -v,  u ) );
+        case 2:  return normalize( vec3(  u,  1.0,  v ) );
+        case 3:  return normalize( vec3(  u, -1.0, -v ) );
+        case 4:  return normalize( vec3(  u, -v,  1.0 ) );
+        default: return normalize( vec3( -u, -v, -1.0 ) );
+    }
+}
@@ -0,0 +1,10 @@
+#pragma once
+
+// NOTE: Data Block | 40 bytes
+layout (std140, binding = 2) uniform Data {
+    vec4  camera_position; // 16 | offset: 0
+    vec4  fog_color;       // 16 | offset: 16
+    float near_clip;       // 4  | offset: 32
+    float far_clip;        // 4  | offset: 36
+    // 40, padded to 48
+};
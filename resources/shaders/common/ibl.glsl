@@ -0,0 +1,36 @@
+#pragma once
+
+// NOTE: Image-based lighting, set by Material::set_environment_lighting
+//       from the maps in EnvironmentLighting
+
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D   brdf_lut;
+uniform float       prefiltered_max_lod;
+uniform float       environment_intensity;
+
+vec3 FresnelSchlickRoughness( float cos_theta, vec3 f0, float roughness ) {
+    return f0 + ( max( vec3( 1.0 - roughness ), f0 ) - f0 ) * pow( clamp( 1.0 - cos_theta, 0.0, 1.0 ), 5.0 );
+}
+
+// ambient diffuse and specular light from the environment
+vec3 EnvironmentLighting(
+    vec3 norm, vec3 cam_dir,
+    vec3 albedo, vec3 f0,
+    float roughness, float metallic
+) {
+    float n_dot_v = max( dot( norm, cam_dir ), 0.0 );
+
+    vec3 fresnel = FresnelSchlickRoughness( n_dot_v, f0, roughness );
+    vec3 diffuseAmount = ( 1.0 - fresnel ) * ( 1.0 - metallic );
+
+    vec3 irradiance = texture( irradiance_map, norm ).rgb;
+    vec3 diffuse    = irradiance * albedo * diffuseAmount;
+
+    vec3 reflection = reflect( -cam_dir, norm );
+    vec3 prefiltered = textureLod( prefiltered_map, reflection, roughness * prefiltered_max_lod ).rgb;
+    vec2 brdf = texture( brdf_lut, vec2( n_dot_v, roughness ) ).rg;
+    vec3 specular = prefiltered * ( fresnel * brdf.x + brdf.y );
+
+    return ( diffuse + specular ) * environment_intensity;
+}
@@ -0,0 +1,71 @@
+#pragma once
+
+// NOTE: Sampling for generating environment lighting, see EnvironmentLighting::generate
+
+const float PI = 3.14159265359;
+
+// Van der Corput sequence, bits of i mirrored around the decimal point
+float RadicalInverse( uint bits ) {
+    bits = ( bits << 16u ) | ( bits >> 16u );
+    bits = ( ( bits & 0x55555555u ) << 1u ) | ( ( bits & 0xAAAAAAAAu ) >> 1u );
+    bits = ( ( bits & 0x33333333u ) << 2u ) | ( ( bits & 0xCCCCCCCCu ) >> 2u );
+    bits = ( ( bits & 0x0F0F0F0Fu ) << 4u ) | ( ( bits & 0xF0F0F0F0u ) >> 4u );
+    bits = ( ( bits & 0x00FF00FFu ) << 8u ) | ( ( bits & 0xFF00FF00u ) >> 8u );
+    return float( bits ) * 2.3283064365386963e-10;
+}
+
+// evenly spread point i of count in the unit square
+vec2 Hammersley( uint i, uint count ) {
+    return vec2( float( i ) / float( count ), RadicalInverse( i ) );
+}
+
+// turns a direction around +Z into one around n
+vec3 TangentToWorld( vec3 direction, vec3 n ) {
+    vec3 up        = abs( n.z ) < 0.999 ? vec3( 0.0, 0.0, 1.0 ) : vec3( 1.0, 0.0, 0.0 );
+    vec3 tangent   = normalize( cross( up, n ) );
+    vec3 bitangent = cross( n, tangent );
+    return normalize( tangent * direction.x + bitangent * direction.y + n * direction.z );
+}
+
+// half vector around n distributed like GGX for roughness
+vec3 ImportanceSampleGGX( vec2 xi, vec3 n, float roughness ) {
+    float a = roughness * roughness;
+
+    float phi      = 2.0 * PI * xi.x;
+    float cosTheta = sqrt( ( 1.0 - xi.y ) / ( 1.0 + ( a * a - 1.0 ) * xi.y ) );
+    float sinTheta = sqrt( 1.0 - cosTheta * cosTheta );
+
+    return TangentToWorld( vec3( cos( phi ) * sinTheta, sin( phi ) * sinTheta, cosTheta ), n );
+}
+
+// direction around n distributed by cos theta
+vec3 CosineSampleHemisphere( vec2 xi, vec3 n ) {
+    float phi      = 2.0 * PI * xi.x;
+    float cosTheta = sqrt( 1.0 - xi.y );
+    float sinTheta = sqrt( xi.y );
+
+    return TangentToWorld( vec3( cos( phi ) * sinTheta, sin( phi ) * sinTheta, cosTheta ), n );
+}
+
+float DistributionGGX( float n_dot_h, float roughness ) {
+    float a2    = roughness * roughness * roughness * roughness;
+    float denom = n_dot_h * n_dot_h * ( a2 - 1.0 ) + 1.0;
+    return a2 / ( PI * denom * denom );
+}
+
+// Schlick-GGX with the k used for image-based lighting
+float GeometrySchlickGGX( float n_dot_v, float roughness ) {
+    float k = ( roughness * roughness ) / 2.0;
+    return n_dot_v / ( n_dot_v * ( 1.0 - k ) + k );
+}
+
+float GeometrySmith( float n_dot_v, float n_dot_l, float roughness ) {
+    return GeometrySchlickGGX( n_dot_v, roughness ) * GeometrySchlickGGX( n_dot_l, roughness );
+}
+
+// mip level of a cubemap whose texels cover about the solid angle of a sample
+float SampleLod( float pdf, float environment_size, uint sample_count ) {
+    float texelSolidAngle  = 4.0 * PI / ( 6.0 * environment_size * environment_size );
+    float sampleSolidAngle = 1.0 / ( float( sample_count ) * pdf + 0.0001 );
+    return max( 0.5 * log2( sampleSolidAngle / texelSolidAngle ) + 1.0, 0.0 );
+}
@@ -0,0 +1,48 @@
+#pragma once
+
+// NOTE: Directional struct | 48 bytes
+struct Directional {
+    vec4 direction; // 16 | offset: 0
+    vec4 diffuse;   // 16 | offset: 16
+    vec4 specular;  // 16 | offset: 32
+};
+
+#define MAX_POINT_LIGHTS 4 // NOTE: Point lights | 256 bytes
+// NOTE: Point struct | 64 bytes
+struct Point {
+    vec4 position;   // 16 | offset: 0
+    vec4 diffuse;    // 16 | offset: 16
+    vec4 specular;   // 16 | offset: 32
+
+    float constant;  // 4  | offset: 48
+    float linear;    // 4  | offset: 52
+    float quadratic; // 4  | offset: 56
+
+    bool  is_active; // 4  | offset: 60
+};
+
+#define MAX_SPOT_LIGHTS 2 // NOTE: Spot lights | 192 bytes
+// NOTE: Spot struct | 96 bytes
+struct Spot {
+    vec4 position;      // 16 | offset: 0
+    vec4 direction;     // 16 | offset: 16
+    vec4 diffuse;       // 16 | offset: 32
+    vec4 specular;      // 16 | offset: 48
+
+    float inner_cutoff; // 4  | offset: 64
+    float outer_cutoff; // 4  | offset: 68
+    float constant;     // 4  | offset: 72
+    float linear;       // 4  | offset: 76
+    float quadratic;    // 4  | offset: 80
+
+    bool  is_active;    // 4  | offset: 84
+    // 88, padded to 96
+};
+
+// NOTE: Lights Block | 496 bytes
+layout (std140, binding = 1) uniform Lights {
+    Directional directional_light;                // 48  | offset: 0
+    Point       point_lights[ MAX_POINT_LIGHTS ]; // 256 | offset: 48 
+    Spot        spot_lights[ MAX_SPOT_LIGHTS ];   // 192 | offset: 304
+    // 496
+};
@@ -0,0 +1,9 @@
+#pragma once
+
+// NOTE: Matrices Block | 128 bytes
+layout (std140, binding = 0) uniform Matrices {
+
+    mat4 view;       // 64
+    mat4 projection; // 64
+
+};
@@ -0,0 +1,17 @@
+#pragma once
+
+// NOTE: Tangent space normal maps
+
+// basis that takes tangent space vectors to world space,
+// transpose it to go the other way
+mat3 TangentBasis( vec3 tangent, vec3 bitangent, vec3 normal ) {
+    return mat3( normalize( tangent ), normalize( bitangent ), normalize( normal ) );
+}
+
+// normal map texel to a tangent space normal,
+// strength scales how far it tilts away from the surface normal
+vec3 UnpackNormal( vec3 texel, float strength ) {
+    vec3 normal = texel * 2.0 - 1.0;
+    normal.xy *= strength;
+    return normalize( normal );
+}
@@ -0,0 +1,9 @@
+#pragma once
+
+// NOTE: Object Block | 112 bytes
+layout (std140, binding = 3) uniform Object {
+
+    mat4 model;      // 64 | offset: 0
+    mat3 normal_mat; // 48 | offset: 64
+
+};
@@ -0,0 +1,79 @@
+#pragma once
+
+// NOTE: Parallax occlusion mapping, enabled by the PARALLAX_MAPPING keyword.
+//       Heights are white at the surface and black height_scale below it.
+//       Vectors are in tangent space, pointing away from the surface.
+
+uniform sampler2D height_sampler;
+uniform float     height_scale;
+
+const float PARALLAX_MIN_LAYERS = 8.0;
+const float PARALLAX_MAX_LAYERS = 32.0;
+// how quickly occluders darken self-shadowing
+const float PARALLAX_SHADOW_HARDNESS = 8.0;
+
+// derivatives of the unshifted uv, implicit ones aren't defined in loops
+// or in lights skipped per fragment, set by ParallaxOcclusion
+vec2 parallaxDx;
+vec2 parallaxDy;
+
+float ParallaxDepth( vec2 uv ) {
+    return 1.0 - textureGrad( height_sampler, uv, parallaxDx, parallaxDy ).r;
+}
+
+// uv where the view ray first hits the height field,
+// depth is how deep that is from 0 at the surface to 1 at height_scale.
+// Call it outside of branches, before ParallaxShadow
+vec2 ParallaxOcclusion( vec2 uv, vec3 view_dir, out float depth ) {
+    parallaxDx = dFdx( uv );
+    parallaxDy = dFdy( uv );
+
+    // grazing angles travel further across the surface so need more layers
+    float layers     = mix( PARALLAX_MAX_LAYERS, PARALLAX_MIN_LAYERS, abs( view_dir.z ) );
+    float layerDepth = 1.0 / layers;
+    vec2  uvStep     = ( view_dir.xy / max( view_dir.z, 0.05 ) ) * height_scale / layers;
+
+    vec2  currentUV    = uv;
+    float currentLayer = 0.0;
+    float currentDepth = ParallaxDepth( currentUV );
+    for( int i = 0; i < int( PARALLAX_MAX_LAYERS ) && currentLayer < currentDepth; ++i ) {
+        currentUV    -= uvStep;
+        currentLayer += layerDepth;
+        currentDepth  = ParallaxDepth( currentUV );
+    }
+
+    // intersect the ray with the height field between the last two layers
+    vec2  previousUV  = currentUV + uvStep;
+    float afterDepth  = currentDepth - currentLayer;
+    float beforeDepth = ParallaxDepth( previousUV ) - ( currentLayer - layerDepth );
+    float weight      = afterDepth / ( afterDepth - beforeDepth );
+    if( isnan( weight ) || isinf( weight ) ) { weight = 0.0; }
+    weight = clamp( weight, 0.0, 1.0 );
+
+    depth = clamp( currentLayer - layerDepth * weight, 0.0, 1.0 );
+    return mix( currentUV, previousUV, weight );
+}
+
+// 1 if light reaches uv at depth without passing through the height field,
+// fading to 0 the further into it the light would have to go
+float ParallaxShadow( vec2 uv, float depth, vec3 light_dir ) {
+    if( light_dir.z <= 0.0 || depth <= 0.0 ) { return 1.0; }
+
+    float layers     = mix( PARALLAX_MAX_LAYERS, PARALLAX_MIN_LAYERS, light_dir.z );
+    float layerDepth = depth / layers;
+    vec2  uvStep     = ( light_dir.xy / light_dir.z ) * height_scale * layerDepth;
+
+    vec2  currentUV    = uv + uvStep;
+    float currentLayer = depth - layerDepth;
+    float blocked      = 0.0;
+    for( int i = 1; i < int( PARALLAX_MAX_LAYERS ) && currentLayer > 0.0; ++i ) {
+        // occluders close to the point cast harder shadows
+        float rise = currentLayer - ParallaxDepth( currentUV );
+        blocked = max( blocked, rise * ( 1.0 - float( i ) / layers ) );
+
+        currentUV    += uvStep;
+        currentLayer -= layerDepth;
+    }
+
+    return 1.0 - clamp( blocked * PARALLAX_SHADOW_HARDNESS, 0.0, 1.0 );
+}
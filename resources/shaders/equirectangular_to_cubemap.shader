@@ -0,0 +1,33 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+out vec2 position;
+
+#include "common/cubemap.glsl"
+
+void main()
+{
+    position = FullscreenPosition();
+    gl_Position = vec4( position, 0.0, 1.0 );
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+in vec2 position;
+
+#include "common/cubemap.glsl"
+
+uniform sampler2D equirectangular_sampler;
+
+const vec2 INVERSE_ATAN = vec2( 0.1591, 0.3183 );
+
+out vec4 FRAG_COLOR;
+void main()
+{
+    vec3 direction = CubemapDirection( face, position );
+    vec2 uv = vec2( atan( direction.z, direction.x ), asin( direction.y ) ) * INVERSE_ATAN + 0.5;
+    FRAG_COLOR = vec4( texture( equirectangular_sampler, uv ).rgb, 1.0 );
+}
@@ -0,0 +1,44 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+out vec2 position;
+
+#include "common/cubemap.glsl"
+
+void main()
+{
+    position = FullscreenPosition();
+    gl_Position = vec4( position, 0.0, 1.0 );
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+in vec2 position;
+
+#include "common/cubemap.glsl"
+#include "common/ibl_sampling.glsl"
+
+uniform samplerCube environment_sampler;
+uniform float       environment_size;
+uniform int         sample_count;
+
+out vec4 FRAG_COLOR;
+void main()
+{
+    vec3 normal = CubemapDirection( face, position );
+    uint count  = uint( max( sample_count, 1 ) );
+
+    // cosine weighted samples, the pdf cancels the cosine and 1 / PI
+    vec3 irradiance = vec3( 0.0 );
+    for( uint i = 0u; i < count; ++i ) {
+        vec3 direction = CosineSampleHemisphere( Hammersley( i, count ), normal );
+        float pdf = max( dot( normal, direction ), 0.0 ) / PI;
+        float lod = SampleLod( pdf, environment_size, count );
+        irradiance += textureLod( environment_sampler, direction, lod ).rgb;
+    }
+
+    FRAG_COLOR = vec4( irradiance / float( count ), 1.0 );
+}
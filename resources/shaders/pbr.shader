@@ -0,0 +1,286 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+layout ( location = 0 ) in vec3 Position;
+layout ( location = 1 ) in vec3 Normal;
+layout ( location = 2 ) in vec4 Tangent;
+layout ( location = 3 ) in vec2 UV;
+layout ( location = 4 ) in vec3 Color;
+
+out struct {
+
+    vec3 world_position;
+
+    vec3 normal;
+    vec3 tangent;
+    vec3 bitangent;
+
+    vec2 uv;
+
+    vec3 color;
+
+} v2f;
+
+#include "common/matrices.glsl"
+#include "common/object.glsl"
+
+void main()
+{
+    vec4 worldPosition = model * vec4( Position, 1.0 );
+    v2f.world_position = worldPosition.xyz;
+
+    // glTF tangents keep the bitangent's handedness in w
+    v2f.normal    = normal_mat * Normal;
+    v2f.tangent   = normal_mat * Tangent.xyz;
+    v2f.bitangent = cross( v2f.normal, v2f.tangent ) * ( Tangent.w < 0.0 ? -1.0 : 1.0 );
+
+    v2f.uv    = UV;
+    v2f.color = Color;
+
+    gl_Position = projection * view * worldPosition;
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+#pragma multi_compile VERTEX_COLOR ENVIRONMENT_LIGHTING PARALLAX_MAPPING
+
+in struct {
+
+    vec3 world_position;
+
+    vec3 normal;
+    vec3 tangent;
+    vec3 bitangent;
+
+    vec2 uv;
+
+    vec3 color;
+
+} v2f;
+
+#include "common/lights.glsl"
+#include "common/data.glsl"
+#include "common/color.glsl"
+#include "common/normal_mapping.glsl"
+
+#ifdef ENVIRONMENT_LIGHTING
+#include "common/ibl.glsl"
+#endif
+
+#ifdef PARALLAX_MAPPING
+#include "common/parallax.glsl"
+#endif
+
+// NOTE: glTF metallic-roughness inputs, see MetallicRoughness
+uniform vec4      base_color;
+uniform sampler2D base_color_sampler;         // sRGB
+uniform float     metallic;
+uniform float     roughness;
+uniform sampler2D metallic_roughness_sampler; // roughness in green, metallic in blue
+uniform sampler2D normal_sampler;
+uniform float     normal_scale;
+uniform sampler2D occlusion_sampler;          // red
+uniform float     occlusion_strength;
+uniform vec3      emissive;
+uniform sampler2D emissive_sampler;           // sRGB
+
+const float PI = 3.14159265359;
+// ambient light without an environment, matches the Blinn-Phong shader's
+const float AMBIENT = 0.3;
+
+struct Surface {
+    vec3  albedo;
+    vec3  f0;
+    float metallic;
+    float roughness;
+    vec3  normal;
+    vec3  cam_dir;
+};
+
+vec3 DirectionalLight( vec3 dir, vec3 radiance, Surface surface );
+
+vec3 PointLight(
+    vec3 pos, vec3 world_pos, vec3 radiance,
+    float constant, float lin, float quad,
+    Surface surface
+);
+
+vec3 SpotLight(
+    vec3 pos, vec3 dir, vec3 world_pos, vec3 radiance,
+    float constant, float lin, float quad,
+    float inner, float outer,
+    Surface surface
+);
+
+// 1 unless the height map shadows the fragment from a light in direction dir
+float SelfShadow( vec3 dir );
+
+// set in main for SelfShadow
+mat3  tbn;
+vec2  uv;
+float parallaxDepth = 0.0;
+
+out vec4 FRAG_COLOR;
+void main()
+{
+    vec3 cameraDirectionRaw = camera_position.xyz - v2f.world_position;
+    tbn = TangentBasis( v2f.tangent, v2f.bitangent, v2f.normal );
+
+    uv = v2f.uv;
+#ifdef PARALLAX_MAPPING
+    uv = ParallaxOcclusion( v2f.uv, normalize( transpose( tbn ) * cameraDirectionRaw ), parallaxDepth );
+#endif
+
+    // base color and emissive maps are sRGB textures, sampling them returns linear colors
+    vec4 baseColor = texture( base_color_sampler, uv ) * base_color;
+
+#ifdef VERTEX_COLOR
+    baseColor.rgb *= v2f.color;
+#endif
+
+    vec3 metallicRoughness = texture( metallic_roughness_sampler, uv ).rgb;
+
+    Surface surface;
+    surface.metallic  = clamp( metallic * metallicRoughness.b, 0.0, 1.0 );
+    // very low roughness turns highlights into single bright pixels
+    surface.roughness = clamp( roughness * metallicRoughness.g, 0.03, 1.0 );
+    surface.albedo    = baseColor.rgb;
+    surface.f0        = mix( vec3( 0.04 ), baseColor.rgb, surface.metallic );
+
+    surface.normal  = normalize( tbn * UnpackNormal( texture( normal_sampler, uv ).rgb, normal_scale ) );
+    surface.cam_dir = normalize( cameraDirectionRaw );
+
+    float occlusion = 1.0 + occlusion_strength * ( texture( occlusion_sampler, uv ).r - 1.0 );
+
+    vec3 color = vec3(0.0);
+
+    color += DirectionalLight(
+        normalize( directional_light.direction.xyz ),
+        SrgbToLinear( directional_light.diffuse.rgb )
+            * SelfShadow( normalize( directional_light.direction.xyz ) ),
+        surface
+    );
+
+    for( int i = 0; i < MAX_POINT_LIGHTS; ++i ) {
+        if( !point_lights[i].is_active ) { continue; }
+        color += PointLight(
+            point_lights[i].position.xyz, v2f.world_position,
+            SrgbToLinear( point_lights[i].diffuse.rgb )
+                * SelfShadow( normalize( point_lights[i].position.xyz - v2f.world_position ) ),
+            point_lights[i].constant, point_lights[i].linear, point_lights[i].quadratic,
+            surface
+        );
+    }
+
+    for( int i = 0; i < MAX_SPOT_LIGHTS; ++i ) {
+        if( !spot_lights[i].is_active ) { continue; }
+        color += SpotLight(
+            spot_lights[i].position.xyz, spot_lights[i].direction.xyz, v2f.world_position,
+            SrgbToLinear( spot_lights[i].diffuse.rgb )
+                * SelfShadow( normalize( spot_lights[i].position.xyz - v2f.world_position ) ),
+            spot_lights[i].constant, spot_lights[i].linear, spot_lights[i].quadratic,
+            spot_lights[i].inner_cutoff, spot_lights[i].outer_cutoff,
+            surface
+        );
+    }
+
+#ifdef ENVIRONMENT_LIGHTING
+    color += EnvironmentLighting(
+        surface.normal, surface.cam_dir,
+        surface.albedo, surface.f0,
+        surface.roughness, surface.metallic
+    ) * occlusion;
+#else
+    color += SrgbToLinear( directional_light.diffuse.rgb ) * AMBIENT
+        * surface.albedo * ( 1.0 - surface.metallic ) * occlusion;
+#endif
+
+    color += texture( emissive_sampler, uv ).rgb * emissive;
+
+    // fog color is sRGB, fade to it after encoding
+    float distance_to_camera = length( cameraDirectionRaw );
+    float fog_mask = smoothstep( near_clip, far_clip, distance_to_camera );
+    vec3 encoded = mix( LinearToSrgb( color ), fog_color.rgb, fog_mask );
+
+    FRAG_COLOR = vec4( encoded, baseColor.a );
+}
+
+float SelfShadow( vec3 dir ) {
+#ifdef PARALLAX_MAPPING
+    return ParallaxShadow( uv, parallaxDepth, transpose( tbn ) * dir );
+#else
+    return 1.0;
+#endif
+}
+
+float DistributionGGX( float n_dot_h, float rough ) {
+    float a2    = rough * rough * rough * rough;
+    float denom = n_dot_h * n_dot_h * ( a2 - 1.0 ) + 1.0;
+    return a2 / ( PI * denom * denom );
+}
+
+// Schlick-GGX with the k for direct lighting
+float GeometrySmith( float n_dot_v, float n_dot_l, float rough ) {
+    float k = ( rough + 1.0 ) * ( rough + 1.0 ) / 8.0;
+    float ggxV = n_dot_v / ( n_dot_v * ( 1.0 - k ) + k );
+    float ggxL = n_dot_l / ( n_dot_l * ( 1.0 - k ) + k );
+    return ggxV * ggxL;
+}
+
+vec3 FresnelSchlick( float cos_theta, vec3 f0 ) {
+    return f0 + ( 1.0 - f0 ) * pow( clamp( 1.0 - cos_theta, 0.0, 1.0 ), 5.0 );
+}
+
+// Cook-Torrance specular with a Lambert diffuse
+vec3 DirectionalLight( vec3 dir, vec3 radiance, Surface surface )
+{
+    vec3  halfway = normalize( dir + surface.cam_dir );
+    float n_dot_l = max( dot( surface.normal, dir ), 0.0 );
+    float n_dot_v = max( dot( surface.normal, surface.cam_dir ), 0.0001 );
+    float n_dot_h = max( dot( surface.normal, halfway ), 0.0 );
+    float h_dot_v = max( dot( halfway, surface.cam_dir ), 0.0 );
+
+    if( n_dot_l <= 0.0 ) { return vec3( 0.0 ); }
+
+    vec3  fresnel      = FresnelSchlick( h_dot_v, surface.f0 );
+    float distribution = DistributionGGX( n_dot_h, surface.roughness );
+    float geometry     = GeometrySmith( n_dot_v, n_dot_l, surface.roughness );
+
+    vec3 specular = ( distribution * geometry * fresnel ) / ( 4.0 * n_dot_v * n_dot_l + 0.0001 );
+    vec3 diffuse  = ( 1.0 - fresnel ) * ( 1.0 - surface.metallic ) * surface.albedo / PI;
+
+    // lights are authored for Blinn-Phong where a white light lights white fully,
+    // PI makes the Lambert term match
+    return ( diffuse + specular ) * radiance * n_dot_l * PI;
+}
+
+vec3 PointLight(
+    vec3 pos, vec3 world_pos, vec3 radiance,
+    float constant, float lin, float quad,
+    Surface surface
+)
+{
+    vec3 lightDir = pos - world_pos;
+    float dist  = length( lightDir );
+    float atten = 1.0 / ( constant + lin * dist + quad * ( dist * dist ) );
+
+    return DirectionalLight( lightDir / max( dist, 0.0001 ), radiance * atten, surface );
+}
+
+vec3 SpotLight(
+    vec3 pos, vec3 dir, vec3 world_pos, vec3 radiance,
+    float constant, float lin, float quad,
+    float inner, float outer,
+    Surface surface
+)
+{
+    vec3  dir2      = normalize( pos - world_pos );
+    float theta     = dot( dir2, normalize( -dir ) );
+    float epsilon   = inner - outer;
+    float intensity = clamp( ( theta - outer ) / epsilon, 0.0, 1.0 );
+
+    return PointLight( pos, world_pos, radiance * intensity, constant, lin, quad, surface );
+}
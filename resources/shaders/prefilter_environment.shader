@@ -0,0 +1,60 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+out vec2 position;
+
+#include "common/cubemap.glsl"
+
+void main()
+{
+    position = FullscreenPosition();
+    gl_Position = vec4( position, 0.0, 1.0 );
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+in vec2 position;
+
+#include "common/cubemap.glsl"
+#include "common/ibl_sampling.glsl"
+
+uniform samplerCube environment_sampler;
+uniform float       environment_size;
+uniform float       roughness;
+uniform int         sample_count;
+
+out vec4 FRAG_COLOR;
+void main()
+{
+    // assumes the view direction is the normal, as split-sum does
+    vec3 normal = CubemapDirection( face, position );
+    uint count  = uint( max( sample_count, 1 ) );
+
+    if( roughness <= 0.0 ) {
+        FRAG_COLOR = vec4( textureLod( environment_sampler, normal, 0.0 ).rgb, 1.0 );
+        return;
+    }
+
+    vec3  color  = vec3( 0.0 );
+    float weight = 0.0;
+    for( uint i = 0u; i < count; ++i ) {
+        vec3 halfway   = ImportanceSampleGGX( Hammersley( i, count ), normal, roughness );
+        vec3 direction = normalize( 2.0 * dot( normal, halfway ) * halfway - normal );
+
+        float n_dot_l = dot( normal, direction );
+        if( n_dot_l <= 0.0 ) { continue; }
+
+        // sample a blurrier mip for unlikely directions to avoid bright dots
+        float n_dot_h = max( dot( normal, halfway ), 0.0 );
+        float pdf = DistributionGGX( n_dot_h, roughness ) / 4.0 + 0.0001;
+        float lod = SampleLod( pdf, environment_size, count );
+
+        color  += textureLod( environment_sampler, direction, lod ).rgb * n_dot_l;
+        weight += n_dot_l;
+    }
+
+    FRAG_COLOR = vec4( color / max( weight, 0.0001 ), 1.0 );
+}
@@ -0,0 +1,32 @@
+#vertex -------------------------------------------------------------
+
+#version 460 core
+
+out vec3 direction;
+
+#include "common/matrices.glsl"
+
+void main()
+{
+    // fullscreen triangle at the far plane
+    vec2 position = vec2( ( gl_VertexID << 1 ) & 2, gl_VertexID & 2 ) * 2.0 - 1.0;
+    gl_Position = vec4( position, 1.0, 1.0 );
+
+    // undo the projection, then only the rotation of the view
+    vec4 viewDirection = inverse( projection ) * vec4( position, 1.0, 1.0 );
+    direction = transpose( mat3( view ) ) * ( viewDirection.xyz / viewDirection.w );
+}
+
+#fragment -----------------------------------------------------------
+
+#version 460 core
+
+in vec3 direction;
+
+uniform samplerCube skybox_sampler;
+
+out vec4 FRAG_COLOR;
+void main()
+{
+    FRAG_COLOR = vec4( texture( skybox_sampler, normalize( direction ) ).rgb, 1.0 );
+}
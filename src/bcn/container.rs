@@ -0,0 +1,253 @@
+use super::{ BakedTexture, BcFormat };
+
+const DDS_MAGIC:&[u8;4] = b"DDS ";
+const DDS_HEADER_SIZE:u32 = 124;
+const DDS_PIXEL_FORMAT_SIZE:u32 = 32;
+// CAPS | HEIGHT | WIDTH | PIXELFORMAT | MIPMAPCOUNT | LINEARSIZE
+const DDSD_FLAGS:u32 = 0x1 | 0x2 | 0x4 | 0x1000 | 0x20000 | 0x80000;
+const DDPF_FOURCC:u32 = 0x4;
+const DDSCAPS_COMPLEX:u32 = 0x8;
+const DDSCAPS_TEXTURE:u32 = 0x1000;
+const DDSCAPS_MIPMAP:u32 = 0x400000;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D:u32 = 3;
+
+const KTX2_IDENTIFIER:[u8;12] = [ 0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A ];
+const KTX2_HEADER_SIZE:usize = 80;
+const KTX2_LEVEL_INDEX_SIZE:usize = 24;
+// data format descriptor values from the Khronos Data Format spec
+const KHR_DF_VERSION:u32 = 2;
+const KHR_DF_PRIMARIES_BT709:u8 = 1;
+const KHR_DF_TRANSFER_LINEAR:u8 = 1;
+const KHR_DF_TRANSFER_SRGB:u8 = 2;
+const KHR_DF_SAMPLE_LINEAR:u8 = 0x10;
+// right and up when the first row is the bottom of the texture, like GL expects
+const KTX_ORIENTATION_BOTTOM_UP:&str = "ru";
+const KTX_ORIENTATION_TOP_DOWN:&str = "rd";
+
+/// DDS file with a DX10 header, which is the only way to store BC7 and sRGB
+pub fn write_dds( texture:&BakedTexture ) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity( 148 + texture.levels.iter().map( |level| level.len() ).sum::<usize>() );
+    bytes.extend_from_slice( DDS_MAGIC );
+
+    let mut caps = DDSCAPS_TEXTURE;
+    if texture.levels.len() > 1 { caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP; }
+    let header = [
+        DDS_HEADER_SIZE, DDSD_FLAGS,
+        texture.height, texture.width,
+        texture.levels[0].len() as u32, 0, texture.levels.len() as u32,
+    ];
+    push_u32s( &mut bytes, &header );
+    push_u32s( &mut bytes, &[0;11] );
+    push_u32s( &mut bytes, &[ DDS_PIXEL_FORMAT_SIZE, DDPF_FOURCC ] );
+    bytes.extend_from_slice( b"DX10" );
+    push_u32s( &mut bytes, &[0;5] );
+    push_u32s( &mut bytes, &[ caps, 0, 0, 0, 0 ] );
+
+    // DX10 header, a single 2D texture
+    push_u32s( &mut bytes, &[ dxgi_format( texture ), D3D10_RESOURCE_DIMENSION_TEXTURE2D, 0, 1, 0 ] );
+
+    for level in texture.levels.iter() { bytes.extend_from_slice( level ); }
+    bytes
+}
+
+/// KTX2 file without supercompression
+pub fn write_ktx2( texture:&BakedTexture ) -> Vec<u8> {
+    let level_count = texture.levels.len();
+    let data_format_descriptor = data_format_descriptor( texture );
+    let orientation = if texture.bottom_up { KTX_ORIENTATION_BOTTOM_UP } else { KTX_ORIENTATION_TOP_DOWN };
+    let key_values = key_value( "KTXorientation", orientation );
+
+    let dfd_offset = KTX2_HEADER_SIZE + level_count * KTX2_LEVEL_INDEX_SIZE;
+    let kvd_offset = dfd_offset + data_format_descriptor.len();
+
+    // levels go smallest first, each aligned to the block size
+    let alignment = texture.format.block_size();
+    let mut level_offsets = vec![ 0usize; level_count ];
+    let mut end = kvd_offset + key_values.len();
+    for level in ( 0..level_count ).rev() {
+        end = end.div_ceil( alignment ) * alignment;
+        level_offsets[level] = end;
+        end += texture.levels[level].len();
+    }
+
+    let mut bytes = Vec::with_capacity( end );
+    bytes.extend_from_slice( &KTX2_IDENTIFIER );
+    push_u32s( &mut bytes, &[
+        vk_format( texture ), 1,
+        texture.width, texture.height, 0,
+        0, 1, level_count as u32,
+        0,
+        dfd_offset as u32, data_format_descriptor.len() as u32,
+        kvd_offset as u32, key_values.len() as u32,
+    ] );
+    // no supercompression global data
+    bytes.extend_from_slice( &[0;16] );
+
+    for ( offset, level ) in level_offsets.iter().zip( texture.levels.iter() ) {
+        for value in [ *offset as u64, level.len() as u64, level.len() as u64 ] {
+            bytes.extend_from_slice( &value.to_le_bytes() );
+        }
+    }
+    bytes.extend_from_slice( &data_format_descriptor );
+    bytes.extend_from_slice( &key_values );
+
+    for level in ( 0..level_count ).rev() {
+        bytes.resize( level_offsets[level], 0 );
+        bytes.extend_from_slice( &texture.levels[level] );
+    }
+    bytes
+}
+
+fn dxgi_format( texture:&BakedTexture ) -> u32 {
+    match ( texture.format, texture.srgb ) {
+        ( BcFormat::Bc1, false ) => 71,
+        ( BcFormat::Bc1, true  ) => 72,
+        ( BcFormat::Bc3, false ) => 77,
+        ( BcFormat::Bc3, true  ) => 78,
+        ( BcFormat::Bc4, _     ) => 80,
+        ( BcFormat::Bc5, _     ) => 83,
+        ( BcFormat::Bc7, false ) => 98,
+        ( BcFormat::Bc7, true  ) => 99,
+    }
+}
+
+fn vk_format( texture:&BakedTexture ) -> u32 {
+    match ( texture.format, texture.srgb ) {
+        ( BcFormat::Bc1, false ) => 133,
+        ( BcFormat::Bc1, true  ) => 134,
+        ( BcFormat::Bc3, false ) => 137,
+        ( BcFormat::Bc3, true  ) => 138,
+        ( BcFormat::Bc4, _     ) => 139,
+        ( BcFormat::Bc5, _     ) => 141,
+        ( BcFormat::Bc7, false ) => 145,
+        ( BcFormat::Bc7, true  ) => 146,
+    }
+}
+
+/// Basic data format descriptor block describing one 4x4 block
+fn data_format_descriptor( texture:&BakedTexture ) -> Vec<u8> {
+    // color model and ( bit offset, bit length, channel id ) per sample
+    let ( color_model, samples ):( u8, &[( u16, u8, u8 )] ) = match texture.format {
+        BcFormat::Bc1 => ( 128, &[ ( 0, 64, 1 ) ] ),
+        BcFormat::Bc3 => ( 130, &[ ( 0, 64, 15 ), ( 64, 64, 0 ) ] ),
+        BcFormat::Bc4 => ( 131, &[ ( 0, 64, 0 ) ] ),
+        BcFormat::Bc5 => ( 132, &[ ( 0, 64, 0 ), ( 64, 64, 1 ) ] ),
+        BcFormat::Bc7 => ( 134, &[ ( 0, 128, 0 ) ] ),
+    };
+    let block_size = 24 + 16 * samples.len();
+
+    let mut bytes = Vec::with_capacity( 4 + block_size );
+    push_u32s( &mut bytes, &[ 4 + block_size as u32, 0, KHR_DF_VERSION | ( ( block_size as u32 ) << 16 ) ] );
+    bytes.extend_from_slice( &[
+        color_model, KHR_DF_PRIMARIES_BT709,
+        if texture.srgb { KHR_DF_TRANSFER_SRGB } else { KHR_DF_TRANSFER_LINEAR }, 0,
+        3, 3, 0, 0,
+        texture.format.block_size() as u8, 0, 0, 0, 0, 0, 0, 0,
+    ] );
+    for &( offset, length, channel ) in samples.iter() {
+        // alpha stays linear in sRGB textures
+        let qualifiers = if texture.srgb && channel == 15 { KHR_DF_SAMPLE_LINEAR } else { 0 };
+        bytes.extend_from_slice( &offset.to_le_bytes() );
+        bytes.extend_from_slice( &[ length - 1, channel | qualifiers, 0, 0, 0, 0 ] );
+        push_u32s( &mut bytes, &[ 0, u32::MAX ] );
+    }
+    bytes
+}
+
+/// Key and value, null terminated and padded to 4 bytes
+fn key_value( key:&str, value:&str ) -> Vec<u8> {
+    let length = key.len() + value.len() + 2;
+    let mut bytes = Vec::with_capacity( 4 + length.div_ceil( 4 ) * 4 );
+    bytes.extend_from_slice( &( length as u32 ).to_le_bytes() );
+    bytes.extend_from_slice( key.as_bytes() );
+    bytes.push( 0 );
+    bytes.extend_from_slice( value.as_bytes() );
+    bytes.push( 0 );
+    bytes.resize( 4 + length.div_ceil( 4 ) * 4, 0 );
+    bytes
+}
+
+fn push_u32s( bytes:&mut Vec<u8>, values:&[u32] ) {
+    for value in values.iter() { bytes.extend_from_slice( &value.to_le_bytes() ); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ bake, BakeOptions, test_image::test_image };
+
+    fn baked( format:BcFormat, srgb:bool ) -> BakedTexture {
+        let mut options = BakeOptions::default();
+        options.set_format( format );
+        options.set_srgb( srgb );
+        bake( &test_image(), &options )
+    }
+
+    fn read_u32( bytes:&[u8], offset:usize ) -> u32 {
+        u32::from_le_bytes( bytes[offset..offset + 4].try_into().unwrap() )
+    }
+
+    fn read_u64( bytes:&[u8], offset:usize ) -> usize {
+        u64::from_le_bytes( bytes[offset..offset + 8].try_into().unwrap() ) as usize
+    }
+
+    #[test]
+    fn baked_levels_cover_every_block() {
+        let texture = baked( BcFormat::Bc1, false );
+        // 12x8, 6x4, 3x2, 1x1
+        let blocks = [ 3 * 2, 2, 1, 1 ];
+        assert_eq!( texture.levels.len(), blocks.len() );
+        for ( level, blocks ) in texture.levels.iter().zip( blocks.iter() ) {
+            assert_eq!( level.len(), blocks * 8 );
+        }
+    }
+
+    #[test]
+    fn dds_header() {
+        let texture = baked( BcFormat::Bc7, true );
+        let bytes = write_dds( &texture );
+        assert_eq!( &bytes[0..4], DDS_MAGIC );
+        assert_eq!( ( read_u32( &bytes, 12 ), read_u32( &bytes, 16 ) ), ( 8, 12 ) );
+        assert_eq!( read_u32( &bytes, 28 ) as usize, texture.levels.len() );
+        assert_eq!( &bytes[84..88], b"DX10" );
+        // BC7_UNORM_SRGB
+        assert_eq!( read_u32( &bytes, 128 ), 99 );
+        let data_size:usize = texture.levels.iter().map( Vec::len ).sum();
+        assert_eq!( bytes.len(), 148 + data_size );
+    }
+
+    #[test]
+    fn ktx2_levels_are_aligned_smallest_first() {
+        for format in [ BcFormat::Bc1, BcFormat::Bc3, BcFormat::Bc4, BcFormat::Bc5, BcFormat::Bc7 ] {
+            let texture = baked( format, false );
+            let bytes = write_ktx2( &texture );
+            assert_eq!( &bytes[0..12], &KTX2_IDENTIFIER );
+            assert_eq!( read_u32( &bytes, 40 ) as usize, texture.levels.len() );
+            for ( index, level ) in texture.levels.iter().enumerate() {
+                let entry  = KTX2_HEADER_SIZE + index * KTX2_LEVEL_INDEX_SIZE;
+                let offset = read_u64( &bytes, entry );
+                assert_eq!( offset % format.block_size(), 0 );
+                assert_eq!( read_u64( &bytes, entry + 8 ), level.len() );
+                assert_eq!( &bytes[offset..offset + level.len()], level.as_slice() );
+                if index > 0 { assert!( offset < read_u64( &bytes, entry - KTX2_LEVEL_INDEX_SIZE ) ); }
+            }
+        }
+    }
+
+    #[test]
+    fn ktx2_orientation_follows_the_flip() {
+        let contains = |bytes:Vec<u8>, key_value:&[u8]| bytes.windows( key_value.len() ).any( |window| window == key_value );
+        let mut texture = baked( BcFormat::Bc1, false );
+        assert!( contains( write_ktx2( &texture ), b"KTXorientation\0ru\0" ) );
+        texture.bottom_up = false;
+        assert!( contains( write_ktx2( &texture ), b"KTXorientation\0rd\0" ) );
+    }
+
+    #[test]
+    fn ktx2_marks_srgb_alpha_linear() {
+        let descriptor = data_format_descriptor( &baked( BcFormat::Bc3, true ) );
+        assert_eq!( descriptor[14], KHR_DF_TRANSFER_SRGB );
+        // channel byte of the alpha sample, the first of the two BC3 samples
+        assert_eq!( descriptor[28 + 3], 15 | KHR_DF_SAMPLE_LINEAR );
+    }
+}
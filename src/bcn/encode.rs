@@ -0,0 +1,427 @@
+use super::BcFormat;
+
+/// 4x4 pixels, row by row
+type Block = [[u8;4];16];
+
+// BC7 interpolation weights out of 64 for 4 bit indices
+const BC7_WEIGHTS:[u32;16] = [ 0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64 ];
+// mode 6: one subset, 7 bit RGBA endpoints with a p-bit each, 4 bit indices
+const BC7_MODE_6:u128 = 1 << 6;
+
+/// Encodes a level block by block, blocks reaching past the edges repeat the last row and column
+pub fn encode_surface( pixels:&[[u8;4]], width:u32, height:u32, format:BcFormat ) -> Vec<u8> {
+    let ( width, height ) = ( width as usize, height as usize );
+    let blocks_x = width.div_ceil( 4 );
+    let blocks_y = height.div_ceil( 4 );
+    let mut encoded = Vec::with_capacity( blocks_x * blocks_y * format.block_size() );
+
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let mut block:Block = [[0;4];16];
+            for ( i, pixel ) in block.iter_mut().enumerate() {
+                let x = ( block_x * 4 + i % 4 ).min( width  - 1 );
+                let y = ( block_y * 4 + i / 4 ).min( height - 1 );
+                *pixel = pixels[ y * width + x ];
+            }
+            match format {
+                BcFormat::Bc1 => encoded.extend_from_slice( &encode_bc1( &block ) ),
+                BcFormat::Bc3 => {
+                    encoded.extend_from_slice( &encode_bc4( &channel( &block, 3 ) ) );
+                    encoded.extend_from_slice( &encode_color( &block, false ) );
+                },
+                BcFormat::Bc4 => encoded.extend_from_slice( &encode_bc4( &channel( &block, 0 ) ) ),
+                BcFormat::Bc5 => {
+                    encoded.extend_from_slice( &encode_bc4( &channel( &block, 0 ) ) );
+                    encoded.extend_from_slice( &encode_bc4( &channel( &block, 1 ) ) );
+                },
+                BcFormat::Bc7 => encoded.extend_from_slice( &encode_bc7( &block ) ),
+            }
+        }
+    }
+    encoded
+}
+
+fn channel( block:&Block, channel:usize ) -> [u8;16] {
+    let mut values = [0;16];
+    for ( value, pixel ) in values.iter_mut().zip( block.iter() ) { *value = pixel[channel]; }
+    values
+}
+
+/// BC1 uses its 3 color mode to cut out pixels with alpha under half
+fn encode_bc1( block:&Block ) -> [u8;8] {
+    encode_color( block, block.iter().any( |pixel| pixel[3] < 128 ) )
+}
+
+/// The RGB half of BC1 and BC3: two RGB565 endpoints and 2 bit indices
+fn encode_color( block:&Block, punch_through:bool ) -> [u8;8] {
+    let opaque:Vec<[f32;3]> = block.iter()
+        .filter( |pixel| !punch_through || pixel[3] >= 128 )
+        .map( |pixel| [ pixel[0] as f32, pixel[1] as f32, pixel[2] as f32 ] )
+        .collect();
+    // every pixel cut out, 3 color mode with index 3 everywhere
+    if opaque.is_empty() {
+        return [ 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF ];
+    }
+
+    let ( low, high ) = principal_endpoints( &opaque );
+    let mut best = encode_color_endpoints( block, to_565( high ), to_565( low ), punch_through );
+
+    // least squares fit of the endpoints to the chosen indices
+    let weights:&[f32] = if punch_through { &[ 0.0, 1.0, 0.5 ] } else { &[ 0.0, 1.0, 1.0 / 3.0, 2.0 / 3.0 ] };
+    let mut points  = Vec::with_capacity( 16 );
+    let mut targets = Vec::with_capacity( 16 );
+    for ( i, pixel ) in block.iter().enumerate() {
+        let index = best.indices[i] as usize;
+        if index < weights.len() {
+            points.push( [ pixel[0] as f32, pixel[1] as f32, pixel[2] as f32 ] );
+            targets.push( weights[index] );
+        }
+    }
+    if let Some( ( start, end ) ) = least_squares_endpoints( &points, &targets ) {
+        let refined = encode_color_endpoints( block, to_565( start ), to_565( end ), punch_through );
+        if refined.error < best.error { best = refined; }
+    }
+
+    let mut bytes = [0u8;8];
+    bytes[0..2].copy_from_slice( &best.color0.to_le_bytes() );
+    bytes[2..4].copy_from_slice( &best.color1.to_le_bytes() );
+    let mut bits:u32 = 0;
+    for ( i, index ) in best.indices.iter().enumerate() { bits |= ( *index as u32 ) << ( i * 2 ); }
+    bytes[4..8].copy_from_slice( &bits.to_le_bytes() );
+    bytes
+}
+
+struct ColorBlock {
+    color0:u16,
+    color1:u16,
+    indices:[u8;16],
+    error:f32,
+}
+
+/// Orders the endpoints for the mode asked for and picks the closest palette entry per pixel.
+/// color0 > color1 selects 4 colors, color0 <= color1 selects 3 colors and transparent black.
+fn encode_color_endpoints( block:&Block, a:u16, b:u16, punch_through:bool ) -> ColorBlock {
+    let ( color0, color1 ) = if punch_through { ( a.min( b ), a.max( b ) ) } else { ( a.max( b ), a.min( b ) ) };
+    let ( c0, c1 ) = ( from_565( color0 ), from_565( color1 ) );
+    let lerp = |t:f32| [ 0, 1, 2 ].map( |i| c0[i] + ( c1[i] - c0[i] ) * t );
+
+    let palette:Vec<[f32;3]> = if color0 > color1 {
+        vec![ c0, c1, lerp( 1.0 / 3.0 ), lerp( 2.0 / 3.0 ) ]
+    } else {
+        vec![ c0, c1, lerp( 0.5 ) ]
+    };
+
+    let mut indices = [0u8;16];
+    let mut error = 0.0;
+    for ( i, pixel ) in block.iter().enumerate() {
+        if punch_through && pixel[3] < 128 {
+            indices[i] = 3;
+            continue;
+        }
+        let point = [ pixel[0] as f32, pixel[1] as f32, pixel[2] as f32 ];
+        let ( index, distance ) = closest( &palette, &point );
+        indices[i] = index as u8;
+        error += distance;
+    }
+    ColorBlock { color0, color1, indices, error }
+}
+
+/// BC4 block, also the alpha half of BC3 and each half of BC5.
+/// Uses the 8 value mode between the lowest and highest values.
+fn encode_bc4( values:&[u8;16] ) -> [u8;8] {
+    let high = *values.iter().max().unwrap_or( &0 );
+    let low  = *values.iter().min().unwrap_or( &0 );
+
+    let mut bytes = [0u8;8];
+    bytes[0] = high;
+    bytes[1] = low;
+    if high == low { return bytes; }
+
+    let mut palette = [0.0f32;8];
+    palette[0] = high as f32;
+    palette[1] = low  as f32;
+    for ( i, entry ) in palette.iter_mut().enumerate().skip( 2 ) {
+        *entry = ( ( 8 - i ) as f32 * high as f32 + ( i - 1 ) as f32 * low as f32 ) / 7.0;
+    }
+
+    let mut bits:u64 = 0;
+    for ( i, value ) in values.iter().enumerate() {
+        let ( index, _ ) = closest( &palette.map( |entry| [entry] ), &[ *value as f32 ] );
+        bits |= ( index as u64 ) << ( i * 3 );
+    }
+    bytes[2..8].copy_from_slice( &bits.to_le_bytes()[0..6] );
+    bytes
+}
+
+/// BC7 block in mode 6, which covers all of RGBA with one pair of endpoints
+fn encode_bc7( block:&Block ) -> [u8;16] {
+    let points:Vec<[f32;4]> = block.iter()
+        .map( |pixel| pixel.map( |channel| channel as f32 ) )
+        .collect();
+
+    let ( low, high ) = principal_endpoints( &points );
+    let mut best = encode_bc7_endpoints( &points, quantize_bc7( low ), quantize_bc7( high ) );
+
+    let targets:Vec<f32> = best.indices.iter().map( |index| BC7_WEIGHTS[*index as usize] as f32 / 64.0 ).collect();
+    if let Some( ( start, end ) ) = least_squares_endpoints( &points, &targets ) {
+        let refined = encode_bc7_endpoints( &points, quantize_bc7( start ), quantize_bc7( end ) );
+        if refined.error < best.error { best = refined; }
+    }
+
+    // the first index is stored with 3 bits, so its top bit has to be 0
+    if best.indices[0] & 0b1000 != 0 {
+        best.endpoints.swap( 0, 1 );
+        for index in best.indices.iter_mut() { *index = 15 - *index; }
+    }
+
+    let mut bits = BC7_MODE_6;
+    let mut position = 7;
+    let mut write = |value:u32, count:u32| {
+        bits |= ( value as u128 ) << position;
+        position += count;
+    };
+    for channel in 0..4 {
+        for endpoint in best.endpoints.iter() { write( endpoint.0[channel] as u32, 7 ); }
+    }
+    for endpoint in best.endpoints.iter() { write( endpoint.1 as u32, 1 ); }
+    write( best.indices[0] as u32, 3 );
+    for index in best.indices.iter().skip( 1 ) { write( *index as u32, 4 ); }
+
+    bits.to_le_bytes()
+}
+
+/// 7 bit RGBA endpoint and its p-bit, the stored value is ( channel << 1 ) | p
+type Bc7Endpoint = ( [u8;4], u8 );
+
+struct Bc7Block {
+    endpoints:[Bc7Endpoint;2],
+    indices:[u8;16],
+    error:f32,
+}
+
+fn quantize_bc7( endpoint:[f32;4] ) -> Bc7Endpoint {
+    let mut best = ( [0u8;4], 0u8 );
+    let mut best_error = f32::MAX;
+    for p in 0..2u8 {
+        let channels = endpoint.map( |value| ( ( value - p as f32 ) * 0.5 ).round().clamp( 0.0, 127.0 ) as u8 );
+        let error:f32 = channels.iter().zip( endpoint.iter() )
+            .map( |( channel, value )| {
+                let difference = ( ( *channel << 1 ) | p ) as f32 - value;
+                difference * difference
+            } ).sum();
+        if error < best_error {
+            best_error = error;
+            best = ( channels, p );
+        }
+    }
+    best
+}
+
+fn encode_bc7_endpoints( points:&[[f32;4]], start:Bc7Endpoint, end:Bc7Endpoint ) -> Bc7Block {
+    let expand = |endpoint:&Bc7Endpoint| endpoint.0.map( |channel| ( ( channel << 1 ) | endpoint.1 ) as u32 );
+    let ( e0, e1 ) = ( expand( &start ), expand( &end ) );
+    let palette:Vec<[f32;4]> = BC7_WEIGHTS.iter()
+        .map( |weight| [ 0, 1, 2, 3 ].map( |i| ( ( ( 64 - weight ) * e0[i] + weight * e1[i] + 32 ) >> 6 ) as f32 ) )
+        .collect();
+
+    let mut indices = [0u8;16];
+    let mut error = 0.0;
+    for ( i, point ) in points.iter().enumerate() {
+        let ( index, distance ) = closest( &palette, point );
+        indices[i] = index as u8;
+        error += distance;
+    }
+    Bc7Block { endpoints: [ start, end ], indices, error }
+}
+
+/// Ends of the line through the points along their principal axis
+fn principal_endpoints<const N:usize>( points:&[[f32;N]] ) -> ( [f32;N], [f32;N] ) {
+    let count = points.len() as f32;
+    let mut mean = [0.0;N];
+    for point in points.iter() {
+        for i in 0..N { mean[i] += point[i] / count; }
+    }
+
+    let mut covariance = [[0.0;N];N];
+    for point in points.iter() {
+        for row in 0..N {
+            for column in 0..N {
+                covariance[row][column] += ( point[row] - mean[row] ) * ( point[column] - mean[column] );
+            }
+        }
+    }
+
+    // power iteration, starting from the diagonal of the bounding box
+    let mut axis = [0.0;N];
+    for i in 0..N {
+        let ( low, high ) = points.iter().fold( ( f32::MAX, f32::MIN ), |( low, high ), point| ( low.min( point[i] ), high.max( point[i] ) ) );
+        axis[i] = high - low;
+    }
+    for _ in 0..8 {
+        let mut next = [0.0;N];
+        for row in 0..N {
+            for column in 0..N { next[row] += covariance[row][column] * axis[column]; }
+        }
+        let length = next.iter().map( |value| value * value ).sum::<f32>().sqrt();
+        if length < 1e-6 { break; }
+        axis = next.map( |value| value / length );
+    }
+    let length = axis.iter().map( |value| value * value ).sum::<f32>().sqrt();
+    if length < 1e-6 { return ( mean, mean ); }
+    let axis = axis.map( |value| value / length );
+
+    let ( mut low, mut high ) = ( f32::MAX, f32::MIN );
+    for point in points.iter() {
+        let t:f32 = ( 0..N ).map( |i| ( point[i] - mean[i] ) * axis[i] ).sum();
+        low  = low.min( t );
+        high = high.max( t );
+    }
+    let along = |t:f32| {
+        let mut endpoint = [0.0;N];
+        for i in 0..N { endpoint[i] = ( mean[i] + axis[i] * t ).clamp( 0.0, 255.0 ); }
+        endpoint
+    };
+    ( along( low ), along( high ) )
+}
+
+/// Endpoints that best reproduce the points when each is blended
+/// from start to end by its target weight, None when the weights can't tell them apart
+fn least_squares_endpoints<const N:usize>( points:&[[f32;N]], weights:&[f32] ) -> Option<( [f32;N], [f32;N] )> {
+    let ( mut aa, mut ab, mut bb ) = ( 0.0, 0.0, 0.0 );
+    let ( mut ax, mut bx ) = ( [0.0;N], [0.0;N] );
+    for ( point, weight ) in points.iter().zip( weights.iter() ) {
+        let ( a, b ) = ( 1.0 - weight, *weight );
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        for i in 0..N {
+            ax[i] += a * point[i];
+            bx[i] += b * point[i];
+        }
+    }
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 { return None; }
+
+    let mut start = [0.0;N];
+    let mut end   = [0.0;N];
+    for i in 0..N {
+        start[i] = ( ( bb * ax[i] - ab * bx[i] ) / determinant ).clamp( 0.0, 255.0 );
+        end[i]   = ( ( aa * bx[i] - ab * ax[i] ) / determinant ).clamp( 0.0, 255.0 );
+    }
+    Some( ( start, end ) )
+}
+
+/// Index of the closest palette entry and its squared distance
+fn closest<const N:usize>( palette:&[[f32;N]], point:&[f32;N] ) -> ( usize, f32 ) {
+    let mut best = ( 0, f32::MAX );
+    for ( index, entry ) in palette.iter().enumerate() {
+        let distance:f32 = entry.iter().zip( point.iter() ).map( |( a, b )| ( a - b ) * ( a - b ) ).sum();
+        if distance < best.1 { best = ( index, distance ); }
+    }
+    best
+}
+
+fn to_565( color:[f32;3] ) -> u16 {
+    let r = ( color[0] * 31.0 / 255.0 ).round().clamp( 0.0, 31.0 ) as u16;
+    let g = ( color[1] * 63.0 / 255.0 ).round().clamp( 0.0, 63.0 ) as u16;
+    let b = ( color[2] * 31.0 / 255.0 ).round().clamp( 0.0, 31.0 ) as u16;
+    ( r << 11 ) | ( g << 5 ) | b
+}
+
+fn from_565( color:u16 ) -> [f32;3] {
+    let r = ( color >> 11 ) & 0x1F;
+    let g = ( color >> 5 )  & 0x3F;
+    let b = color & 0x1F;
+    [ ( ( r << 3 ) | ( r >> 2 ) ) as f32, ( ( g << 2 ) | ( g >> 4 ) ) as f32, ( ( b << 3 ) | ( b >> 2 ) ) as f32 ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Palette of a BC1 block and the index of every pixel, alpha 0 for transparent black
+    fn decode_bc1( bytes:&[u8] ) -> [[u8;4];16] {
+        let color0 = u16::from_le_bytes( [ bytes[0], bytes[1] ] );
+        let color1 = u16::from_le_bytes( [ bytes[2], bytes[3] ] );
+        let ( c0, c1 ) = ( from_565( color0 ), from_565( color1 ) );
+        let lerp = |t:f32| [ 0, 1, 2 ].map( |i| c0[i] + ( c1[i] - c0[i] ) * t );
+        let rgba = |c:[f32;3], a:u8| [ c[0].round() as u8, c[1].round() as u8, c[2].round() as u8, a ];
+        let palette = if color0 > color1 {
+            [ rgba( c0, 255 ), rgba( c1, 255 ), rgba( lerp( 1.0 / 3.0 ), 255 ), rgba( lerp( 2.0 / 3.0 ), 255 ) ]
+        } else {
+            [ rgba( c0, 255 ), rgba( c1, 255 ), rgba( lerp( 0.5 ), 255 ), [ 0, 0, 0, 0 ] ]
+        };
+        let bits = u32::from_le_bytes( [ bytes[4], bytes[5], bytes[6], bytes[7] ] );
+        core::array::from_fn( |i| palette[ ( ( bits >> ( i * 2 ) ) & 3 ) as usize ] )
+    }
+
+    fn decode_bc4( bytes:&[u8] ) -> [u8;16] {
+        let ( red0, red1 ) = ( bytes[0] as f32, bytes[1] as f32 );
+        let palette:[f32;8] = if red0 > red1 {
+            core::array::from_fn( |i| match i {
+                0 => red0, 1 => red1,
+                _ => ( ( 8 - i ) as f32 * red0 + ( i - 1 ) as f32 * red1 ) / 7.0,
+            } )
+        } else {
+            core::array::from_fn( |i| match i {
+                0 => red0, 1 => red1, 6 => 0.0, 7 => 255.0,
+                _ => ( ( 6 - i ) as f32 * red0 + ( i - 1 ) as f32 * red1 ) / 5.0,
+            } )
+        };
+        let mut bits = [0u8;8];
+        bits[0..6].copy_from_slice( &bytes[2..8] );
+        let bits = u64::from_le_bytes( bits );
+        core::array::from_fn( |i| palette[ ( ( bits >> ( i * 3 ) ) & 7 ) as usize ].round() as u8 )
+    }
+
+    #[test]
+    fn bc1_two_colors_decode_exactly() {
+        let block:Block = core::array::from_fn( |i| if i < 8 { [ 0, 0, 0, 255 ] } else { [ 255, 255, 255, 255 ] } );
+        let encoded = encode_bc1( &block );
+        let color0 = u16::from_le_bytes( [ encoded[0], encoded[1] ] );
+        let color1 = u16::from_le_bytes( [ encoded[2], encoded[3] ] );
+        assert!( color0 > color1, "opaque blocks use the 4 color mode" );
+        assert_eq!( ( color0, color1 ), ( 0xFFFF, 0x0000 ) );
+        assert_eq!( decode_bc1( &encoded ), block );
+    }
+
+    #[test]
+    fn bc1_cuts_out_transparent_pixels() {
+        let block:Block = core::array::from_fn( |i| if i % 2 == 0 { [ 200, 40, 40, 255 ] } else { [ 0, 0, 0, 0 ] } );
+        let encoded = encode_bc1( &block );
+        let color0 = u16::from_le_bytes( [ encoded[0], encoded[1] ] );
+        let color1 = u16::from_le_bytes( [ encoded[2], encoded[3] ] );
+        assert!( color0 <= color1, "blocks with cut out pixels use the 3 color mode" );
+        for ( decoded, pixel ) in decode_bc1( &encoded ).iter().zip( block.iter() ) {
+            assert_eq!( decoded[3], pixel[3] );
+            if pixel[3] == 255 {
+                for channel in 0..3 { assert!( decoded[channel].abs_diff( pixel[channel] ) <= 8 ); }
+            }
+        }
+    }
+
+    #[test]
+    fn bc4_endpoints_are_the_block_range() {
+        let values:[u8;16] = core::array::from_fn( |i| ( i * 16 ) as u8 );
+        let encoded = encode_bc4( &values );
+        assert_eq!( ( encoded[0], encoded[1] ), ( 240, 0 ) );
+        for ( decoded, value ) in decode_bc4( &encoded ).iter().zip( values.iter() ) {
+            // half a step of the 8 value palette
+            assert!( decoded.abs_diff( *value ) <= 18, "{} decoded as {}", value, decoded );
+        }
+    }
+
+    #[test]
+    fn bc4_flat_block_is_exact() {
+        let encoded = encode_bc4( &[ 77;16 ] );
+        assert_eq!( decode_bc4( &encoded ), [ 77;16 ] );
+    }
+
+    #[test]
+    fn surfaces_cover_partial_blocks() {
+        let pixels = vec![ [ 10, 20, 30, 255 ]; 10 * 6 ];
+        assert_eq!( encode_surface( &pixels, 10, 6, BcFormat::Bc1 ).len(), 3 * 2 * 8 );
+        assert_eq!( encode_surface( &pixels, 10, 6, BcFormat::Bc7 ).len(), 3 * 2 * 16 );
+        assert_eq!( encode_surface( &pixels, 1, 1, BcFormat::Bc4 ).len(), 8 );
+    }
+}
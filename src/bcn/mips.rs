@@ -0,0 +1,206 @@
+use core::f32::consts::PI;
+
+// Kaiser window settings, the filter reaches 1.5 destination pixels to either side
+const KAISER_WIDTH:f32 = 3.0;
+const KAISER_ALPHA:f32 = 4.0;
+
+/// Filter used to shrink each mip level into the next
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    /// Averages the pixels each destination pixel covers, fast but blurry
+    Box,
+    /// Kaiser windowed sinc, keeps smaller levels sharp
+    Kaiser,
+}
+
+impl MipFilter {
+    pub fn from_name( name:&str ) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "box"    => Some( Self::Box ),
+            "kaiser" => Some( Self::Kaiser ),
+            _ => None,
+        }
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::Box    => "Box",
+            Self::Kaiser => "Kaiser",
+        }
+    }
+}
+
+/// A mip level in linear float RGBA,
+/// normal maps hold vectors in -1..1 instead of colors
+pub struct Surface {
+    pub width:u32,
+    pub height:u32,
+    pub pixels:Vec<[f32;4]>,
+}
+
+impl Surface {
+    /// RGBA floats in 0..1 as loaded from an image,
+    /// srgb color is linearized and normal maps are unpacked to vectors
+    pub fn from_rgba( width:u32, height:u32, data:&[f32], srgb:bool, normal_map:bool ) -> Self {
+        let pixels = data.chunks_exact( 4 ).map( |texel| {
+            let mut pixel = [ texel[0], texel[1], texel[2], texel[3] ];
+            for channel in pixel.iter_mut().take( 3 ) {
+                if srgb            { *channel = srgb_to_linear( *channel ); }
+                else if normal_map { *channel = *channel * 2.0 - 1.0; }
+            }
+            pixel
+        } ).collect();
+        Self { width, height, pixels }
+    }
+
+    /// Back to 8 bit RGBA for the block encoders
+    pub fn to_rgba8( &self, srgb:bool, normal_map:bool ) -> Vec<[u8;4]> {
+        self.pixels.iter().map( |pixel| {
+            let mut texel = [0u8;4];
+            for ( channel, value ) in pixel.iter().enumerate() {
+                let value = if channel == 3     { *value }
+                    else if srgb                { linear_to_srgb( *value ) }
+                    else if normal_map          { *value * 0.5 + 0.5 }
+                    else                        { *value };
+                texel[channel] = ( value.clamp( 0.0, 1.0 ) * 255.0 ).round() as u8;
+            }
+            texel
+        } ).collect()
+    }
+
+    fn pixel( &self, x:usize, y:usize ) -> [f32;4] {
+        self.pixels[ y * self.width as usize + x ]
+    }
+}
+
+/// The base level followed by every smaller level down to 1x1
+pub fn generate_mip_chain( base:Surface, filter:MipFilter, normal_map:bool ) -> Vec<Surface> {
+    let mut chain = vec![ base ];
+    loop {
+        let last = &chain[ chain.len() - 1 ];
+        if last.width == 1 && last.height == 1 { break; }
+        let mut next = downsample( last, filter );
+        if normal_map { renormalize( &mut next ); }
+        chain.push( next );
+    }
+    chain
+}
+
+/// Halves a surface, separably filtering rows and then columns
+fn downsample( source:&Surface, filter:MipFilter ) -> Surface {
+    let width  = ( source.width  / 2 ).max( 1 );
+    let height = ( source.height / 2 ).max( 1 );
+    let column_taps = filter_taps( source.width  as usize, width  as usize, filter );
+    let row_taps    = filter_taps( source.height as usize, height as usize, filter );
+
+    let mut horizontal = Vec::with_capacity( width as usize * source.height as usize );
+    for y in 0..source.height as usize {
+        for taps in column_taps.iter() {
+            horizontal.push( weighted_sum( taps.iter().map( |&( x, weight )| ( source.pixel( x, y ), weight ) ) ) );
+        }
+    }
+    let horizontal = Surface { width, height: source.height, pixels: horizontal };
+
+    let mut pixels = Vec::with_capacity( width as usize * height as usize );
+    for taps in row_taps.iter() {
+        for x in 0..width as usize {
+            pixels.push( weighted_sum( taps.iter().map( |&( y, weight )| ( horizontal.pixel( x, y ), weight ) ) ) );
+        }
+    }
+    Surface { width, height, pixels }
+}
+
+fn weighted_sum( samples:impl Iterator<Item = ( [f32;4], f32 )> ) -> [f32;4] {
+    let mut sum = [0.0;4];
+    for ( pixel, weight ) in samples {
+        for ( total, value ) in sum.iter_mut().zip( pixel.iter() ) { *total += value * weight; }
+    }
+    sum
+}
+
+/// For every destination pixel, the source pixels it reads and their weights.
+/// Taps past the edges are clamped and weights sum to 1.
+fn filter_taps( source_size:usize, destination_size:usize, filter:MipFilter ) -> Vec<Vec<( usize, f32 )>> {
+    if source_size == destination_size {
+        return ( 0..source_size ).map( |i| vec![ ( i, 1.0 ) ] ).collect();
+    }
+    let scale = source_size as f32 / destination_size as f32;
+
+    ( 0..destination_size ).map( |i| {
+        let center = ( i as f32 + 0.5 ) * scale;
+        let radius = match filter {
+            MipFilter::Box    => scale * 0.5,
+            MipFilter::Kaiser => KAISER_WIDTH * 0.5 * scale,
+        };
+        let first = ( center - radius ).floor() as i64;
+        let last  = ( center + radius ).ceil()  as i64;
+
+        let mut taps:Vec<( usize, f32 )> = Vec::new();
+        for j in first..last {
+            let weight = match filter {
+                // how much of the source pixel the destination pixel covers
+                MipFilter::Box => {
+                    ( center + radius ).min( j as f32 + 1.0 ) - ( center - radius ).max( j as f32 )
+                },
+                MipFilter::Kaiser => {
+                    let x = ( j as f32 + 0.5 - center ) / scale;
+                    sinc( x ) * kaiser( x / ( KAISER_WIDTH * 0.5 ) )
+                },
+            };
+            if weight == 0.0 { continue; }
+            let index = j.clamp( 0, source_size as i64 - 1 ) as usize;
+            match taps.iter_mut().find( |( tap, _ )| *tap == index ) {
+                Some( tap ) => tap.1 += weight,
+                None => taps.push( ( index, weight ) ),
+            }
+        }
+
+        let total:f32 = taps.iter().map( |( _, weight )| weight ).sum();
+        for tap in taps.iter_mut() { tap.1 /= total; }
+        taps
+    } ).collect()
+}
+
+fn sinc( x:f32 ) -> f32 {
+    if x.abs() < 1e-5 { 1.0 } else { ( PI * x ).sin() / ( PI * x ) }
+}
+
+fn kaiser( t:f32 ) -> f32 {
+    if t.abs() > 1.0 { return 0.0; }
+    bessel_i0( KAISER_ALPHA * ( 1.0 - t * t ).sqrt() ) / bessel_i0( KAISER_ALPHA )
+}
+
+// modified Bessel function of the first kind, order 0
+fn bessel_i0( x:f32 ) -> f32 {
+    let mut sum  = 1.0;
+    let mut term = 1.0;
+    let half_x = x * 0.5;
+    for k in 1..32 {
+        term *= ( half_x / k as f32 ) * ( half_x / k as f32 );
+        sum += term;
+        if term < sum * 1e-8 { break; }
+    }
+    sum
+}
+
+fn renormalize( surface:&mut Surface ) {
+    for pixel in surface.pixels.iter_mut() {
+        let length = ( pixel[0] * pixel[0] + pixel[1] * pixel[1] + pixel[2] * pixel[2] ).sqrt();
+        if length > 1e-6 {
+            for channel in pixel.iter_mut().take( 3 ) { *channel /= length; }
+        } else {
+            pixel[0] = 0.0; pixel[1] = 0.0; pixel[2] = 1.0;
+        }
+    }
+}
+
+fn srgb_to_linear( value:f32 ) -> f32 {
+    if value <= 0.04045 { value / 12.92 }
+    else { ( ( value + 0.055 ) / 1.055 ).powf( 2.4 ) }
+}
+
+fn linear_to_srgb( value:f32 ) -> f32 {
+    let value = value.max( 0.0 );
+    if value <= 0.0031308 { value * 12.92 }
+    else { 1.055 * value.powf( 1.0 / 2.4 ) - 0.055 }
+}
@@ -0,0 +1,197 @@
+//! CPU block compression for baking textures offline.
+//!
+//! Images are converted to linear float, each mip level is filtered from the one above it
+//! and every level is encoded as BC1, BC3, BC4, BC5 or BC7,
+//! ready to be written out as a DDS or KTX2 file and loaded with resources::load_texture.
+//!
+//! ```ignore
+//! let image = image::open( "resources/textures/crate.png" )?;
+//! let baked = bcn::bake( &image, &BakeOptions::default() );
+//! std::fs::write( "resources/textures/crate.ktx2", bcn::write_ktx2( &baked ) )?;
+//! ```
+
+use core::fmt;
+
+mod encode;
+mod mips;
+mod container;
+#[cfg(test)]
+pub(crate) mod test_image;
+
+pub use mips::{ Surface, MipFilter };
+pub use container::{ write_dds, write_ktx2 };
+
+/// Block compressed formats the baker can encode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BcFormat {
+    /// RGB with 1 bit alpha, 8 bytes per block
+    Bc1,
+    /// RGB with smooth alpha, 16 bytes per block
+    Bc3,
+    /// Single channel, 8 bytes per block. Samples as red only,
+    /// greyscale color needs BC7 to keep its sRGB decode and fill RGB
+    Bc4,
+    /// Two channels, 16 bytes per block. Blue samples as 0, so normal maps
+    /// need a shader that rebuilds Z, UnpackNormal in the lit shaders doesn't
+    Bc5,
+    /// High quality RGBA, 16 bytes per block
+    Bc7,
+}
+
+impl BcFormat {
+    pub fn from_name( name:&str ) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bc1" | "dxt1" => Some( Self::Bc1 ),
+            "bc3" | "dxt5" => Some( Self::Bc3 ),
+            "bc4"          => Some( Self::Bc4 ),
+            "bc5"          => Some( Self::Bc5 ),
+            "bc7"          => Some( Self::Bc7 ),
+            _ => None,
+        }
+    }
+
+    pub fn block_size( &self ) -> usize {
+        match self {
+            Self::Bc1 | Self::Bc4 => 8,
+            Self::Bc3 | Self::Bc5 | Self::Bc7 => 16,
+        }
+    }
+
+    /// BC4 and BC5 hold raw data and have no sRGB version
+    pub fn has_srgb( &self ) -> bool {
+        matches!( self, Self::Bc1 | Self::Bc3 | Self::Bc7 )
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::Bc1 => "BC1",
+            Self::Bc3 => "BC3",
+            Self::Bc4 => "BC4",
+            Self::Bc5 => "BC5",
+            Self::Bc7 => "BC7",
+        }
+    }
+}
+
+impl fmt::Display for BcFormat {
+    fn fmt( &self, f:&mut fmt::Formatter<'_> ) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
+
+/// How an image is baked
+#[derive(Clone, Copy, Debug)]
+pub struct BakeOptions {
+    format:BcFormat,
+    srgb:bool,
+    normal_map:bool,
+    filter:MipFilter,
+    generate_mipmaps:bool,
+    flip_vertically:bool,
+}
+
+impl Default for BakeOptions {
+    /// BC7 sRGB color texture with Kaiser filtered mipmaps,
+    /// flipped to match how the engine loads PNGs
+    fn default() -> Self {
+        Self {
+            format: BcFormat::Bc7,
+            srgb: true,
+            normal_map: false,
+            filter: MipFilter::Kaiser,
+            generate_mipmaps: true,
+            flip_vertically: true,
+        }
+    }
+}
+
+impl BakeOptions {
+
+    /// Linear BC7 normal map with renormalized mipmaps
+    pub fn normal_map() -> Self {
+        let mut options = Self::default();
+        options.set_normal_map( true );
+        options
+    }
+
+    pub fn format( &self ) -> BcFormat { self.format }
+    pub fn srgb( &self ) -> bool { self.srgb && self.format.has_srgb() && !self.normal_map }
+    pub fn is_normal_map( &self ) -> bool { self.normal_map }
+    pub fn filter( &self ) -> MipFilter { self.filter }
+    pub fn generate_mipmaps( &self ) -> bool { self.generate_mipmaps }
+    pub fn flip_vertically( &self ) -> bool { self.flip_vertically }
+
+    pub fn set_format( &mut self, format:BcFormat ) { self.format = format; }
+    /// Color is filtered in linear space and stored with an sRGB format,
+    /// ignored for BC4, BC5 and normal maps
+    pub fn set_srgb( &mut self, srgb:bool ) { self.srgb = srgb; }
+    /// Normal maps are never sRGB and are renormalized after filtering each level
+    pub fn set_normal_map( &mut self, normal_map:bool ) {
+        self.normal_map = normal_map;
+        if normal_map { self.srgb = false; }
+    }
+    pub fn set_filter( &mut self, filter:MipFilter ) { self.filter = filter; }
+    pub fn set_generate_mipmaps( &mut self, generate_mipmaps:bool ) { self.generate_mipmaps = generate_mipmaps; }
+    /// Compressed data can't be flipped when it's uploaded like PNGs are,
+    /// so 2D textures are flipped here instead. Turn off for cubemap faces.
+    pub fn set_flip_vertically( &mut self, flip:bool ) { self.flip_vertically = flip; }
+}
+
+/// A block compressed image with its mip chain, largest level first
+pub struct BakedTexture {
+    pub width:u32,
+    pub height:u32,
+    pub format:BcFormat,
+    pub srgb:bool,
+    /// First row of every level is the bottom of the image
+    pub bottom_up:bool,
+    pub levels:Vec<Vec<u8>>,
+}
+
+/// Filters the mip chain of an image and block compresses every level
+pub fn bake( image:&image::DynamicImage, options:&BakeOptions ) -> BakedTexture {
+    let image = if options.flip_vertically() { image.flipv() } else { image.clone() };
+    let rgba = image.to_rgba32f();
+    let srgb = options.srgb();
+
+    let base = Surface::from_rgba( rgba.width(), rgba.height(), rgba.as_raw(), srgb, options.is_normal_map() );
+    let surfaces = if options.generate_mipmaps() {
+        mips::generate_mip_chain( base, options.filter(), options.is_normal_map() )
+    } else {
+        vec![ base ]
+    };
+
+    let levels = surfaces.iter().map( |surface| {
+        let pixels = surface.to_rgba8( srgb, options.is_normal_map() );
+        encode::encode_surface( &pixels, surface.width, surface.height, options.format() )
+    } ).collect();
+
+    BakedTexture {
+        width: image.width(), height: image.height(),
+        format: options.format(), srgb,
+        bottom_up: options.flip_vertically(),
+        levels,
+    }
+}
+
+pub enum Error {
+    ImageLoad(String),
+    WriteFile(String),
+    InvalidArgument(String),
+}
+
+impl Error {
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::ImageLoad(s)       |
+            Self::WriteFile(s)       |
+            Self::InvalidArgument(s) => s,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt( &self, f:&mut fmt::Formatter<'_> ) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
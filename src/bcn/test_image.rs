@@ -0,0 +1,8 @@
+//! The image baked by the bcn and texture container tests
+
+/// 12x8 gradient whose right half is translucent, so every format has something to lose
+pub fn test_image() -> image::DynamicImage {
+    image::DynamicImage::ImageRgba8( image::RgbaImage::from_fn( 12, 8, |x, y| {
+        image::Rgba( [ ( x * 20 ) as u8, ( y * 30 ) as u8, 128, if x < 6 { 255 } else { 100 } ] )
+    } ) )
+}
@@ -0,0 +1,158 @@
+//! Bakes PNG/JPG textures into block compressed DDS or KTX2 files with mipmaps.
+//!
+//! cargo run --release --bin bake_textures -- [options] <image or folder>...
+//!
+//! Baked files are written next to their sources and load with resources::load_texture,
+//! like `load_texture( "crate.ktx2", None )`.
+
+extern crate image;
+extern crate opengl;
+
+use std::path::{ Path, PathBuf };
+use opengl::bcn::{ self, BakeOptions, BcFormat, MipFilter, Error };
+
+const USAGE:&str = "\
+Usage: bake_textures [options] <image or folder>...
+
+Folders bake every png, jpg, tga and bmp image directly inside them.
+
+Options:
+  --format <bc1|bc3|bc4|bc5|bc7>  block format, bc7 by default. bc4 samples as red only and is never sRGB
+  --container <ktx2|dds>          file type to write, ktx2 by default
+  --output <path>                 where to write, only with a single image
+  --normal-map                    linear, renormalizes every mip level
+  --linear                        color that isn't sRGB, like roughness or height maps
+  --filter <kaiser|box>           mip filter, kaiser by default
+  --no-mipmaps                    only store the full size image
+  --no-flip                       keep the first row at the top, for cubemap faces
+";
+
+const SOURCE_EXTS:[&str;5] = [ "png", "jpg", "jpeg", "tga", "bmp" ];
+
+struct Arguments {
+    inputs:Vec<PathBuf>,
+    output:Option<PathBuf>,
+    container:String,
+    options:BakeOptions,
+}
+
+fn main() {
+    let arguments = match parse_arguments( std::env::args().skip( 1 ).collect() ) {
+        Ok( arguments ) => arguments,
+        Err( error ) => {
+            eprintln!( "{}\n\n{}", error, USAGE );
+            std::process::exit( 1 );
+        }
+    };
+
+    let mut failed = false;
+    for input in arguments.inputs.iter() {
+        if let Err( error ) = bake_path( input, &arguments ) {
+            eprintln!( "{}: {}", input.display(), error );
+            failed = true;
+        }
+    }
+    if failed { std::process::exit( 1 ); }
+}
+
+fn parse_arguments( args:Vec<String> ) -> Result<Arguments, Error> {
+    let mut arguments = Arguments {
+        inputs: Vec::new(),
+        output: None,
+        container: String::from( "ktx2" ),
+        options: BakeOptions::default(),
+    };
+
+    let mut args = args.into_iter();
+    while let Some( arg ) = args.next() {
+        let mut value = |name:&str| args.next()
+            .ok_or_else( || Error::InvalidArgument( format!( "{} needs a value!", name ) ) );
+        match arg.as_str() {
+            "--format" => {
+                let name = value( "--format" )?;
+                arguments.options.set_format( BcFormat::from_name( &name )
+                    .ok_or_else( || Error::InvalidArgument( format!( "\"{}\" isn't a format the baker can encode!", name ) ) )? );
+            },
+            "--container" => {
+                let name = value( "--container" )?.to_lowercase();
+                if name != "ktx2" && name != "dds" {
+                    return Err( Error::InvalidArgument( format!( "\"{}\" isn't ktx2 or dds!", name ) ) );
+                }
+                arguments.container = name;
+            },
+            "--output" => arguments.output = Some( PathBuf::from( value( "--output" )? ) ),
+            "--normal-map" => arguments.options.set_normal_map( true ),
+            "--linear" => arguments.options.set_srgb( false ),
+            "--filter" => {
+                let name = value( "--filter" )?;
+                arguments.options.set_filter( MipFilter::from_name( &name )
+                    .ok_or_else( || Error::InvalidArgument( format!( "\"{}\" isn't kaiser or box!", name ) ) )? );
+            },
+            "--no-mipmaps" => arguments.options.set_generate_mipmaps( false ),
+            "--no-flip" => arguments.options.set_flip_vertically( false ),
+            "-h" | "--help" => {
+                println!( "{}", USAGE );
+                std::process::exit( 0 );
+            },
+            _ if arg.starts_with( "--" ) => return Err( Error::InvalidArgument( format!( "Unknown option \"{}\"!", arg ) ) ),
+            _ => arguments.inputs.push( PathBuf::from( arg ) ),
+        }
+    }
+
+    if arguments.inputs.is_empty() {
+        return Err( Error::InvalidArgument( String::from( "No images to bake!" ) ) );
+    }
+    if arguments.output.is_some() && ( arguments.inputs.len() > 1 || arguments.inputs[0].is_dir() ) {
+        return Err( Error::InvalidArgument( String::from( "--output only works with a single image!" ) ) );
+    }
+    Ok( arguments )
+}
+
+/// Bakes an image or every image in a folder
+fn bake_path( input:&Path, arguments:&Arguments ) -> Result<(), Error> {
+    if !input.is_dir() {
+        let output = bake_image( input, arguments )?;
+        println!( "{} -> {}", input.display(), output.display() );
+        return Ok(());
+    }
+
+    let entries = std::fs::read_dir( input )
+        .map_err( |e| Error::ImageLoad( format!( "{} at {}", e, input.display() ) ) )?;
+    let mut failed = 0;
+    for entry in entries.filter_map( |entry| entry.ok() ) {
+        let path = entry.path();
+        let is_source = path.extension()
+            .and_then( |ext| ext.to_str() )
+            .map( |ext| SOURCE_EXTS.contains( &ext.to_lowercase().as_str() ) )
+            .unwrap_or( false );
+        if !path.is_file() || !is_source { continue; }
+        match bake_image( &path, arguments ) {
+            Ok( output ) => println!( "{} -> {}", path.display(), output.display() ),
+            Err( error ) => {
+                eprintln!( "{}: {}", path.display(), error );
+                failed += 1;
+            },
+        }
+    }
+    if failed > 0 {
+        return Err( Error::ImageLoad( format!( "{} images in the folder failed to bake", failed ) ) );
+    }
+    Ok(())
+}
+
+fn bake_image( input:&Path, arguments:&Arguments ) -> Result<PathBuf, Error> {
+    let image = image::open( input )
+        .map_err( |e| Error::ImageLoad( format!( "{}", e ) ) )?;
+
+    // greyscale stays BC7 unless asked, BC4 would sample as red only and lose sRGB
+    let baked = bcn::bake( &image, &arguments.options );
+    let bytes = if arguments.container == "dds" { bcn::write_dds( &baked ) } else { bcn::write_ktx2( &baked ) };
+
+    let output = match &arguments.output {
+        Some( output ) => output.clone(),
+        None => input.with_extension( &arguments.container ),
+    };
+    std::fs::write( &output, bytes )
+        .map_err( |e| Error::WriteFile( format!( "{} at {}", e, output.display() ) ) )?;
+    Ok( output )
+}
@@ -18,6 +18,7 @@ pub fn log( _msg:&str, _src:&str ) {
 pub enum Error {
 
     ResourcesReadFile(String)           ,
+    ResourcesWriteFile(String)          ,
     ResourcesNoFileType(String)         ,
     ResourcesUnrecognizedFileExt(String),
     
@@ -27,12 +28,20 @@ pub enum Error {
     GLTFJsonError(String),
 
     TextureUnsupportedColorFormat,
+    TextureInvalidImage(String),
+    TextureUnsupportedFormat(String),
 
     ShaderLinker(String)   ,
     ShaderCompiler(String) ,
     ShaderParse(String)    ,
+    ShaderVariant(String)  ,
 
     UniformNotFound(String),
+    UniformTypeMismatch(String),
+
+    BufferSize(String),
+
+    MaterialParse(String),
 
     CStringNul(String)     ,
     UTF8(String)           ,
@@ -48,19 +57,28 @@ impl Error {
             Self::ImageCrateLoad(_)               => "image Crate",
 
             Self::ResourcesReadFile(_)            |
+            Self::ResourcesWriteFile(_)           |
             Self::ResourcesNoFileType(_)          |
             Self::ResourcesUnrecognizedFileExt(_) => "Resources",
 
             Self::OBJParse(_) => "Wavefront OBJ Parser",
             Self::GLTFJsonError(_) => "glTF Parser",
 
-            Self::TextureUnsupportedColorFormat => "Texture",
+            Self::TextureUnsupportedColorFormat |
+            Self::TextureInvalidImage(_)        |
+            Self::TextureUnsupportedFormat(_)   => "Texture",
 
             Self::ShaderLinker(_)   => "Shader Linker",
             Self::ShaderCompiler(_) => "Shader Compiler",
             Self::ShaderParse(_)    => "Shader Parsing",
+            Self::ShaderVariant(_)  => "Shader Variants",
+
+            Self::UniformNotFound(_)     |
+            Self::UniformTypeMismatch(_) => "Uniform Values",
+
+            Self::BufferSize(_) => "Buffer",
 
-            Self::UniformNotFound(_) => "Uniform Values",
+            Self::MaterialParse(_) => "Material Parsing",
 
             Self::CStringNul(_)     => "CString Null",
             Self::UTF8(_)           => "UTF-8 Conversion",
@@ -75,14 +93,21 @@ impl Error {
             Self::TextureUnsupportedColorFormat => "Unsupported Color Format!",
             Self::ImageCrateLoad(s)               |
             Self::ResourcesReadFile(s)            |
+            Self::ResourcesWriteFile(s)           |
             Self::ResourcesNoFileType(s)          |
             Self::ResourcesUnrecognizedFileExt(s) |
+            Self::TextureInvalidImage(s)          |
+            Self::TextureUnsupportedFormat(s)     |
             Self::OBJParse(s)                     |
             Self::GLTFJsonError(s)                |
             Self::ShaderLinker(s)                 |
             Self::ShaderCompiler(s)               |
             Self::ShaderParse(s)                  |
+            Self::ShaderVariant(s)                |
             Self::UniformNotFound(s)              |
+            Self::UniformTypeMismatch(s)          |
+            Self::BufferSize(s)                   |
+            Self::MaterialParse(s)                |
             Self::CStringNul(s)                   |
             Self::UTF8(s)                         |
             Self::ParseFloat(s)
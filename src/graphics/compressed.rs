@@ -0,0 +1,363 @@
+use gl::types::*;
+use core::fmt;
+use crate::{ Rc, debugging::{ Error, log } };
+use super::texture::{ TextureOptions, TextureObject, bind_texture, take_handles };
+
+// S3TC formats come from EXT_texture_compression_s3tc and EXT_texture_sRGB,
+// which every desktop driver has but aren't part of core
+const COMPRESSED_RGB_S3TC_DXT1:GLenum        = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1:GLenum       = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3:GLenum       = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5:GLenum       = 0x83F3;
+const COMPRESSED_SRGB_S3TC_DXT1:GLenum       = 0x8C4C;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1:GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3:GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5:GLenum = 0x8C4F;
+
+/// Whether an internal format is the sRGB version of a compressed format
+pub fn is_srgb_compressed_format( internal_format:GLenum ) -> bool {
+    matches!( internal_format,
+        COMPRESSED_SRGB_S3TC_DXT1 | COMPRESSED_SRGB_ALPHA_S3TC_DXT1 |
+        COMPRESSED_SRGB_ALPHA_S3TC_DXT3 | COMPRESSED_SRGB_ALPHA_S3TC_DXT5 |
+        gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM |
+        gl::COMPRESSED_SRGB8_ETC2 | gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 |
+        gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC
+    )
+}
+
+/// Block compressed formats that textures can be loaded in.
+/// Every format encodes 4x4 pixel blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressedFormat {
+    Bc1Rgb,
+    Bc1Rgba,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc4Signed,
+    Bc5,
+    Bc5Signed,
+    Bc6hUnsigned,
+    Bc6hSigned,
+    Bc7,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+    EacR11,
+    EacR11Signed,
+    EacRg11,
+    EacRg11Signed,
+}
+
+impl CompressedFormat {
+    /// Internal format to upload with, srgb picks the sRGB version for formats that have one
+    pub fn as_glenum( &self, srgb:bool ) -> GLenum {
+        match ( self, srgb ) {
+            ( Self::Bc1Rgb,  false ) => COMPRESSED_RGB_S3TC_DXT1,
+            ( Self::Bc1Rgb,  true  ) => COMPRESSED_SRGB_S3TC_DXT1,
+            ( Self::Bc1Rgba, false ) => COMPRESSED_RGBA_S3TC_DXT1,
+            ( Self::Bc1Rgba, true  ) => COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+            ( Self::Bc2,     false ) => COMPRESSED_RGBA_S3TC_DXT3,
+            ( Self::Bc2,     true  ) => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+            ( Self::Bc3,     false ) => COMPRESSED_RGBA_S3TC_DXT5,
+            ( Self::Bc3,     true  ) => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+            ( Self::Bc4,          _ ) => gl::COMPRESSED_RED_RGTC1,
+            ( Self::Bc4Signed,    _ ) => gl::COMPRESSED_SIGNED_RED_RGTC1,
+            ( Self::Bc5,          _ ) => gl::COMPRESSED_RG_RGTC2,
+            ( Self::Bc5Signed,    _ ) => gl::COMPRESSED_SIGNED_RG_RGTC2,
+            ( Self::Bc6hUnsigned, _ ) => gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            ( Self::Bc6hSigned,   _ ) => gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            ( Self::Bc7,     false ) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            ( Self::Bc7,     true  ) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            ( Self::Etc2Rgb,   false ) => gl::COMPRESSED_RGB8_ETC2,
+            ( Self::Etc2Rgb,   true  ) => gl::COMPRESSED_SRGB8_ETC2,
+            ( Self::Etc2RgbA1, false ) => gl::COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            ( Self::Etc2RgbA1, true  ) => gl::COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            ( Self::Etc2Rgba,  false ) => gl::COMPRESSED_RGBA8_ETC2_EAC,
+            ( Self::Etc2Rgba,  true  ) => gl::COMPRESSED_SRGB8_ALPHA8_ETC2_EAC,
+            ( Self::EacR11,        _ ) => gl::COMPRESSED_R11_EAC,
+            ( Self::EacR11Signed,  _ ) => gl::COMPRESSED_SIGNED_R11_EAC,
+            ( Self::EacRg11,       _ ) => gl::COMPRESSED_RG11_EAC,
+            ( Self::EacRg11Signed, _ ) => gl::COMPRESSED_SIGNED_RG11_EAC,
+        }
+    }
+
+    pub fn has_srgb( &self ) -> bool {
+        matches!( self,
+            Self::Bc1Rgb | Self::Bc1Rgba | Self::Bc2 | Self::Bc3 | Self::Bc7 |
+            Self::Etc2Rgb | Self::Etc2RgbA1 | Self::Etc2Rgba
+        )
+    }
+
+    /// Bytes of each 4x4 block
+    pub fn block_size( &self ) -> usize {
+        match self {
+            Self::Bc1Rgb | Self::Bc1Rgba | Self::Bc4 | Self::Bc4Signed |
+            Self::Etc2Rgb | Self::Etc2RgbA1 | Self::EacR11 | Self::EacR11Signed => 8,
+            _ => 16,
+        }
+    }
+
+    /// Bytes of a width by height image, partial blocks take a whole block
+    pub fn byte_size( &self, width:GLint, height:GLint ) -> usize {
+        let blocks_x = ( width.max( 1 ) as usize ).div_ceil( 4 );
+        let blocks_y = ( height.max( 1 ) as usize ).div_ceil( 4 );
+        blocks_x * blocks_y * self.block_size()
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::Bc1Rgb        => "BC1 RGB",
+            Self::Bc1Rgba       => "BC1 RGBA",
+            Self::Bc2           => "BC2",
+            Self::Bc3           => "BC3",
+            Self::Bc4           => "BC4",
+            Self::Bc4Signed     => "BC4 Signed",
+            Self::Bc5           => "BC5",
+            Self::Bc5Signed     => "BC5 Signed",
+            Self::Bc6hUnsigned  => "BC6H Unsigned",
+            Self::Bc6hSigned    => "BC6H Signed",
+            Self::Bc7           => "BC7",
+            Self::Etc2Rgb       => "ETC2 RGB",
+            Self::Etc2RgbA1     => "ETC2 RGB A1",
+            Self::Etc2Rgba      => "ETC2 RGBA",
+            Self::EacR11        => "EAC R11",
+            Self::EacR11Signed  => "EAC R11 Signed",
+            Self::EacRg11       => "EAC RG11",
+            Self::EacRg11Signed => "EAC RG11 Signed",
+        }
+    }
+}
+
+impl fmt::Display for CompressedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
+
+/// Block compressed texture data with a prebuilt mip chain, read from a DDS or KTX2 file.
+/// Each level holds every layer and face, layers outermost and faces in
+/// CUBEMAP_FACES order, the way glCompressedTexImage3D takes them.
+/// Rows are uploaded in the order they're stored and GL reads the first row as the bottom,
+/// so 2D textures should be stored flipped to match images loaded from PNG/JPG.
+#[derive(Debug)]
+pub struct CompressedImage {
+    pub width:GLint, pub height:GLint,
+    /// Number of array layers, 0 if the image isn't an array
+    pub layers:GLint,
+    /// 6 for cubemaps, 1 otherwise
+    pub faces:GLint,
+    pub format:CompressedFormat,
+    /// Colors are sRGB encoded
+    pub srgb:bool,
+    pub levels:Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    pub fn is_cubemap( &self ) -> bool { self.faces == 6 }
+    pub fn is_array( &self )   -> bool { self.layers > 0 }
+
+    /// Texture target the image is uploaded to
+    pub fn target( &self ) -> GLenum {
+        match ( self.is_array(), self.is_cubemap() ) {
+            ( false, false ) => gl::TEXTURE_2D,
+            ( false, true  ) => gl::TEXTURE_CUBE_MAP,
+            ( true,  false ) => gl::TEXTURE_2D_ARRAY,
+            ( true,  true  ) => gl::TEXTURE_CUBE_MAP_ARRAY,
+        }
+    }
+
+    /// Width and height of a mip level
+    pub fn level_size( &self, level:usize ) -> ( GLint, GLint ) {
+        ( ( self.width >> level ).max( 1 ), ( self.height >> level ).max( 1 ) )
+    }
+
+    /// Layers times faces, the images each level holds
+    pub fn image_count( &self ) -> usize {
+        self.layers.max( 1 ) as usize * self.faces.max( 1 ) as usize
+    }
+
+    /// Bytes of one face of one layer of a mip level
+    pub fn image_byte_size( &self, level:usize ) -> usize {
+        let ( width, height ) = self.level_size( level );
+        self.format.byte_size( width, height )
+    }
+
+    /// Internal format to upload with, sRGB if the file or the options ask for it
+    pub fn internal_format( &self, options:&TextureOptions ) -> GLenum {
+        self.format.as_glenum( self.srgb || options.srgb() )
+    }
+
+    /// Checks every level holds the data its size needs, source_name is used in errors
+    pub fn validate( &self, source_name:&str ) -> Result<(), Error> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err( Error::TextureInvalidImage( format!(
+                "\"{}\" is {}x{}!", source_name, self.width, self.height
+            ) ) );
+        }
+        if self.is_cubemap() && self.width != self.height {
+            return Err( Error::TextureInvalidImage( format!(
+                "\"{}\" is a cubemap with {}x{} faces, faces have to be square!",
+                source_name, self.width, self.height
+            ) ) );
+        }
+        if self.levels.is_empty() {
+            return Err( Error::TextureInvalidImage( format!( "\"{}\" has no mip levels!", source_name ) ) );
+        }
+        for ( level, data ) in self.levels.iter().enumerate() {
+            let expected = self.image_byte_size( level ) * self.image_count();
+            if data.len() != expected {
+                return Err( Error::TextureInvalidImage( format!(
+                    "\"{}\" level {} has {} bytes, {} {}x{} needs {}!",
+                    source_name, level, data.len(), self.format,
+                    self.level_size( level ).0, self.level_size( level ).1, expected
+                ) ) );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the driver can sample textures of the target stored in the internal format
+pub fn is_format_supported( target:GLenum, internal_format:GLenum ) -> bool {
+    let mut supported = gl::FALSE as GLint;
+    unsafe {
+        gl::GetInternalformativ( target, internal_format, gl::INTERNALFORMAT_SUPPORTED, 1, &mut supported );
+    }
+    supported == gl::TRUE as GLint
+}
+
+/// Creates a texture of image.target() and uploads every level of the image to it.
+/// Sampling stops at the last level, the mip chain isn't generated.
+pub fn upload_compressed( image:&CompressedImage, options:&TextureOptions ) -> Result<GLuint, Error> {
+    let target = image.target();
+    let internal_format = image.internal_format( options );
+    if ( image.srgb || options.srgb() ) && !image.format.has_srgb() {
+        log(
+            &format!( "{} has no sRGB format, storing it as linear.", image.format ),
+            "Texture | Warning"
+        );
+    }
+    if !is_format_supported( target, internal_format ) {
+        return Err( Error::TextureUnsupportedFormat( format!(
+            "This driver can't sample {} textures ({:#06X}), convert the texture to a format it has!",
+            image.format, internal_format
+        ) ) );
+    }
+
+    let mut handle = 0;
+    unsafe {
+
+        gl::GenTextures( 1, &mut handle );
+        bind_texture( target, handle );
+
+        if image.is_cubemap() {
+            for wrap in [ gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R ].iter() {
+                gl::TexParameteri( target, *wrap, gl::CLAMP_TO_EDGE as GLint );
+            }
+        } else {
+            gl::TexParameteri( target, gl::TEXTURE_WRAP_S, options.wrapping_x().as_glint() );
+            gl::TexParameteri( target, gl::TEXTURE_WRAP_T, options.wrapping_y().as_glint() );
+        }
+        match options.border_color() {
+            Some( color ) => {
+                let color = color.as_array_rgba_f32();
+                gl::TexParameterfv( target, gl::TEXTURE_BORDER_COLOR, color.as_ptr() );
+            },
+            None => {},
+        }
+        gl::TexParameteri( target, gl::TEXTURE_MIN_FILTER, options.min_filtering().as_glint() );
+        gl::TexParameteri( target, gl::TEXTURE_MAG_FILTER, options.mag_filtering().as_glint() );
+        gl::TexParameteri( target, gl::TEXTURE_MAX_LEVEL, image.levels.len() as GLint - 1 );
+
+        for ( level, data ) in image.levels.iter().enumerate() {
+            let ( width, height ) = image.level_size( level );
+            let image_size = image.image_byte_size( level );
+            match target {
+                gl::TEXTURE_2D => gl::CompressedTexImage2D(
+                    target, level as GLint, internal_format, width, height, 0,
+                    data.len() as GLsizei, data.as_ptr() as *const GLvoid
+                ),
+                gl::TEXTURE_CUBE_MAP => for face in 0..6 {
+                    let face_data = &data[face * image_size..( face + 1 ) * image_size];
+                    gl::CompressedTexImage2D(
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as GLenum, level as GLint,
+                        internal_format, width, height, 0,
+                        image_size as GLsizei, face_data.as_ptr() as *const GLvoid
+                    );
+                },
+                // cubemap arrays count every face as a layer
+                _ => gl::CompressedTexImage3D(
+                    target, level as GLint, internal_format,
+                    width, height, image.image_count() as GLsizei, 0,
+                    data.len() as GLsizei, data.as_ptr() as *const GLvoid
+                ),
+            }
+        }
+
+    }
+    Ok( handle )
+}
+
+/// Compressed cubemap arrays, sampled through samplerCubeArray uniforms.
+/// 2D arrays load as Texture2DArray, single textures and cubemaps as Texture and Cubemap.
+#[derive(Debug)]
+pub struct CompressedTexture {
+    handle:GLuint,
+    target:GLenum,
+    width:GLint, height:GLint,
+    layers:GLint,
+    level_count:usize,
+    format:CompressedFormat,
+    options:TextureOptions,
+}
+
+impl CompressedTexture {
+    /// Errors if the image isn't a cubemap array or the driver lacks its format
+    pub fn new( image:&CompressedImage, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        if image.target() != gl::TEXTURE_CUBE_MAP_ARRAY {
+            return Err( Error::TextureInvalidImage( format!(
+                "A {} {}x{} image with {} layers and {} faces isn't a cubemap array!",
+                image.format, image.width, image.height, image.layers, image.faces
+            ) ) );
+        }
+        let handle = upload_compressed( image, &options )?;
+        Ok( Rc::new( Self {
+            handle, target: image.target(),
+            width: image.width, height: image.height, layers: image.layers.max( 1 ),
+            level_count: image.levels.len(), format: image.format, options
+        } ) )
+    }
+
+    pub fn handle( &self )      -> GLuint            { self.handle       }
+    pub fn width( &self )       -> GLint             { self.width        }
+    pub fn height( &self )      -> GLint             { self.height       }
+    /// Array layers, each one a whole cubemap for cubemap arrays
+    pub fn layers( &self )      -> GLint             { self.layers       }
+    pub fn level_count( &self ) -> usize             { self.level_count  }
+    pub fn format( &self )      -> CompressedFormat  { self.format       }
+    pub fn options( &self )     -> &TextureOptions   { &self.options     }
+}
+
+impl TextureObject for CompressedTexture {
+    fn handle( &self ) -> GLuint { CompressedTexture::handle( self ) }
+    fn target( &self ) -> GLenum { self.target }
+}
+
+impl fmt::Display for CompressedTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "CompressedTexture {} | {} {}x{} layers: {} levels: {}",
+            self.handle(), self.format, self.width, self.height, self.layers, self.level_count
+        )
+    }
+}
+
+/// Deletes compressed textures, panics if any of them is still used elsewhere
+///
+/// # Safety
+///
+/// Needs a current GL context, the one the textures were created in.
+pub unsafe fn delete_compressed_textures( textures: Vec<Rc<CompressedTexture>> ) {
+    let handles = take_handles( textures, |texture| texture.handle() );
+    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+}
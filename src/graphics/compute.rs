@@ -0,0 +1,216 @@
+use gl::types::*;
+use core::{ fmt, cell::Cell };
+use crate::{ Rc, debugging::{ Error, log } };
+use super::{ Shader, ShaderProgram, program_cache, shader::{ ShaderKind, StageSource } };
+
+pub struct ComputeProgram {
+    program: Rc<ShaderProgram>,
+    /// Queried again when a hot reload bumps the program's generation
+    work_group_size: Cell<( u32, [GLint;3] )>,
+}
+
+impl ComputeProgram {
+
+    pub fn from_shader( shader:&Shader ) -> Result<Self, Error> {
+        if shader.kind() != ShaderKind::Compute {
+            return Err( Error::ShaderLinker(
+                format!( "Attempted to create a Compute Program from a {} shader!", shader.kind() )
+            ) );
+        }
+        Self::from_program( ShaderProgram::from_shaders( core::slice::from_ref( shader ) )? )
+    }
+
+    pub fn from_shaders( shaders:&[Shader] ) -> Result<Self, Error> {
+        match shaders {
+            [ shader ] => Self::from_shader( shader ),
+            _ => Err( Error::ShaderLinker(
+                format!( "Compute Program expects 1 compute shader, got {} shaders!", shaders.len() )
+            ) ),
+        }
+    }
+
+    /// Links a parsed .shader file with a single #compute stage,
+    /// loading it from the program binary cache when possible
+    pub fn from_stages( stages:&[StageSource] ) -> Result<Self, Error> {
+        match stages {
+            [ stage ] if stage.kind() == ShaderKind::Compute => {},
+            _ => return Err( Error::ShaderLinker(
+                format!( "Compute Program expects 1 compute stage, got {} stages!", stages.len() )
+            ) ),
+        }
+        Self::from_program( program_cache::load_or_link( stages, false )? )
+    }
+
+    fn from_program( program:Rc<ShaderProgram> ) -> Result<Self, Error> {
+        let work_group_size = query_work_group_size( &program );
+        log(
+            &format!(
+                "Compute Program {} work group size: {}x{}x{}",
+                program.handle(), work_group_size[0], work_group_size[1], work_group_size[2]
+            ),
+            "Compute Program"
+        );
+        let generation = program.generation();
+        Ok( Self { program, work_group_size: Cell::new( ( generation, work_group_size ) ) } )
+    }
+
+    pub fn program(&self) -> &Rc<ShaderProgram> { &self.program }
+    pub fn handle(&self)  -> GLuint { self.program.handle() }
+    pub fn work_group_size(&self) -> [GLint;3] {
+        let ( generation, work_group_size ) = self.work_group_size.get();
+        if generation == self.program.generation() { return work_group_size; }
+        let work_group_size = query_work_group_size( &self.program );
+        self.work_group_size.set( ( self.program.generation(), work_group_size ) );
+        work_group_size
+    }
+
+    pub fn use_program(&self) { self.program.use_program() }
+
+    /// Dispatches the given number of work groups.
+    pub fn dispatch( &self, groups_x:GLuint, groups_y:GLuint, groups_z:GLuint ) {
+        self.use_program();
+        unsafe { gl::DispatchCompute( groups_x, groups_y, groups_z ) }
+    }
+
+    /// Dispatches enough work groups to cover the given number of invocations.
+    pub fn dispatch_invocations( &self, x:GLuint, y:GLuint, z:GLuint ) {
+        let groups = | invocations:GLuint, size:GLint | -> GLuint {
+            let size = size.max( 1 ) as GLuint;
+            invocations.div_ceil( size )
+        };
+        let work_group_size = self.work_group_size();
+        self.dispatch(
+            groups( x, work_group_size[0] ),
+            groups( y, work_group_size[1] ),
+            groups( z, work_group_size[2] ),
+        );
+    }
+
+    /// Dispatches using the work group counts stored in a buffer at the given byte offset.
+    pub fn dispatch_indirect( &self, indirect_buffer:GLuint, offset:usize ) {
+        self.use_program();
+        unsafe {
+            gl::BindBuffer( gl::DISPATCH_INDIRECT_BUFFER, indirect_buffer );
+            gl::DispatchComputeIndirect( offset as GLintptr );
+        }
+    }
+
+}
+
+impl fmt::Display for ComputeProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let work_group_size = self.work_group_size();
+        write!( f, "Compute {} | Work Group Size: {}x{}x{}",
+            self.program,
+            work_group_size[0], work_group_size[1], work_group_size[2]
+        )
+    }
+}
+
+fn query_work_group_size( program:&ShaderProgram ) -> [GLint;3] {
+    let mut work_group_size = [0;3];
+    unsafe {
+        gl::GetProgramiv(
+            program.handle(), gl::COMPUTE_WORK_GROUP_SIZE,
+            work_group_size.as_mut_ptr()
+        );
+    }
+    work_group_size
+}
+
+/// Makes writes from shaders visible to later commands of the given kinds
+pub fn memory_barrier( barriers:&[MemoryBarrier] ) {
+    let mut bits = 0;
+    for barrier in barriers.iter() {
+        bits |= barrier.as_glenum();
+    }
+    unsafe { gl::MemoryBarrier( bits ) }
+}
+
+/// Like memory_barrier but only covers reads from fragment shaders in the same draw call
+pub fn memory_barrier_by_region( barriers:&[MemoryBarrier] ) {
+    let mut bits = 0;
+    for barrier in barriers.iter() {
+        bits |= barrier.as_glenum();
+    }
+    unsafe { gl::MemoryBarrierByRegion( bits ) }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MemoryBarrier {
+    /// Vertex data sourced from buffer objects
+    VertexAttribArray,
+    /// Indices sourced from element buffer objects
+    ElementArray,
+    /// Uniform blocks
+    Uniform,
+    /// Texture fetches from samplers
+    TextureFetch,
+    /// Image load, store and atomics
+    ShaderImageAccess,
+    /// Indirect draw and dispatch commands
+    Command,
+    /// Pixel pack and unpack buffers
+    PixelBuffer,
+    /// Texture uploads and readbacks
+    TextureUpdate,
+    /// Buffer uploads, copies and readbacks
+    BufferUpdate,
+    /// Framebuffer reads and writes
+    Framebuffer,
+    /// Transform feedback
+    TransformFeedback,
+    /// Atomic counter buffers
+    AtomicCounter,
+    /// Shader storage blocks
+    ShaderStorage,
+    /// Everything
+    All,
+}
+
+impl MemoryBarrier {
+
+    pub fn as_glenum( &self ) -> GLenum {
+        match self {
+            Self::VertexAttribArray => gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT  ,
+            Self::ElementArray      => gl::ELEMENT_ARRAY_BARRIER_BIT        ,
+            Self::Uniform           => gl::UNIFORM_BARRIER_BIT              ,
+            Self::TextureFetch      => gl::TEXTURE_FETCH_BARRIER_BIT        ,
+            Self::ShaderImageAccess => gl::SHADER_IMAGE_ACCESS_BARRIER_BIT  ,
+            Self::Command           => gl::COMMAND_BARRIER_BIT              ,
+            Self::PixelBuffer       => gl::PIXEL_BUFFER_BARRIER_BIT         ,
+            Self::TextureUpdate     => gl::TEXTURE_UPDATE_BARRIER_BIT       ,
+            Self::BufferUpdate      => gl::BUFFER_UPDATE_BARRIER_BIT        ,
+            Self::Framebuffer       => gl::FRAMEBUFFER_BARRIER_BIT          ,
+            Self::TransformFeedback => gl::TRANSFORM_FEEDBACK_BARRIER_BIT   ,
+            Self::AtomicCounter     => gl::ATOMIC_COUNTER_BARRIER_BIT       ,
+            Self::ShaderStorage     => gl::SHADER_STORAGE_BARRIER_BIT       ,
+            Self::All               => gl::ALL_BARRIER_BITS                 ,
+        }
+    }
+
+    pub fn msg(&self) -> &str {
+        match self {
+            Self::VertexAttribArray => "Vertex Attrib Array" ,
+            Self::ElementArray      => "Element Array"       ,
+            Self::Uniform           => "Uniform"             ,
+            Self::TextureFetch      => "Texture Fetch"       ,
+            Self::ShaderImageAccess => "Shader Image Access" ,
+            Self::Command           => "Command"             ,
+            Self::PixelBuffer       => "Pixel Buffer"        ,
+            Self::TextureUpdate     => "Texture Update"      ,
+            Self::BufferUpdate      => "Buffer Update"       ,
+            Self::Framebuffer       => "Framebuffer"         ,
+            Self::TransformFeedback => "Transform Feedback"  ,
+            Self::AtomicCounter     => "Atomic Counter"      ,
+            Self::ShaderStorage     => "Shader Storage"      ,
+            Self::All               => "All"                 ,
+        }
+    }
+}
+
+impl fmt::Display for MemoryBarrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
@@ -0,0 +1,428 @@
+use gl::types::*;
+use crate::{ Rc, debugging::{ Error, log } };
+use core::{ fmt, cell::{ Cell, RefCell } };
+use std::path::PathBuf;
+use fmath::types::color::RGB;
+use super::{
+    Material, ShaderProgram, Texture,
+    offscreen::OffscreenPass,
+    compressed::{ CompressedImage, upload_compressed },
+    texture::{
+        ImageGL, TextureOptions, TextureObject,
+        bind_texture, take_handles, read_texture_level, level_byte_size, set_swizzle,
+    },
+};
+
+/// Faces in the order GL numbers them, from GL_TEXTURE_CUBE_MAP_POSITIVE_X
+pub const CUBEMAP_FACES:[CubemapFace;6] = [
+    CubemapFace::PositiveX, CubemapFace::NegativeX,
+    CubemapFace::PositiveY, CubemapFace::NegativeY,
+    CubemapFace::PositiveZ, CubemapFace::NegativeZ,
+];
+
+/// Int uniform render_to_faces sets to the index of the face being rendered
+pub const CUBEMAP_FACE_UNIFORM:&str = "face";
+/// Name of the sampler2D uniform an equirectangular conversion shader samples
+pub const EQUIRECTANGULAR_SAMPLER:&str = "equirectangular_sampler";
+
+/// Samples across cube face edges instead of clamping to each face.
+/// Applies to every cubemap in the context.
+pub fn set_seamless_filtering( enabled:bool ) {
+    unsafe {
+        if enabled { gl::Enable( gl::TEXTURE_CUBE_MAP_SEAMLESS ) }
+        else { gl::Disable( gl::TEXTURE_CUBE_MAP_SEAMLESS ) }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cubemap {
+    handle:Cell<GLuint>,
+    size:Cell<GLint>,
+    face_colors:Cell<[RGB;6]>,
+    options:TextureOptions,
+    sources:RefCell<Vec<PathBuf>>,
+}
+
+impl Cubemap {
+
+    /// Creates a cubemap from six square faces of the same size, in CUBEMAP_FACES order.
+    /// Faces are clamped to their edges, wrapping in the options is ignored.
+    pub fn new( faces:[ImageGL;6], options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        let size = faces[0].width;
+        for ( face, image ) in CUBEMAP_FACES.iter().zip( faces.iter() ) {
+            if image.width != image.height || image.width != size {
+                return Err( Error::TextureInvalidImage( format!(
+                    "Cubemap face {} is {}x{}, every face has to be {}x{}!",
+                    face, image.width, image.height, size, size
+                ) ) );
+            }
+        }
+
+        let mut handle = 0;
+        unsafe {
+
+            gl::GenTextures( 1, &mut handle );
+            bind_texture( gl::TEXTURE_CUBE_MAP, handle );
+
+            for wrap in [ gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R ].iter() {
+                gl::TexParameteri( gl::TEXTURE_CUBE_MAP, *wrap, gl::CLAMP_TO_EDGE as GLint );
+            }
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER,
+                options.min_filtering().as_glint()
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER,
+                options.mag_filtering().as_glint()
+            );
+
+            match faces[0].swizzle {
+                Some( swizzle ) => set_swizzle( gl::TEXTURE_CUBE_MAP, swizzle ),
+                None => {},
+            }
+
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            for ( face, image ) in CUBEMAP_FACES.iter().zip( faces.iter() ) {
+                gl::TexImage2D(
+                    face.as_glenum(), 0, options.internal_format( image ) as GLint,
+                    image.width, image.height,
+                    0, image.format, image.data_type,
+                    image.data.as_ptr() as *const GLvoid
+                );
+            }
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+
+            gl::GenerateMipmap( gl::TEXTURE_CUBE_MAP );
+
+        }
+
+        let mut face_colors = [RGB::new_black();6];
+        for ( color, image ) in face_colors.iter_mut().zip( faces.iter() ) {
+            *color = average_color( image );
+        }
+
+        Ok( Rc::new( Self {
+            handle: Cell::new( handle ), size: Cell::new( size ),
+            face_colors: Cell::new( face_colors ), options,
+            sources: RefCell::new( Vec::new() )
+        } ) )
+    }
+
+    /// Uploads a compressed cubemap with its mip chain,
+    /// errors if the image isn't a single cubemap or the driver lacks its format
+    pub fn from_compressed( image:&CompressedImage, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        if image.target() != gl::TEXTURE_CUBE_MAP {
+            return Err( Error::TextureInvalidImage( format!(
+                "A {} {}x{} image with {} layers and {} faces isn't a cubemap!",
+                image.format, image.width, image.height, image.layers, image.faces
+            ) ) );
+        }
+        let handle = upload_compressed( image, &options )?;
+        let cubemap = Rc::new( Self {
+            handle: Cell::new( handle ), size: Cell::new( image.width ),
+            face_colors: Cell::new( [RGB::new_black();6] ), options,
+            sources: RefCell::new( Vec::new() )
+        } );
+        cubemap.update_face_colors( image.levels.len() as GLint - 1 );
+        Ok( cubemap )
+    }
+
+    /// Creates a cubemap with undefined contents to render into,
+    /// like `GL_RGBA16F` for HDR environments.
+    /// GL doesn't require `GL_RGB16F` and other 3 channel float formats to be color-renderable.
+    pub fn new_empty( size:GLint, internal_format:GLenum, options:TextureOptions ) -> Rc<Self> {
+        let mut handle = 0;
+        unsafe {
+
+            gl::GenTextures( 1, &mut handle );
+            bind_texture( gl::TEXTURE_CUBE_MAP, handle );
+
+            for wrap in [ gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R ].iter() {
+                gl::TexParameteri( gl::TEXTURE_CUBE_MAP, *wrap, gl::CLAMP_TO_EDGE as GLint );
+            }
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER,
+                options.min_filtering().as_glint()
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER,
+                options.mag_filtering().as_glint()
+            );
+
+            for face in CUBEMAP_FACES.iter() {
+                gl::TexImage2D(
+                    face.as_glenum(), 0, internal_format as GLint,
+                    size, size, 0, gl::RGB, gl::FLOAT,
+                    core::ptr::null()
+                );
+            }
+            // allocates the rest of the mip chain
+            gl::GenerateMipmap( gl::TEXTURE_CUBE_MAP );
+
+        }
+
+        Rc::new( Self {
+            handle: Cell::new( handle ), size: Cell::new( size ),
+            face_colors: Cell::new( [RGB::new_black();6] ), options,
+            sources: RefCell::new( Vec::new() )
+        } )
+    }
+
+    /// Renders an equirectangular (latitude-longitude) map, like an .hdr sky,
+    /// into a new cubemap with mipmaps.
+    /// The shader needs a `sampler2D equirectangular_sampler` and an `int face`,
+    /// resources/shaders/equirectangular_to_cubemap.shader is the default one.
+    pub fn from_equirectangular(
+        source:&Rc<Texture>, size:GLint, internal_format:GLenum,
+        shader:Rc<ShaderProgram>, options:TextureOptions
+    ) -> Result<Rc<Self>, Error> {
+        let cubemap = Self::new_empty( size, internal_format, options );
+
+        let mut material = Material::new( shader );
+        material.get_uniform_mut_by_name( EQUIRECTANGULAR_SAMPLER )?
+            .set_texture2d( source.clone() );
+
+        cubemap.render_to_faces( &mut material, 0 )?;
+        cubemap.generate_mipmaps();
+        Ok( cubemap )
+    }
+
+    /// Draws a fullscreen triangle with the material into every face of a mip level,
+    /// setting the material's `face` uniform to each face's index first
+    pub fn render_to_faces( &self, material:&mut Material, level:GLint ) -> Result<(), Error> {
+        let level_size = self.level_size( level );
+        let pass = OffscreenPass::begin();
+        for ( idx, face ) in CUBEMAP_FACES.iter().enumerate() {
+            pass.attach( face.as_glenum(), self.handle(), level, level_size, level_size )?;
+            material.get_uniform_mut_by_name( CUBEMAP_FACE_UNIFORM )?.set_i32( idx as i32 );
+            pass.draw( material );
+        }
+        Ok(())
+    }
+
+    /// Width and height of a mip level's faces
+    pub fn level_size( &self, level:GLint ) -> GLint { ( self.size() >> level ).max( 1 ) }
+
+    /// Number of mip levels down to 1x1
+    pub fn level_count( &self ) -> GLint {
+        ( self.size().max( 1 ) as f32 ).log2().floor() as GLint + 1
+    }
+
+    /// Limits sampling to levels up to max_level,
+    /// for cubemaps that only fill part of their mip chain
+    pub fn set_max_level( &self, max_level:GLint ) {
+        bind_texture( gl::TEXTURE_CUBE_MAP, self.handle() );
+        unsafe { gl::TexParameteri( gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAX_LEVEL, max_level ); }
+    }
+
+    /// Reads back a face's mip level, like read_texture_level
+    pub fn read_face_level( &self, face:CubemapFace, level:GLint, format:GLenum, data_type:GLenum ) -> Vec<u8> {
+        let size = self.level_size( level );
+        read_texture_level( self.handle(), level, face as GLint, size, size, format, data_type )
+    }
+
+    /// Replaces a face's mip level with data read by read_face_level
+    pub fn write_face_level(
+        &self, face:CubemapFace, level:GLint, format:GLenum, data_type:GLenum, data:&[u8]
+    ) -> Result<(), Error> {
+        let size = self.level_size( level );
+        let expected = level_byte_size( size, size, format, data_type );
+        if data.len() != expected {
+            return Err( Error::TextureInvalidImage( format!(
+                "Cubemap face {} level {} needs {} bytes, got {}!", face, level, expected, data.len()
+            ) ) );
+        }
+        bind_texture( gl::TEXTURE_CUBE_MAP, self.handle() );
+        unsafe {
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            gl::TexSubImage2D(
+                face.as_glenum(), level, 0, 0, size, size,
+                format, data_type, data.as_ptr() as *const GLvoid
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+        }
+        Ok(())
+    }
+
+    /// Regenerates every mip level from level 0
+    /// and updates the face colors from the smallest level
+    pub fn generate_mipmaps( &self ) {
+        bind_texture( gl::TEXTURE_CUBE_MAP, self.handle() );
+        unsafe { gl::GenerateMipmap( gl::TEXTURE_CUBE_MAP ); }
+        self.update_face_colors( self.level_count() - 1 );
+    }
+
+    /// Sets the face colors to the average of a mip level, small levels are quicker
+    fn update_face_colors( &self, level:GLint ) {
+        let size = self.level_size( level );
+        let mut face_colors = [RGB::new_black();6];
+        for ( color, face ) in face_colors.iter_mut().zip( CUBEMAP_FACES.iter() ) {
+            let image = ImageGL {
+                width: size, height: size,
+                internal_format: gl::RGB32F, format: gl::RGB,
+                data_type: gl::FLOAT, swizzle: None,
+                data: self.read_face_level( *face, level, gl::RGB, gl::FLOAT )
+            };
+            *color = average_color( &image );
+        }
+        self.face_colors.set( face_colors );
+    }
+
+    pub fn handle( &self )  -> GLuint          { self.handle.get() }
+    /// Width and height of every face
+    pub fn size( &self )    -> GLint           { self.size.get()   }
+    pub fn options( &self ) -> &TextureOptions { &self.options     }
+
+    /// Files the cubemap was loaded from, empty for cubemaps made in code
+    pub fn sources( &self ) -> Vec<PathBuf> { self.sources.borrow().clone() }
+
+    /// Set by the resources loaders, what generated maps like environment lighting are keyed on
+    pub fn set_sources( &self, sources:Vec<PathBuf> ) { *self.sources.borrow_mut() = sources; }
+
+    /// Average color of a face
+    pub fn face_color( &self, face:CubemapFace ) -> RGB {
+        self.face_colors.get()[face as usize]
+    }
+
+    /// Average color of the four side faces,
+    /// what the sky looks like where distant geometry fades into it
+    pub fn horizon_color( &self ) -> RGB {
+        let sides = [ CubemapFace::PositiveX, CubemapFace::NegativeX, CubemapFace::PositiveZ, CubemapFace::NegativeZ ];
+        let mut sum = [0u32;3];
+        for face in sides.iter() {
+            let color = self.face_color( *face ).as_array_rgb();
+            for i in 0..3 { sum[i] += color[i] as u32; }
+        }
+        RGB::new( ( sum[0] / 4 ) as u8, ( sum[1] / 4 ) as u8, ( sum[2] / 4 ) as u8 )
+    }
+
+    /// Swaps the GL texture of another Cubemap into this one.
+    /// The old texture ends up in `other`, ready to be deleted with delete_cubemaps.
+    pub fn replace_with( &self, other:&Cubemap ) {
+        self.handle.swap( &other.handle );
+        self.size.swap( &other.size );
+        self.face_colors.swap( &other.face_colors );
+    }
+
+}
+
+impl TextureObject for Cubemap {
+    fn handle( &self ) -> GLuint { Cubemap::handle( self ) }
+    fn target( &self ) -> GLenum { gl::TEXTURE_CUBE_MAP }
+}
+
+impl fmt::Display for Cubemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Cubemap {} | size: {}x{}", self.handle(), self.size(), self.size() )
+    }
+}
+
+/// Deletes cubemaps, panics if any of them is still used elsewhere
+///
+/// # Safety
+///
+/// Needs a current GL context, the one the cubemaps were created in.
+pub unsafe fn delete_cubemaps( cubemaps: Vec<Rc<Cubemap>> ) {
+    let handles = take_handles( cubemaps, |cubemap| cubemap.handle() );
+    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+}
+
+fn average_color( image:&ImageGL ) -> RGB {
+    let channel_size = match image.data_type {
+        gl::UNSIGNED_BYTE  => 1,
+        gl::UNSIGNED_SHORT => 2,
+        gl::FLOAT          => 4,
+        _ => {
+            log(
+                &format!( "Can't average a face with type {:#06X}, using black.", image.data_type ),
+                "Cubemap | Warning"
+            );
+            return RGB::new_black();
+        },
+    };
+    // greyscale images use their first channel for all three
+    let ( channels, grey ) = match image.format {
+        gl::RED  => ( 1, true  ),
+        gl::RG   => ( 2, true  ),
+        gl::RGB  => ( 3, false ),
+        gl::RGBA => ( 4, false ),
+        _ => {
+            log(
+                &format!( "Can't average a face with format {:#06X}, using black.", image.format ),
+                "Cubemap | Warning"
+            );
+            return RGB::new_black();
+        },
+    };
+    let mut sum = [0f64;3];
+    let mut count = 0u64;
+    for pixel in image.data.chunks_exact( channels * channel_size ) {
+        for ( i, sum ) in sum.iter_mut().enumerate() {
+            let channel = if grey { 0 } else { i };
+            let bytes = &pixel[channel * channel_size..( channel + 1 ) * channel_size];
+            *sum += match channel_size {
+                1 => bytes[0] as f64 / 255.0,
+                2 => u16::from_ne_bytes( [ bytes[0], bytes[1] ] ) as f64 / 65535.0,
+                _ => f32::from_ne_bytes( [ bytes[0], bytes[1], bytes[2], bytes[3] ] ) as f64,
+            };
+        }
+        count += 1;
+    }
+    if count == 0 { return RGB::new_black(); }
+    color_from_floats( [
+        ( sum[0] / count as f64 ) as f32,
+        ( sum[1] / count as f64 ) as f32,
+        ( sum[2] / count as f64 ) as f32,
+    ] )
+}
+
+/// HDR values above 1 are clamped
+fn color_from_floats( color:[f32;3] ) -> RGB {
+    let channel = |c:f32| ( c.clamp( 0.0, 1.0 ) * 255.0 ).round() as u8;
+    RGB::new( channel( color[0] ), channel( color[1] ), channel( color[2] ) )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubemapFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubemapFace {
+    pub fn as_glenum( &self ) -> GLenum {
+        gl::TEXTURE_CUBE_MAP_POSITIVE_X + *self as GLenum
+    }
+
+    /// Name of the face's image inside a cubemap folder, without the extension
+    pub fn file_stem( &self ) -> &str {
+        match self {
+            Self::PositiveX => "right" ,
+            Self::NegativeX => "left"  ,
+            Self::PositiveY => "top"   ,
+            Self::NegativeY => "bottom",
+            Self::PositiveZ => "front" ,
+            Self::NegativeZ => "back"  ,
+        }
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::PositiveX => "+X",
+            Self::NegativeX => "-X",
+            Self::PositiveY => "+Y",
+            Self::NegativeY => "-Y",
+            Self::PositiveZ => "+Z",
+            Self::NegativeZ => "-Z",
+        }
+    }
+}
+
+impl fmt::Display for CubemapFace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{} ({})", self.msg(), self.file_stem() )
+    }
+}
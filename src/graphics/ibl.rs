@@ -0,0 +1,409 @@
+use gl::types::*;
+use core::cell::RefCell;
+use std::{ fs, path::PathBuf, time::UNIX_EPOCH };
+use crate::{ Rc, resources, debugging::{ Error, log } };
+use super::{
+    Material, ShaderProgram, Texture, Cubemap,
+    cubemap::CUBEMAP_FACES,
+    offscreen::OffscreenPass,
+    program_cache::{ fnv1a, FNV_OFFSET_BASIS },
+    texture::{ ImageGL, TextureOptions, TextureWrapping, read_texture_level },
+};
+
+/// Shader keyword Material::set_environment_lighting toggles
+pub const ENVIRONMENT_LIGHTING:&str = "ENVIRONMENT_LIGHTING";
+
+// uniforms set on materials that use environment lighting, see common/ibl.glsl
+pub const IRRADIANCE_MAP:&str        = "irradiance_map";
+pub const PREFILTERED_MAP:&str       = "prefiltered_map";
+pub const BRDF_LUT:&str              = "brdf_lut";
+pub const PREFILTERED_MAX_LOD:&str   = "prefiltered_max_lod";
+pub const ENVIRONMENT_INTENSITY:&str = "environment_intensity";
+/// Every uniform the environment sets, materials don't own their values
+pub const ENVIRONMENT_UNIFORMS:[&str;5] = [
+    IRRADIANCE_MAP, PREFILTERED_MAP, BRDF_LUT, PREFILTERED_MAX_LOD, ENVIRONMENT_INTENSITY
+];
+
+// uniforms of the generation shaders
+pub const ENVIRONMENT_SAMPLER:&str = "environment_sampler";
+pub const ENVIRONMENT_SIZE:&str    = "environment_size";
+pub const ROUGHNESS:&str           = "roughness";
+pub const SAMPLE_COUNT:&str        = "sample_count";
+
+/// Directory inside the cache directory that generated maps are saved to
+pub const IBL_CACHE_DIRECTORY:&str = "ibl";
+
+const CACHE_MAGIC:&[u8;4] = b"GLIB";
+// magic + irradiance size + prefiltered size + prefiltered levels + lut size
+const CACHE_HEADER_SIZE:usize = 20;
+// bump when generation changes so old caches are regenerated
+const CACHE_VERSION:u8 = 1;
+
+const CUBEMAP_FORMAT:GLenum  = gl::RGB;
+const LUT_FORMAT:GLenum      = gl::RG;
+const CACHE_DATA_TYPE:GLenum = gl::HALF_FLOAT;
+
+thread_local!(
+    static ENVIRONMENT: RefCell<( u64, Option<Rc<EnvironmentLighting>> )> = RefCell::new( ( 1, None ) );
+);
+
+/// Sets the environment materials that opt in with
+/// Material::set_environment_lighting are lit by
+pub fn set_environment( environment:Option<Rc<EnvironmentLighting>> ) {
+    ENVIRONMENT.with( |e| {
+        let mut e = e.borrow_mut();
+        e.0 += 1;
+        e.1 = environment;
+    } );
+}
+
+pub fn environment() -> Option<Rc<EnvironmentLighting>> {
+    ENVIRONMENT.with( |e| e.borrow().1.clone() )
+}
+
+/// Changes every time set_environment is called
+pub fn environment_generation() -> u64 {
+    ENVIRONMENT.with( |e| e.borrow().0 )
+}
+
+/// Programs that generate the maps, irradiance_convolution, prefilter_environment
+/// and brdf_lut in resources/shaders
+#[derive(Clone)]
+pub struct EnvironmentShaders {
+    pub irradiance:Rc<ShaderProgram>,
+    pub prefilter:Rc<ShaderProgram>,
+    pub brdf_lut:Rc<ShaderProgram>,
+    /// Files the programs were built from, includes too, so editing them invalidates the cache
+    pub sources:Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EnvironmentSettings {
+    irradiance_size:GLint,
+    prefiltered_size:GLint,
+    prefiltered_levels:GLint,
+    brdf_lut_size:GLint,
+    sample_count:GLint,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            irradiance_size: 32, prefiltered_size: 128, prefiltered_levels: 5,
+            brdf_lut_size: 512, sample_count: 1024
+        }
+    }
+}
+
+impl EnvironmentSettings {
+
+    pub fn irradiance_size( &self )    -> GLint { self.irradiance_size    }
+    pub fn prefiltered_size( &self )   -> GLint { self.prefiltered_size   }
+    /// Roughness 0 is level 0, roughness 1 the last level
+    pub fn prefiltered_levels( &self ) -> GLint { self.prefiltered_levels }
+    pub fn brdf_lut_size( &self )      -> GLint { self.brdf_lut_size      }
+    /// Samples per texel when convolving
+    pub fn sample_count( &self )       -> GLint { self.sample_count       }
+
+    pub fn set_irradiance_size( &mut self, size:GLint ) { self.irradiance_size = size.max( 1 ); }
+    pub fn set_prefiltered_size( &mut self, size:GLint ) { self.prefiltered_size = size.max( 1 ); }
+    pub fn set_prefiltered_levels( &mut self, levels:GLint ) { self.prefiltered_levels = levels.max( 1 ); }
+    pub fn set_brdf_lut_size( &mut self, size:GLint ) { self.brdf_lut_size = size.max( 1 ); }
+    pub fn set_sample_count( &mut self, count:GLint ) { self.sample_count = count.max( 1 ); }
+}
+
+/// Maps for lighting materials with an environment cubemap:
+/// diffuse irradiance, specular prefiltered by roughness per mip level
+/// and the split-sum BRDF lookup table.
+pub struct EnvironmentLighting {
+    irradiance:Rc<Cubemap>,
+    prefiltered:Rc<Cubemap>,
+    brdf_lut:Rc<Texture>,
+    settings:EnvironmentSettings,
+    intensity:f32,
+    environment:Rc<Cubemap>,
+    shaders:EnvironmentShaders,
+}
+
+impl EnvironmentLighting {
+
+    /// Loads the maps from the cache if this environment was generated
+    /// with the same settings and shaders before, otherwise generates and caches them.
+    /// The cache is keyed on the paths and modification times of the environment's
+    /// and the shaders' sources, environments without sources aren't cached.
+    pub fn load_or_generate(
+        environment:&Rc<Cubemap>, settings:EnvironmentSettings, shaders:EnvironmentShaders
+    ) -> Result<Self, Error> {
+        let path = match cache_path( environment, &shaders, &settings ) {
+            Some( path ) => path,
+            None => return Self::generate( environment, settings, shaders ),
+        };
+        match load_cached( &path, &settings ) {
+            Some( ( irradiance, prefiltered, brdf_lut, settings ) ) => return Ok( Self {
+                irradiance, prefiltered, brdf_lut, settings, intensity: 1.0,
+                environment: environment.clone(), shaders
+            } ),
+            None => {},
+        }
+
+        let lighting = Self::generate( environment, settings, shaders )?;
+        lighting.save_cached( &path );
+        Ok( lighting )
+    }
+
+    pub fn generate(
+        environment:&Rc<Cubemap>, settings:EnvironmentSettings, shaders:EnvironmentShaders
+    ) -> Result<Self, Error> {
+        let lighting = Self {
+            irradiance:  Cubemap::new_empty( settings.irradiance_size, gl::RGBA16F, cubemap_options() ),
+            prefiltered: Cubemap::new_empty( settings.prefiltered_size, gl::RGBA16F, cubemap_options() ),
+            brdf_lut:    Texture::new( lut_image( settings.brdf_lut_size, Vec::new() ), lut_options() ),
+            settings, intensity: 1.0,
+            environment: environment.clone(), shaders,
+        };
+        lighting.render()?;
+        Ok( lighting )
+    }
+
+    /// Renders the maps again in place, for when the environment or the shaders changed.
+    /// Materials keep sampling the same textures, so they see the new maps right away.
+    pub fn regenerate( &self ) -> Result<(), Error> {
+        self.render()?;
+        match cache_path( &self.environment, &self.shaders, &self.settings ) {
+            Some( path ) => self.save_cached( &path ),
+            None => {},
+        }
+        Ok(())
+    }
+
+    fn render( &self ) -> Result<(), Error> {
+        let ( environment, settings, shaders ) = ( &self.environment, &self.settings, &self.shaders );
+
+        let mut material = Material::new( shaders.irradiance.clone() );
+        material.get_uniform_mut_by_name( ENVIRONMENT_SAMPLER )?.set_sampler_texture( environment.clone() );
+        material.get_uniform_mut_by_name( ENVIRONMENT_SIZE )?.set_f32( environment.size() as f32 );
+        material.get_uniform_mut_by_name( SAMPLE_COUNT )?.set_i32( settings.sample_count );
+        self.irradiance.render_to_faces( &mut material, 0 )?;
+        self.irradiance.set_max_level( 0 );
+
+        let mut material = Material::new( shaders.prefilter.clone() );
+        material.get_uniform_mut_by_name( ENVIRONMENT_SAMPLER )?.set_sampler_texture( environment.clone() );
+        material.get_uniform_mut_by_name( ENVIRONMENT_SIZE )?.set_f32( environment.size() as f32 );
+        material.get_uniform_mut_by_name( SAMPLE_COUNT )?.set_i32( settings.sample_count );
+        let levels = settings.prefiltered_levels.min( self.prefiltered.level_count() );
+        for level in 0..levels {
+            let roughness = if levels > 1 { level as f32 / ( levels - 1 ) as f32 } else { 0.0 };
+            material.get_uniform_mut_by_name( ROUGHNESS )?.set_f32( roughness );
+            self.prefiltered.render_to_faces( &mut material, level )?;
+        }
+        self.prefiltered.set_max_level( levels - 1 );
+
+        let mut material = Material::new( shaders.brdf_lut.clone() );
+        material.get_uniform_mut_by_name( SAMPLE_COUNT )?.set_i32( settings.sample_count );
+        {
+            let pass = OffscreenPass::begin();
+            pass.attach( gl::TEXTURE_2D, self.brdf_lut.handle(), 0, self.brdf_lut.width(), self.brdf_lut.height() )?;
+            pass.draw( &mut material );
+        }
+        self.brdf_lut.generate_mipmaps();
+
+        log(
+            &format!( "Generated environment lighting from {}", environment ),
+            "Environment Lighting"
+        );
+        Ok(())
+    }
+
+    /// Files the maps are generated from, the environment's then the shaders'
+    pub fn sources( &self ) -> Vec<PathBuf> {
+        let mut sources = self.environment.sources();
+        sources.extend( self.shaders.sources.iter().cloned() );
+        sources
+    }
+
+    pub fn irradiance( &self )  -> &Rc<Cubemap>        { &self.irradiance  }
+    pub fn prefiltered( &self ) -> &Rc<Cubemap>        { &self.prefiltered }
+    pub fn brdf_lut( &self )    -> &Rc<Texture>        { &self.brdf_lut    }
+    pub fn settings( &self )    -> &EnvironmentSettings { &self.settings    }
+    pub fn intensity( &self )   -> f32                 { self.intensity    }
+    pub fn environment( &self ) -> &Rc<Cubemap>        { &self.environment }
+
+    /// Scales all environment lighting, applied when the environment is set
+    pub fn set_intensity( &mut self, intensity:f32 ) { self.intensity = intensity; }
+
+    /// Mip level of the prefiltered map that holds roughness 1
+    pub fn prefiltered_max_lod( &self ) -> f32 {
+        ( self.settings.prefiltered_levels.min( self.prefiltered.level_count() ) - 1 ) as f32
+    }
+
+    /// Sets the uniforms in ENVIRONMENT_UNIFORMS the material's shader declares
+    pub fn apply_to( &self, material:&mut Material ) {
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( IRRADIANCE_MAP ) {
+            uniform.set_sampler_texture( self.irradiance.clone() );
+        }
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( PREFILTERED_MAP ) {
+            uniform.set_sampler_texture( self.prefiltered.clone() );
+        }
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( BRDF_LUT ) {
+            uniform.set_texture2d( self.brdf_lut.clone() );
+        }
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( PREFILTERED_MAX_LOD ) {
+            uniform.set_f32( self.prefiltered_max_lod() );
+        }
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( ENVIRONMENT_INTENSITY ) {
+            uniform.set_f32( self.intensity );
+        }
+    }
+
+    fn save_cached( &self, path:&PathBuf ) {
+        let settings = &self.settings;
+        let levels = self.prefiltered_max_lod() as GLint + 1;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice( CACHE_MAGIC );
+        for value in [ settings.irradiance_size, settings.prefiltered_size, levels, settings.brdf_lut_size ].iter() {
+            bytes.extend_from_slice( &( *value as u32 ).to_le_bytes() );
+        }
+        for face in CUBEMAP_FACES.iter() {
+            bytes.extend( self.irradiance.read_face_level( *face, 0, CUBEMAP_FORMAT, CACHE_DATA_TYPE ) );
+        }
+        for level in 0..levels {
+            for face in CUBEMAP_FACES.iter() {
+                bytes.extend( self.prefiltered.read_face_level( *face, level, CUBEMAP_FORMAT, CACHE_DATA_TYPE ) );
+            }
+        }
+        bytes.extend( read_texture_level(
+            self.brdf_lut.handle(), 0, 0, self.brdf_lut.width(), self.brdf_lut.height(),
+            LUT_FORMAT, CACHE_DATA_TYPE
+        ) );
+
+        match resources::save_bytes_path( path, &bytes ) {
+            Ok(()) => log(
+                &format!( "Saved environment lighting {}", path.display() ),
+                "Environment Lighting"
+            ),
+            Err( e ) => log(
+                &format!( "Failed to save environment lighting! {}", e.msg() ),
+                "Environment Lighting | Warning"
+            ),
+        }
+    }
+
+}
+
+/// Maps and the settings they were generated with, as read from the cache
+type CachedMaps = ( Rc<Cubemap>, Rc<Cubemap>, Rc<Texture>, EnvironmentSettings );
+
+fn load_cached( path:&PathBuf, settings:&EnvironmentSettings ) -> Option<CachedMaps> {
+    // a missing file is just a cache miss
+    let bytes = resources::load_bytes_path( path ).ok()?;
+    match from_cache_bytes( &bytes, settings ) {
+        Ok( maps ) => Some( maps ),
+        Err( e ) => {
+            log(
+                &format!( "{} Regenerating {}", e.msg(), path.display() ),
+                "Environment Lighting | Warning"
+            );
+            None
+        },
+    }
+}
+
+fn from_cache_bytes( bytes:&[u8], settings:&EnvironmentSettings ) -> Result<CachedMaps, Error> {
+    let malformed = || Error::TextureInvalidImage( "Malformed environment lighting cache!".to_owned() );
+    if bytes.len() < CACHE_HEADER_SIZE || &bytes[0..4] != CACHE_MAGIC { return Err( malformed() ); }
+    let header = |idx:usize| {
+        let offset = 4 + idx * 4;
+        u32::from_le_bytes( [ bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3] ] ) as GLint
+    };
+    let ( irradiance_size, prefiltered_size, levels, lut_size ) = ( header(0), header(1), header(2), header(3) );
+    if irradiance_size != settings.irradiance_size || prefiltered_size != settings.prefiltered_size ||
+        lut_size != settings.brdf_lut_size || levels < 1 {
+        return Err( malformed() );
+    }
+
+    let mut data = &bytes[CACHE_HEADER_SIZE..];
+
+    let irradiance = Cubemap::new_empty( irradiance_size, gl::RGBA16F, cubemap_options() );
+    let face_size = super::texture::level_byte_size( irradiance_size, irradiance_size, CUBEMAP_FORMAT, CACHE_DATA_TYPE );
+    for face in CUBEMAP_FACES.iter() {
+        irradiance.write_face_level( *face, 0, CUBEMAP_FORMAT, CACHE_DATA_TYPE, take( &mut data, face_size )? )?;
+    }
+    irradiance.set_max_level( 0 );
+
+    let prefiltered = Cubemap::new_empty( prefiltered_size, gl::RGBA16F, cubemap_options() );
+    if levels > prefiltered.level_count() { return Err( malformed() ); }
+    for level in 0..levels {
+        let size = prefiltered.level_size( level );
+        let face_size = super::texture::level_byte_size( size, size, CUBEMAP_FORMAT, CACHE_DATA_TYPE );
+        for face in CUBEMAP_FACES.iter() {
+            prefiltered.write_face_level( *face, level, CUBEMAP_FORMAT, CACHE_DATA_TYPE, take( &mut data, face_size )? )?;
+        }
+    }
+    prefiltered.set_max_level( levels - 1 );
+
+    let lut_bytes = take( &mut data, super::texture::level_byte_size( lut_size, lut_size, LUT_FORMAT, CACHE_DATA_TYPE ) )?;
+    let brdf_lut = Texture::new( lut_image( lut_size, lut_bytes.to_vec() ), lut_options() );
+
+    let mut settings = *settings;
+    settings.prefiltered_levels = levels;
+    Ok( ( irradiance, prefiltered, brdf_lut, settings ) )
+}
+
+/// Splits the next size bytes off the cached data
+fn take<'a>( data:&mut &'a [u8], size:usize ) -> Result<&'a [u8], Error> {
+    if data.len() < size {
+        return Err( Error::TextureInvalidImage( "Malformed environment lighting cache!".to_owned() ) );
+    }
+    let ( taken, rest ) = data.split_at( size );
+    *data = rest;
+    Ok( taken )
+}
+
+fn cubemap_options() -> TextureOptions { TextureOptions::default() }
+
+fn lut_options() -> TextureOptions {
+    let mut options = TextureOptions::default();
+    options.set_wrapping( TextureWrapping::ClampToEdge );
+    options
+}
+
+fn lut_image( size:GLint, data:Vec<u8> ) -> ImageGL {
+    ImageGL {
+        width: size, height: size,
+        internal_format: gl::RG16F, format: LUT_FORMAT,
+        data_type: CACHE_DATA_TYPE, swizzle: None,
+        data
+    }
+}
+
+/// None for environments made in code, nothing tells their contents apart
+fn cache_path( environment:&Cubemap, shaders:&EnvironmentShaders, settings:&EnvironmentSettings ) -> Option<PathBuf> {
+    let mut sources = environment.sources();
+    if sources.is_empty() { return None; }
+    sources.extend( shaders.sources.iter().cloned() );
+    Some( resources::cache_path_from_local_path(
+        &format!( "{}/{:016x}.bin", IBL_CACHE_DIRECTORY, cache_key( &sources, settings ) )
+    ) )
+}
+
+/// Hash of the source files, when they were last modified
+/// and the settings the maps are generated with
+fn cache_key( sources:&[PathBuf], settings:&EnvironmentSettings ) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    hash = fnv1a( hash, &[ CACHE_VERSION ] );
+    for value in [
+        settings.irradiance_size, settings.prefiltered_size, settings.prefiltered_levels,
+        settings.brdf_lut_size, settings.sample_count
+    ].iter() {
+        hash = fnv1a( hash, &value.to_le_bytes() );
+    }
+    for source in sources.iter() {
+        hash = fnv1a( hash, source.to_string_lossy().as_bytes() );
+        // a file that can't be read hashes as never modified, loading it fails anyway
+        let modified = fs::metadata( source ).and_then( |metadata| metadata.modified() ).ok()
+            .and_then( |modified| modified.duration_since( UNIX_EPOCH ).ok() )
+            .map_or( 0, |since_epoch| since_epoch.as_nanos() );
+        hash = fnv1a( hash, &modified.to_le_bytes() );
+    }
+    hash
+}
@@ -1,44 +1,319 @@
 use gl::types::*;
-use crate::{ Rc, debugging::Error };
-use super::{ ShaderProgram, Uniform, null_shader };
-use core::{ fmt, ops::{ Index, IndexMut } };
+use crate::{ Rc, debugging::{ Error, log } };
+use super::{
+    ShaderProgram, Uniform, null_shader, variants::ShaderVariants,
+    sampler::SamplerObject,
+    texture::TextureUnitAllocator,
+    uniform::{ UniformPathSegment, parse_uniform_path },
+    property_block::{ PropertyBlock, Property },
+    pbr::MetallicRoughness,
+    ibl,
+};
+use core::{ fmt, cell::RefCell, ops::{ Index, IndexMut } };
 
 pub struct Material {
     shader: Rc<ShaderProgram>,
     uniforms: ( Vec<Uniform>, Vec<bool> ),
+    uniform_names: Vec<String>,
+    shader_generation: u32,
+    variants: Option<Rc<ShaderVariants>>,
+    keywords: Vec<String>,
+    /// Property block values the program currently has instead of the material's
+    applied_properties: Vec<AppliedProperty>,
+    /// Paths of properties already warned about, blocks are often made every frame
+    /// so the path is all that's kept, cleared when the shader changes
+    warned_properties: Vec<String>,
+    /// Lit by the environment set with ibl::set_environment
+    environment_lighting: bool,
+    /// ibl::environment_generation when the environment was last applied
+    environment_generation: u64,
 }
 
 impl Material {
 
     pub fn new( shader:Rc<ShaderProgram> ) -> Self {
-        let uniforms = shader.generate_uniforms();
-        Self { shader, uniforms }
+        let ( uniform_names, uniforms ) = shader.generate_named_uniforms();
+        let dirty = vec![true;uniforms.len()];
+        let shader_generation = shader.generation();
+        Self {
+            shader, uniforms: ( uniforms, dirty ), uniform_names, shader_generation,
+            variants: None, keywords: Vec::new(),
+            applied_properties: Vec::new(), warned_properties: Vec::new(),
+            environment_lighting: false, environment_generation: 0
+        }
+    }
+
+    /// Material for resources/shaders/pbr.shader, or any shader
+    /// with the same uniforms, set up from glTF style properties.
+    /// Keywords pick the variant like from_variants, properties only reach uniforms
+    /// the variant declares, so apply them again after enabling PARALLAX_MAPPING.
+    pub fn new_metallic_roughness(
+        variants:Rc<ShaderVariants>, keywords:&[&str], properties:&MetallicRoughness
+    ) -> Result<Self, Error> {
+        let mut material = Self::from_variants( variants, keywords )?;
+        properties.apply_to( &mut material );
+        Ok( material )
     }
 
     pub fn new_null() -> Self {
         Self::new( null_shader() )
     }
 
+    /// Material whose shader is picked from its enabled keywords
+    pub fn from_variants( variants:Rc<ShaderVariants>, keywords:&[&str] ) -> Result<Self, Error> {
+        let shader = variants.get_variant( keywords )?;
+        let mut material = Self::new( shader );
+        material.keywords = keywords.iter()
+            .filter( |k| variants.has_keyword( k ) )
+            .map( |k| k.to_string() )
+            .collect();
+        material.variants = Some( variants );
+        Ok( material )
+    }
+
     pub fn clone_from( m:&Self ) -> Self {
-        Self { shader: m.shader.clone(), uniforms: m.uniforms.clone() }
+        Self {
+            shader: m.shader.clone(), uniforms: m.uniforms.clone(),
+            uniform_names: m.uniform_names.clone(),
+            shader_generation: m.shader_generation,
+            variants: m.variants.clone(), keywords: m.keywords.clone(),
+            applied_properties: Vec::new(), warned_properties: Vec::new(),
+            environment_lighting: m.environment_lighting, environment_generation: 0
+        }
     }
 
     pub fn shader(&self)   -> &Rc<ShaderProgram> { &self.shader }
     pub fn uniforms(&self) -> &Vec<Uniform>  { &self.uniforms.0 }
+    pub fn uniform_names(&self) -> &[String] { &self.uniform_names }
+    pub fn keywords(&self) -> &[String]      { &self.keywords }
+    pub fn variants(&self) -> Option<&Rc<ShaderVariants>> { self.variants.as_ref() }
+
+    pub fn uses_environment_lighting(&self) -> bool { self.environment_lighting }
+
+    /// Opts in to having the uniforms in ibl::ENVIRONMENT_UNIFORMS
+    /// set from the current environment whenever it changes,
+    /// and toggles the ibl::ENVIRONMENT_LIGHTING keyword if the shader has it
+    pub fn set_environment_lighting( &mut self, enabled:bool ) -> Result<(), Error> {
+        self.environment_lighting   = enabled;
+        self.environment_generation = 0;
+        let has_keyword = self.variants.as_ref()
+            .is_some_and( |variants| variants.has_keyword( ibl::ENVIRONMENT_LIGHTING ) );
+        if !has_keyword { return Ok(()); }
+        if enabled {
+            self.enable_keyword( ibl::ENVIRONMENT_LIGHTING )
+        } else {
+            self.disable_keyword( ibl::ENVIRONMENT_LIGHTING )
+        }
+    }
+
+    fn apply_environment_lighting( &mut self ) {
+        if !self.environment_lighting { return; }
+        let generation = ibl::environment_generation();
+        if generation == self.environment_generation { return; }
+        self.environment_generation = generation;
+        match ibl::environment() {
+            Some( environment ) => environment.apply_to( self ),
+            None => if let Ok( intensity ) = self.get_uniform_mut_by_name( ibl::ENVIRONMENT_INTENSITY ) {
+                intensity.set_f32( 0.0 );
+            },
+        }
+    }
+
+    pub fn is_keyword_enabled( &self, keyword:&str ) -> bool {
+        self.keywords.iter().any( |k| k == keyword )
+    }
+
+    pub fn enable_keyword( &mut self, keyword:&str ) -> Result<(), Error> {
+        if self.is_keyword_enabled( keyword ) { return Ok(()); }
+        match &self.variants {
+            Some( variants ) => if !variants.has_keyword( keyword ) {
+                return Err( Error::ShaderVariant(
+                    format!( "Shader \"{}\" has no keyword \"{}\"!", variants.name(), keyword )
+                ) );
+            },
+            None => return Err( Error::ShaderVariant(
+                format!( "Attempted to enable keyword \"{}\" on a Material without shader variants!", keyword )
+            ) ),
+        }
+        self.keywords.push( keyword.to_owned() );
+        self.select_variant()
+    }
+
+    pub fn disable_keyword( &mut self, keyword:&str ) -> Result<(), Error> {
+        if !self.is_keyword_enabled( keyword ) { return Ok(()); }
+        self.keywords.retain( |k| k != keyword );
+        self.select_variant()
+    }
+
+    fn select_variant( &mut self ) -> Result<(), Error> {
+        let variants = match &self.variants {
+            Some( variants ) => variants.clone(),
+            None => return Ok(()),
+        };
+        let keywords:Vec<&str> = self.keywords.iter().map( |k| k.as_str() ).collect();
+        let shader = variants.get_variant( &keywords )?;
+        if !Rc::ptr_eq( &shader, &self.shader ) {
+            self.swap_shader( shader );
+        }
+        Ok(())
+    }
+
+    /// Picks up a hot reloaded shader, keeping the values of uniforms that still exist.
+    /// Returns true if the shader had changed.
+    pub fn refresh_shader( &mut self ) -> bool {
+        if self.shader.generation() == self.shader_generation { return false; }
+        self.swap_shader( self.shader.clone() );
+        true
+    }
+
+    /// Switches to a new shader, keeping the values of uniforms that exist in both.
+    fn swap_shader( &mut self, shader:Rc<ShaderProgram> ) {
+        let ( names, mut uniforms ) = shader.generate_named_uniforms();
+        for ( old, name ) in self.uniforms.0.iter().zip( self.uniform_names.iter() ) {
+            match names.iter().position( |n| n == name ) {
+                Some( idx ) => { uniforms[idx].copy_value_from( old ); },
+                None => {},
+            }
+        }
+        let dirty = vec![true;uniforms.len()];
+        self.uniform_names     = names;
+        self.shader_generation = shader.generation();
+        self.shader   = shader;
+        self.uniforms = ( uniforms, dirty );
+        self.applied_properties.clear();
+        self.warned_properties.clear();
+        self.environment_generation = 0;
+    }
 
     pub fn use_shader(&self) { self.shader.use_program() }
     pub fn send_uniforms_to_gl(&mut self) {
+        self.send_uniforms_with_units( &mut TextureUnitAllocator::new() );
+    }
+    /// Like send_uniforms_to_gl but takes texture units from an allocator
+    /// shared with other programs used in the same draw
+    pub fn send_uniforms_with_units(&mut self, units:&mut TextureUnitAllocator) {
+        self.send_uniforms_with_blocks( &[], units );
+    }
+    /// Uses the material with property blocks applied on top, in order
+    pub fn use_material_with(&mut self, blocks:&[&PropertyBlock]) {
+        self.use_shader();
+        self.send_uniforms_with_blocks( blocks, &mut TextureUnitAllocator::new() );
+    }
+    /// Sends changed uniforms, then the values of the property blocks.
+    /// Values a block already set are only resent when the block changed them since,
+    /// uniforms overridden by blocks that are no longer applied are restored.
+    pub fn send_uniforms_with_blocks(
+        &mut self, blocks:&[&PropertyBlock], units:&mut TextureUnitAllocator
+    ) {
+        self.refresh_shader();
+        self.apply_environment_lighting();
+
+        // resolve every property to the uniform it overrides, later blocks win
+        let mut targets:Vec<( &PropertyBlock, &Property, usize, Vec<UniformPathSegment> )> = Vec::new();
+        for block in blocks.iter() {
+            for property in block.properties().iter() {
+                match self.resolve_path( property.path() ) {
+                    Ok( ( idx, path ) ) => {
+                        targets.retain( |( _, other, other_idx, _ )| *other_idx != idx || other.path() != property.path() );
+                        targets.push( ( block, property, idx, path ) );
+                    },
+                    Err(_) => self.warn_property( property, format!(
+                        "Property \"{}\" doesn't match any uniform!", property.path()
+                    ) ),
+                }
+            }
+        }
+
+        // restore what blocks that aren't applied anymore overrode,
+        // resending a uniform overwrites every block value in it
+        for applied in self.applied_properties.iter() {
+            let still_applied = targets.iter()
+                .any( |( _, property, idx, _ )| *idx == applied.uniform && property.path() == applied.path );
+            if !still_applied { self.uniforms.1[applied.uniform] = true; }
+        }
+        let dirty = &self.uniforms.1;
+        self.applied_properties.retain( |applied| !dirty[applied.uniform] );
+
+        self.bind_textures( units );
         for (idx, uniform) in self.uniforms.0.iter().enumerate() {
             let dirty_flag = &mut self.uniforms.1[idx];
             uniform.send_if_dirty( *dirty_flag );
             *dirty_flag = false;
         }
+
+        for ( block, property, idx, path ) in targets.iter() {
+            let current = self.applied_properties.iter()
+                .position( |applied| applied.uniform == *idx && applied.path == property.path() );
+            let up_to_date = current.is_some_and( |current| {
+                let applied = &self.applied_properties[current];
+                applied.block == block.id() && applied.revision == property.revision()
+            } );
+            // samplers are rebound every draw so the material's textures can't take their units
+            let is_sampler = matches!( property.value(), Uniform::Sampler2D(_, _) | Uniform::Sampler(_, _) );
+            if up_to_date && !is_sampler { continue; }
+
+            let mut value = match self.uniforms.0[*idx].get_path( path ) {
+                Ok( uniform ) => uniform.clone(),
+                Err(_) => continue,
+            };
+            if !value.copy_value_from( property.value() ) {
+                let msg = format!(
+                    "Property \"{}\" is {} but the uniform is {}!",
+                    property.path(), property.value().type_name(), value.type_name()
+                );
+                self.warn_property( property, msg );
+                continue;
+            }
+            match value.sampler_object() {
+                Some( object ) if !value.accepts_sampler_object( &object ) => {
+                    let msg = format!(
+                        "Property \"{}\" has a depth compare sampler object but the uniform is {}!",
+                        property.path(), value.type_name()
+                    );
+                    self.warn_property( property, msg );
+                    continue;
+                },
+                _ => {},
+            }
+            value.bind_textures( &self.shader, units );
+            if up_to_date { continue; }
+            value.send_to_gl();
+
+            let applied = AppliedProperty {
+                uniform: *idx, path: property.path().to_owned(),
+                block: block.id(), revision: property.revision(),
+            };
+            match current {
+                Some( current ) => self.applied_properties[current] = applied,
+                None => self.applied_properties.push( applied ),
+            }
+        }
+    }
+
+    /// Logs a warning about a property path once until the shader changes
+    fn warn_property( &mut self, property:&Property, msg:String ) {
+        if self.warned_properties.iter().any( |path| path == property.path() ) { return; }
+        self.warned_properties.push( property.path().to_owned() );
+        log( &msg, "Property Block | Warning" );
     }
-    pub fn send_all_uniforms_to_gl(&self) {
+    pub fn send_all_uniforms_to_gl(&mut self) {
+        self.send_all_uniforms_with_units( &mut TextureUnitAllocator::new() );
+    }
+    pub fn send_all_uniforms_with_units(&mut self, units:&mut TextureUnitAllocator) {
+        self.apply_environment_lighting();
+        self.shader.clear_sampler_units();
+        self.applied_properties.clear();
+        self.bind_textures( units );
         for uniform in self.uniforms.0.iter() {
             uniform.send_to_gl();
         }
     }
+    /// Binds every texture the material samples to a texture unit
+    pub fn bind_textures(&mut self, units:&mut TextureUnitAllocator) {
+        for uniform in self.uniforms.0.iter_mut() {
+            uniform.bind_textures( &self.shader, units );
+        }
+    }
     pub fn use_material(&mut self) { self.use_shader(); self.send_uniforms_to_gl(); }
 
     pub fn get_uniform_location( &self, name:&str ) -> usize {
@@ -47,8 +322,30 @@ impl Material {
         result as usize
     }
 
+    /// True if the shader has a uniform at this path, like `lights[2].color`
+    pub fn has_uniform( &self, name:&str ) -> bool {
+        self.get_uniform_by_name( name ).is_ok()
+    }
+
+    /// Index of the top level uniform a path starts at and the rest of the path
+    fn resolve_path( &self, name:&str ) -> Result<( usize, Vec<UniformPathSegment> ), Error> {
+        let mut path = parse_uniform_path( name )?;
+        let root = match path.remove( 0 ) {
+            UniformPathSegment::Member( root ) => root,
+            UniformPathSegment::Element(_) => unreachable!(),
+        };
+        match self.uniform_names.iter().position( |n| *n == root ) {
+            Some( idx ) => Ok( ( idx, path ) ),
+            None => Err( Error::UniformNotFound( format!("Uniform \"{}\" not found!", name) ) ),
+        }
+    }
+
+    /// Gets a uniform by name. Array elements and struct members
+    /// can be reached with paths like `colors[3]` or `lights[2].color`.
     pub fn get_uniform_by_name( &self, name:&str ) -> Result<&Uniform, Error> {
-        Uniform::find_uniform_by_name( &self.uniforms.0, &self.shader, name )
+        let ( idx, path ) = self.resolve_path( name )?;
+        self.uniforms.0[idx].get_path( &path )
+            .map_err( |e| Error::UniformNotFound( format!( "Uniform \"{}\": {}", name, e.msg() ) ) )
     }
 
     pub fn get_uniform_by_location( &self, location:GLint ) -> Result<&Uniform, Error> {
@@ -56,26 +353,106 @@ impl Material {
     }
 
     pub fn get_uniform_mut_by_name( &mut self, name:&str ) -> Result<&mut Uniform, Error> {
-        let loc = self.shader.get_uniform_location(name);
-        match self.uniforms.0.iter().position( |u| u.location() == loc ) {
-            Some(idx) => {
-                self.uniforms.1[idx] = true;
-                Ok( &mut self.uniforms.0[idx] )
-            },
-            None => Err( Error::UniformNotFound( format!("Uniform \"{}\" not found!", name) ) ),
-        }
+        let ( idx, path ) = self.resolve_path( name )?;
+        self.uniforms.1[idx] = true;
+        self.uniforms.0[idx].get_path_mut( &path )
+            .map_err( |e| Error::UniformNotFound( format!( "Uniform \"{}\": {}", name, e.msg() ) ) )
     }
 
     pub fn get_uniform_mut_by_location( &mut self, location:GLint ) -> Result<&mut Uniform, Error> {
-        match self.uniforms.0.iter().position( |u| u.location() == location ) {
+        match self.uniforms.0.iter().position( |u| u.find_location( location ).is_some() ) {
             Some(idx) => {
                 self.uniforms.1[idx] = true;
-                Ok( &mut self.uniforms.0[idx] )
+                Ok( self.uniforms.0[idx].find_location_mut( location ).unwrap() )
             },
             None => Err( Error::UniformNotFound( format!("Uniform at location {} not found!", location) ) ),
         }
     }
 
+    /// Samples the texture of a sampler uniform with a sampler object, whatever texture is set on it.
+    /// None goes back to the parameters of the texture itself.
+    /// Sampler objects that compare depth only go on shadow samplers.
+    pub fn set_sampler_object( &mut self, name:&str, object:Option<Rc<SamplerObject>> ) -> Result<(), Error> {
+        let uniform = self.get_uniform_mut_by_name( name )?;
+        match uniform {
+            Uniform::Sampler2D(_, _) | Uniform::Sampler(_, _) => {
+                if let Some( object ) = object.as_ref() {
+                    if !uniform.accepts_sampler_object( object ) {
+                        return Err( Error::UniformTypeMismatch( format!(
+                            "Uniform \"{}\" is {}, depth compare sampler objects need a shadow sampler!",
+                            name, uniform.type_name()
+                        ) ) );
+                    }
+                }
+                uniform.set_sampler_object( object );
+                Ok(())
+            },
+            _ => Err( Error::UniformTypeMismatch( format!(
+                "Uniform \"{}\" is {}, not a sampler!", name, uniform.type_name()
+            ) ) ),
+        }
+    }
+
+}
+
+/// A property block value a material sent in place of its own
+struct AppliedProperty {
+    uniform: usize,
+    path: String,
+    block: u64,
+    revision: u64,
+}
+
+/// Shares the shader and uniform values of a parent Material
+/// and overrides only the uniforms set on it.
+/// Changes to the parent show up in every instance.
+pub struct MaterialInstance {
+    parent: Rc<RefCell<Material>>,
+    overrides: PropertyBlock,
+}
+
+impl MaterialInstance {
+    pub fn new( parent:&Rc<RefCell<Material>> ) -> Self {
+        Self { parent: parent.clone(), overrides: PropertyBlock::new() }
+    }
+
+    pub fn parent(&self)    -> &Rc<RefCell<Material>> { &self.parent    }
+    pub fn overrides(&self) -> &PropertyBlock         { &self.overrides }
+
+    /// Overrides a uniform, checked against the parent's uniform at the same path
+    pub fn set( &mut self, path:&str, value:Uniform ) -> Result<(), Error> {
+        {
+            let parent = self.parent.borrow();
+            let mut check = parent.get_uniform_by_name( path )?.clone();
+            if !check.copy_value_from( &value ) {
+                return Err( Error::UniformTypeMismatch( format!(
+                    "Uniform \"{}\" is {} but the override is {}!",
+                    path, check.type_name(), value.type_name()
+                ) ) );
+            }
+            match check.sampler_object() {
+                Some( object ) if !check.accepts_sampler_object( &object ) => return Err( Error::UniformTypeMismatch( format!(
+                    "Uniform \"{}\" is {}, depth compare sampler objects need a shadow sampler!",
+                    path, check.type_name()
+                ) ) ),
+                _ => {},
+            }
+        }
+        self.overrides.set( path, value );
+        Ok(())
+    }
+
+    /// Goes back to the parent's value
+    pub fn reset( &mut self, path:&str ) { self.overrides.remove( path ) }
+
+    pub fn use_material( &self ) {
+        self.parent.borrow_mut().use_material_with( &[ &self.overrides ] );
+    }
+
+    /// Uses the instance with a per-draw property block on top of its overrides
+    pub fn use_material_with( &self, block:&PropertyBlock ) {
+        self.parent.borrow_mut().use_material_with( &[ &self.overrides, block ] );
+    }
 }
 
 impl Index<usize> for Material {
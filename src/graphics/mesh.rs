@@ -9,21 +9,21 @@ use crate::{ Rc, debugging::Error };
 
 use super::Culling;
 
-use core::fmt;
+use core::{ fmt, cell::{ Cell, RefCell, Ref } };
 
 #[derive(Debug, Clone)]
 pub struct Mesh {
 
-    vertices: Vec<f32>,
-    indices:  Vec<GLuint>,
+    vertices: RefCell<Vec<f32>>,
+    indices:  RefCell<Vec<GLuint>>,
     culling:  Culling,
 
     // opengl render data
-    vao: GLuint,
-    vbo: GLuint,
-    ebo: GLuint,
+    vao: Cell<GLuint>,
+    vbo: Cell<GLuint>,
+    ebo: Cell<GLuint>,
 
-    index_count: usize,
+    index_count: Cell<usize>,
 
 }
 
@@ -373,9 +373,13 @@ impl Mesh {
 
         Rc::new(
             Self {
-                vertices, indices,
-                culling, vao, vbo, ebo,
-                index_count
+                vertices: RefCell::new( vertices ),
+                indices:  RefCell::new( indices ),
+                culling,
+                vao: Cell::new( vao ),
+                vbo: Cell::new( vbo ),
+                ebo: Cell::new( ebo ),
+                index_count: Cell::new( index_count )
             }
         )
     }
@@ -392,7 +396,7 @@ impl Mesh {
         unsafe {
             gl::DrawElements(
                 gl::TRIANGLES,
-                self.index_count as GLsizei,
+                self.index_count.get() as GLsizei,
                 gl::UNSIGNED_INT,
                 core::ptr::null_mut() as *const GLvoid
             );
@@ -405,26 +409,37 @@ impl Mesh {
         self.draw();
     }
 
-    pub fn vertices(&self) -> &[f32]    { &self.vertices }
-    pub fn indices(&self)  -> &[GLuint] { &self.indices }
+    pub fn vertices(&self) -> Ref<'_, Vec<f32>>    { self.vertices.borrow() }
+    pub fn indices(&self)  -> Ref<'_, Vec<GLuint>> { self.indices.borrow() }
 
     pub fn culling(&self) -> &Culling { &self.culling }
     pub fn culling_mut(&mut self) -> &mut Culling { &mut self.culling }
 
-    pub fn vertex_array_object(&self)   -> GLuint { self.vao }
-    pub fn vertex_buffer_object(&self)  -> GLuint { self.vbo }
-    pub fn element_buffer_object(&self) -> GLuint { self.ebo }
-
-    pub fn vertex_count(&self)   -> usize { self.index_count }
-    pub fn triangle_count(&self) -> usize { self.index_count / 3 }
+    pub fn vertex_array_object(&self)   -> GLuint { self.vao.get() }
+    pub fn vertex_buffer_object(&self)  -> GLuint { self.vbo.get() }
+    pub fn element_buffer_object(&self) -> GLuint { self.ebo.get() }
+
+    pub fn vertex_count(&self)   -> usize { self.index_count.get() }
+    pub fn triangle_count(&self) -> usize { self.index_count.get() / 3 }
+
+    /// Swaps the GL buffers and data of another Mesh into this one.
+    /// The old buffers end up in `other`, ready to be deleted with delete_meshes.
+    pub fn replace_with( &self, other:&Mesh ) {
+        self.vertices.swap( &other.vertices );
+        self.indices.swap( &other.indices );
+        self.vao.swap( &other.vao );
+        self.vbo.swap( &other.vbo );
+        self.ebo.swap( &other.ebo );
+        self.index_count.swap( &other.index_count );
+    }
 
     pub fn delete_meshes( meshes:Vec<Rc<Self>> ) {
         let mut vertex_arrays:Vec<GLuint> = Vec::with_capacity( meshes.len() );
         let mut buffers:Vec<GLuint>       = Vec::with_capacity( meshes.len() * 2 );
         for mesh in meshes.iter() {
-            vertex_arrays.push( mesh.vao );
-            buffers.push( mesh.vbo );
-            buffers.push( mesh.ebo );
+            vertex_arrays.push( mesh.vertex_array_object() );
+            buffers.push( mesh.vertex_buffer_object() );
+            buffers.push( mesh.element_buffer_object() );
         }
         unsafe {
             gl::DeleteVertexArrays(
@@ -1,13 +1,28 @@
-mod uniform;
-pub use uniform::{ Uniform, UniformBlock };
+pub mod uniform;
+pub use uniform::{ Uniform, UniformBlock, StorageBuffer, BufferUsage };
+pub mod ring_buffer;
+pub use ring_buffer::UniformRingBuffer;
 
 pub mod camera;
 pub use camera::Camera;
 
 pub mod shader;
 pub use shader::{ Shader, ShaderProgram, null_shader };
+pub mod preprocessor;
+pub mod program_cache;
+pub mod spirv;
+pub mod variants;
+pub use variants::ShaderVariants;
+pub mod pipeline;
+pub use pipeline::ProgramPipeline;
+pub mod compute;
+pub use compute::ComputeProgram;
 pub mod material;
-pub use material::Material;
+pub use material::{ Material, MaterialInstance };
+pub mod pbr;
+pub use pbr::MetallicRoughness;
+pub mod property_block;
+pub use property_block::PropertyBlock;
 
 pub mod mesh;
 pub use mesh::Mesh;
@@ -16,6 +31,19 @@ pub use model::Model;
 
 pub mod texture;
 pub use texture::{ Texture, Sampler };
+pub mod sampler;
+pub use sampler::{ SamplerDescription, SamplerObject };
+pub mod texture_array;
+pub use texture_array::{ Texture2DArray, Texture3D };
+pub mod compressed;
+pub use compressed::CompressedTexture;
+pub mod cubemap;
+pub use cubemap::Cubemap;
+pub mod skybox;
+pub use skybox::Skybox;
+pub mod offscreen;
+pub mod ibl;
+pub use ibl::EnvironmentLighting;
 
 pub mod light;
 
@@ -29,6 +57,10 @@ pub fn load_glfn( video_subsystem:&sdl2::VideoSubsystem ) {
         |symbol|
             video_subsystem.gl_get_proc_address(&symbol) as *const GLvoid
     );
+    spirv::load_with(
+        |symbol|
+            video_subsystem.gl_get_proc_address(&symbol) as *const GLvoid
+    );
 }
 
 pub fn set_clear_color( color:&fmath::types::color::RGB ) {
@@ -0,0 +1,85 @@
+use gl::types::*;
+use crate::debugging::Error;
+use super::Material;
+
+/// Draws fullscreen triangles into texture levels through a temporary framebuffer,
+/// for passes like cubemap conversion and lookup table generation.
+/// Shaders build the triangle from gl_VertexID, see common/cubemap.glsl.
+/// The framebuffer, viewport, depth test and culling in use before
+/// are restored when the pass is dropped.
+pub struct OffscreenPass {
+    framebuffer:GLuint,
+    vao:GLuint,
+    previous_framebuffer:GLint,
+    previous_viewport:[GLint;4],
+    depth_test_enabled:bool,
+    culling_enabled:bool,
+}
+
+impl OffscreenPass {
+    pub fn begin() -> Self {
+        let mut pass = Self {
+            framebuffer: 0, vao: 0,
+            previous_framebuffer: 0, previous_viewport: [0;4],
+            depth_test_enabled: false, culling_enabled: false,
+        };
+        unsafe {
+            gl::GetIntegerv( gl::DRAW_FRAMEBUFFER_BINDING, &mut pass.previous_framebuffer );
+            gl::GetIntegerv( gl::VIEWPORT, pass.previous_viewport.as_mut_ptr() );
+            pass.depth_test_enabled = gl::IsEnabled( gl::DEPTH_TEST ) == gl::TRUE;
+            pass.culling_enabled    = gl::IsEnabled( gl::CULL_FACE )  == gl::TRUE;
+
+            gl::GenFramebuffers( 1, &mut pass.framebuffer );
+            gl::BindFramebuffer( gl::FRAMEBUFFER, pass.framebuffer );
+            gl::GenVertexArrays( 1, &mut pass.vao );
+
+            gl::Disable( gl::DEPTH_TEST );
+            gl::Disable( gl::CULL_FACE );
+        }
+        pass
+    }
+
+    /// Renders into a level of a texture from now on.
+    /// target is GL_TEXTURE_2D or the cubemap face to render into.
+    pub fn attach(
+        &self, target:GLenum, handle:GLuint, level:GLint, width:GLint, height:GLint
+    ) -> Result<(), Error> {
+        unsafe {
+            gl::FramebufferTexture2D( gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, target, handle, level );
+            let status = gl::CheckFramebufferStatus( gl::FRAMEBUFFER );
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err( Error::TextureInvalidImage( format!(
+                    "Can't render to texture {} level {}, framebuffer status {:#06X}!",
+                    handle, level, status
+                ) ) );
+            }
+            gl::Viewport( 0, 0, width.max( 1 ), height.max( 1 ) );
+        }
+        Ok(())
+    }
+
+    /// Sends the material's changed uniforms and draws a fullscreen triangle
+    pub fn draw( &self, material:&mut Material ) {
+        material.use_material();
+        unsafe {
+            gl::BindVertexArray( self.vao );
+            gl::DrawArrays( gl::TRIANGLES, 0, 3 );
+        }
+    }
+}
+
+impl Drop for OffscreenPass {
+    fn drop( &mut self ) {
+        unsafe {
+            gl::BindFramebuffer( gl::FRAMEBUFFER, self.previous_framebuffer as GLuint );
+            gl::DeleteFramebuffers( 1, &self.framebuffer );
+            gl::DeleteVertexArrays( 1, &self.vao );
+            gl::Viewport(
+                self.previous_viewport[0], self.previous_viewport[1],
+                self.previous_viewport[2], self.previous_viewport[3]
+            );
+            if self.depth_test_enabled { gl::Enable( gl::DEPTH_TEST ); }
+            if self.culling_enabled    { gl::Enable( gl::CULL_FACE );  }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use fmath::types::{ Vector3, Vector4, color::RGB };
+use crate::{ Rc, debugging::log };
+use super::{ Material, Texture };
+
+// uniforms of the metallic-roughness shader, see resources/shaders/pbr.shader
+pub const BASE_COLOR:&str                 = "base_color";
+pub const BASE_COLOR_SAMPLER:&str         = "base_color_sampler";
+pub const METALLIC:&str                   = "metallic";
+pub const ROUGHNESS:&str                  = "roughness";
+pub const METALLIC_ROUGHNESS_SAMPLER:&str = "metallic_roughness_sampler";
+pub const NORMAL_SAMPLER:&str             = "normal_sampler";
+pub const NORMAL_SCALE:&str               = "normal_scale";
+pub const OCCLUSION_SAMPLER:&str          = "occlusion_sampler";
+pub const OCCLUSION_STRENGTH:&str         = "occlusion_strength";
+pub const EMISSIVE:&str                   = "emissive";
+pub const EMISSIVE_SAMPLER:&str           = "emissive_sampler";
+// with the PARALLAX_MAPPING keyword, see common/parallax.glsl
+pub const HEIGHT_SAMPLER:&str             = "height_sampler";
+pub const HEIGHT_SCALE:&str               = "height_scale";
+
+/// Inputs of the glTF metallic-roughness model.
+/// Factors multiply their texture, missing textures are white
+/// or a flat normal so the factors are used as they are.
+/// Base color and emissive textures have to be loaded with TextureOptions::set_srgb,
+/// the shader doesn't decode them itself and setting a linear one logs a warning. The rest are linear,
+/// metallic is read from blue and roughness from green.
+/// The height map isn't part of glTF, it's only used by shader
+/// variants with the PARALLAX_MAPPING keyword.
+#[derive(Debug, Clone)]
+pub struct MetallicRoughness {
+    base_color:Vector4,
+    base_color_texture:Option<Rc<Texture>>,
+    metallic:f32,
+    roughness:f32,
+    metallic_roughness_texture:Option<Rc<Texture>>,
+    normal_texture:Option<Rc<Texture>>,
+    normal_scale:f32,
+    occlusion_texture:Option<Rc<Texture>>,
+    occlusion_strength:f32,
+    emissive:Vector3,
+    emissive_texture:Option<Rc<Texture>>,
+    height_texture:Option<Rc<Texture>>,
+    height_scale:f32,
+}
+
+impl Default for MetallicRoughness {
+    /// The glTF defaults, a white fully metallic and fully rough surface
+    fn default() -> Self {
+        Self {
+            base_color: Vector4::new( 1.0, 1.0, 1.0, 1.0 ), base_color_texture: None,
+            metallic: 1.0, roughness: 1.0, metallic_roughness_texture: None,
+            normal_texture: None, normal_scale: 1.0,
+            occlusion_texture: None, occlusion_strength: 1.0,
+            emissive: Vector3::new( 0.0, 0.0, 0.0 ), emissive_texture: None,
+            height_texture: None, height_scale: 0.05,
+        }
+    }
+}
+
+impl MetallicRoughness {
+    pub fn base_color( &self )         -> Vector4 { self.base_color         }
+    pub fn metallic( &self )           -> f32     { self.metallic           }
+    pub fn roughness( &self )          -> f32     { self.roughness          }
+    pub fn normal_scale( &self )       -> f32     { self.normal_scale       }
+    pub fn occlusion_strength( &self ) -> f32     { self.occlusion_strength }
+    pub fn emissive( &self )           -> Vector3 { self.emissive           }
+    pub fn height_scale( &self )       -> f32     { self.height_scale       }
+    pub fn base_color_texture( &self )         -> Option<&Rc<Texture>> { self.base_color_texture.as_ref()         }
+    pub fn metallic_roughness_texture( &self ) -> Option<&Rc<Texture>> { self.metallic_roughness_texture.as_ref() }
+    pub fn normal_texture( &self )             -> Option<&Rc<Texture>> { self.normal_texture.as_ref()             }
+    pub fn occlusion_texture( &self )          -> Option<&Rc<Texture>> { self.occlusion_texture.as_ref()          }
+    pub fn emissive_texture( &self )           -> Option<&Rc<Texture>> { self.emissive_texture.as_ref()           }
+    pub fn height_texture( &self )             -> Option<&Rc<Texture>> { self.height_texture.as_ref()             }
+
+    /// Linear RGBA, alpha is written to the output alpha
+    pub fn set_base_color( &mut self, color:Vector4 ) { self.base_color = color; }
+    pub fn set_metallic( &mut self, metallic:f32 ) { self.metallic = metallic.clamp( 0.0, 1.0 ); }
+    pub fn set_roughness( &mut self, roughness:f32 ) { self.roughness = roughness.clamp( 0.0, 1.0 ); }
+    pub fn set_normal_scale( &mut self, scale:f32 ) { self.normal_scale = scale; }
+    pub fn set_occlusion_strength( &mut self, strength:f32 ) { self.occlusion_strength = strength.clamp( 0.0, 1.0 ); }
+    /// Linear RGB, can go above 1 for brighter emission
+    pub fn set_emissive( &mut self, emissive:Vector3 ) { self.emissive = emissive; }
+    /// Depth of black in the height map, in uv units
+    pub fn set_height_scale( &mut self, scale:f32 ) { self.height_scale = scale.max( 0.0 ); }
+
+    pub fn set_base_color_texture( &mut self, texture:Option<Rc<Texture>> ) {
+        warn_if_linear( texture.as_ref(), "Base color" );
+        self.base_color_texture = texture;
+    }
+    pub fn set_metallic_roughness_texture( &mut self, texture:Option<Rc<Texture>> ) { self.metallic_roughness_texture = texture; }
+    pub fn set_normal_texture( &mut self, texture:Option<Rc<Texture>> ) { self.normal_texture = texture; }
+    pub fn set_occlusion_texture( &mut self, texture:Option<Rc<Texture>> ) { self.occlusion_texture = texture; }
+    pub fn set_emissive_texture( &mut self, texture:Option<Rc<Texture>> ) {
+        warn_if_linear( texture.as_ref(), "Emissive" );
+        self.emissive_texture = texture;
+    }
+    /// White is the surface, darker is deeper
+    pub fn set_height_texture( &mut self, texture:Option<Rc<Texture>> ) { self.height_texture = texture; }
+
+    /// Sets the uniforms the material's shader declares,
+    /// so shaders that only use some of the inputs work too
+    pub fn apply_to( &self, material:&mut Material ) {
+        let textures = [
+            ( BASE_COLOR_SAMPLER,         self.base_color_texture.clone().unwrap_or_else( white_texture ) ),
+            ( METALLIC_ROUGHNESS_SAMPLER, self.metallic_roughness_texture.clone().unwrap_or_else( white_texture ) ),
+            ( NORMAL_SAMPLER,             self.normal_texture.clone().unwrap_or_else( flat_normal_texture ) ),
+            ( OCCLUSION_SAMPLER,          self.occlusion_texture.clone().unwrap_or_else( white_texture ) ),
+            ( EMISSIVE_SAMPLER,           self.emissive_texture.clone().unwrap_or_else( white_texture ) ),
+            ( HEIGHT_SAMPLER,             self.height_texture.clone().unwrap_or_else( white_texture ) ),
+        ];
+        for ( name, texture ) in textures.iter() {
+            if let Ok( uniform ) = material.get_uniform_mut_by_name( name ) {
+                uniform.set_texture2d( texture.clone() );
+            }
+        }
+
+        let factors = [
+            ( METALLIC, self.metallic ), ( ROUGHNESS, self.roughness ),
+            ( NORMAL_SCALE, self.normal_scale ), ( OCCLUSION_STRENGTH, self.occlusion_strength ),
+            ( HEIGHT_SCALE, self.height_scale ),
+        ];
+        for ( name, value ) in factors.iter() {
+            if let Ok( uniform ) = material.get_uniform_mut_by_name( name ) {
+                uniform.set_f32( *value );
+            }
+        }
+
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( BASE_COLOR ) {
+            uniform.set_vector4( self.base_color );
+        }
+        if let Ok( uniform ) = material.get_uniform_mut_by_name( EMISSIVE ) {
+            uniform.set_vector3( self.emissive );
+        }
+    }
+}
+
+/// Color maps that aren't sRGB would be read as linear and render washed out
+fn warn_if_linear( texture:Option<&Rc<Texture>>, input:&str ) {
+    match texture {
+        Some( texture ) if !texture.is_srgb() => log(
+            &format!(
+                "{} texture {} isn't sRGB and will look washed out, load it with TextureOptions::set_srgb.",
+                input, texture.handle()
+            ),
+            "Material | Warning"
+        ),
+        _ => {},
+    }
+}
+
+/// Stands in for missing base color, metallic-roughness, occlusion, emissive and height textures
+pub fn white_texture() -> Rc<Texture> {
+    thread_local!(
+        static WHITE_TEXTURE: Rc<Texture> = Texture::new_color_texture( RGB::new( 255, 255, 255 ) );
+    );
+    WHITE_TEXTURE.with( |t| t.clone() )
+}
+
+/// Tangent space normal pointing straight out of the surface
+pub fn flat_normal_texture() -> Rc<Texture> {
+    thread_local!(
+        static FLAT_NORMAL_TEXTURE: Rc<Texture> = Texture::new_color_texture( RGB::new( 128, 128, 255 ) );
+    );
+    FLAT_NORMAL_TEXTURE.with( |t| t.clone() )
+}
@@ -0,0 +1,250 @@
+use gl::types::*;
+use core::{ fmt, ops::{ Index, IndexMut } };
+use crate::{ Rc, debugging::{ Error, log } };
+use super::{
+    ShaderProgram, Uniform, Material,
+    shader::{ ShaderKind, ALL_SHADER_KINDS },
+    texture::TextureUnitAllocator,
+};
+
+/// Combines separable stage programs at draw time,
+/// so one stage can be swapped without relinking the others.
+///
+/// Each stage program keeps its own uniform values in a Material.
+/// Uniforms looked up by name are read from the first stage program,
+/// in pipeline order, that declares them. Values set through the pipeline
+/// are copied to every other stage declaring the same uniform before the next draw.
+pub struct ProgramPipeline {
+    handle: GLuint,
+    stages: Vec<PipelineStage>,
+    /// Uniforms changed through the pipeline since the last sync
+    changed: Vec<String>,
+}
+
+impl Default for ProgramPipeline {
+    fn default() -> Self { Self::new() }
+}
+
+struct PipelineStage {
+    kinds: Vec<ShaderKind>,
+    material: Material,
+}
+
+impl ProgramPipeline {
+
+    pub fn new() -> Self {
+        let mut handle = 0;
+        unsafe { gl::CreateProgramPipelines( 1, &mut handle ); }
+        Self { handle, stages: Vec::new(), changed: Vec::new() }
+    }
+
+    pub fn handle(&self) -> GLuint { self.handle }
+
+    /// Uses a separable program for the given stages,
+    /// replacing whatever program those stages used before.
+    pub fn set_stages( &mut self, kinds:&[ShaderKind], program:Rc<ShaderProgram> ) -> Result<(), Error> {
+        if !program.is_separable() {
+            return Err( Error::ShaderLinker(
+                format!( "Program {} was not linked as separable!", program.handle() )
+            ) );
+        }
+        if kinds.contains( &ShaderKind::Compute ) {
+            return Err( Error::ShaderLinker(
+                "Compute programs can't be used in a Program Pipeline!".to_owned()
+            ) );
+        }
+
+        self.remove_stages( kinds );
+        unsafe { gl::UseProgramStages( self.handle, stage_bits( kinds ), program.handle() ); }
+
+        match self.stages.iter_mut().find( |stage| Rc::ptr_eq( stage.material.shader(), &program ) ) {
+            Some( stage ) => stage.kinds.extend_from_slice( kinds ),
+            None => self.stages.push( PipelineStage {
+                kinds: kinds.to_vec(),
+                material: Material::new( program ),
+            } ),
+        }
+        self.sort_stages();
+        Ok(())
+    }
+
+    /// Stops using any program for the given stages
+    pub fn clear_stages( &mut self, kinds:&[ShaderKind] ) {
+        self.remove_stages( kinds );
+        unsafe { gl::UseProgramStages( self.handle, stage_bits( kinds ), 0 ); }
+    }
+
+    fn remove_stages( &mut self, kinds:&[ShaderKind] ) {
+        for stage in self.stages.iter_mut() {
+            stage.kinds.retain( |kind| !kinds.contains( kind ) );
+        }
+        self.stages.retain( |stage| !stage.kinds.is_empty() );
+    }
+
+    fn sort_stages( &mut self ) {
+        let first_stage = | stage:&PipelineStage | stage.kinds.iter()
+            .filter_map( |kind| ALL_SHADER_KINDS.iter().position( |k| k == kind ) )
+            .min().unwrap_or( usize::MAX );
+        self.stages.sort_by_key( first_stage );
+    }
+
+    pub fn program( &self, kind:ShaderKind ) -> Option<&Rc<ShaderProgram>> {
+        self.stage( kind ).map( |stage| stage.material.shader() )
+    }
+
+    /// Uniform values of the program used for a stage
+    pub fn stage_material( &self, kind:ShaderKind ) -> Option<&Material> {
+        self.stage( kind ).map( |stage| &stage.material )
+    }
+
+    pub fn stage_material_mut( &mut self, kind:ShaderKind ) -> Option<&mut Material> {
+        self.stages.iter_mut()
+            .find( |stage| stage.kinds.contains( &kind ) )
+            .map( |stage| &mut stage.material )
+    }
+
+    fn stage( &self, kind:ShaderKind ) -> Option<&PipelineStage> {
+        self.stages.iter().find( |stage| stage.kinds.contains( &kind ) )
+    }
+
+    pub fn get_uniform_by_name( &self, name:&str ) -> Result<&Uniform, Error> {
+        for stage in self.stages.iter() {
+            if stage.material.has_uniform( name ) {
+                return stage.material.get_uniform_by_name( name );
+            }
+        }
+        Err( Error::UniformNotFound( format!( "Uniform \"{}\" not found in any pipeline stage!", name ) ) )
+    }
+
+    /// Uniform of the first stage that declares it,
+    /// stages after it get the value on the next use_pipeline
+    pub fn get_uniform_mut_by_name( &mut self, name:&str ) -> Result<&mut Uniform, Error> {
+        let changed = &mut self.changed;
+        for stage in self.stages.iter_mut() {
+            if stage.material.has_uniform( name ) {
+                if !changed.iter().any( |n| n == name ) { changed.push( name.to_owned() ); }
+                return stage.material.get_uniform_mut_by_name( name );
+            }
+        }
+        Err( Error::UniformNotFound( format!( "Uniform \"{}\" not found in any pipeline stage!", name ) ) )
+    }
+
+    /// Copies uniforms changed through the pipeline from the first stage
+    /// that declares them to every other stage that does
+    fn sync_changed_uniforms( &mut self ) {
+        for name in self.changed.drain(..) {
+            let mut stages = self.stages.iter_mut().filter( |stage| stage.material.has_uniform( &name ) );
+            let value = match stages.next().map( |stage| stage.material.get_uniform_by_name( &name ) ) {
+                Some( Ok( value ) ) => value.clone(),
+                _ => continue,
+            };
+            for stage in stages {
+                if let Ok( uniform ) = stage.material.get_uniform_mut_by_name( &name ) {
+                    uniform.copy_value_from( &value );
+                }
+            }
+        }
+    }
+
+    /// Binds the pipeline and sends changed uniforms to each stage program
+    pub fn use_pipeline( &mut self ) {
+        unsafe {
+            // a bound program takes precedence over the bound pipeline
+            gl::UseProgram( 0 );
+            gl::BindProgramPipeline( self.handle );
+        }
+        self.sync_changed_uniforms();
+        // every stage samples from the same set of units
+        let mut units = TextureUnitAllocator::new();
+        for stage in self.stages.iter_mut() {
+            // a hot reloaded program has a new handle
+            if stage.material.refresh_shader() {
+                unsafe {
+                    gl::UseProgramStages(
+                        self.handle, stage_bits( &stage.kinds ),
+                        stage.material.shader().handle()
+                    );
+                }
+            }
+            // routes glUniform* calls to this stage's program
+            unsafe { gl::ActiveShaderProgram( self.handle, stage.material.shader().handle() ); }
+            stage.material.send_uniforms_with_units( &mut units );
+        }
+    }
+
+    /// Sends every uniform regardless of whether it changed,
+    /// needed after another pipeline used the same programs
+    pub fn send_all_uniforms_to_gl( &mut self ) {
+        self.sync_changed_uniforms();
+        let mut units = TextureUnitAllocator::new();
+        for stage in self.stages.iter_mut() {
+            unsafe { gl::ActiveShaderProgram( self.handle, stage.material.shader().handle() ); }
+            stage.material.send_all_uniforms_with_units( &mut units );
+        }
+    }
+
+    /// Checks that the stage programs' interfaces match
+    pub fn validate( &self ) -> Result<(), Error> {
+        unsafe {
+            gl::ValidateProgramPipeline( self.handle );
+            let mut status = 0;
+            gl::GetProgramPipelineiv( self.handle, gl::VALIDATE_STATUS, &mut status );
+            if status != 0 { return Ok(()); }
+
+            let mut len = 0;
+            gl::GetProgramPipelineiv( self.handle, gl::INFO_LOG_LENGTH, &mut len );
+            let mut buffer = vec![0u8;len.max( 1 ) as usize];
+            gl::GetProgramPipelineInfoLog(
+                self.handle, len,
+                core::ptr::null_mut(),
+                buffer.as_mut_ptr() as *mut GLchar
+            );
+            let message = String::from_utf8_lossy( &buffer );
+            let message = message.trim_end_matches( |c:char| c == '\0' || c.is_whitespace() );
+            log( message, &format!( "Program Pipeline {} | Warning", self.handle ) );
+            Err( Error::ShaderLinker( message.to_owned() ) )
+        }
+    }
+
+}
+
+fn stage_bits( kinds:&[ShaderKind] ) -> GLbitfield {
+    kinds.iter().fold( 0, |bits, kind| bits | kind.stage_bit() )
+}
+
+impl Drop for ProgramPipeline {
+    fn drop( &mut self ) {
+        unsafe { gl::DeleteProgramPipelines( 1, &self.handle ) }
+    }
+}
+
+impl Index<&str> for ProgramPipeline {
+    type Output = Uniform;
+
+    fn index( &self, name:&str ) -> &Uniform {
+        match self.get_uniform_by_name( name ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
+impl IndexMut<&str> for ProgramPipeline {
+    fn index_mut( &mut self, name:&str ) -> &mut Uniform {
+        match self.get_uniform_mut_by_name( name ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
+impl fmt::Display for ProgramPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = String::new();
+        for stage in self.stages.iter() {
+            let kinds:Vec<&str> = stage.kinds.iter().map( |kind| kind.msg() ).collect();
+            buffer.push_str( &format!( "   {}: Shader {}\n", kinds.join(", "), stage.material.shader().handle() ) );
+        }
+        write!( f, "Program Pipeline {}\n{}", self.handle, buffer )
+    }
+}
@@ -0,0 +1,219 @@
+use core::fmt;
+use crate::{ Rc, debugging::Error };
+
+/// Loads the source of an included file from its include path
+pub type IncludeLoader = Rc<dyn Fn( &str ) -> Result<String, Error>>;
+
+/// Options for the .shader pre-processor
+#[derive(Clone)]
+pub struct ShaderParseOptions {
+    defines: Vec<( String, String )>,
+    include_loader: Option<IncludeLoader>,
+    source_name: String,
+    separable: bool,
+}
+
+impl Default for ShaderParseOptions {
+    fn default() -> Self {
+        Self {
+            defines: Vec::new(), include_loader: None,
+            source_name: "shader".to_owned(), separable: false
+        }
+    }
+}
+
+impl ShaderParseOptions {
+    pub fn defines(&self) -> &[( String, String )] { &self.defines }
+    pub fn source_name(&self) -> &str { &self.source_name }
+    pub fn separable(&self)   -> bool { self.separable    }
+
+    /// Link as a separable program for use in a ProgramPipeline.
+    /// Separable .shader files may contain any subset of stages.
+    pub fn set_separable( &mut self, separable:bool ) {
+        self.separable = separable;
+    }
+
+    /// Name of the .shader file used in compile error messages
+    pub fn set_source_name( &mut self, name:&str ) {
+        self.source_name = name.to_owned();
+    }
+
+    /// Adds a #define that's inserted after #version in every stage.
+    /// Redefining a name replaces its value.
+    pub fn set_define( &mut self, name:&str, value:&str ) {
+        match self.defines.iter_mut().find( |( n, _ )| n == name ) {
+            Some( ( _, v ) ) => *v = value.to_owned(),
+            None => self.defines.push( ( name.to_owned(), value.to_owned() ) ),
+        }
+    }
+
+    pub fn include_loader(&self) -> Option<&IncludeLoader> { self.include_loader.as_ref() }
+
+    pub fn set_include_loader( &mut self, loader:IncludeLoader ) {
+        self.include_loader = Some( loader );
+    }
+}
+
+const PPD_INCLUDE:&str       = "#include";
+const PPD_PRAGMA_ONCE:&str   = "#pragma once";
+const PPD_VERSION:&str       = "#version";
+const PPD_MULTI_COMPILE:&str = "#pragma multi_compile";
+
+pub fn is_keyword_pragma( line:&str ) -> bool {
+    line.trim_start().starts_with( PPD_MULTI_COMPILE )
+}
+
+/// Collects the keywords declared with `#pragma multi_compile`.
+/// Each keyword can be enabled independently of the others.
+pub fn parse_keywords( src:&str ) -> Vec<String> {
+    let mut keywords:Vec<String> = Vec::new();
+    for line in src.lines().filter( |l| is_keyword_pragma( l ) ) {
+        let declared = line.trim_start()[PPD_MULTI_COMPILE.len()..].split_whitespace();
+        for keyword in declared {
+            if !keywords.iter().any( |k| k == keyword ) {
+                keywords.push( keyword.to_owned() );
+            }
+        }
+    }
+    keywords
+}
+
+/// File and 1-based line a line of generated source came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    file: Rc<str>,
+    line: usize,
+}
+
+impl SourceLocation {
+    pub fn new( file:Rc<str>, line:usize ) -> Self { Self { file, line } }
+    pub fn file(&self) -> &str  { &self.file }
+    pub fn line(&self) -> usize { self.line  }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}:{}", self.file, self.line )
+    }
+}
+
+/// Maps each line of a generated stage source back to where it came from.
+/// Lines the pre-processor generated itself, like #defines, map to None.
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    locations: Vec<Option<SourceLocation>>,
+}
+
+impl LineMap {
+    pub fn new() -> Self { Self { locations: Vec::new() } }
+
+    pub fn len(&self) -> usize { self.locations.len() }
+    pub fn is_empty(&self) -> bool { self.locations.is_empty() }
+
+    pub fn push( &mut self, location:Option<SourceLocation> ) {
+        self.locations.push( location );
+    }
+
+    pub fn insert( &mut self, idx:usize, location:Option<SourceLocation> ) {
+        self.locations.insert( idx, location );
+    }
+
+    /// Looks up a 1-based line of generated source
+    pub fn lookup( &self, line:usize ) -> Option<&SourceLocation> {
+        if line == 0 { return None; }
+        self.locations.get( line - 1 ).and_then( |location| location.as_ref() )
+    }
+}
+
+/// Resolves #include directives in a stage's source.
+/// Files containing `#pragma once` are only included once per stage.
+/// `first_line` is the 1-based line in the .shader file the stage starts at.
+pub fn expand_includes(
+    lines:&[&str], first_line:usize, options:&ShaderParseOptions
+) -> Result<( Vec<String>, LineMap ), Error> {
+    let mut output:Vec<String> = Vec::with_capacity( lines.len() );
+    let mut line_map = LineMap::new();
+    let mut stack:Vec<String>  = Vec::new();
+    let mut once:Vec<String>   = Vec::new();
+    let file:Rc<str> = Rc::from( options.source_name() );
+    expand_lines(
+        lines, &file, first_line, options,
+        &mut stack, &mut once, &mut output, &mut line_map
+    )?;
+    Ok( ( output, line_map ) )
+}
+
+fn expand_lines(
+    lines:&[&str], file:&Rc<str>, first_line:usize, options:&ShaderParseOptions,
+    stack:&mut Vec<String>, once:&mut Vec<String>,
+    output:&mut Vec<String>, line_map:&mut LineMap
+) -> Result<(), Error> {
+    for ( idx, line ) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed == PPD_PRAGMA_ONCE { continue; }
+        if !trimmed.starts_with( PPD_INCLUDE ) {
+            output.push( line.to_string() );
+            line_map.push( Some( SourceLocation::new( file.clone(), first_line + idx ) ) );
+            continue;
+        }
+
+        let include_path = parse_include_path( trimmed )?;
+
+        if stack.contains( &include_path ) {
+            let mut chain = stack.clone();
+            chain.push( include_path );
+            return Err( Error::ShaderParse(
+                format!( "Include cycle detected! {}", chain.join(" -> ") )
+            ) );
+        }
+        if once.contains( &include_path ) { continue; }
+
+        let loader = options.include_loader.as_ref()
+            .ok_or( Error::ShaderParse(
+                format!( "Found #include \"{}\" but no include loader was given!", include_path )
+            ) )?;
+        let src = loader( &include_path )?;
+        let included_lines:Vec<&str> = src.lines().collect();
+
+        if included_lines.iter().any( |l| l.trim() == PPD_PRAGMA_ONCE ) {
+            once.push( include_path.clone() );
+        }
+
+        let included_file:Rc<str> = Rc::from( include_path.as_str() );
+        stack.push( include_path );
+        expand_lines(
+            &included_lines, &included_file, 1, options,
+            stack, once, output, line_map
+        )?;
+        stack.pop();
+    }
+    Ok(())
+}
+
+fn parse_include_path( line:&str ) -> Result<String, Error> {
+    let path = line[PPD_INCLUDE.len()..].trim();
+    if path.len() < 2 || !path.starts_with('"') || !path.ends_with('"') {
+        return Err( Error::ShaderParse(
+            format!( "Include path must be in quotes! \"{}\"", line )
+        ) );
+    }
+    let path = path[1..path.len() - 1]
+        .replace( "\\", "/" );
+    Ok( path.trim_start_matches( "./" ).to_owned() )
+}
+
+/// Inserts #defines after the #version directive,
+/// or at the top if there's no #version.
+pub fn insert_defines( lines:&mut Vec<String>, line_map:&mut LineMap, defines:&[( String, String )] ) {
+    if defines.is_empty() { return; }
+
+    let insert_at = match lines.iter().position( |l| l.trim_start().starts_with( PPD_VERSION ) ) {
+        Some( idx ) => idx + 1,
+        None => 0,
+    };
+
+    for ( offset, ( name, value ) ) in defines.iter().enumerate() {
+        lines.insert( insert_at + offset, format!( "#define {} {}", name, value ) );
+        line_map.insert( insert_at + offset, None );
+    }
+}
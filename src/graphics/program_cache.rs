@@ -0,0 +1,133 @@
+use gl::types::*;
+use core::cell::Cell;
+use std::path::PathBuf;
+use crate::{ Rc, resources, debugging::{ Error, log, gl_str } };
+use super::{ ShaderProgram, shader::{ Shader, StageSource } };
+
+/// Directory inside the cache directory that program binaries are saved to
+pub const PROGRAM_CACHE_DIRECTORY:&str = "programs";
+
+const CACHE_MAGIC:&[u8;4] = b"GLPB";
+// magic + binary format
+const CACHE_HEADER_SIZE:usize = 8;
+
+thread_local!(
+    static CACHE_ENABLED: Cell<bool> = Cell::new( true );
+);
+
+pub fn set_enabled( enabled:bool ) { CACHE_ENABLED.with( |e| e.set( enabled ) ) }
+pub fn is_enabled() -> bool { CACHE_ENABLED.with( |e| e.get() ) }
+
+/// Loads the linked program from the cache if the same stages were linked
+/// by the same driver before, otherwise compiles, links and caches them.
+/// Binaries the driver rejects are recompiled and overwritten.
+pub fn load_or_link( stages:&[StageSource], separable:bool ) -> Result<Rc<ShaderProgram>, Error> {
+    if !is_supported() { return link_stages( stages, separable ); }
+
+    let path = cache_path( stages, separable );
+    match load_cached( &path, separable ) {
+        Some( program ) => return Ok( program ),
+        None => {},
+    }
+
+    let program = link_stages( stages, separable )?;
+    save_cached( &path, &program );
+    Ok( program )
+}
+
+fn link_stages( stages:&[StageSource], separable:bool ) -> Result<Rc<ShaderProgram>, Error> {
+    let mut shaders = Vec::with_capacity( stages.len() );
+    for stage in stages.iter() {
+        shaders.push( Shader::from_stage( stage )? );
+    }
+    if separable { ShaderProgram::separable_from_shaders( &shaders ) }
+    else { ShaderProgram::from_shaders( &shaders ) }
+}
+
+fn is_supported() -> bool {
+    if !is_enabled() { return false; }
+    let mut format_count = 0;
+    unsafe { gl::GetIntegerv( gl::NUM_PROGRAM_BINARY_FORMATS, &mut format_count ); }
+    format_count > 0
+}
+
+fn load_cached( path:&PathBuf, separable:bool ) -> Option<Rc<ShaderProgram>> {
+    // a missing file is just a cache miss
+    let bytes = resources::load_bytes_path( path ).ok()?;
+
+    if bytes.len() <= CACHE_HEADER_SIZE || &bytes[0..4] != CACHE_MAGIC {
+        log(
+            &format!( "Ignoring malformed program binary {}", path.display() ),
+            "Program Cache | Warning"
+        );
+        return None;
+    }
+
+    let format = GLenum::from_le_bytes( [ bytes[4], bytes[5], bytes[6], bytes[7] ] );
+    match ShaderProgram::from_binary( format, &bytes[CACHE_HEADER_SIZE..], separable ) {
+        Ok( program ) => Some( program ),
+        Err( e ) => {
+            log(
+                &format!( "{} Recompiling {}", e.msg(), path.display() ),
+                "Program Cache | Warning"
+            );
+            None
+        },
+    }
+}
+
+fn save_cached( path:&PathBuf, program:&ShaderProgram ) {
+    let ( format, binary ) = match program.binary() {
+        Some( binary ) => binary,
+        None => return,
+    };
+
+    let mut bytes = Vec::with_capacity( CACHE_HEADER_SIZE + binary.len() );
+    bytes.extend_from_slice( CACHE_MAGIC );
+    bytes.extend_from_slice( &format.to_le_bytes() );
+    bytes.extend_from_slice( &binary );
+
+    match resources::save_bytes_path( path, &bytes ) {
+        Ok(()) => log(
+            &format!( "Saved program binary {}", path.display() ),
+            "Program Cache"
+        ),
+        Err( e ) => log(
+            &format!( "Failed to save program binary! {}", e.msg() ),
+            "Program Cache | Warning"
+        ),
+    }
+}
+
+fn cache_path( stages:&[StageSource], separable:bool ) -> PathBuf {
+    resources::cache_path_from_local_path(
+        &format!( "{}/{:016x}.bin", PROGRAM_CACHE_DIRECTORY, cache_key( stages, separable ) )
+    )
+}
+
+/// Hash of the generated stage sources, how they're linked and the driver that links them
+fn cache_key( stages:&[StageSource], separable:bool ) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    hash = fnv1a( hash, &[ separable as u8 ] );
+    for stage in stages.iter() {
+        hash = fnv1a( hash, stage.kind().directive().as_bytes() );
+        hash = fnv1a( hash, stage.source().as_bytes() );
+    }
+    for name in [ gl::VENDOR, gl::RENDERER, gl::VERSION ].iter() {
+        hash = fnv1a( hash, gl_str( name ).as_bytes() );
+    }
+    hash
+}
+
+pub(crate) const FNV_OFFSET_BASIS:u64 = 0xcbf29ce484222325;
+const FNV_PRIME:u64        = 0x100000001b3;
+
+/// FNV-1a, used instead of std's hasher because its output has to stay the same between builds.
+/// Each field is terminated with a zero byte so field boundaries affect the hash.
+pub(crate) fn fnv1a( mut hash:u64, bytes:&[u8] ) -> u64 {
+    for byte in bytes.iter().chain( [0u8].iter() ) {
+        hash ^= *byte as u64;
+        hash  = hash.wrapping_mul( FNV_PRIME );
+    }
+    hash
+}
@@ -0,0 +1,152 @@
+use core::{ fmt, sync::atomic::{ AtomicU64, Ordering } };
+use fmath::types::*;
+use crate::Rc;
+use super::{ Uniform, Texture, Sampler, sampler::SamplerObject, texture::{ TextureObject, SamplerKind } };
+use crate::debugging::log;
+
+static NEXT_BLOCK_ID:AtomicU64 = AtomicU64::new( 1 );
+
+fn next_block_id() -> u64 { NEXT_BLOCK_ID.fetch_add( 1, Ordering::Relaxed ) }
+
+/// Uniform values applied on top of a Material for a single draw,
+/// without changing the material itself.
+///
+/// Values are set by uniform path, like `glossiness` or `lights[2].color`,
+/// and are checked against the material's uniform types when applied.
+/// Materials remember which block and revision each overridden value came from,
+/// so only values that changed since this block last set them are resent.
+pub struct PropertyBlock {
+    id: u64,
+    revision: u64,
+    properties: Vec<Property>,
+}
+
+pub struct Property {
+    path: String,
+    value: Uniform,
+    revision: u64,
+}
+
+impl Property {
+    pub fn path(&self)  -> &str     { &self.path  }
+    pub fn value(&self) -> &Uniform { &self.value }
+    /// The block's revision when the value was last changed
+    pub fn revision(&self) -> u64   { self.revision }
+}
+
+impl PropertyBlock {
+    pub fn new() -> Self {
+        Self { id: next_block_id(), revision: 0, properties: Vec::new() }
+    }
+
+    /// Identifies the block when a material checks where its values came from
+    pub fn id(&self) -> u64 { self.id }
+    pub fn properties(&self) -> &[Property] { &self.properties }
+    pub fn is_empty(&self) -> bool { self.properties.is_empty() }
+
+    pub fn get( &self, path:&str ) -> Option<&Uniform> {
+        self.properties.iter().find( |p| p.path == path ).map( |p| &p.value )
+    }
+
+    /// Gets a value to modify in place, marking it to be resent
+    pub fn get_mut( &mut self, path:&str ) -> Option<&mut Uniform> {
+        self.revision += 1;
+        match self.properties.iter_mut().find( |p| p.path == path ) {
+            Some( property ) => {
+                property.revision = self.revision;
+                Some( &mut property.value )
+            },
+            None => None,
+        }
+    }
+
+    /// Sets a value, the uniform's location doesn't matter
+    pub fn set( &mut self, path:&str, value:Uniform ) {
+        self.revision += 1;
+        let revision = self.revision;
+        match self.properties.iter_mut().find( |p| p.path == path ) {
+            Some( property ) => {
+                property.value    = value;
+                property.revision = revision;
+            },
+            None => self.properties.push( Property { path: path.to_owned(), value, revision } ),
+        }
+    }
+
+    pub fn set_f32( &mut self, path:&str, v:f32 )             { self.set( path, Uniform::Float32( v, -1 ) ) }
+    pub fn set_vector2( &mut self, path:&str, v:Vector2 )     { self.set( path, Uniform::Float32Vec2( v, -1 ) ) }
+    pub fn set_vector3( &mut self, path:&str, v:Vector3 )     { self.set( path, Uniform::Float32Vec3( v, -1 ) ) }
+    pub fn set_vector4( &mut self, path:&str, v:Vector4 )     { self.set( path, Uniform::Float32Vec4( v, -1 ) ) }
+    pub fn set_rgb( &mut self, path:&str, v:color::RGB )      { self.set_vector3( path, v.as_vector3() ) }
+    /// Linear RGBA, for vec4 colors whose alpha isn't 1
+    pub fn set_rgba( &mut self, path:&str, v:[f32;4] )        { self.set_vector4( path, Vector4::new( v[0], v[1], v[2], v[3] ) ) }
+    pub fn set_f64( &mut self, path:&str, v:f64 )             { self.set( path, Uniform::Float64( v, -1 ) ) }
+    pub fn set_i32( &mut self, path:&str, v:i32 )             { self.set( path, Uniform::Int32( v, -1 ) ) }
+    pub fn set_u32( &mut self, path:&str, v:u32 )             { self.set( path, Uniform::UInt32( v, -1 ) ) }
+    pub fn set_bool( &mut self, path:&str, v:bool )           { self.set( path, Uniform::Bool( v, -1 ) ) }
+    pub fn set_matrix3x3( &mut self, path:&str, v:Matrix3x3 ) { self.set( path, Uniform::Float32Mat3( v, -1 ) ) }
+    pub fn set_matrix4x4( &mut self, path:&str, v:Matrix4x4 ) { self.set( path, Uniform::Float32Mat4( v, -1 ) ) }
+    pub fn set_texture2d( &mut self, path:&str, texture:Rc<Texture> ) {
+        self.set( path, Uniform::Sampler2D( ( texture, Sampler::empty() ), -1 ) )
+    }
+    /// Sets a sampler3D, samplerCube, sampler2DArray or samplerCubeArray from the texture's target
+    pub fn set_sampler_texture( &mut self, path:&str, texture:Rc<dyn TextureObject> ) {
+        match SamplerKind::from_texture_target( texture.target() ) {
+            Some( kind ) => self.set( path, Uniform::Sampler( ( kind, Some( texture ), Sampler::empty() ), -1 ) ),
+            None => log(
+                &format!( "No sampler type samples textures with target {:#06X}!", texture.target() ),
+                "Property Block | Warning"
+            ),
+        }
+    }
+
+    /// Samples the texture set at path with a sampler object.
+    /// None goes back to the texture's own parameters, even where the material has a sampler object,
+    /// while a texture set without calling this keeps the material's one.
+    pub fn set_sampler_object( &mut self, path:&str, object:Option<Rc<SamplerObject>> ) {
+        match self.get_mut( path ) {
+            Some( value ) => value.set_sampler_object( object ),
+            None => log(
+                &format!( "No texture is set at \"{}\" to sample with a sampler object!", path ),
+                "Property Block | Warning"
+            ),
+        }
+    }
+
+    /// Materials restore the values removed properties overrode the next time the block is applied
+    pub fn remove( &mut self, path:&str ) {
+        self.properties.retain( |p| p.path != path );
+    }
+
+    pub fn clear( &mut self ) {
+        self.properties.clear();
+    }
+}
+
+impl Default for PropertyBlock {
+    fn default() -> Self { Self::new() }
+}
+
+impl Clone for PropertyBlock {
+    /// Clones get their own id, applying one says nothing about the other
+    fn clone( &self ) -> Self {
+        Self {
+            id: next_block_id(), revision: self.revision,
+            properties: self.properties.iter().map( |p| Property {
+                path: p.path.clone(), value: p.value.clone(), revision: p.revision
+            } ).collect(),
+        }
+    }
+}
+
+impl fmt::Display for PropertyBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = String::new();
+        for property in self.properties.iter() {
+            buffer.push_str( &format!(
+                "   {}: {} {}\n", property.path, property.value.type_name(), property.value.format_data()
+            ) );
+        }
+        write!( f, "Property Block {}\n{}", self.id, buffer )
+    }
+}
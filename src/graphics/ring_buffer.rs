@@ -0,0 +1,254 @@
+use gl::types::*;
+use fmath::types::*;
+use crate::debugging::log;
+use core::fmt;
+
+/// Number of frames the CPU is allowed to run ahead of the GPU
+pub const DEFAULT_FRAMES_IN_FLIGHT:usize = 3;
+
+/// Per-frame ring buffer for uniform data that changes every draw.
+///
+/// Each frame gets its own segment of the buffer. Data is sub-allocated
+/// from the current segment and bound with `glBindBufferRange`.
+/// When `glBufferStorage` is available the buffer is persistently mapped
+/// and a fence guards each segment, otherwise data is written with `glBufferSubData`.
+pub struct UniformRingBuffer {
+    ubo: GLuint,
+
+    frame_size:  usize,
+    frame_count: usize,
+    frame_index: usize,
+    cursor:      usize,
+    alignment:   usize,
+
+    mapped: *mut u8,
+    fences: Vec<GLsync>,
+}
+
+impl UniformRingBuffer {
+    pub fn new( frame_size:usize, frame_count:usize ) -> Self {
+        let alignment = {
+            let mut alignment = 0;
+            unsafe { gl::GetIntegerv( gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut alignment ); }
+            ( alignment.max( 1 ) ) as usize
+        };
+        let frame_size  = align_to( frame_size, alignment );
+        let frame_count = frame_count.max( 1 );
+        let total_size  = ( frame_size * frame_count ) as GLsizeiptr;
+
+        let mut ubo = 0;
+        let mut mapped = core::ptr::null_mut();
+        unsafe {
+            gl::GenBuffers( 1, &mut ubo );
+            gl::BindBuffer( gl::UNIFORM_BUFFER, ubo );
+
+            if gl::BufferStorage::is_loaded() {
+                let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+                // dynamic storage keeps glBufferSubData working if mapping fails
+                gl::BufferStorage(
+                    gl::UNIFORM_BUFFER, total_size,
+                    core::ptr::null(), flags | gl::DYNAMIC_STORAGE_BIT
+                );
+                mapped = gl::MapBufferRange(
+                    gl::UNIFORM_BUFFER, 0, total_size, flags
+                ) as *mut u8;
+                if mapped.is_null() {
+                    log(
+                        "Failed to map the buffer persistently, writing with glBufferSubData instead.",
+                        "Uniform Ring Buffer | Warning"
+                    );
+                }
+            } else {
+                gl::BufferData(
+                    gl::UNIFORM_BUFFER, total_size,
+                    core::ptr::null(), gl::STREAM_DRAW
+                );
+            }
+        }
+
+        let result = Self {
+            ubo, frame_size, frame_count,
+            frame_index: 0, cursor: 0, alignment,
+            mapped,
+            fences: vec![ core::ptr::null(); frame_count ],
+        };
+
+        log(
+            &format!( "Created {}", result ),
+            "Uniform Ring Buffer"
+        );
+
+        result
+    }
+
+    pub fn handle(&self)        -> GLuint { self.ubo         }
+    pub fn frame_size(&self)    -> usize  { self.frame_size  }
+    pub fn frame_count(&self)   -> usize  { self.frame_count }
+    pub fn alignment(&self)     -> usize  { self.alignment   }
+    pub fn is_persistent(&self) -> bool   { !self.mapped.is_null() }
+
+    /// Moves on to the next frame segment,
+    /// waiting for the GPU if it's still reading from it.
+    pub fn begin_frame( &mut self ) {
+        self.frame_index = ( self.frame_index + 1 ) % self.frame_count;
+        self.cursor = 0;
+
+        let fence = self.fences[self.frame_index];
+        if fence.is_null() { return; }
+
+        unsafe {
+            loop {
+                let result = gl::ClientWaitSync( fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT_NS );
+                match result {
+                    gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => break,
+                    gl::TIMEOUT_EXPIRED => continue,
+                    _ => {
+                        log(
+                            &format!( "Waiting on fence for frame {} failed!", self.frame_index ),
+                            "Uniform Ring Buffer | Warning"
+                        );
+                        break;
+                    }
+                }
+            }
+            gl::DeleteSync( fence );
+        }
+        self.fences[self.frame_index] = core::ptr::null();
+    }
+
+    /// Places a fence after the commands that read from the current frame segment.
+    pub fn end_frame( &mut self ) {
+        if !self.is_persistent() { return; }
+        unsafe {
+            let previous = self.fences[self.frame_index];
+            if !previous.is_null() { gl::DeleteSync( previous ); }
+            self.fences[self.frame_index] = gl::FenceSync( gl::SYNC_GPU_COMMANDS_COMPLETE, 0 );
+        }
+    }
+
+    /// Copies data into the current frame segment.
+    /// Returns None if the segment is out of space.
+    pub fn allocate( &mut self, data:&[u8] ) -> Option<RingAllocation> {
+        let size = data.len();
+        if self.cursor + size > self.frame_size {
+            log(
+                &format!(
+                    "Frame segment is out of space! Requested {} bytes with {} bytes left.",
+                    size, self.frame_size - self.cursor
+                ),
+                "Uniform Ring Buffer | Warning"
+            );
+            return None;
+        }
+
+        let offset = ( self.frame_index * self.frame_size ) + self.cursor;
+        unsafe {
+            if self.is_persistent() {
+                core::ptr::copy_nonoverlapping(
+                    data.as_ptr(), self.mapped.add( offset ), size
+                );
+            } else {
+                gl::BindBuffer( gl::UNIFORM_BUFFER, self.ubo );
+                gl::BufferSubData(
+                    gl::UNIFORM_BUFFER, offset as GLintptr,
+                    size as GLsizeiptr,
+                    data.as_ptr() as *const GLvoid
+                );
+            }
+        }
+        self.cursor = align_to( self.cursor + size, self.alignment );
+
+        Some( RingAllocation { offset, size } )
+    }
+
+    pub fn bind_range( &self, buffer_point:GLuint, allocation:&RingAllocation ) {
+        unsafe {
+            gl::BindBufferRange(
+                gl::UNIFORM_BUFFER, buffer_point, self.ubo,
+                allocation.offset as GLintptr,
+                allocation.size as GLsizeiptr
+            );
+        }
+    }
+
+    /// Allocates data and binds it to the buffer point in one go.
+    pub fn push( &mut self, data:&[u8], buffer_point:GLuint ) -> Option<RingAllocation> {
+        let allocation = self.allocate( data )?;
+        self.bind_range( buffer_point, &allocation );
+        Some( allocation )
+    }
+
+}
+
+impl Drop for UniformRingBuffer {
+    fn drop( &mut self ) {
+        unsafe {
+            for fence in self.fences.iter() {
+                if !fence.is_null() { gl::DeleteSync( *fence ); }
+            }
+            if self.is_persistent() {
+                gl::BindBuffer( gl::UNIFORM_BUFFER, self.ubo );
+                gl::UnmapBuffer( gl::UNIFORM_BUFFER );
+            }
+            gl::DeleteBuffers( 1, &self.ubo );
+        }
+    }
+}
+
+impl fmt::Display for UniformRingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.is_persistent() { "Persistent Mapped" } else { "Buffer Sub Data" };
+        write!( f, "Uniform Ring Buffer {} | {} frames of {} bytes, alignment: {} mode: {}",
+            self.handle(), self.frame_count(), self.frame_size(), self.alignment(), mode
+        )
+    }
+}
+
+/// Region of a ring buffer written to this frame
+#[derive(Debug, Clone, Copy)]
+pub struct RingAllocation {
+    offset: usize,
+    size:   usize,
+}
+
+impl RingAllocation {
+    pub fn offset(&self) -> usize { self.offset }
+    pub fn size(&self)   -> usize { self.size   }
+}
+
+/// Per-draw data matching the std140 `Object` block
+pub struct ObjectData {
+    bytes: [u8;OBJECT_DATA_SIZE],
+}
+
+const OBJECT_MODEL_BYTE_OFFSET:usize  = 0;
+const OBJECT_NORMAL_BYTE_OFFSET:usize = 64;
+pub const OBJECT_DATA_SIZE:usize      = 112;
+
+impl ObjectData {
+    pub fn new( model:&Matrix4x4 ) -> Self {
+        let mut bytes = [0u8;OBJECT_DATA_SIZE];
+
+        bytes[OBJECT_MODEL_BYTE_OFFSET..OBJECT_NORMAL_BYTE_OFFSET]
+            .copy_from_slice( &model.to_le_bytes() );
+
+        // std140 pads each mat3 column to a vec4
+        let normal = Matrix3x3::new_normal_matrix( model ).unwrap_or( Matrix3x3::new_zero() );
+        for ( column_idx, column ) in normal.as_array().chunks( 3 ).enumerate() {
+            for ( row_idx, value ) in column.iter().enumerate() {
+                let offset = OBJECT_NORMAL_BYTE_OFFSET + ( column_idx * 16 ) + ( row_idx * 4 );
+                bytes[offset..offset + 4].copy_from_slice( &value.to_le_bytes() );
+            }
+        }
+
+        Self { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] { &self.bytes }
+}
+
+const FENCE_TIMEOUT_NS:GLuint64 = 1_000_000;
+
+fn align_to( value:usize, alignment:usize ) -> usize {
+    value.div_ceil( alignment ) * alignment
+}
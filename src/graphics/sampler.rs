@@ -0,0 +1,267 @@
+use gl::types::*;
+use crate::{ Rc, debugging::log };
+use core::{ fmt, cell::RefCell };
+use std::collections::HashMap;
+use fmath::types::color::RGB;
+use super::{
+    TestKind,
+    texture::{ TextureOptions, TextureWrapping, TextureFiltering, MipmapFiltering },
+};
+
+// anisotropic filtering is core since 4.6, before that it's EXT_texture_filter_anisotropic
+const TEXTURE_MAX_ANISOTROPY:GLenum     = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY:GLenum = 0x84FF;
+
+/// How a texture is sampled, independent of the texture itself.
+/// Every texture keeps the filtering and wrapping of its TextureOptions,
+/// a sampler object bound to the same unit overrides them.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDescription {
+    wrapping_x:TextureWrapping, wrapping_y:TextureWrapping, wrapping_z:TextureWrapping,
+    min_filtering:MipmapFiltering, mag_filtering:TextureFiltering,
+    max_anisotropy:f32,
+    lod_bias:f32,
+    min_lod:f32, max_lod:f32,
+    border_color:[f32;4],
+    depth_compare:Option<TestKind>,
+}
+
+impl Default for SamplerDescription {
+    /// GL's defaults, apart from trilinear filtering
+    fn default() -> Self {
+        Self {
+            wrapping_x: TextureWrapping::Repeat,
+            wrapping_y: TextureWrapping::Repeat,
+            wrapping_z: TextureWrapping::Repeat,
+            min_filtering: MipmapFiltering::LinearLinear, mag_filtering: TextureFiltering::Linear,
+            max_anisotropy: 1.0,
+            lod_bias: 0.0,
+            min_lod: -1000.0, max_lod: 1000.0,
+            border_color: [0.0;4],
+            depth_compare: None,
+        }
+    }
+}
+
+impl SamplerDescription {
+
+    /// Samples the way a texture created with the options does
+    pub fn from_texture_options( options:&TextureOptions ) -> Self {
+        let mut description = Self {
+            wrapping_x: options.wrapping_x(),
+            wrapping_y: options.wrapping_y(),
+            wrapping_z: options.wrapping_z(),
+            min_filtering: options.min_filtering(),
+            mag_filtering: options.mag_filtering(),
+            ..Self::default()
+        };
+        if let Some( color ) = options.border_color() {
+            description.set_border_color( color );
+        }
+        description
+    }
+
+    /// Compares against a depth texture, for sampler2DShadow and samplerCubeShadow,
+    /// clamped to a white border so nothing outside the shadow map is in shadow
+    pub fn shadow( test:TestKind ) -> Self {
+        let mut description = Self::default();
+        description.set_wrapping( TextureWrapping::ClampToBorder );
+        description.set_border_color( RGB::new_white() );
+        // depth maps have a single level, a mipmapped filter would leave them incomplete
+        description.set_min_filtering( MipmapFiltering::Linear );
+        description.set_depth_compare( Some( test ) );
+        description
+    }
+
+    pub fn wrapping_x( &self )     -> TextureWrapping  { self.wrapping_x     }
+    pub fn wrapping_y( &self )     -> TextureWrapping  { self.wrapping_y     }
+    pub fn wrapping_z( &self )     -> TextureWrapping  { self.wrapping_z     }
+    pub fn min_filtering( &self )  -> MipmapFiltering  { self.min_filtering  }
+    pub fn mag_filtering( &self )  -> TextureFiltering { self.mag_filtering  }
+    pub fn max_anisotropy( &self ) -> f32              { self.max_anisotropy }
+    pub fn lod_bias( &self )       -> f32              { self.lod_bias       }
+    pub fn min_lod( &self )        -> f32              { self.min_lod        }
+    pub fn max_lod( &self )        -> f32              { self.max_lod        }
+    pub fn border_color( &self )   -> [f32;4]          { self.border_color   }
+    pub fn depth_compare( &self )  -> Option<TestKind> { self.depth_compare  }
+
+    pub fn set_wrapping( &mut self, wrapping:TextureWrapping ) {
+        self.wrapping_x = wrapping;
+        self.wrapping_y = wrapping;
+        self.wrapping_z = wrapping;
+    }
+
+    pub fn set_wrapping_x( &mut self, wrapping:TextureWrapping ) {
+        self.wrapping_x = wrapping;
+    }
+
+    pub fn set_wrapping_y( &mut self, wrapping:TextureWrapping ) {
+        self.wrapping_y = wrapping;
+    }
+
+    pub fn set_wrapping_z( &mut self, wrapping:TextureWrapping ) {
+        self.wrapping_z = wrapping;
+    }
+
+    pub fn set_min_filtering( &mut self, filtering:MipmapFiltering ) {
+        self.min_filtering = filtering;
+    }
+
+    pub fn set_mag_filtering( &mut self, filtering:TextureFiltering ) {
+        self.mag_filtering = filtering;
+    }
+
+    /// Samples up to this many times along the direction a texture is viewed at an angle.
+    /// 1 turns it off, drivers usually go up to 16 and higher values are clamped to that.
+    pub fn set_max_anisotropy( &mut self, max_anisotropy:f32 ) {
+        self.max_anisotropy = max_anisotropy.max( 1.0 );
+    }
+
+    /// Added to the mip level picked for every sample, positive values blur
+    pub fn set_lod_bias( &mut self, lod_bias:f32 ) {
+        self.lod_bias = lod_bias;
+    }
+
+    /// Limits sampling to mip levels from min_lod to max_lod
+    pub fn set_lod_range( &mut self, min_lod:f32, max_lod:f32 ) {
+        self.min_lod = min_lod;
+        self.max_lod = max_lod;
+    }
+
+    /// Color outside the texture with TextureWrapping::ClampToBorder
+    pub fn set_border_color( &mut self, color:RGB ) {
+        self.border_color = color.as_array_rgba_f32();
+    }
+
+    pub fn set_border_color_rgba( &mut self, color:[f32;4] ) {
+        self.border_color = color;
+    }
+
+    /// Some test makes shadow samplers return how much of the filtered area passes it
+    /// against the reference depth, None samples depth as a plain value
+    pub fn set_depth_compare( &mut self, depth_compare:Option<TestKind> ) {
+        self.depth_compare = depth_compare;
+    }
+
+    /// Everything that tells two descriptions apart, floats compared by bits
+    fn key( &self ) -> SamplerKey {
+        (
+            [ self.wrapping_x.as_glint(), self.wrapping_y.as_glint(), self.wrapping_z.as_glint() ],
+            [ self.min_filtering.as_glint(), self.mag_filtering.as_glint() ],
+            [ self.max_anisotropy, self.lod_bias, self.min_lod, self.max_lod ].map( f32::to_bits ),
+            self.border_color.map( f32::to_bits ),
+            self.depth_compare.map( |test| test.as_glenum() ),
+        )
+    }
+}
+
+type SamplerKey = ( [GLint;3], [GLint;2], [u32;4], [u32;4], Option<GLenum> );
+
+thread_local!(
+    static SAMPLER_CACHE: RefCell<HashMap<SamplerKey, Rc<SamplerObject>>> = RefCell::new( HashMap::new() );
+);
+
+/// A GL sampler object, shared between every slot that asks for the same description
+#[derive(Debug)]
+pub struct SamplerObject {
+    handle:GLuint,
+    description:SamplerDescription,
+}
+
+impl SamplerObject {
+
+    /// The sampler object for a description, created the first time it's asked for
+    pub fn cached( description:&SamplerDescription ) -> Rc<Self> {
+        let key = description.key();
+        SAMPLER_CACHE.with( |cache| {
+            cache.borrow_mut().entry( key )
+                .or_insert_with( || Rc::new( Self::create( description ) ) )
+                .clone()
+        } )
+    }
+
+    fn create( description:&SamplerDescription ) -> Self {
+        let mut handle = 0;
+        unsafe {
+
+            gl::GenSamplers( 1, &mut handle );
+
+            gl::SamplerParameteri( handle, gl::TEXTURE_WRAP_S, description.wrapping_x.as_glint() );
+            gl::SamplerParameteri( handle, gl::TEXTURE_WRAP_T, description.wrapping_y.as_glint() );
+            gl::SamplerParameteri( handle, gl::TEXTURE_WRAP_R, description.wrapping_z.as_glint() );
+            gl::SamplerParameteri( handle, gl::TEXTURE_MIN_FILTER, description.min_filtering.as_glint() );
+            gl::SamplerParameteri( handle, gl::TEXTURE_MAG_FILTER, description.mag_filtering.as_glint() );
+
+            if description.max_anisotropy > 1.0 {
+                let mut max_supported = 1.0;
+                gl::GetFloatv( MAX_TEXTURE_MAX_ANISOTROPY, &mut max_supported );
+                if description.max_anisotropy > max_supported {
+                    log(
+                        &format!(
+                            "Anisotropy {} is above the {} this driver supports, clamping it.",
+                            description.max_anisotropy, max_supported
+                        ),
+                        "Sampler | Warning"
+                    );
+                }
+                gl::SamplerParameterf(
+                    handle, TEXTURE_MAX_ANISOTROPY,
+                    description.max_anisotropy.min( max_supported.max( 1.0 ) )
+                );
+            }
+
+            gl::SamplerParameterf( handle, gl::TEXTURE_LOD_BIAS, description.lod_bias );
+            gl::SamplerParameterf( handle, gl::TEXTURE_MIN_LOD, description.min_lod );
+            gl::SamplerParameterf( handle, gl::TEXTURE_MAX_LOD, description.max_lod );
+            gl::SamplerParameterfv( handle, gl::TEXTURE_BORDER_COLOR, description.border_color.as_ptr() );
+
+            match description.depth_compare {
+                Some( test ) => {
+                    gl::SamplerParameteri( handle, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as GLint );
+                    gl::SamplerParameteri( handle, gl::TEXTURE_COMPARE_FUNC, test.as_glenum() as GLint );
+                },
+                None => gl::SamplerParameteri( handle, gl::TEXTURE_COMPARE_MODE, gl::NONE as GLint ),
+            }
+
+        }
+        Self { handle, description:*description }
+    }
+
+    pub fn handle( &self )      -> GLuint              { self.handle       }
+    pub fn description( &self ) -> &SamplerDescription { &self.description }
+}
+
+impl fmt::Display for SamplerObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = &self.description;
+        write!( f, "Sampler Object {} | wrapping: {}/{}/{} filtering: {}/{} anisotropy: {}",
+            self.handle,
+            description.wrapping_x.name(), description.wrapping_y.name(), description.wrapping_z.name(),
+            description.min_filtering.name(), description.mag_filtering.name(),
+            description.max_anisotropy
+        )?;
+        match description.depth_compare {
+            Some( test ) => write!( f, " compare: {}", test.msg() ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Deletes cached sampler objects that nothing else holds on to
+///
+/// # Safety
+///
+/// Needs a current GL context, the one the samplers were created in.
+pub unsafe fn delete_unused_samplers() {
+    let handles:Vec<GLuint> = SAMPLER_CACHE.with( |cache| {
+        let mut handles = Vec::new();
+        cache.borrow_mut().retain( |_, sampler| {
+            if Rc::strong_count( sampler ) > 1 { return true; }
+            handles.push( sampler.handle() );
+            false
+        } );
+        handles
+    } );
+    for handle in handles.iter() { super::texture::forget_sampler( *handle ); }
+    gl::DeleteSamplers( handles.len() as GLsizei, handles.as_ptr() );
+}
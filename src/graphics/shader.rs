@@ -1,16 +1,40 @@
 use gl::types::*;
-use core::fmt;
+use core::{ fmt, cell::{ Cell, RefCell } };
 use crate::{ cstr::*, Rc, debugging::{ Error, log } };
 use super::uniform::{ UniformInfo, Uniform };
+use super::program_cache;
+use super::spirv::{ self, SpirvOptions };
+use super::preprocessor::{
+    ShaderParseOptions, LineMap,
+    expand_includes, insert_defines, is_keyword_pragma
+};
 
 pub struct ShaderProgram {
-    handle: GLuint,
-    uniform_info: Vec<UniformInfo>,
+    handle: Cell<GLuint>,
+    uniform_info: RefCell<Vec<UniformInfo>>,
+    /// Incremented every time the program is replaced by a reload
+    generation: Cell<u32>,
+    /// Texture unit last sent to each sampler location
+    sampler_units: RefCell<Vec<( GLint, GLint )>>,
 }
 
 impl ShaderProgram {
 
     pub fn from_shaders( shaders: &[Shader] ) -> Result<Rc<Self>, Error> {
+        let kinds:Vec<ShaderKind> = shaders.iter().map( |shader| shader.kind() ).collect();
+        validate_stages( &kinds )?;
+        Self::link( shaders, false )
+    }
+
+    /// Links a program with GL_PROGRAM_SEPARABLE so it can be
+    /// combined with other stage programs in a ProgramPipeline
+    pub fn separable_from_shaders( shaders: &[Shader] ) -> Result<Rc<Self>, Error> {
+        let kinds:Vec<ShaderKind> = shaders.iter().map( |shader| shader.kind() ).collect();
+        validate_separable_stages( &kinds )?;
+        Self::link( shaders, true )
+    }
+
+    fn link( shaders: &[Shader], separable:bool ) -> Result<Rc<Self>, Error> {
         unsafe {
             let handle:GLuint = gl::CreateProgram();
 
@@ -18,13 +42,20 @@ impl ShaderProgram {
                 gl::AttachShader( handle, shader.handle() );
             }
 
+            if separable {
+                gl::ProgramParameteri( handle, gl::PROGRAM_SEPARABLE, gl::TRUE as GLint );
+            }
+            // lets the program binary cache read the linked program back
+            gl::ProgramParameteri( handle, gl::PROGRAM_BINARY_RETRIEVABLE_HINT, gl::TRUE as GLint );
             gl::LinkProgram( handle );
 
             let mut link_status:GLint = 1;
             gl::GetProgramiv( handle, gl::LINK_STATUS, &mut link_status );
 
             if link_status == FAILED {
-                return Err( linking_error( handle ) );
+                let error = linking_error( handle );
+                gl::DeleteProgram( handle );
+                return Err( error );
             }
 
             log(
@@ -36,8 +67,74 @@ impl ShaderProgram {
                 gl::DetachShader( handle, shader.handle() );
             }
 
-            return Ok( Rc::new( Self { handle, uniform_info: Self::gen_uniform_info( handle ) } ) );
+            return Ok( Self::from_linked_handle( handle ) );
+
+        }
+    }
+
+    /// Creates a program from a binary returned by `binary()`.
+    /// Separable binaries need separable set again, loading one counts as linking.
+    /// Fails if the driver rejects the binary, e.g. after a driver update.
+    pub fn from_binary( format:GLenum, binary:&[u8], separable:bool ) -> Result<Rc<Self>, Error> {
+        unsafe {
+            let handle:GLuint = gl::CreateProgram();
+            if separable {
+                gl::ProgramParameteri( handle, gl::PROGRAM_SEPARABLE, gl::TRUE as GLint );
+            }
+            gl::ProgramBinary(
+                handle, format,
+                binary.as_ptr() as *const GLvoid,
+                binary.len() as GLsizei
+            );
+
+            let mut link_status:GLint = 1;
+            gl::GetProgramiv( handle, gl::LINK_STATUS, &mut link_status );
+
+            if link_status == FAILED {
+                gl::DeleteProgram( handle );
+                return Err( Error::ShaderLinker(
+                    format!( "Program binary with format {:#X} was rejected by the driver!", format )
+                ) );
+            }
+
+            log(
+                "Successfully loaded program binary.",
+                "Shader Linker"
+            );
+
+            Ok( Self::from_linked_handle( handle ) )
+        }
+    }
+
+    fn from_linked_handle( handle:GLuint ) -> Rc<Self> {
+        Rc::new( Self {
+            handle: Cell::new( handle ),
+            uniform_info: RefCell::new( Self::gen_uniform_info( handle ) ),
+            generation: Cell::new( 0 ),
+            sampler_units: RefCell::new( Vec::new() ),
+        } )
+    }
 
+    /// Reads back the linked program's binary and its format.
+    /// Returns None if the driver doesn't provide one.
+    pub fn binary(&self) -> Option<( GLenum, Vec<u8> )> {
+        unsafe {
+            let mut len:GLint = 0;
+            gl::GetProgramiv( self.handle(), gl::PROGRAM_BINARY_LENGTH, &mut len );
+            if len <= 0 { return None; }
+
+            let mut binary = vec![0u8;len as usize];
+            let mut written:GLsizei = 0;
+            let mut format:GLenum = 0;
+            gl::GetProgramBinary(
+                self.handle(), len,
+                &mut written, &mut format,
+                binary.as_mut_ptr() as *mut GLvoid
+            );
+            if written <= 0 { return None; }
+            binary.truncate( written as usize );
+
+            Some( ( format, binary ) )
         }
     }
 
@@ -82,13 +179,33 @@ impl ShaderProgram {
                 );
                 name_buffer.truncate( (name_buffer_len + 1) as usize );
                 let name = CString::from_vec_with_nul_unchecked( name_buffer );
-                let location = Self::gl_get_uniform_location(handle, &name);
-
-                if location == INVALID_LOCATION { continue; }
-
-                let uniform = UniformInfo::new( name, kind, location );
 
-                result.push( uniform );
+                // arrays of basic types are reported once as "name[0]" with their length as the size,
+                // every element gets its own location
+                let array_base = name.to_str().ok()
+                    .and_then( |name| name.strip_suffix( "[0]" ) )
+                    .map( |base| base.to_owned() );
+                match array_base {
+                    Some( base ) if data_size > 1 => for element in 0..data_size {
+                        let element_name = match CString::new( format!( "{}[{}]", base, element ) ) {
+                            Ok( element_name ) => element_name,
+                            Err(_) => continue,
+                        };
+                        let location = Self::gl_get_uniform_location( handle, &element_name );
+                        // unused trailing elements are optimized out
+                        if location == INVALID_LOCATION { continue; }
+                        result.push( UniformInfo::new( element_name, kind, location ) );
+                    },
+                    _ => {
+                        let location = Self::gl_get_uniform_location(handle, &name);
+
+                        if location == INVALID_LOCATION { continue; }
+
+                        let uniform = UniformInfo::new( name, kind, location );
+
+                        result.push( uniform );
+                    },
+                }
             }
 
             result.shrink_to_fit();
@@ -108,17 +225,83 @@ impl ShaderProgram {
         }
     }
 
+    fn gl_get_storage_block_index( handle:GLuint, name:&CStr ) -> GLuint {
+        unsafe {
+            gl::GetProgramResourceIndex(
+                handle, gl::SHADER_STORAGE_BLOCK,
+                name.as_ptr() as *const GLchar
+            )
+        }
+    }
+
     pub fn use_program(&self) { unsafe { gl::UseProgram( self.handle() ); } }
 
+    /// Top level uniforms with array elements and struct members grouped under them
     pub fn generate_uniforms(&self) -> ( Vec<Uniform>, Vec<bool> ) {
-        let uniforms = UniformInfo::generate_values(&self.uniform_info);
+        let ( _, uniforms ) = self.generate_named_uniforms();
         let dirty = vec![true;uniforms.len()];
         ( uniforms, dirty )
     }
 
-    pub fn handle(&self) -> GLuint { self.handle }
+    /// Like generate_uniforms but also returns the name of each top level uniform
+    pub fn generate_named_uniforms(&self) -> ( Vec<String>, Vec<Uniform> ) {
+        UniformInfo::generate_tree( &self.uniform_info.borrow() )
+    }
+
+    pub fn handle(&self) -> GLuint { self.handle.get() }
 
-    pub fn uniform_count(&self) -> usize { self.uniform_info.len() }
+    pub fn is_separable(&self) -> bool {
+        let mut separable = 0;
+        unsafe { gl::GetProgramiv( self.handle(), gl::PROGRAM_SEPARABLE, &mut separable ); }
+        separable == gl::TRUE as GLint
+    }
+    pub fn generation(&self) -> u32 { self.generation.get() }
+
+    pub fn uniform_count(&self) -> usize { self.uniform_info.borrow().len() }
+
+    pub fn uniform_name( &self, location:GLint ) -> Option<String> {
+        self.uniform_info.borrow().iter()
+            .find( |info| info.location() == location )
+            .and_then( |info| info.name().to_str().ok() )
+            .map( |name| name.to_owned() )
+    }
+
+    /// Like get_uniform_location but doesn't log when the uniform is missing
+    pub fn find_uniform_location( &self, name:&str ) -> Option<GLint> {
+        self.uniform_info.borrow().iter()
+            .find( |info| info.name().to_str() == Ok( name ) )
+            .map( |info| info.location() )
+    }
+
+    /// Swaps the GL program of another ShaderProgram into this one.
+    /// Everything holding this Rc uses the new program from now on
+    /// and the old program is deleted when `other` is dropped.
+    pub fn replace_with( &self, other:&ShaderProgram ) {
+        self.handle.swap( &other.handle );
+        self.uniform_info.swap( &other.uniform_info );
+        self.generation.set( self.generation.get().wrapping_add( 1 ) );
+        self.clear_sampler_units();
+    }
+
+    /// Points a sampler uniform at a texture unit.
+    /// Skips the call if the program already has that unit,
+    /// materials sharing a program can each set their own.
+    pub fn set_sampler_unit( &self, location:GLint, unit:GLint ) {
+        let mut sampler_units = self.sampler_units.borrow_mut();
+        match sampler_units.iter_mut().find( |( l, _ )| *l == location ) {
+            Some( ( _, last_unit ) ) => {
+                if *last_unit == unit { return; }
+                *last_unit = unit;
+            },
+            None => sampler_units.push( ( location, unit ) ),
+        }
+        unsafe { gl::ProgramUniform1i( self.handle(), location, unit ); }
+    }
+
+    /// Forgets the units sent by set_sampler_unit so they're sent again
+    pub fn clear_sampler_units( &self ) {
+        self.sampler_units.borrow_mut().clear();
+    }
 
     pub fn get_uniform_location(&self, name:&str) -> GLint {
         let cname = CString::new( name ).unwrap();
@@ -162,6 +345,34 @@ impl ShaderProgram {
         self.bind_uniform_block( idx, block_binding );
     }
 
+    pub fn get_storage_block_index(&self, name:&str) -> GLuint {
+        let cname = CString::new( name ).unwrap();
+        let result = Self::gl_get_storage_block_index( self.handle(), &cname );
+        #[cfg(debug_assertions)]
+        if result == gl::INVALID_INDEX {
+            log(
+                &format!( "Storage Block \"{}\" not found!", name ),
+                &format!( "Shader {} get_storage_block_index()", self.handle() )
+            )
+        }
+
+        result
+    }
+
+    pub fn bind_storage_block( &self, block_index:GLuint, block_binding:GLuint ) {
+        unsafe {
+            gl::ShaderStorageBlockBinding(
+                self.handle(), block_index,
+                block_binding
+            );
+        }
+    }
+
+    pub fn bind_storage_block_by_name( &self, name:&str, block_binding:GLuint ) {
+        let idx = self.get_storage_block_index( name );
+        self.bind_storage_block( idx, block_binding );
+    }
+
 }
 
 impl Drop for ShaderProgram {
@@ -173,18 +384,19 @@ impl Drop for ShaderProgram {
 impl fmt::Display for ShaderProgram {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut buffer = String::new();
-        for uniform in self.uniform_info.iter() {
+        for uniform in self.uniform_info.borrow().iter() {
             buffer.push_str( &format!( "   {}\n", uniform ) )
         }
         write!( f, "Shader {}\n{}", self.handle(), buffer )
     }
 }
 
-pub struct Shader { handle:GLuint }
+pub struct Shader { handle:GLuint, kind:ShaderKind }
 
 impl Shader {
 
     pub fn handle(&self) -> GLuint { self.handle }
+    pub fn kind(&self) -> ShaderKind { self.kind }
 
     pub fn vert_from_source( src:&CStr ) -> Result<Self, Error> {
         Self::from_source(src, ShaderKind::Vertex)
@@ -195,6 +407,48 @@ impl Shader {
     }
 
     pub fn from_source( src:&CStr, kind:ShaderKind ) -> Result<Self, Error> {
+        Self::compile( src, kind, None )
+    }
+
+    /// Compiles a stage parsed from a .shader file.
+    /// Compile errors point at the .shader file and line they came from.
+    pub fn from_stage( stage:&StageSource ) -> Result<Self, Error> {
+        let src = CString::new( stage.source() )
+            .map_err( |e| Error::CStringNul( format!( "{}", e ) ) )?;
+        Self::compile( &src, stage.kind(), Some( stage ) )
+    }
+
+    /// Creates a shader from a SPIR-V module, specialized with the given
+    /// entry point and specialization constants.
+    pub fn from_spirv( binary:&[u8], kind:ShaderKind, options:&SpirvOptions ) -> Result<Self, Error> {
+        unsafe {
+            let handle:GLuint = gl::CreateShader( kind as GLenum );
+            match spirv::specialize( handle, binary, options ) {
+                Ok(()) => {},
+                Err( e ) => {
+                    gl::DeleteShader( handle );
+                    return Err( e );
+                }
+            }
+
+            let mut compile_status:GLint = 1;
+            gl::GetShaderiv( handle, gl::COMPILE_STATUS, &mut compile_status );
+
+            if compile_status == FAILED {
+                let error = compilation_error( handle );
+                gl::DeleteShader( handle );
+                return Err( error );
+            }
+
+            log(
+                &format!( "Successfully specialized {} shader \"{}\".", kind, options.entry_point() ),
+                "Shader Compiler"
+            );
+            Ok( Self{ handle, kind } )
+        }
+    }
+
+    fn compile( src:&CStr, kind:ShaderKind, stage:Option<&StageSource> ) -> Result<Self, Error> {
         unsafe {
             let handle:GLuint = gl::CreateShader( kind as GLenum );
             gl::ShaderSource(
@@ -206,13 +460,20 @@ impl Shader {
             let mut compile_status:GLint = 1;
             gl::GetShaderiv( handle, gl::COMPILE_STATUS, &mut compile_status );
 
-            if compile_status == FAILED { return Err( compilation_error( handle ) ); }
+            if compile_status == FAILED {
+                let error = match stage {
+                    Some( stage ) => mapped_compilation_error( handle, stage ),
+                    None => compilation_error( handle ),
+                };
+                gl::DeleteShader( handle );
+                return Err( error );
+            }
             else {
                 log(
                     format!("Successfully compiled {} shader.", kind ).as_str(),
                     "Shader Compiler"
                 );
-                return Ok( Self{ handle } );
+                return Ok( Self{ handle, kind } );
             }
         }
     }
@@ -226,24 +487,151 @@ impl Drop for Shader {
 }
 
 /// I dont want my fn to accept just any GLenum
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ShaderKind {
-    Vertex   = 0x8B31,
-    Fragment = 0x8B30,
+    Vertex         = 0x8B31,
+    TessControl    = 0x8E88,
+    TessEvaluation = 0x8E87,
+    Geometry       = 0x8DD9,
+    Fragment       = 0x8B30,
+    Compute        = 0x91B9,
+}
+
+impl ShaderKind {
+    pub fn as_glenum(&self) -> GLenum { *self as GLenum }
+
+    /// Pre-processor directive that starts this stage's section in a .shader file
+    pub fn directive(&self) -> &str {
+        match self {
+            Self::Vertex         => "#vertex"       ,
+            Self::TessControl    => "#tess_control" ,
+            Self::TessEvaluation => "#tess_eval"    ,
+            Self::Geometry       => "#geometry"     ,
+            Self::Fragment       => "#fragment"     ,
+            Self::Compute        => "#compute"      ,
+        }
+    }
+
+    /// Bit used with glUseProgramStages
+    pub fn stage_bit(&self) -> GLbitfield {
+        match self {
+            Self::Vertex         => gl::VERTEX_SHADER_BIT          ,
+            Self::TessControl    => gl::TESS_CONTROL_SHADER_BIT    ,
+            Self::TessEvaluation => gl::TESS_EVALUATION_SHADER_BIT ,
+            Self::Geometry       => gl::GEOMETRY_SHADER_BIT        ,
+            Self::Fragment       => gl::FRAGMENT_SHADER_BIT        ,
+            Self::Compute        => gl::COMPUTE_SHADER_BIT         ,
+        }
+    }
+
+    /// Stage extension of a SPIR-V file, as in `name.vert.spv`
+    pub fn spirv_extension(&self) -> &str {
+        match self {
+            Self::Vertex         => "vert" ,
+            Self::TessControl    => "tesc" ,
+            Self::TessEvaluation => "tese" ,
+            Self::Geometry       => "geom" ,
+            Self::Fragment       => "frag" ,
+            Self::Compute        => "comp" ,
+        }
+    }
+
+    pub fn from_directive( line:&str ) -> Option<Self> {
+        let directive = line.trim_start().split_whitespace().next()?;
+        ALL_SHADER_KINDS.iter()
+            .find( |kind| kind.directive() == directive )
+            .copied()
+    }
+
+    pub fn msg(&self) -> &str {
+        match self {
+            Self::Vertex         => "Vertex"                 ,
+            Self::TessControl    => "Tessellation Control"   ,
+            Self::TessEvaluation => "Tessellation Evaluation",
+            Self::Geometry       => "Geometry"               ,
+            Self::Fragment       => "Fragment"               ,
+            Self::Compute        => "Compute"                ,
+        }
+    }
 }
 
 impl fmt::Display for ShaderKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ShaderKind::Vertex   => write!( f, "Vertex" ),
-            ShaderKind::Fragment => write!( f, "Fragment" ),
+        write!( f, "{}", self.msg() )
+    }
+}
+
+/// In pipeline order
+pub const ALL_SHADER_KINDS:[ShaderKind;6] = [
+    ShaderKind::Vertex,
+    ShaderKind::TessControl,
+    ShaderKind::TessEvaluation,
+    ShaderKind::Geometry,
+    ShaderKind::Fragment,
+    ShaderKind::Compute,
+];
+
+/// Checks that a set of stages can be linked into one program
+pub fn validate_stages( kinds:&[ShaderKind] ) -> Result<(), Error> {
+    if kinds.is_empty() {
+        return Err( Error::ShaderLinker( "No shader stages to link!".to_owned() ) );
+    }
+
+    for ( idx, kind ) in kinds.iter().enumerate() {
+        if kinds[idx + 1..].contains( kind ) {
+            return Err( Error::ShaderLinker( format!( "Found more than one {} shader!", kind ) ) );
+        }
+    }
+
+    let has = | kind:ShaderKind | kinds.contains( &kind );
+
+    if has( ShaderKind::Compute ) {
+        if kinds.len() > 1 {
+            return Err( Error::ShaderLinker(
+                "Compute shaders can't be linked with other stages!".to_owned()
+            ) );
         }
+        return Ok(());
     }
+
+    if !has( ShaderKind::Vertex ) {
+        return Err( Error::ShaderLinker( "Program is missing a Vertex shader!".to_owned() ) );
+    }
+
+    if has( ShaderKind::TessControl ) && !has( ShaderKind::TessEvaluation ) {
+        return Err( Error::ShaderLinker(
+            "Tessellation Control shader requires a Tessellation Evaluation shader!".to_owned()
+        ) );
+    }
+
+    Ok(())
+}
+
+/// Checks that a set of stages can be linked into one separable program.
+/// Unlike validate_stages any stage can be left out.
+pub fn validate_separable_stages( kinds:&[ShaderKind] ) -> Result<(), Error> {
+    if kinds.is_empty() {
+        return Err( Error::ShaderLinker( "No shader stages to link!".to_owned() ) );
+    }
+
+    for ( idx, kind ) in kinds.iter().enumerate() {
+        if kinds[idx + 1..].contains( kind ) {
+            return Err( Error::ShaderLinker( format!( "Found more than one {} shader!", kind ) ) );
+        }
+    }
+
+    if kinds.contains( &ShaderKind::Compute ) && kinds.len() > 1 {
+        return Err( Error::ShaderLinker(
+            "Compute shaders can't be linked with other stages!".to_owned()
+        ) );
+    }
+
+    Ok(())
 }
 
 const FAILED:GLint = 0;
 
-pub fn compilation_error( shader_handle:GLuint ) -> Error {
+fn shader_info_log( shader_handle:GLuint ) -> Result<String, Error> {
     unsafe {
         let mut len = 0;
         gl::GetShaderiv( shader_handle, gl::INFO_LOG_LENGTH, &mut len );
@@ -254,12 +642,19 @@ pub fn compilation_error( shader_handle:GLuint ) -> Error {
             message_buffer.as_ptr() as *mut GLchar
         );
         match message_buffer.to_str() {
-            Ok( message ) => Error::ShaderCompiler( message.replace("\n", "").to_owned() ),
-            Err( e ) => Error::UTF8( format!( "{}", e ) ),
+            Ok( message ) => Ok( message.trim_end_matches( |c:char| c == '\0' || c.is_whitespace() ).to_owned() ),
+            Err( e ) => Err( Error::UTF8( format!( "{}", e ) ) ),
         }
     }
 }
 
+pub fn compilation_error( shader_handle:GLuint ) -> Error {
+    match shader_info_log( shader_handle ) {
+        Ok( message ) => Error::ShaderCompiler( message ),
+        Err( e ) => e,
+    }
+}
+
 pub fn linking_error( shader_handle:GLuint ) -> Error {
     unsafe {
         let mut len:GLint = 0;
@@ -271,74 +666,214 @@ pub fn linking_error( shader_handle:GLuint ) -> Error {
             message_buffer.as_ptr() as *mut GLchar
         );
         match message_buffer.to_str() {
-            Ok( message ) => Error::ShaderCompiler( message.replace("\n", "").to_owned() ),
+            Ok( message ) => Error::ShaderCompiler(
+                message.trim_end_matches( |c:char| c == '\0' || c.is_whitespace() ).to_owned()
+            ),
             Err( e ) => Error::UTF8( format!( "{}", e ) ),
         }
     }
 }
 
-pub fn shader_parser( src:&str ) -> Result<[Shader;2], Error> {
+/// Like compilation_error but re-emits each diagnostic as
+/// `path:line: message` followed by the offending line of source.
+pub fn mapped_compilation_error( shader_handle:GLuint, stage:&StageSource ) -> Error {
+    match shader_info_log( shader_handle ) {
+        Ok( message ) => Error::ShaderCompiler( map_diagnostics( &message, stage ) ),
+        Err( e ) => e,
+    }
+}
 
-    // PPD - Pre-processor directive
-    const PPD_VERTEX:&str   = "#vertex";
-    const PPD_FRAGMENT:&str = "#fragment";
+/// Rewrites the line numbers in a driver's info log using a stage's line map.
+/// Lines that aren't recognised as diagnostics are kept as they are.
+pub fn map_diagnostics( info_log:&str, stage:&StageSource ) -> String {
+    let source_lines:Vec<&str> = stage.source().lines().collect();
+    let mut diagnostics:Vec<String> = Vec::new();
+    for log_line in info_log.lines() {
+        if log_line.trim().is_empty() { continue; }
+
+        let ( line, message ) = match parse_diagnostic( log_line ) {
+            Some( diagnostic ) => diagnostic,
+            None => {
+                diagnostics.push( log_line.trim().to_owned() );
+                continue;
+            }
+        };
+
+        let location = match stage.line_map().lookup( line ) {
+            Some( location ) => format!( "{}", location ),
+            None => format!( "<generated {} source>:{}", stage.kind(), line ),
+        };
+        let mut diagnostic = format!( "{}: {}", location, message );
+        match source_lines.get( line.wrapping_sub( 1 ) ) {
+            Some( source_line ) => diagnostic.push_str( &format!( "\n    | {}", source_line.trim() ) ),
+            None => {},
+        }
+        diagnostics.push( diagnostic );
+    }
+    diagnostics.join("\n")
+}
 
-    // split into lines
-    let lines:Vec<&str> = src.split('\n').collect();
+/// Finds the line number and message in one line of a driver's info log.
+/// Handles `0(12) : error ...` (NVIDIA), `0:12(5): error: ...` (Mesa)
+/// and `ERROR: 0:12: ...` (AMD, Intel on Windows).
+fn parse_diagnostic( log_line:&str ) -> Option<( usize, String )> {
+    let bytes = log_line.as_bytes();
+    for start in 0..bytes.len() {
+        if !bytes[start].is_ascii_digit() { continue; }
+        if start > 0 && bytes[start - 1].is_ascii_alphanumeric() { continue; }
+
+        let ( line, end ) = match parse_diagnostic_location( &log_line[start..] ) {
+            Some( location ) => location,
+            None => continue,
+        };
+
+        let prefix  = log_line[..start].trim();
+        let message = log_line[start + end..]
+            .trim_start_matches( |c:char| c == ':' || c.is_whitespace() )
+            .trim_end();
+        let message = if prefix.is_empty() { message.to_owned() }
+            else { format!( "{} {}", prefix, message ) };
+        return Some( ( line, message ) );
+    }
+    None
+}
 
-    let mut vert_buffer:Vec<&str> = Vec::new();
-    let mut frag_buffer:Vec<&str> = Vec::new();
+/// Parses `string(line)` or `string:line`, optionally followed by `(column)`,
+/// returning the line and the byte length of the location.
+fn parse_diagnostic_location( src:&str ) -> Option<( usize, usize )> {
+    let digits = | s:&str | s.bytes().take_while( |b| b.is_ascii_digit() ).count();
+
+    let string_len = digits( src );
+    let closing = match src[string_len..].chars().next()? {
+        '(' => Some( ')' ),
+        ':' => None,
+        _ => return None,
+    };
+
+    let line_start = string_len + 1;
+    let line_len = digits( &src[line_start..] );
+    if line_len == 0 { return None; }
+    let line:usize = src[line_start..line_start + line_len].parse().ok()?;
+
+    let mut end = line_start + line_len;
+    match closing {
+        Some( closing ) => {
+            if !src[end..].starts_with( closing ) { return None; }
+            end += 1;
+        },
+        None => {},
+    }
 
-    let mut shader_kind = ParseKind::None;
+    if src[end..].starts_with( '(' ) {
+        let column_len = digits( &src[end + 1..] );
+        if column_len > 0 && src[end + 1 + column_len..].starts_with( ')' ) {
+            end += column_len + 2;
+        }
+    }
 
-    for line in lines.iter() {
-        // skip empty
-        if line.is_empty() { continue; }
+    if !src[end..].trim_start().starts_with( ':' ) { return None; }
+    Some( ( line, end ) )
+}
 
-        if line.contains( PPD_VERTEX ) {
-            shader_kind = ParseKind::Vertex;
-            continue;
-        } else if line.contains( PPD_FRAGMENT ) {
-            shader_kind = ParseKind::Fragment;
-            continue;
-        }
+/// Source of one stage generated from a .shader file
+pub struct StageSource {
+    kind:     ShaderKind,
+    source:   String,
+    line_map: LineMap,
+}
 
-        match shader_kind {
-            ParseKind::Vertex => {
-                vert_buffer.push( line );
+impl StageSource {
+    pub fn kind(&self)     -> ShaderKind { self.kind      }
+    pub fn source(&self)   -> &str       { &self.source   }
+    pub fn line_map(&self) -> &LineMap   { &self.line_map }
+}
+
+/// Splits a .shader file into the source of each stage,
+/// resolving #includes and inserting #defines from the options.
+/// Each stage keeps a line map back to the .shader file and its includes.
+pub fn parse_stages( src:&str, options:&ShaderParseOptions ) -> Result<Vec<StageSource>, Error> {
+
+    // split into lines
+    let lines:Vec<&str> = src.split('\n').collect();
+
+    // stage kind, 1-based line its source starts at, source lines
+    let mut buffers:Vec<( ShaderKind, usize, Vec<&str> )> = Vec::new();
+
+    for ( idx, line ) in lines.iter().enumerate() {
+        match ShaderKind::from_directive( line ) {
+            Some( kind ) => {
+                if buffers.iter().any( |( k, _, _ )| *k == kind ) {
+                    return Err( Error::ShaderParse(
+                        format!( "{}:{}: Shader contains more than one {} section!",
+                            options.source_name(), idx + 1, kind.directive()
+                        )
+                    ) );
+                }
+                buffers.push( ( kind, idx + 2, Vec::new() ) );
+                continue;
             },
-            ParseKind::Fragment => {
-                frag_buffer.push( line );
+            None => {},
+        }
+
+        match buffers.last_mut() {
+            // keywords are handled by ShaderVariants,
+            // blank them so line numbers still line up
+            Some( ( _, _, buffer ) ) => if is_keyword_pragma( line ) {
+                buffer.push( "" );
+            } else {
+                buffer.push( line.trim_end_matches( '\r' ) );
             },
-            _ => continue,
+            None => continue,
         }
     }
 
-    if vert_buffer.is_empty() || frag_buffer.is_empty() {
-        return Err( Error::ShaderParse( "Shader is not formatted properly!".to_owned() ) );
+    let is_empty = | buffer:&Vec<&str> | buffer.iter().all( |line| line.trim().is_empty() );
+    if buffers.is_empty() || buffers.iter().any( |( _, _, buffer )| is_empty( buffer ) ) {
+        return Err( Error::ShaderParse(
+            format!( "{}: Shader is not formatted properly!", options.source_name() )
+        ) );
     }
 
+    let kinds:Vec<ShaderKind> = buffers.iter().map( |( kind, _, _ )| *kind ).collect();
+    let validation = if options.separable() { validate_separable_stages( &kinds ) }
+        else { validate_stages( &kinds ) };
+    validation
+        .map_err( |e| Error::ShaderParse( format!( "{}: {}", options.source_name(), e.msg() ) ) )?;
+
     log(
-        "Successfully parsed Vertex and Fragment shaders.",
+        &format!(
+            "Successfully parsed {} shaders.",
+            kinds.iter().map( |kind| kind.msg() ).collect::<Vec<&str>>().join(", ")
+        ),
         "Shader Parser"
     );
 
-    let vert_src = CString::new( vert_buffer.join("\n") )
-        .map_err( |e| Error::CStringNul( format!( "{}", e ) ) )?;
-    let vert = Shader::vert_from_source( &vert_src )?;
+    let mut stages = Vec::with_capacity( buffers.len() );
+    for ( kind, first_line, buffer ) in buffers.iter() {
+        let ( mut stage_lines, mut line_map ) = expand_includes( buffer, *first_line, options )?;
+        insert_defines( &mut stage_lines, &mut line_map, options.defines() );
+        stages.push( StageSource { kind: *kind, source: stage_lines.join("\n"), line_map } );
+    }
 
-    let frag_src = CString::new( frag_buffer.join("\n") )
-        .map_err( |e| Error::CStringNul( format!( "{}", e ) ) )?;
-    let frag = Shader::frag_from_source( &frag_src )?;
+    Ok( stages )
 
-    Ok( [ vert, frag, ] )
+}
 
+/// Parses a .shader file and links it into a program,
+/// loading it from the program binary cache when possible
+pub fn program_from_source( src:&str, options:&ShaderParseOptions ) -> Result<Rc<ShaderProgram>, Error> {
+    program_cache::load_or_link( &parse_stages( src, options )?, options.separable() )
 }
 
-enum ParseKind {
-    Vertex,
-    Fragment,
-    None,
+pub fn shader_parser( src:&str, options:&ShaderParseOptions ) -> Result<Vec<Shader>, Error> {
+
+    let mut shaders = Vec::new();
+    for stage in parse_stages( src, options )?.iter() {
+        shaders.push( Shader::from_stage( stage )? );
+    }
+
+    Ok( shaders )
+
 }
 
 const INVALID_LOCATION:GLint = -1;
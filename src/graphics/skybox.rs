@@ -0,0 +1,69 @@
+use gl::types::*;
+use fmath::types::color::RGB;
+use crate::{ Rc, debugging::Error };
+use super::{ Material, ShaderProgram, DepthTest, TestKind, cubemap::Cubemap };
+
+/// Name of the samplerCube uniform a skybox shader samples
+pub const SKYBOX_SAMPLER:&str = "skybox_sampler";
+
+/// Draws a cubemap behind everything else.
+/// Render it after opaque geometry so only uncovered pixels are shaded.
+pub struct Skybox {
+    cubemap:Rc<Cubemap>,
+    material:Material,
+    // empty, the shader builds a fullscreen triangle from gl_VertexID
+    vao:GLuint,
+}
+
+impl Skybox {
+    /// The shader needs a `samplerCube skybox_sampler`,
+    /// resources/shaders/skybox.shader is the default one
+    pub fn new( cubemap:Rc<Cubemap>, shader:Rc<ShaderProgram> ) -> Result<Self, Error> {
+        let mut material = Material::new( shader );
+        material.get_uniform_mut_by_name( SKYBOX_SAMPLER )?
+            .set_sampler_texture( cubemap.clone() );
+
+        let mut vao = 0;
+        unsafe { gl::GenVertexArrays( 1, &mut vao ); }
+
+        Ok( Self { cubemap, material, vao } )
+    }
+
+    pub fn cubemap( &self )  -> &Rc<Cubemap> { &self.cubemap  }
+    pub fn material( &self ) -> &Material    { &self.material }
+    pub fn material_mut( &mut self ) -> &mut Material { &mut self.material }
+
+    pub fn set_cubemap( &mut self, cubemap:Rc<Cubemap> ) -> Result<(), Error> {
+        self.material.get_uniform_mut_by_name( SKYBOX_SAMPLER )?
+            .set_sampler_texture( cubemap.clone() );
+        self.cubemap = cubemap;
+        Ok(())
+    }
+
+    /// Color to fade distant geometry into so it blends with the sky,
+    /// also a good clear color
+    pub fn fog_color( &self ) -> RGB { self.cubemap.horizon_color() }
+
+    /// Draws the sky at the far plane with depth writes off,
+    /// passing wherever the depth buffer is still clear
+    pub fn render( &mut self, depth_test:&mut DepthTest ) {
+        let previous_test = *depth_test.depth_test_kind();
+        depth_test.set_depth_test_kind( TestKind::LessEquals );
+
+        self.material.use_material();
+        unsafe {
+            gl::DepthMask( gl::FALSE );
+            gl::BindVertexArray( self.vao );
+            gl::DrawArrays( gl::TRIANGLES, 0, 3 );
+            gl::DepthMask( gl::TRUE );
+        }
+
+        depth_test.set_depth_test_kind( previous_test );
+    }
+}
+
+impl Drop for Skybox {
+    fn drop( &mut self ) {
+        unsafe { gl::DeleteVertexArrays( 1, &self.vao ) }
+    }
+}
@@ -0,0 +1,129 @@
+use gl::types::*;
+use core::{ fmt, cell::Cell };
+use crate::{ cstr::*, debugging::Error };
+
+/// GL_SHADER_BINARY_FORMAT_SPIR_V, not in the generated 4.5 bindings
+pub const SHADER_BINARY_FORMAT_SPIR_V:GLenum = 0x9551;
+
+/// First word of every SPIR-V module
+pub const SPIRV_MAGIC_NUMBER:u32 = 0x07230203;
+
+type SpecializeShaderFn = unsafe extern "system" fn(
+    shader:GLuint, entry_point:*const GLchar,
+    constant_count:GLuint, constant_indices:*const GLuint, constant_values:*const GLuint
+);
+
+thread_local!(
+    static SPECIALIZE_SHADER: Cell<Option<SpecializeShaderFn>> = Cell::new( None );
+);
+
+/// Loads glSpecializeShader (GL 4.6) or glSpecializeShaderARB,
+/// which the generated bindings don't include.
+pub fn load_with<F>( mut loader:F ) where F: FnMut( &str ) -> *const GLvoid {
+    for symbol in [ "glSpecializeShader", "glSpecializeShaderARB" ].iter() {
+        let ptr = loader( symbol );
+        if !ptr.is_null() {
+            let specialize_shader = unsafe { core::mem::transmute::<*const GLvoid, SpecializeShaderFn>( ptr ) };
+            SPECIALIZE_SHADER.with( |f| f.set( Some( specialize_shader ) ) );
+            return;
+        }
+    }
+}
+
+/// True if the context can load SPIR-V shaders
+pub fn is_supported() -> bool {
+    SPECIALIZE_SHADER.with( |f| f.get().is_some() )
+}
+
+/// Checks the SPIR-V magic number and that the module is made of 32-bit words
+pub fn is_spirv( binary:&[u8] ) -> bool {
+    binary.len() >= 4 && binary.len() % 4 == 0 &&
+    u32::from_le_bytes( [ binary[0], binary[1], binary[2], binary[3] ] ) == SPIRV_MAGIC_NUMBER
+}
+
+/// Uploads a SPIR-V module to a shader object and specializes it.
+/// SPIR-V keeps uniform names only as debug info that drivers don't have to report,
+/// so give uniforms explicit locations and look them up by location, not by name.
+pub fn specialize( shader_handle:GLuint, binary:&[u8], options:&SpirvOptions ) -> Result<(), Error> {
+    let specialize_shader = SPECIALIZE_SHADER.with( |f| f.get() ).ok_or( Error::ShaderCompiler(
+        "SPIR-V shaders require OpenGL 4.6 or ARB_gl_spirv!".to_owned()
+    ) )?;
+    if !is_spirv( binary ) {
+        return Err( Error::ShaderCompiler( "Binary is not a SPIR-V module!".to_owned() ) );
+    }
+
+    let entry_point = CString::new( options.entry_point() )
+        .map_err( |e| Error::CStringNul( format!( "{}", e ) ) )?;
+    let indices:Vec<GLuint> = options.constants().iter().map( |c| c.index() ).collect();
+    let values:Vec<GLuint>  = options.constants().iter().map( |c| c.value() ).collect();
+
+    unsafe {
+        gl::ShaderBinary(
+            1, &shader_handle,
+            SHADER_BINARY_FORMAT_SPIR_V,
+            binary.as_ptr() as *const GLvoid,
+            binary.len() as GLsizei
+        );
+    }
+    unsafe {
+        specialize_shader(
+            shader_handle, entry_point.as_ptr() as *const GLchar,
+            indices.len() as GLuint, indices.as_ptr(), values.as_ptr()
+        );
+    }
+    Ok(())
+}
+
+/// Entry point and specialization constants used to specialize a SPIR-V module
+#[derive(Debug, Clone)]
+pub struct SpirvOptions {
+    entry_point: String,
+    constants: Vec<SpecializationConstant>,
+}
+
+impl Default for SpirvOptions {
+    fn default() -> Self {
+        Self { entry_point: "main".to_owned(), constants: Vec::new() }
+    }
+}
+
+impl SpirvOptions {
+    pub fn entry_point(&self) -> &str { &self.entry_point }
+    pub fn constants(&self) -> &[SpecializationConstant] { &self.constants }
+
+    pub fn set_entry_point( &mut self, entry_point:&str ) {
+        self.entry_point = entry_point.to_owned();
+    }
+
+    /// Sets a constant by its `constant_id`, replacing any previous value
+    pub fn set_constant( &mut self, constant:SpecializationConstant ) {
+        match self.constants.iter_mut().find( |c| c.index() == constant.index() ) {
+            Some( c ) => *c = constant,
+            None => self.constants.push( constant ),
+        }
+    }
+}
+
+/// Value for a `layout(constant_id = index)` constant,
+/// stored as the 32 bits GL expects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpecializationConstant {
+    index: GLuint,
+    value: GLuint,
+}
+
+impl SpecializationConstant {
+    pub fn new_u32( index:GLuint, value:u32 ) -> Self { Self { index, value } }
+    pub fn new_i32( index:GLuint, value:i32 ) -> Self { Self { index, value: value as u32 } }
+    pub fn new_f32( index:GLuint, value:f32 ) -> Self { Self { index, value: value.to_bits() } }
+    pub fn new_bool( index:GLuint, value:bool ) -> Self { Self { index, value: value as u32 } }
+
+    pub fn index(&self) -> GLuint { self.index }
+    pub fn value(&self) -> GLuint { self.value }
+}
+
+impl fmt::Display for SpecializationConstant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Specialization Constant {}: {:#010X}", self.index(), self.value() )
+    }
+}
@@ -1,21 +1,25 @@
 use gl::types::*;
-use crate::{ Rc, debugging::Error };
-use core::fmt;
+use crate::{ Rc, debugging::{ Error, log } };
+use core::{ fmt, cell::{ Cell, RefCell, Ref } };
 use fmath::types::color::RGB;
+use super::compressed::{ CompressedImage, upload_compressed };
+use super::sampler::SamplerObject;
 
 #[derive(Debug)]
 pub struct Texture {
-    handle:GLuint,
-    image:ImageGL,
-    options:TextureOptions
+    handle:Cell<GLuint>,
+    image:RefCell<ImageGL>,
+    options:TextureOptions,
+    /// Path relative to resources/textures if loaded from a file
+    local_path:RefCell<Option<String>>,
 }
 
 impl Texture {
 
     pub fn empty() -> Rc<Self> {
         Rc::new( Self {
-            handle: 0, image:ImageGL::empty(),
-            options:TextureOptions::default()
+            handle: Cell::new( 0 ), image: RefCell::new( ImageGL::empty() ),
+            options:TextureOptions::default(), local_path: RefCell::new( None )
         } )
     }
 
@@ -24,6 +28,8 @@ impl Texture {
             width: 8, height: 8,
             internal_format: gl::RGBA8,
             format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            swizzle: None,
             data: Vec::from( NULL_TEXTURE_IMAGE )
         };
         let mut options = TextureOptions::default();
@@ -38,20 +44,23 @@ impl Texture {
             width: 1, height: 1,
             internal_format: gl::RGB8,
             format: gl::RGB,
+            data_type: gl::UNSIGNED_BYTE,
+            swizzle: None,
             data: Vec::from( color.as_array_rgb() )
         };
         let options = TextureOptions::default();
         Self::new( image, options )
     }
 
-    pub fn new( image:ImageGL, options:TextureOptions ) -> Rc<Self> {
+    pub fn new( mut image:ImageGL, options:TextureOptions ) -> Rc<Self> {
         let mut handle = 0;
+        image.internal_format = options.internal_format( &image );
 
         unsafe {
 
             gl::GenTextures( 1, &mut handle );
-            gl::BindTexture( gl::TEXTURE_2D, handle );
-                
+            bind_texture( gl::TEXTURE_2D, handle );
+
             match options.border_color {
                 Some(c) => {
                     let col = c.as_array_rgba_f32();
@@ -81,35 +90,98 @@ impl Texture {
                 options.mag_filtering.as_glint()
             );
 
+            match image.swizzle {
+                Some( swizzle ) => set_swizzle( gl::TEXTURE_2D, swizzle ),
+                None => {},
+            }
+
+            // rows of odd width images aren't padded to 4 bytes
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
             gl::TexImage2D(
                 gl::TEXTURE_2D, 0, image.internal_format as GLint,
                 image.width, image.height,
-                0, image.format, gl::UNSIGNED_BYTE,
-                image.data.as_ptr() as *const GLvoid
+                0, image.format, image.data_type,
+                // no data leaves the texture's contents undefined, to render into
+                if image.data.is_empty() { core::ptr::null() }
+                else { image.data.as_ptr() as *const GLvoid }
             );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
 
             gl::GenerateMipmap( gl::TEXTURE_2D );
 
         }
 
-        Rc::new( Texture { handle, image, options } )
+        Rc::new( Texture {
+            handle: Cell::new( handle ), image: RefCell::new( image ), options,
+            local_path: RefCell::new( None )
+        } )
+    }
+
+    /// Uploads a compressed image with its mip chain,
+    /// errors if the image is a cubemap or array or the driver lacks its format
+    pub fn from_compressed( image:&CompressedImage, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        if image.target() != gl::TEXTURE_2D {
+            return Err( Error::TextureInvalidImage( format!(
+                "A {} {}x{} image with {} layers and {} faces isn't a 2D texture!",
+                image.format, image.width, image.height, image.layers, image.faces
+            ) ) );
+        }
+        let handle = upload_compressed( image, &options )?;
+        // the data only lives on the GPU
+        let image = ImageGL {
+            width: image.width, height: image.height,
+            internal_format: image.internal_format( &options ),
+            format: 0, data_type: 0, swizzle: None,
+            data: Vec::new()
+        };
+        Ok( Rc::new( Texture {
+            handle: Cell::new( handle ), image: RefCell::new( image ), options,
+            local_path: RefCell::new( None )
+        } ) )
     }
 
-    pub fn handle( &self )        -> GLuint            { self.handle                }
-    pub fn width( &self )         -> GLint             { self.image.width           }
-    pub fn height( &self )        -> GLint             { self.image.height          }
-    pub fn image_data( &self )    -> &Vec<u8>          { &self.image.data            }
+    pub fn handle( &self )        -> GLuint            { self.handle.get()          }
+    pub fn width( &self )         -> GLint             { self.image.borrow().width  }
+    pub fn height( &self )        -> GLint             { self.image.borrow().height }
+    pub fn image_data( &self )    -> Ref<'_, Vec<u8>>  { Ref::map( self.image.borrow(), |image| &image.data ) }
+    pub fn options( &self )       -> &TextureOptions   { &self.options               }
+    /// Sampling returns linear color, from the options or a compressed file's own format
+    pub fn is_srgb( &self )       -> bool              { is_srgb_internal_format( self.image.borrow().internal_format ) }
     pub fn wrapping_x( &self )    -> &TextureWrapping  { &self.options.wrapping_x    }
     pub fn wrapping_y( &self )    -> &TextureWrapping  { &self.options.wrapping_y    }
     pub fn min_filtering( &self ) -> &MipmapFiltering  { &self.options.min_filtering }
     pub fn mag_filtering( &self ) -> &TextureFiltering { &self.options.mag_filtering }
+    pub fn local_path( &self )    -> Option<String>    { self.local_path.borrow().clone() }
+
+    pub fn set_local_path( &self, local_path:&str ) {
+        *self.local_path.borrow_mut() = Some( local_path.to_owned() );
+    }
+
+    /// Color of a texture made with new_color_texture
+    pub fn solid_color( &self ) -> Option<RGB> {
+        let image = self.image.borrow();
+        if image.width != 1 || image.height != 1 || image.data.len() < 3 { return None; }
+        if image.data_type != gl::UNSIGNED_BYTE { return None; }
+        Some( RGB::new( image.data[0], image.data[1], image.data[2] ) )
+    }
+
+    /// Swaps the GL texture of another Texture into this one.
+    /// The old texture ends up in `other`, ready to be deleted with delete_textures.
+    pub fn replace_with( &self, other:&Texture ) {
+        self.handle.swap( &other.handle );
+        self.image.swap( &other.image );
+    }
+
+    /// Regenerates every mip level from level 0, after rendering into it
+    pub fn generate_mipmaps( &self ) {
+        bind_texture( gl::TEXTURE_2D, self.handle() );
+        unsafe { gl::GenerateMipmap( gl::TEXTURE_2D ); }
+    }
 
     pub fn use_texture( &self, sampler:&Sampler, uniform_handle:GLint ) {
-        unsafe {
-            gl::ActiveTexture( sampler.handle() );
-            gl::BindTexture( gl::TEXTURE_2D, self.handle() );
-            gl::Uniform1i( uniform_handle, *sampler.id() );
-        }
+        bind_to_unit( *sampler.id(), gl::TEXTURE_2D, self.handle() );
+        bind_sampler( *sampler.id(), sampler.object_handle() );
+        unsafe { gl::Uniform1i( uniform_handle, *sampler.id() ); }
     }
 
 }
@@ -123,7 +195,154 @@ impl fmt::Display for Texture {
 }
 
 pub unsafe fn delete_textures( textures: Vec<Rc<Texture>> ) {
+    let handles = take_handles( textures, |texture| texture.handle() );
+    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+}
+
+/// Reads back a mip level of any texture without binding it.
+/// layer is the face of a cubemap or the layer of an array texture, 0 otherwise.
+pub fn read_texture_level(
+    handle:GLuint, level:GLint, layer:GLint,
+    width:GLint, height:GLint, format:GLenum, data_type:GLenum
+) -> Vec<u8> {
+    let mut data = vec![ 0u8; level_byte_size( width, height, format, data_type ) ];
+    unsafe {
+        gl::PixelStorei( gl::PACK_ALIGNMENT, 1 );
+        gl::GetTextureSubImage(
+            handle, level, 0, 0, layer, width, height, 1,
+            format, data_type, data.len() as GLsizei, data.as_mut_ptr() as *mut GLvoid
+        );
+        gl::PixelStorei( gl::PACK_ALIGNMENT, 4 );
+    }
+    data
+}
+
+/// Sets which channels are sampled as red, green, blue and alpha
+/// of the texture bound to target
+pub fn set_swizzle( target:GLenum, swizzle:[GLenum;4] ) {
+    let swizzle = swizzle.map( |channel| channel as GLint );
+    unsafe { gl::TexParameteriv( target, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr() ); }
+}
+
+/// The sRGB version of an 8-bit color internal format, if there is one
+pub fn srgb_internal_format( internal_format:GLenum ) -> Option<GLenum> {
+    match internal_format {
+        gl::RGB8  | gl::RGB  | gl::SRGB8        => Some( gl::SRGB8 ),
+        gl::RGBA8 | gl::RGBA | gl::SRGB8_ALPHA8 => Some( gl::SRGB8_ALPHA8 ),
+        _ => None,
+    }
+}
+
+/// Whether sampling a texture stored in the internal format decodes sRGB to linear
+pub fn is_srgb_internal_format( internal_format:GLenum ) -> bool {
+    matches!( internal_format, gl::SRGB8 | gl::SRGB8_ALPHA8 )
+        || super::compressed::is_srgb_compressed_format( internal_format )
+}
+
+/// Size of tightly packed pixel data
+pub fn level_byte_size( width:GLint, height:GLint, format:GLenum, data_type:GLenum ) -> usize {
+    let channels = match format {
+        gl::RED  | gl::RED_INTEGER  | gl::DEPTH_COMPONENT => 1,
+        gl::RG   | gl::RG_INTEGER   => 2,
+        gl::RGB  | gl::RGB_INTEGER  | gl::BGR  => 3,
+        _ => 4,
+    };
+    let channel_size = match data_type {
+        gl::UNSIGNED_BYTE  | gl::BYTE  => 1,
+        gl::UNSIGNED_SHORT | gl::SHORT | gl::HALF_FLOAT => 2,
+        _ => 4,
+    };
+    width.max( 0 ) as usize * height.max( 0 ) as usize * channels * channel_size
+}
+
+/// What each texture unit has bound, as far as this module knows
+struct TextureUnits {
+    active: GLint,
+    bound: Vec<Option<( GLenum, GLuint )>>,
+    /// sampler object of each unit, 0 samples with the texture's own parameters
+    samplers: Vec<GLuint>,
+}
+
+thread_local!(
+    static TEXTURE_UNITS: RefCell<Option<TextureUnits>> = RefCell::new( None );
+);
+
+fn with_texture_units<R>( f:impl FnOnce( &mut TextureUnits ) -> R ) -> R {
+    TEXTURE_UNITS.with( |units| {
+        let mut units = units.borrow_mut();
+        let units = units.get_or_insert_with( || {
+            let mut count = 0;
+            unsafe { gl::GetIntegerv( gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS, &mut count ); }
+            let count = count.max( 1 ) as usize;
+            TextureUnits { active: 0, bound: vec![ None; count ], samplers: vec![ 0; count ] }
+        } );
+        f( units )
+    } )
+}
+
+/// GL_MAX_COMBINED_TEXTURE_IMAGE_UNITS
+pub fn max_texture_units() -> usize {
+    with_texture_units( |units| units.bound.len() )
+}
+
+/// Binds a texture to the active texture unit, e.g. to upload to it
+pub fn bind_texture( target:GLenum, handle:GLuint ) {
+    with_texture_units( |units| {
+        let active = units.active as usize;
+        units.bound[active] = Some( ( target, handle ) );
+    } );
+    unsafe { gl::BindTexture( target, handle ); }
+}
 
+/// Binds a texture to a texture unit unless it's already bound there
+pub fn bind_to_unit( unit:GLint, target:GLenum, handle:GLuint ) {
+    with_texture_units( |units| {
+        let idx = unit as usize;
+        if idx >= units.bound.len() { return; }
+        if units.bound[idx] == Some( ( target, handle ) ) { return; }
+        unsafe {
+            if units.active != unit {
+                gl::ActiveTexture( gl::TEXTURE0 + unit as GLuint );
+                units.active = unit;
+            }
+            gl::BindTexture( target, handle );
+        }
+        units.bound[idx] = Some( ( target, handle ) );
+    } );
+}
+
+/// Binds a sampler object to a texture unit unless it's already bound there,
+/// 0 goes back to the parameters of the texture itself
+pub fn bind_sampler( unit:GLint, sampler:GLuint ) {
+    with_texture_units( |units| {
+        let idx = unit as usize;
+        if idx >= units.samplers.len() || units.samplers[idx] == sampler { return; }
+        unsafe { gl::BindSampler( unit as GLuint, sampler ); }
+        units.samplers[idx] = sampler;
+    } );
+}
+
+/// Drops a deleted sampler object from the units it was bound to,
+/// deleting it already unbound it
+pub fn forget_sampler( handle:GLuint ) {
+    with_texture_units( |units| for sampler in units.samplers.iter_mut() {
+        if *sampler == handle { *sampler = 0; }
+    } );
+}
+
+/// Drops a deleted texture from the units it was bound to,
+/// GL may hand its name out again
+pub fn forget_texture( handle:GLuint ) {
+    with_texture_units( |units| for binding in units.bound.iter_mut() {
+        match binding {
+            Some( ( _, bound ) ) if *bound == handle => *binding = None,
+            _ => {},
+        }
+    } );
+}
+
+/// Drops the last references to textures about to be deleted and forgets their bindings
+pub(crate) fn take_handles<T>( textures:Vec<Rc<T>>, handle:impl Fn( &T ) -> GLuint ) -> Vec<GLuint> {
     let mut handles:Vec<GLuint> = Vec::with_capacity( textures.len() );
     for texture in textures.iter() {
         let ref_count = Rc::strong_count( texture );
@@ -131,25 +350,93 @@ pub unsafe fn delete_textures( textures: Vec<Rc<Texture>> ) {
             // make sure each texture being deleted is not in use
             panic!( "Attempted to delete a texture that is still in use! Reference Count: {}", ref_count );
         }
-        handles.push( texture.handle() );
+        handles.push( handle( texture ) );
     }
     drop( textures );
-    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+    for handle in handles.iter() { forget_texture( *handle ); }
+    handles
+}
+
+/// Hands out texture units to the samplers of one draw.
+/// Textures that are already bound keep their unit,
+/// everything else goes to the units this draw isn't using.
+pub struct TextureUnitAllocator {
+    claimed: Vec<bool>,
+}
+
+impl Default for TextureUnitAllocator {
+    fn default() -> Self { Self::new() }
 }
 
+impl TextureUnitAllocator {
+    pub fn new() -> Self {
+        Self { claimed: vec![ false; max_texture_units() ] }
+    }
+
+    /// Returns the unit the texture and sampler object are bound to,
+    /// or None if every unit is taken by this draw
+    pub fn allocate( &mut self, target:GLenum, handle:GLuint, sampler:GLuint ) -> Option<GLint> {
+        let claimed = &mut self.claimed;
+        let unit = with_texture_units( |units| {
+            // already bound, by an earlier draw or an earlier sampler of this one
+            let bound = units.bound.iter().zip( units.samplers.iter() )
+                .position( |( b, s )| *b == Some( ( target, handle ) ) && *s == sampler );
+            if let Some( unit ) = bound { return Some( unit ); }
+
+            // rather replace nothing than a texture a later draw might reuse
+            units.bound.iter().enumerate()
+                .position( |( unit, b )| b.is_none() && !claimed[unit] )
+                .or_else( || claimed.iter().position( |c| !c ) )
+        } );
+
+        match unit {
+            Some( unit ) => {
+                claimed[unit] = true;
+                bind_to_unit( unit as GLint, target, handle );
+                bind_sampler( unit as GLint, sampler );
+                Some( unit as GLint )
+            },
+            None => {
+                log(
+                    &format!( "Ran out of texture units! Only {} are available.", claimed.len() ),
+                    "Texture Units | Warning"
+                );
+                None
+            },
+        }
+    }
+}
+
+/// Texture unit of a sampler uniform and the sampler object bound with its texture
 #[derive(Clone, Debug)]
-pub struct Sampler { id:GLint }
+pub struct Sampler { id:GLint, object:Option<Rc<SamplerObject>>, clear_object:bool }
 
 impl Sampler {
 
-    pub fn new( id:GLint ) -> Self { Self{ id } }
-    pub fn empty() -> Self { Self{ id:0 } }
+    pub fn new( id:GLint ) -> Self { Self{ id, object:None, clear_object:false } }
+    pub fn empty() -> Self { Self{ id:0, object:None, clear_object:false } }
 
     pub fn handle( &self ) -> GLenum {
         gl::TEXTURE0 + (self.id as GLuint)
     }
 
     pub fn id( &self ) -> &GLint { &self.id }
+    pub fn object( &self ) -> Option<&Rc<SamplerObject>> { self.object.as_ref() }
+    /// True once the object was set to None, copying this value clears the object of the uniform it lands in
+    pub fn clears_object( &self ) -> bool { self.clear_object }
+
+    /// Handle of the sampler object, 0 if the texture's own parameters are used
+    pub fn object_handle( &self ) -> GLuint {
+        self.object.as_ref().map_or( 0, |object| object.handle() )
+    }
+
+    pub fn set_id( &mut self, id:GLint ) { self.id = id; }
+
+    /// None samples with the texture's own parameters
+    pub fn set_object( &mut self, object:Option<Rc<SamplerObject>> ) {
+        self.clear_object = object.is_none();
+        self.object = object;
+    }
 
 }
 
@@ -159,22 +446,278 @@ impl fmt::Display for Sampler {
     }
 }
 
+/// Any GL texture object that can be bound to a texture or image unit
+pub trait TextureObject: fmt::Debug {
+    fn handle( &self ) -> GLuint;
+    /// Texture target the object was created with, like GL_TEXTURE_2D
+    fn target( &self ) -> GLenum;
+}
+
+impl TextureObject for Texture {
+    fn handle( &self ) -> GLuint { Texture::handle( self ) }
+    fn target( &self ) -> GLenum { gl::TEXTURE_2D }
+}
+
+/// GLSL sampler types other than sampler2D
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Sampler1D,
+    Sampler3D,
+    SamplerCube,
+    Sampler2DArray,
+    SamplerCubeArray,
+    Sampler2DShadow,
+    SamplerCubeShadow,
+    Sampler2DArrayShadow,
+    IntSampler2D,
+    IntSampler3D,
+    IntSamplerCube,
+    IntSampler2DArray,
+    UIntSampler2D,
+    UIntSampler3D,
+    UIntSamplerCube,
+    UIntSampler2DArray,
+    /// Rectangle textures are sampled with unnormalized coordinates and have no mipmaps
+    Sampler2DRect,
+    /// Multisample textures are read per sample with texelFetch, they can't be filtered
+    Sampler2DMultisample,
+    IntSampler2DMultisample,
+    UIntSampler2DMultisample,
+    /// Buffer textures are read with texelFetch, sampler objects don't apply to them
+    SamplerBuffer,
+    IntSamplerBuffer,
+    UIntSamplerBuffer,
+}
+
+impl SamplerKind {
+    pub fn from_glenum( uniform_type:GLenum ) -> Option<Self> {
+        match uniform_type {
+            gl::SAMPLER_1D                          => Some( Self::Sampler1D                ),
+            gl::SAMPLER_3D                          => Some( Self::Sampler3D                ),
+            gl::SAMPLER_CUBE                        => Some( Self::SamplerCube              ),
+            gl::SAMPLER_2D_ARRAY                    => Some( Self::Sampler2DArray           ),
+            gl::SAMPLER_CUBE_MAP_ARRAY              => Some( Self::SamplerCubeArray         ),
+            gl::SAMPLER_2D_SHADOW                   => Some( Self::Sampler2DShadow          ),
+            gl::SAMPLER_CUBE_SHADOW                 => Some( Self::SamplerCubeShadow        ),
+            gl::SAMPLER_2D_ARRAY_SHADOW             => Some( Self::Sampler2DArrayShadow     ),
+            gl::INT_SAMPLER_2D                      => Some( Self::IntSampler2D             ),
+            gl::INT_SAMPLER_3D                      => Some( Self::IntSampler3D             ),
+            gl::INT_SAMPLER_CUBE                    => Some( Self::IntSamplerCube           ),
+            gl::INT_SAMPLER_2D_ARRAY                => Some( Self::IntSampler2DArray        ),
+            gl::UNSIGNED_INT_SAMPLER_2D             => Some( Self::UIntSampler2D            ),
+            gl::UNSIGNED_INT_SAMPLER_3D             => Some( Self::UIntSampler3D            ),
+            gl::UNSIGNED_INT_SAMPLER_CUBE           => Some( Self::UIntSamplerCube          ),
+            gl::UNSIGNED_INT_SAMPLER_2D_ARRAY       => Some( Self::UIntSampler2DArray       ),
+            gl::SAMPLER_2D_RECT                     => Some( Self::Sampler2DRect            ),
+            gl::SAMPLER_2D_MULTISAMPLE              => Some( Self::Sampler2DMultisample     ),
+            gl::INT_SAMPLER_2D_MULTISAMPLE          => Some( Self::IntSampler2DMultisample  ),
+            gl::UNSIGNED_INT_SAMPLER_2D_MULTISAMPLE => Some( Self::UIntSampler2DMultisample ),
+            gl::SAMPLER_BUFFER                      => Some( Self::SamplerBuffer            ),
+            gl::INT_SAMPLER_BUFFER                  => Some( Self::IntSamplerBuffer         ),
+            gl::UNSIGNED_INT_SAMPLER_BUFFER         => Some( Self::UIntSamplerBuffer        ),
+            _ => None,
+        }
+    }
+
+    /// Float sampler type that samples textures of a target, other than sampler2D
+    pub fn from_texture_target( target:GLenum ) -> Option<Self> {
+        match target {
+            gl::TEXTURE_1D             => Some( Self::Sampler1D            ),
+            gl::TEXTURE_3D             => Some( Self::Sampler3D            ),
+            gl::TEXTURE_CUBE_MAP       => Some( Self::SamplerCube          ),
+            gl::TEXTURE_2D_ARRAY       => Some( Self::Sampler2DArray       ),
+            gl::TEXTURE_CUBE_MAP_ARRAY => Some( Self::SamplerCubeArray     ),
+            gl::TEXTURE_RECTANGLE      => Some( Self::Sampler2DRect        ),
+            gl::TEXTURE_2D_MULTISAMPLE => Some( Self::Sampler2DMultisample ),
+            gl::TEXTURE_BUFFER         => Some( Self::SamplerBuffer        ),
+            _ => None,
+        }
+    }
+
+    /// Shadow samplers are the only ones sampler objects with depth compare give defined results on
+    pub fn is_shadow( &self ) -> bool {
+        matches!( self, Self::Sampler2DShadow | Self::SamplerCubeShadow | Self::Sampler2DArrayShadow )
+    }
+
+    /// Texture target a texture needs to be sampled by this sampler
+    pub fn texture_target( &self ) -> GLenum {
+        match self {
+            Self::Sampler2DShadow   |
+            Self::IntSampler2D      |
+            Self::UIntSampler2D        => gl::TEXTURE_2D,
+            Self::Sampler3D         |
+            Self::IntSampler3D      |
+            Self::UIntSampler3D        => gl::TEXTURE_3D,
+            Self::SamplerCube       |
+            Self::SamplerCubeShadow |
+            Self::IntSamplerCube    |
+            Self::UIntSamplerCube      => gl::TEXTURE_CUBE_MAP,
+            Self::Sampler2DArray       |
+            Self::Sampler2DArrayShadow |
+            Self::IntSampler2DArray    |
+            Self::UIntSampler2DArray   => gl::TEXTURE_2D_ARRAY,
+            Self::SamplerCubeArray     => gl::TEXTURE_CUBE_MAP_ARRAY,
+            Self::Sampler1D            => gl::TEXTURE_1D,
+            Self::Sampler2DRect        => gl::TEXTURE_RECTANGLE,
+            Self::Sampler2DMultisample     |
+            Self::IntSampler2DMultisample  |
+            Self::UIntSampler2DMultisample => gl::TEXTURE_2D_MULTISAMPLE,
+            Self::SamplerBuffer     |
+            Self::IntSamplerBuffer  |
+            Self::UIntSamplerBuffer    => gl::TEXTURE_BUFFER,
+        }
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::Sampler3D                => "Sampler3D",
+            Self::SamplerCube              => "SamplerCube",
+            Self::Sampler2DArray           => "Sampler2DArray",
+            Self::SamplerCubeArray         => "SamplerCubeArray",
+            Self::Sampler2DShadow          => "Sampler2DShadow",
+            Self::SamplerCubeShadow        => "SamplerCubeShadow",
+            Self::Sampler2DArrayShadow     => "Sampler2DArrayShadow",
+            Self::IntSampler2D             => "ISampler2D",
+            Self::IntSampler3D             => "ISampler3D",
+            Self::IntSamplerCube           => "ISamplerCube",
+            Self::IntSampler2DArray        => "ISampler2DArray",
+            Self::UIntSampler2D            => "USampler2D",
+            Self::UIntSampler3D            => "USampler3D",
+            Self::UIntSamplerCube          => "USamplerCube",
+            Self::UIntSampler2DArray       => "USampler2DArray",
+            Self::Sampler1D                => "Sampler1D",
+            Self::Sampler2DRect            => "Sampler2DRect",
+            Self::Sampler2DMultisample     => "Sampler2DMS",
+            Self::IntSampler2DMultisample  => "ISampler2DMS",
+            Self::UIntSampler2DMultisample => "USampler2DMS",
+            Self::SamplerBuffer            => "SamplerBuffer",
+            Self::IntSamplerBuffer         => "ISamplerBuffer",
+            Self::UIntSamplerBuffer        => "USamplerBuffer",
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
+
+/// True for every GLSL image type, like image2D or uimageCube
+pub fn is_image_type( uniform_type:GLenum ) -> bool {
+    match uniform_type {
+        gl::IMAGE_1D | gl::IMAGE_2D | gl::IMAGE_3D | gl::IMAGE_CUBE | gl::IMAGE_2D_ARRAY | gl::IMAGE_BUFFER |
+        gl::INT_IMAGE_1D | gl::INT_IMAGE_2D | gl::INT_IMAGE_3D | gl::INT_IMAGE_CUBE |
+        gl::INT_IMAGE_2D_ARRAY | gl::INT_IMAGE_BUFFER |
+        gl::UNSIGNED_INT_IMAGE_1D | gl::UNSIGNED_INT_IMAGE_2D | gl::UNSIGNED_INT_IMAGE_3D |
+        gl::UNSIGNED_INT_IMAGE_CUBE | gl::UNSIGNED_INT_IMAGE_2D_ARRAY | gl::UNSIGNED_INT_IMAGE_BUFFER => true,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+impl ImageAccess {
+    pub fn as_glenum( &self ) -> GLenum {
+        match self {
+            Self::ReadOnly  => gl::READ_ONLY ,
+            Self::WriteOnly => gl::WRITE_ONLY,
+            Self::ReadWrite => gl::READ_WRITE,
+        }
+    }
+
+    pub fn msg( &self ) -> &str {
+        match self {
+            Self::ReadOnly  => "Read Only" ,
+            Self::WriteOnly => "Write Only",
+            Self::ReadWrite => "Read Write",
+        }
+    }
+}
+
+impl fmt::Display for ImageAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
+
+/// How a texture is bound to an image unit for image load/store
+#[derive(Clone, Copy, Debug)]
+pub struct ImageBinding {
+    unit:GLuint,
+    level:GLint,
+    layered:bool,
+    access:ImageAccess,
+    format:GLenum,
+}
+
+impl ImageBinding {
+    pub fn new( unit:GLuint ) -> Self {
+        Self { unit, level: 0, layered: false, access: ImageAccess::ReadWrite, format: gl::RGBA8 }
+    }
+
+    pub fn unit(&self)    -> GLuint      { self.unit    }
+    pub fn level(&self)   -> GLint       { self.level   }
+    pub fn layered(&self) -> bool        { self.layered }
+    pub fn access(&self)  -> ImageAccess { self.access  }
+    /// Internal format the shader reads and writes as, like GL_RGBA32F
+    pub fn format(&self)  -> GLenum      { self.format  }
+
+    pub fn set_unit( &mut self, unit:GLuint ) { self.unit = unit; }
+    pub fn set_level( &mut self, level:GLint ) { self.level = level; }
+    /// Binds every layer of array, cube and 3D textures instead of only layer 0
+    pub fn set_layered( &mut self, layered:bool ) { self.layered = layered; }
+    pub fn set_access( &mut self, access:ImageAccess ) { self.access = access; }
+    pub fn set_format( &mut self, format:GLenum ) { self.format = format; }
+
+    pub fn bind( &self, texture:&dyn TextureObject ) {
+        unsafe {
+            gl::BindImageTexture(
+                self.unit, texture.handle(), self.level,
+                if self.layered { gl::TRUE } else { gl::FALSE }, 0,
+                self.access.as_glenum(), self.format
+            );
+        }
+    }
+}
+
+impl fmt::Display for ImageBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "IMAGE {} | level: {} {} {:#06X}", self.unit, self.level, self.access, self.format )
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct TextureOptions {
-    wrapping_x:TextureWrapping, wrapping_y:TextureWrapping,
+    wrapping_x:TextureWrapping, wrapping_y:TextureWrapping, wrapping_z:TextureWrapping,
     min_filtering:MipmapFiltering, mag_filtering:TextureFiltering,
-    border_color:Option<RGB>
+    border_color:Option<RGB>,
+    srgb:bool,
 }
 
 impl TextureOptions {
     pub fn default() -> Self {
         Self {
             wrapping_x: TextureWrapping::Repeat, wrapping_y: TextureWrapping::Repeat,
+            wrapping_z: TextureWrapping::Repeat,
             min_filtering: MipmapFiltering::LinearLinear, mag_filtering: TextureFiltering::Linear,
-            border_color:None
+            border_color:None, srgb: false
         }
     }
 
+    pub fn wrapping_x( &self )    -> TextureWrapping  { self.wrapping_x    }
+    pub fn wrapping_y( &self )    -> TextureWrapping  { self.wrapping_y    }
+    pub fn wrapping_z( &self )    -> TextureWrapping  { self.wrapping_z    }
+    pub fn min_filtering( &self ) -> MipmapFiltering  { self.min_filtering }
+    pub fn mag_filtering( &self ) -> TextureFiltering { self.mag_filtering }
+    pub fn border_color( &self )  -> Option<RGB>      { self.border_color  }
+    pub fn srgb( &self )          -> bool             { self.srgb          }
+
     pub fn set_border_color( &mut self, c:RGB ) {
         self.border_color = Some(c);
     }
@@ -182,6 +725,7 @@ impl TextureOptions {
     pub fn set_wrapping( &mut self, wrapping:TextureWrapping ) {
         self.wrapping_x = wrapping;
         self.wrapping_y = wrapping;
+        self.wrapping_z = wrapping;
     }
 
     pub fn set_wrapping_x( &mut self, wrapping:TextureWrapping ) {
@@ -192,6 +736,11 @@ impl TextureOptions {
         self.wrapping_y = wrapping;
     }
 
+    /// Wrapping across the depth of 3D textures, 2D textures ignore it
+    pub fn set_wrapping_z( &mut self, wrapping:TextureWrapping ) {
+        self.wrapping_z = wrapping;
+    }
+
     pub fn set_min_filtering( &mut self, filtering:MipmapFiltering ) {
         self.min_filtering = filtering;
     }
@@ -200,6 +749,35 @@ impl TextureOptions {
         self.mag_filtering = filtering;
     }
 
+    /// Stores color textures as sRGB so sampling them returns linear colors.
+    /// Only 8-bit RGB and RGBA images have sRGB formats,
+    /// leave it off for data like normal, roughness and height maps.
+    /// The pbr shader expects it on its base color and emissive maps,
+    /// blinn-phong lights colors as they're stored and expects it off.
+    pub fn set_srgb( &mut self, srgb:bool ) {
+        self.srgb = srgb;
+    }
+
+    /// Internal format an image is uploaded with under these options
+    pub fn internal_format( &self, image:&ImageGL ) -> GLenum {
+        self.internal_format_of( image.internal_format )
+    }
+
+    /// Like internal_format, for data that isn't an ImageGL
+    pub fn internal_format_of( &self, internal_format:GLenum ) -> GLenum {
+        if !self.srgb { return internal_format; }
+        match srgb_internal_format( internal_format ) {
+            Some( srgb ) => srgb,
+            None => {
+                log(
+                    &format!( "No sRGB format for internal format {:#06X}, storing it as linear.", internal_format ),
+                    "Texture | Warning"
+                );
+                internal_format
+            },
+        }
+    }
+
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -219,6 +797,26 @@ impl TextureWrapping {
             Self::ClampToBorder => gl::CLAMP_TO_BORDER as GLint,
         }
     }
+
+    /// Name used in material files
+    pub fn name( &self ) -> &str {
+        match self {
+            Self::Repeat         => "repeat",
+            Self::MirroredRepeat => "mirrored_repeat",
+            Self::ClampToEdge    => "clamp_to_edge",
+            Self::ClampToBorder  => "clamp_to_border",
+        }
+    }
+
+    pub fn from_name( name:&str ) -> Option<Self> {
+        match name {
+            "repeat"          => Some( Self::Repeat         ),
+            "mirrored_repeat" => Some( Self::MirroredRepeat ),
+            "clamp_to_edge"   => Some( Self::ClampToEdge    ),
+            "clamp_to_border" => Some( Self::ClampToBorder  ),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -234,6 +832,22 @@ impl TextureFiltering {
             Self::Linear  => gl::LINEAR  as GLint,
         }
     }
+
+    /// Name used in material files
+    pub fn name( &self ) -> &str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Linear  => "linear",
+        }
+    }
+
+    pub fn from_name( name:&str ) -> Option<Self> {
+        match name {
+            "nearest" => Some( Self::Nearest ),
+            "linear"  => Some( Self::Linear  ),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -242,6 +856,9 @@ pub enum MipmapFiltering {
     LinearNearest,
     NearestLinear,
     LinearLinear,
+    /// Level 0 only, for textures without mipmaps like depth maps and render targets
+    Nearest,
+    Linear,
 }
 
 impl MipmapFiltering {
@@ -251,6 +868,32 @@ impl MipmapFiltering {
             Self::LinearNearest  => gl::LINEAR_MIPMAP_NEAREST  as GLint,
             Self::NearestLinear  => gl::NEAREST_MIPMAP_LINEAR  as GLint,
             Self::LinearLinear   => gl::LINEAR_MIPMAP_LINEAR   as GLint,
+            Self::Nearest        => gl::NEAREST                as GLint,
+            Self::Linear         => gl::LINEAR                 as GLint,
+        }
+    }
+
+    /// Name used in material files
+    pub fn name( &self ) -> &str {
+        match self {
+            Self::NearestNearest => "nearest_nearest",
+            Self::LinearNearest  => "linear_nearest",
+            Self::NearestLinear  => "nearest_linear",
+            Self::LinearLinear   => "linear_linear",
+            Self::Nearest        => "nearest",
+            Self::Linear         => "linear",
+        }
+    }
+
+    pub fn from_name( name:&str ) -> Option<Self> {
+        match name {
+            "nearest_nearest" => Some( Self::NearestNearest ),
+            "linear_nearest"  => Some( Self::LinearNearest  ),
+            "nearest_linear"  => Some( Self::NearestLinear  ),
+            "linear_linear"   => Some( Self::LinearLinear   ),
+            "nearest"         => Some( Self::Nearest        ),
+            "linear"          => Some( Self::Linear         ),
+            _ => None,
         }
     }
 }
@@ -260,6 +903,10 @@ pub struct ImageGL {
     pub width:GLint, pub height:GLint,
     pub internal_format:GLenum,
     pub format:GLenum,
+    /// Type of each channel in data, GL_UNSIGNED_BYTE, GL_UNSIGNED_SHORT or GL_FLOAT
+    pub data_type:GLenum,
+    /// Channels sampled as red, green, blue and alpha, so greyscale images are grey
+    pub swizzle:Option<[GLenum;4]>,
     pub data:Vec<u8>
 }
 
@@ -269,25 +916,48 @@ impl ImageGL {
         Self {
             width:  0, height: 0,
             internal_format: 0, format: 0,
+            data_type: gl::UNSIGNED_BYTE,
+            swizzle: None,
             data: Vec::new()
         }
     }
 
     pub fn from_dynamic_image( dynamic_image: image::DynamicImage ) -> Result<Self, Error> {
+        Self::from_dynamic_image_unflipped( dynamic_image.flipv() )
+    }
 
-        let dynamic = dynamic_image.flipv();
-
-        let ( format, data) = match dynamic.color() {
-            image::ColorType::Rgb8  => ( gl::RGB,  dynamic.to_rgb8().as_raw().clone()  ),
-            image::ColorType::Rgba8 => ( gl::RGBA, dynamic.to_rgba8().as_raw().clone() ),
-            _ => return Err( Error::TextureUnsupportedColorFormat ),
+    /// Keeps the top row first, the way cubemap faces are expected
+    pub fn from_dynamic_image_unflipped( dynamic: image::DynamicImage ) -> Result<Self, Error> {
+
+        use image::ColorType;
+        // 8 and 16-bit images keep their precision in normalized formats.
+        // HDR (Radiance) and OpenEXR images decode to 32-bit floats,
+        // half floats keep their range at half the memory
+        let ( internal_format, format, data_type, data ) = match dynamic.color() {
+            ColorType::L8    => ( gl::R8,    gl::RED,  gl::UNSIGNED_BYTE, dynamic.to_luma8().into_raw()       ),
+            ColorType::La8   => ( gl::RG8,   gl::RG,   gl::UNSIGNED_BYTE, dynamic.to_luma_alpha8().into_raw() ),
+            ColorType::Rgb8  => ( gl::RGB8,  gl::RGB,  gl::UNSIGNED_BYTE, dynamic.to_rgb8().into_raw()        ),
+            ColorType::Rgba8 => ( gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, dynamic.to_rgba8().into_raw()       ),
+            ColorType::L16    => ( gl::R16,    gl::RED,  gl::UNSIGNED_SHORT, short_bytes( dynamic.to_luma16().as_raw() )       ),
+            ColorType::La16   => ( gl::RG16,   gl::RG,   gl::UNSIGNED_SHORT, short_bytes( dynamic.to_luma_alpha16().as_raw() ) ),
+            ColorType::Rgb16  => ( gl::RGB16,  gl::RGB,  gl::UNSIGNED_SHORT, short_bytes( dynamic.to_rgb16().as_raw() )        ),
+            ColorType::Rgba16 => ( gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT, short_bytes( dynamic.to_rgba16().as_raw() )       ),
+            ColorType::Rgb32F  => ( gl::RGB16F,  gl::RGB,  gl::FLOAT, float_bytes( dynamic.to_rgb32f().as_raw() )  ),
+            ColorType::Rgba32F => ( gl::RGBA16F, gl::RGBA, gl::FLOAT, float_bytes( dynamic.to_rgba32f().as_raw() ) ),
+            // color types newer versions of image add, floats lose the least
+            _ => ( gl::RGBA16F, gl::RGBA, gl::FLOAT, float_bytes( dynamic.to_rgba32f().as_raw() ) ),
+        };
+        let swizzle = match format {
+            gl::RED => Some( GREY_SWIZZLE ),
+            gl::RG  => Some( GREY_ALPHA_SWIZZLE ),
+            _ => None,
         };
 
         Ok( 
             Self {
                 width: dynamic.width() as GLint,
                 height: dynamic.height() as GLint,
-                internal_format: format, format,
+                internal_format, format, data_type, swizzle,
                 data
             }
         )
@@ -296,6 +966,21 @@ impl ImageGL {
 
 }
 
+/// Greyscale images in GL_RED
+pub const GREY_SWIZZLE:[GLenum;4]       = [ gl::RED, gl::RED, gl::RED, gl::ONE   ];
+/// Greyscale images with alpha in GL_RG
+pub const GREY_ALPHA_SWIZZLE:[GLenum;4] = [ gl::RED, gl::RED, gl::RED, gl::GREEN ];
+
+/// Floats in the byte order GL reads them in
+fn float_bytes( floats:&[f32] ) -> Vec<u8> {
+    floats.iter().flat_map( |f| f.to_ne_bytes() ).collect()
+}
+
+/// 16-bit channels in the byte order GL reads them in
+fn short_bytes( shorts:&[u16] ) -> Vec<u8> {
+    shorts.iter().flat_map( |s| s.to_ne_bytes() ).collect()
+}
+
 pub fn null_texture() -> Rc<Texture> {
     thread_local!(
         static NULL_TEXTURE: Rc<Texture> = Texture::null_color();
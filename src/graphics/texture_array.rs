@@ -0,0 +1,401 @@
+use gl::types::*;
+use crate::{ Rc, debugging::Error };
+use core::fmt;
+use super::{
+    compressed::{ CompressedImage, CompressedFormat, upload_compressed },
+    texture::{
+        ImageGL, TextureOptions, TextureObject,
+        bind_texture, take_handles, level_byte_size, set_swizzle,
+    },
+};
+
+/// Layers of same-sized 2D images sampled through a sampler2DArray uniform,
+/// with texture( sampler, vec3( uv, layer ) ).
+/// For terrain splat layers, sprite animation frames and the like.
+#[derive(Debug)]
+pub struct Texture2DArray {
+    handle:GLuint,
+    width:GLint, height:GLint,
+    layers:GLint,
+    internal_format:GLenum,
+    /// Compressed arrays can't have layers updated
+    compressed_format:Option<CompressedFormat>,
+    options:TextureOptions,
+}
+
+impl Texture2DArray {
+
+    /// Creates an array from images that share a size, format and type, one layer each
+    pub fn new( layers:Vec<ImageGL>, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        let first = match layers.first() {
+            Some( first ) => first,
+            None => return Err( Error::TextureInvalidImage( "A texture array needs at least one layer!".to_owned() ) ),
+        };
+        for ( layer, image ) in layers.iter().enumerate() {
+            if image.format != first.format || image.data_type != first.data_type {
+                return Err( Error::TextureInvalidImage( format!(
+                    "Texture array layer {} has format {:#06X} type {:#06X}, layer 0 has format {:#06X} type {:#06X}!",
+                    layer, image.format, image.data_type, first.format, first.data_type
+                ) ) );
+            }
+            check_image_size( image, first.width, first.height, &format!( "Texture array layer {}", layer ) )?;
+        }
+
+        let internal_format = options.internal_format( first );
+        let mut handle = 0;
+        unsafe {
+
+            gl::GenTextures( 1, &mut handle );
+            bind_texture( gl::TEXTURE_2D_ARRAY, handle );
+            set_parameters( gl::TEXTURE_2D_ARRAY, &options );
+
+            match first.swizzle {
+                Some( swizzle ) => set_swizzle( gl::TEXTURE_2D_ARRAY, swizzle ),
+                None => {},
+            }
+
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY, 0, internal_format as GLint,
+                first.width, first.height, layers.len() as GLsizei,
+                0, first.format, first.data_type, core::ptr::null()
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            for ( layer, image ) in layers.iter().enumerate() {
+                gl::TexSubImage3D(
+                    gl::TEXTURE_2D_ARRAY, 0, 0, 0, layer as GLint,
+                    image.width, image.height, 1,
+                    image.format, image.data_type, image.data.as_ptr() as *const GLvoid
+                );
+            }
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+
+            gl::GenerateMipmap( gl::TEXTURE_2D_ARRAY );
+
+        }
+
+        Ok( Rc::new( Self {
+            handle, width: first.width, height: first.height, layers: layers.len() as GLint,
+            internal_format, compressed_format: None, options
+        } ) )
+    }
+
+    /// Uploads a compressed 2D array with its mip chain,
+    /// errors if the image isn't a 2D array or the driver lacks its format
+    pub fn from_compressed( image:&CompressedImage, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        if image.target() != gl::TEXTURE_2D_ARRAY {
+            return Err( Error::TextureInvalidImage( format!(
+                "A {} {}x{} image with {} layers and {} faces isn't a 2D texture array!",
+                image.format, image.width, image.height, image.layers, image.faces
+            ) ) );
+        }
+        let handle = upload_compressed( image, &options )?;
+        Ok( Rc::new( Self {
+            handle, width: image.width, height: image.height, layers: image.layers,
+            internal_format: image.internal_format( &options ),
+            compressed_format: Some( image.format ), options
+        } ) )
+    }
+
+    /// Replaces a layer's full size image.
+    /// Smaller levels keep the old image until generate_mipmaps is called,
+    /// so several layers can be updated before regenerating them once.
+    pub fn update_layer( &self, layer:GLint, image:&ImageGL ) -> Result<(), Error> {
+        match self.compressed_format {
+            Some( format ) => return Err( Error::TextureInvalidImage( format!(
+                "Layers of {} texture array {} can't be updated!", format, self.handle
+            ) ) ),
+            None => {},
+        }
+        if layer < 0 || layer >= self.layers {
+            return Err( Error::TextureInvalidImage( format!(
+                "Texture array {} has no layer {}, it has {} layers!", self.handle, layer, self.layers
+            ) ) );
+        }
+        check_image_size( image, self.width, self.height, "Texture array layer" )?;
+        check_internal_format( self.options.internal_format( image ), self.internal_format, "Texture array layer" )?;
+
+        bind_texture( gl::TEXTURE_2D_ARRAY, self.handle );
+        unsafe {
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            gl::TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY, 0, 0, 0, layer,
+                image.width, image.height, 1,
+                image.format, image.data_type, image.data.as_ptr() as *const GLvoid
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+        }
+        Ok(())
+    }
+
+    /// Regenerates every mip level of every layer from level 0
+    pub fn generate_mipmaps( &self ) {
+        if self.compressed_format.is_some() { return; }
+        bind_texture( gl::TEXTURE_2D_ARRAY, self.handle );
+        unsafe { gl::GenerateMipmap( gl::TEXTURE_2D_ARRAY ); }
+    }
+
+    pub fn handle( &self )          -> GLuint          { self.handle          }
+    pub fn width( &self )           -> GLint           { self.width           }
+    pub fn height( &self )          -> GLint           { self.height          }
+    pub fn layers( &self )          -> GLint           { self.layers          }
+    pub fn internal_format( &self ) -> GLenum          { self.internal_format }
+    pub fn options( &self )         -> &TextureOptions { &self.options        }
+}
+
+impl TextureObject for Texture2DArray {
+    fn handle( &self ) -> GLuint { Texture2DArray::handle( self ) }
+    fn target( &self ) -> GLenum { gl::TEXTURE_2D_ARRAY }
+}
+
+impl fmt::Display for Texture2DArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Texture2DArray {} | width: {} height: {} layers: {}",
+            self.handle, self.width, self.height, self.layers
+        )
+    }
+}
+
+/// Voxels of one format and type, depth slices of width x height one after another
+#[derive(Debug)]
+pub struct VolumeGL {
+    pub width:GLint, pub height:GLint, pub depth:GLint,
+    pub internal_format:GLenum,
+    pub format:GLenum,
+    pub data_type:GLenum,
+    pub swizzle:Option<[GLenum;4]>,
+    pub data:Vec<u8>
+}
+
+impl VolumeGL {
+    /// Stacks same-sized images into a volume, the first image is slice 0
+    pub fn from_slices( slices:Vec<ImageGL> ) -> Result<Self, Error> {
+        let first = match slices.first() {
+            Some( first ) => first,
+            None => return Err( Error::TextureInvalidImage( "A volume needs at least one slice!".to_owned() ) ),
+        };
+        let mut volume = Self {
+            width: first.width, height: first.height, depth: slices.len() as GLint,
+            internal_format: first.internal_format, format: first.format, data_type: first.data_type,
+            swizzle: first.swizzle,
+            data: Vec::with_capacity( first.data.len() * slices.len() ),
+        };
+        for ( slice, image ) in slices.iter().enumerate() {
+            if image.format != volume.format || image.data_type != volume.data_type {
+                return Err( Error::TextureInvalidImage( format!(
+                    "Volume slice {} has format {:#06X} type {:#06X}, slice 0 has format {:#06X} type {:#06X}!",
+                    slice, image.format, image.data_type, volume.format, volume.data_type
+                ) ) );
+            }
+            check_image_size( image, volume.width, volume.height, &format!( "Volume slice {}", slice ) )?;
+            volume.data.extend_from_slice( &image.data );
+        }
+        Ok( volume )
+    }
+}
+
+/// Volume texture sampled through a sampler3D uniform,
+/// filtered between slices as well as within them.
+/// For volumetric fog, 3D noise, color grading tables and the like.
+#[derive(Debug)]
+pub struct Texture3D {
+    handle:GLuint,
+    width:GLint, height:GLint, depth:GLint,
+    internal_format:GLenum,
+    options:TextureOptions,
+}
+
+impl Texture3D {
+
+    /// Creates a volume texture, a volume without data has undefined contents
+    /// to write with compute shaders
+    pub fn new( volume:&VolumeGL, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        check_volume_size( volume, volume.width, volume.height, volume.depth )?;
+        let internal_format = options.internal_format_of( volume.internal_format );
+
+        let mut handle = 0;
+        unsafe {
+
+            gl::GenTextures( 1, &mut handle );
+            bind_texture( gl::TEXTURE_3D, handle );
+            set_parameters( gl::TEXTURE_3D, &options );
+
+            match volume.swizzle {
+                Some( swizzle ) => set_swizzle( gl::TEXTURE_3D, swizzle ),
+                None => {},
+            }
+
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            gl::TexImage3D(
+                gl::TEXTURE_3D, 0, internal_format as GLint,
+                volume.width, volume.height, volume.depth,
+                0, volume.format, volume.data_type,
+                if volume.data.is_empty() { core::ptr::null() }
+                else { volume.data.as_ptr() as *const GLvoid }
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+
+            gl::GenerateMipmap( gl::TEXTURE_3D );
+
+        }
+
+        Ok( Rc::new( Self {
+            handle, width: volume.width, height: volume.height, depth: volume.depth,
+            internal_format, options
+        } ) )
+    }
+
+    /// Creates a volume from same-sized images, one slice each
+    pub fn from_slices( slices:Vec<ImageGL>, options:TextureOptions ) -> Result<Rc<Self>, Error> {
+        Self::new( &VolumeGL::from_slices( slices )?, options )
+    }
+
+    /// Replaces a slice's full size image,
+    /// smaller levels keep the old data until generate_mipmaps is called
+    pub fn update_slice( &self, slice:GLint, image:&ImageGL ) -> Result<(), Error> {
+        if slice < 0 || slice >= self.depth {
+            return Err( Error::TextureInvalidImage( format!(
+                "Texture3D {} has no slice {}, it has {} slices!", self.handle, slice, self.depth
+            ) ) );
+        }
+        check_image_size( image, self.width, self.height, "Volume slice" )?;
+        check_internal_format( self.options.internal_format( image ), self.internal_format, "Volume slice" )?;
+
+        bind_texture( gl::TEXTURE_3D, self.handle );
+        unsafe {
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            gl::TexSubImage3D(
+                gl::TEXTURE_3D, 0, 0, 0, slice,
+                image.width, image.height, 1,
+                image.format, image.data_type, image.data.as_ptr() as *const GLvoid
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+        }
+        Ok(())
+    }
+
+    /// Replaces the whole full size level with a volume of the same size
+    pub fn update_volume( &self, volume:&VolumeGL ) -> Result<(), Error> {
+        if volume.data.is_empty() {
+            return Err( Error::TextureInvalidImage( format!( "Texture3D {} can't be updated without data!", self.handle ) ) );
+        }
+        check_volume_size( volume, self.width, self.height, self.depth )?;
+        check_internal_format( self.options.internal_format_of( volume.internal_format ), self.internal_format, "Volume" )?;
+
+        bind_texture( gl::TEXTURE_3D, self.handle );
+        unsafe {
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 1 );
+            gl::TexSubImage3D(
+                gl::TEXTURE_3D, 0, 0, 0, 0,
+                volume.width, volume.height, volume.depth,
+                volume.format, volume.data_type, volume.data.as_ptr() as *const GLvoid
+            );
+            gl::PixelStorei( gl::UNPACK_ALIGNMENT, 4 );
+        }
+        Ok(())
+    }
+
+    /// Regenerates every mip level from level 0
+    pub fn generate_mipmaps( &self ) {
+        bind_texture( gl::TEXTURE_3D, self.handle );
+        unsafe { gl::GenerateMipmap( gl::TEXTURE_3D ); }
+    }
+
+    pub fn handle( &self )          -> GLuint          { self.handle          }
+    pub fn width( &self )           -> GLint           { self.width           }
+    pub fn height( &self )          -> GLint           { self.height          }
+    pub fn depth( &self )           -> GLint           { self.depth           }
+    pub fn internal_format( &self ) -> GLenum          { self.internal_format }
+    pub fn options( &self )         -> &TextureOptions { &self.options        }
+}
+
+impl TextureObject for Texture3D {
+    fn handle( &self ) -> GLuint { Texture3D::handle( self ) }
+    fn target( &self ) -> GLenum { gl::TEXTURE_3D }
+}
+
+impl fmt::Display for Texture3D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Texture3D {} | width: {} height: {} depth: {}",
+            self.handle, self.width, self.height, self.depth
+        )
+    }
+}
+
+/// Deletes texture arrays, panics if any of them is still used elsewhere
+///
+/// # Safety
+///
+/// Needs a current GL context, the one the textures were created in.
+pub unsafe fn delete_texture_arrays( textures: Vec<Rc<Texture2DArray>> ) {
+    let handles = take_handles( textures, |texture| texture.handle() );
+    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+}
+
+/// Deletes volume textures, panics if any of them is still used elsewhere
+///
+/// # Safety
+///
+/// Needs a current GL context, the one the textures were created in.
+pub unsafe fn delete_textures_3d( textures: Vec<Rc<Texture3D>> ) {
+    let handles = take_handles( textures, |texture| texture.handle() );
+    gl::DeleteTextures( handles.len() as GLsizei, handles.as_ptr() );
+}
+
+/// Wrapping, filtering and border color of the texture bound to target
+unsafe fn set_parameters( target:GLenum, options:&TextureOptions ) {
+    gl::TexParameteri( target, gl::TEXTURE_WRAP_S, options.wrapping_x().as_glint() );
+    gl::TexParameteri( target, gl::TEXTURE_WRAP_T, options.wrapping_y().as_glint() );
+    if target == gl::TEXTURE_3D {
+        gl::TexParameteri( target, gl::TEXTURE_WRAP_R, options.wrapping_z().as_glint() );
+    }
+    gl::TexParameteri( target, gl::TEXTURE_MIN_FILTER, options.min_filtering().as_glint() );
+    gl::TexParameteri( target, gl::TEXTURE_MAG_FILTER, options.mag_filtering().as_glint() );
+    match options.border_color() {
+        Some( color ) => {
+            let color = color.as_array_rgba_f32();
+            gl::TexParameterfv( target, gl::TEXTURE_BORDER_COLOR, color.as_ptr() );
+        },
+        None => {},
+    }
+}
+
+fn check_image_size( image:&ImageGL, width:GLint, height:GLint, name:&str ) -> Result<(), Error> {
+    if image.width != width || image.height != height {
+        return Err( Error::TextureInvalidImage( format!(
+            "{} is {}x{}, it has to be {}x{}!", name, image.width, image.height, width, height
+        ) ) );
+    }
+    let expected = level_byte_size( width, height, image.format, image.data_type );
+    if image.data.len() != expected {
+        return Err( Error::TextureInvalidImage( format!(
+            "{} needs {} bytes, got {}!", name, expected, image.data.len()
+        ) ) );
+    }
+    Ok(())
+}
+
+/// Uploads can convert between formats and types but not change the texture's internal format
+fn check_internal_format( internal_format:GLenum, expected:GLenum, name:&str ) -> Result<(), Error> {
+    if internal_format != expected {
+        return Err( Error::TextureInvalidImage( format!(
+            "{} has internal format {:#06X}, the texture is {:#06X}!", name, internal_format, expected
+        ) ) );
+    }
+    Ok(())
+}
+
+fn check_volume_size( volume:&VolumeGL, width:GLint, height:GLint, depth:GLint ) -> Result<(), Error> {
+    if volume.width != width || volume.height != height || volume.depth != depth {
+        return Err( Error::TextureInvalidImage( format!(
+            "Volume is {}x{}x{}, it has to be {}x{}x{}!",
+            volume.width, volume.height, volume.depth, width, height, depth
+        ) ) );
+    }
+    let expected = level_byte_size( width, height, volume.format, volume.data_type ) * depth.max( 0 ) as usize;
+    if !volume.data.is_empty() && volume.data.len() != expected {
+        return Err( Error::TextureInvalidImage( format!(
+            "A {}x{}x{} volume needs {} bytes, got {}!", width, height, depth, expected, volume.data.len()
+        ) ) );
+    }
+    Ok(())
+}
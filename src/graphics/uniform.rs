@@ -1,8 +1,12 @@
 use crate::{ cstr::*, debugging::{log, Error}, Rc };
 use gl::types::*;
 use fmath::types::*;
-use super::{ Sampler, ShaderProgram, Texture, texture };
-use core::fmt;
+use super::{
+    Sampler, ShaderProgram, Texture,
+    sampler::SamplerObject,
+    texture::{ self, TextureObject, TextureUnitAllocator, SamplerKind, ImageBinding, ImageAccess },
+};
+use core::{ fmt, ops::{ Index, IndexMut } };
 
 pub struct UniformInfo {
     name:     CString,
@@ -22,20 +26,16 @@ impl UniformInfo {
 
     pub fn generate_values( uniforms:&Vec<Self> ) -> Vec<Uniform> {
         let mut buffer = Vec::with_capacity( uniforms.len() );
-        let mut sampler_count = 0;
+        // texture units are picked when a material is bound, image units are fixed.
+        // Samplers start out with the shared default of their target,
+        // null_texture for sampler2D and texture 0 for every other kind.
+        let mut image_count = 0;
         for uniform in uniforms.iter() {
             let mut uniform_value = Uniform::new( uniform.kind(), uniform.location() );
-            match uniform.kind() {
-                gl::SAMPLER_2D => {
-                    if sampler_count == 0 {
-                        uniform_value.set_sampler2d_location( Sampler::new( sampler_count ) );
-                    } else {
-                        uniform_value.set_sampler2d(
-                            Texture::new_color_texture( color::RGB::new_black() ),
-                            Sampler::new( sampler_count )
-                        );
-                    }
-                    sampler_count += 1;
+            match &mut uniform_value {
+                Uniform::Image( ( _, binding ), _ ) => {
+                    binding.set_unit( image_count );
+                    image_count += 1;
                 },
                 _ => {}
             }
@@ -44,6 +44,41 @@ impl UniformInfo {
         buffer
     }
 
+    /// Generates values like generate_values, grouping array elements
+    /// and struct members into Array and Struct uniforms.
+    /// Returns the name and value of each top level uniform.
+    pub fn generate_tree( uniforms:&Vec<Self> ) -> ( Vec<String>, Vec<Uniform> ) {
+        let mut names:Vec<String>   = Vec::new();
+        let mut roots:Vec<Uniform> = Vec::new();
+        for ( info, value ) in uniforms.iter().zip( Self::generate_values( uniforms ).into_iter() ) {
+            let name = match info.name().to_str() {
+                Ok( name ) => name,
+                Err(_) => continue,
+            };
+            let path = match parse_uniform_path( name ) {
+                Ok( path ) => path,
+                Err( e ) => {
+                    log( e.msg(), "Uniform Info | Warning" );
+                    continue;
+                }
+            };
+            let root_name = match path.first() {
+                Some( UniformPathSegment::Member( root_name ) ) => root_name.clone(),
+                _ => continue,
+            };
+            let idx = match names.iter().position( |n| *n == root_name ) {
+                Some( idx ) => idx,
+                None => {
+                    names.push( root_name );
+                    roots.push( Uniform::None );
+                    roots.len() - 1
+                }
+            };
+            roots[idx].insert_at_path( &path[1..], value );
+        }
+        ( names, roots )
+    }
+
 }
 
 impl fmt::Display for UniformInfo {
@@ -56,11 +91,75 @@ impl fmt::Display for UniformInfo {
     }
 }
 
+/// Columns and rows of a matrix uniform
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatrixShape { columns:u8, rows:u8 }
+
+impl MatrixShape {
+    pub fn new( columns:u8, rows:u8 ) -> Self { Self { columns, rows } }
+
+    pub fn columns(&self) -> u8 { self.columns }
+    pub fn rows(&self)    -> u8 { self.rows    }
+    /// Number of values in the matrix
+    pub fn value_count(&self) -> usize { self.columns as usize * self.rows as usize }
+}
+
+impl fmt::Display for MatrixShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}x{}", self.columns, self.rows )
+    }
+}
+
+/// One step of a uniform path like `lights[2].color`
+#[derive(Debug, Clone, PartialEq)]
+pub enum UniformPathSegment {
+    Member( String ),
+    Element( usize ),
+}
+
+/// Splits a uniform path like `lights[2].color` into its segments
+pub fn parse_uniform_path( path:&str ) -> Result<Vec<UniformPathSegment>, Error> {
+    let invalid = || Error::UniformNotFound( format!( "\"{}\" is not a valid uniform path!", path ) );
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let ( member, mut indices ) = match part.find('[') {
+            Some( idx ) => ( &part[..idx], &part[idx..] ),
+            None => ( part, "" ),
+        };
+        if !member.is_empty() {
+            segments.push( UniformPathSegment::Member( member.to_owned() ) );
+        } else if segments.is_empty() {
+            return Err( invalid() );
+        }
+        while !indices.is_empty() {
+            let close = indices.find(']').ok_or_else( invalid )?;
+            let element = indices[1..close].trim().parse::<usize>()
+                .map_err( |_| invalid() )?;
+            segments.push( UniformPathSegment::Element( element ) );
+            indices = &indices[close + 1..];
+            if !indices.is_empty() && !indices.starts_with('[') { return Err( invalid() ); }
+        }
+    }
+    if segments.is_empty() { return Err( invalid() ); }
+    Ok( segments )
+}
+
+/// Parses a path relative to a uniform, like `[2].color`, `color` or `.color`
+fn parse_relative_path( path:&str ) -> Result<Vec<UniformPathSegment>, Error> {
+    let path = path.strip_prefix('.').unwrap_or( path );
+    if path.starts_with('[') {
+        // give the leading index a placeholder member to parse against
+        let mut segments = parse_uniform_path( &format!( "_{}", path ) )?;
+        segments.remove( 0 );
+        Ok( segments )
+    } else {
+        parse_uniform_path( path )
+    }
+}
+
 #[derive(Debug, Clone)]
-// TODO: Array uniforms are counted as only one uniform!
 pub enum Uniform {
     Float32( f32, GLint ),
-    // TODO: f64, i32, u32, bool variants
     Float32Vec2( Vector2, GLint ),
     Float32Vec3( Vector3, GLint ),
     Float32Vec4( Vector4, GLint ),
@@ -70,12 +169,36 @@ pub enum Uniform {
     UInt32 (  u32, GLint ),
     Bool   ( bool, GLint ),
 
-    // TODO: other matrices nxm, f64 variants
+    Int32Vec2 ( [i32;2], GLint ),
+    Int32Vec3 ( [i32;3], GLint ),
+    Int32Vec4 ( [i32;4], GLint ),
+    UInt32Vec2( [u32;2], GLint ),
+    UInt32Vec3( [u32;3], GLint ),
+    UInt32Vec4( [u32;4], GLint ),
+    BoolVec2  ( [bool;2], GLint ),
+    BoolVec3  ( [bool;3], GLint ),
+    BoolVec4  ( [bool;4], GLint ),
+    Float64Vec2( [f64;2], GLint ),
+    Float64Vec3( [f64;3], GLint ),
+    Float64Vec4( [f64;4], GLint ),
+
     Float32Mat3( Matrix3x3, GLint ),
     Float32Mat4( Matrix4x4, GLint ),
+    /// Column major matrices without an fmath type: mat2 and the non-square matrices
+    Float32Matrix( ( MatrixShape, Vec<f32> ), GLint ),
+    /// Column major double matrices of any shape
+    Float64Matrix( ( MatrixShape, Vec<f64> ), GLint ),
 
-    // TODO: other sampler variants
     Sampler2D( ( Rc<Texture>, Sampler), GLint ),
+    /// Every other sampler type, with the texture bound to it if any
+    Sampler( ( SamplerKind, Option<Rc<dyn TextureObject>>, Sampler ), GLint ),
+    /// Image load/store uniforms, with the texture bound to the image unit if any
+    Image( ( Option<Rc<dyn TextureObject>>, ImageBinding ), GLint ),
+
+    /// Elements of an array uniform, each with its own location
+    Array( Vec<Uniform> ),
+    /// Members of a struct uniform, by name
+    Struct( Vec<( String, Uniform )> ),
 
     /// placeholder for unimplemented types
     None,
@@ -83,7 +206,7 @@ pub enum Uniform {
 
 impl Uniform {
     pub fn find_uniform_mut_by_location( uniforms:&mut Vec<Self>, location:GLint ) -> Result<&mut Self, Error> {
-        match uniforms.iter_mut().find( |u| u.location() == location )
+        match uniforms.iter_mut().find_map( |u| u.find_location_mut( location ) )
         {
             Some(res) => Ok(res),
             None => Err( Error::UniformNotFound( format!("Uniform at location {} not found!", location) ) ),
@@ -91,7 +214,7 @@ impl Uniform {
     }
 
     pub fn find_uniform_by_location( uniforms:&Vec<Self>, location:GLint ) -> Result<&Self, Error> {
-        match uniforms.iter().find( |u| u.location() == location )
+        match uniforms.iter().find_map( |u| u.find_location( location ) )
         {
             Some(res) => Ok(res),
             None => Err( Error::UniformNotFound( format!("Uniform at location {} not found!", location) ) ),
@@ -102,7 +225,7 @@ impl Uniform {
         uniforms:&'a mut Vec<Self>, shader:&Rc<ShaderProgram>, name:&str
     ) -> Result<&'a mut Self, Error> {
         let location = shader.get_uniform_location(name);
-        match uniforms.iter_mut().find( |u| u.location() == location )
+        match uniforms.iter_mut().find_map( |u| u.find_location_mut( location ) )
         {
             Some(res) => Ok(res),
             None => Err( Error::UniformNotFound( format!("Uniform \"{}\" not found!", name) ) ),
@@ -113,7 +236,7 @@ impl Uniform {
         uniforms:&'a Vec<Self>, shader:&Rc<ShaderProgram>, name:&str
     ) -> Result<&'a Self, Error> {
         let location = shader.get_uniform_location(name);
-        match uniforms.iter().find( |u| u.location() == location )
+        match uniforms.iter().find_map( |u| u.find_location( location ) )
         {
             Some(res) => Ok(res),
             None => Err( Error::UniformNotFound( format!("Uniform \"{}\" not found!", name) ) ),
@@ -134,9 +257,60 @@ impl Uniform {
             gl::FLOAT_MAT3   => Self::Float32Mat3( Matrix3x3::new_zero(), location ),
             gl::FLOAT_MAT4   => Self::Float32Mat4( Matrix4x4::new_zero(), location ),
 
+            gl::INT_VEC2           => Self::Int32Vec2  ( [0;2], location ),
+            gl::INT_VEC3           => Self::Int32Vec3  ( [0;3], location ),
+            gl::INT_VEC4           => Self::Int32Vec4  ( [0;4], location ),
+            gl::UNSIGNED_INT_VEC2  => Self::UInt32Vec2 ( [0;2], location ),
+            gl::UNSIGNED_INT_VEC3  => Self::UInt32Vec3 ( [0;3], location ),
+            gl::UNSIGNED_INT_VEC4  => Self::UInt32Vec4 ( [0;4], location ),
+            gl::BOOL_VEC2          => Self::BoolVec2   ( [false;2], location ),
+            gl::BOOL_VEC3          => Self::BoolVec3   ( [false;3], location ),
+            gl::BOOL_VEC4          => Self::BoolVec4   ( [false;4], location ),
+            gl::DOUBLE_VEC2        => Self::Float64Vec2( [0.0;2], location ),
+            gl::DOUBLE_VEC3        => Self::Float64Vec3( [0.0;3], location ),
+            gl::DOUBLE_VEC4        => Self::Float64Vec4( [0.0;4], location ),
+
             gl::SAMPLER_2D => Self::Sampler2D( ( texture::null_texture(), Sampler::empty() ), location ),
 
-            _ => Self::None,
+            _ => if let Some( shape ) = Self::float_matrix_shape( g ) {
+                Self::Float32Matrix( ( shape, vec![0.0;shape.value_count()] ), location )
+            } else if let Some( shape ) = Self::double_matrix_shape( g ) {
+                Self::Float64Matrix( ( shape, vec![0.0;shape.value_count()] ), location )
+            } else if let Some( kind ) = SamplerKind::from_glenum( g ) {
+                Self::Sampler( ( kind, None, Sampler::empty() ), location )
+            } else if texture::is_image_type( g ) {
+                Self::Image( ( None, ImageBinding::new( 0 ) ), location )
+            } else {
+                Self::None
+            },
+        }
+    }
+
+    fn float_matrix_shape( g:GLenum ) -> Option<MatrixShape> {
+        match g {
+            gl::FLOAT_MAT2   => Some( MatrixShape::new( 2, 2 ) ),
+            gl::FLOAT_MAT2x3 => Some( MatrixShape::new( 2, 3 ) ),
+            gl::FLOAT_MAT2x4 => Some( MatrixShape::new( 2, 4 ) ),
+            gl::FLOAT_MAT3x2 => Some( MatrixShape::new( 3, 2 ) ),
+            gl::FLOAT_MAT3x4 => Some( MatrixShape::new( 3, 4 ) ),
+            gl::FLOAT_MAT4x2 => Some( MatrixShape::new( 4, 2 ) ),
+            gl::FLOAT_MAT4x3 => Some( MatrixShape::new( 4, 3 ) ),
+            _ => None,
+        }
+    }
+
+    fn double_matrix_shape( g:GLenum ) -> Option<MatrixShape> {
+        match g {
+            gl::DOUBLE_MAT2   => Some( MatrixShape::new( 2, 2 ) ),
+            gl::DOUBLE_MAT3   => Some( MatrixShape::new( 3, 3 ) ),
+            gl::DOUBLE_MAT4   => Some( MatrixShape::new( 4, 4 ) ),
+            gl::DOUBLE_MAT2x3 => Some( MatrixShape::new( 2, 3 ) ),
+            gl::DOUBLE_MAT2x4 => Some( MatrixShape::new( 2, 4 ) ),
+            gl::DOUBLE_MAT3x2 => Some( MatrixShape::new( 3, 2 ) ),
+            gl::DOUBLE_MAT3x4 => Some( MatrixShape::new( 3, 4 ) ),
+            gl::DOUBLE_MAT4x2 => Some( MatrixShape::new( 4, 2 ) ),
+            gl::DOUBLE_MAT4x3 => Some( MatrixShape::new( 4, 3 ) ),
+            _ => None,
         }
     }
 
@@ -150,14 +324,66 @@ impl Uniform {
             Self::Int32      ( _, location ) |
             Self::UInt32     ( _, location ) |
             Self::Bool       ( _, location ) |
+            Self::Int32Vec2  ( _, location ) |
+            Self::Int32Vec3  ( _, location ) |
+            Self::Int32Vec4  ( _, location ) |
+            Self::UInt32Vec2 ( _, location ) |
+            Self::UInt32Vec3 ( _, location ) |
+            Self::UInt32Vec4 ( _, location ) |
+            Self::BoolVec2   ( _, location ) |
+            Self::BoolVec3   ( _, location ) |
+            Self::BoolVec4   ( _, location ) |
+            Self::Float64Vec2( _, location ) |
+            Self::Float64Vec3( _, location ) |
+            Self::Float64Vec4( _, location ) |
             Self::Float32Mat3( _, location ) |
             Self::Float32Mat4( _, location ) |
-            Self::Sampler2D  ( _, location ) => *location,
+            Self::Float32Matrix( _, location ) |
+            Self::Float64Matrix( _, location ) |
+            Self::Sampler2D  ( _, location ) |
+            Self::Sampler    ( _, location ) |
+            Self::Image      ( _, location ) => *location,
 
+            Self::Array(_) | Self::Struct(_) |
             Self::None => -1,
         }
     }
 
+    pub fn set_location( &mut self, new_location:GLint ) {
+        match self {
+            Self::Float32    ( _, location ) |
+            Self::Float32Vec2( _, location ) |
+            Self::Float32Vec3( _, location ) |
+            Self::Float32Vec4( _, location ) |
+            Self::Float64    ( _, location ) |
+            Self::Int32      ( _, location ) |
+            Self::UInt32     ( _, location ) |
+            Self::Bool       ( _, location ) |
+            Self::Int32Vec2  ( _, location ) |
+            Self::Int32Vec3  ( _, location ) |
+            Self::Int32Vec4  ( _, location ) |
+            Self::UInt32Vec2 ( _, location ) |
+            Self::UInt32Vec3 ( _, location ) |
+            Self::UInt32Vec4 ( _, location ) |
+            Self::BoolVec2   ( _, location ) |
+            Self::BoolVec3   ( _, location ) |
+            Self::BoolVec4   ( _, location ) |
+            Self::Float64Vec2( _, location ) |
+            Self::Float64Vec3( _, location ) |
+            Self::Float64Vec4( _, location ) |
+            Self::Float32Mat3( _, location ) |
+            Self::Float32Mat4( _, location ) |
+            Self::Float32Matrix( _, location ) |
+            Self::Float64Matrix( _, location ) |
+            Self::Sampler2D  ( _, location ) |
+            Self::Sampler    ( _, location ) |
+            Self::Image      ( _, location ) => *location = new_location,
+
+            Self::Array(_) | Self::Struct(_) |
+            Self::None => {},
+        }
+    }
+
     pub fn set_f32(&mut self, v:f32) {
         match self {
             Self::Float32(value,_) => { *value = v; }
@@ -265,6 +491,10 @@ impl Uniform {
     pub fn set_matrix3x3(&mut self, v:Matrix3x3) {
         match self {
             Self::Float32Mat3(value,_) => { *value = v; }
+            // mat3 read from a file is stored as column major values
+            Self::Float32Matrix( ( shape, value ), _ ) if shape.value_count() == 9 => {
+                value.copy_from_slice( v.as_array() );
+            },
             _ => {
                 log(
                     &format!("Attempted to assign Matrix3x3 to Uniform of type {}!", self.type_name()),
@@ -277,6 +507,9 @@ impl Uniform {
     pub fn set_matrix4x4(&mut self, v:Matrix4x4) {
         match self {
             Self::Float32Mat4(value,_) => { *value = v; }
+            Self::Float32Matrix( ( shape, value ), _ ) if shape.value_count() == 16 => {
+                value.copy_from_slice( v.as_array() );
+            },
             _ => {
                 log(
                     &format!("Attempted to assign Matrix4x4 to Uniform of type {}!", self.type_name()),
@@ -322,10 +555,414 @@ impl Uniform {
         }
     }
 
+    fn type_mismatch( &self, assigned:&str ) {
+        log(
+            &format!("Attempted to assign {} to Uniform of type {}!", assigned, self.type_name()),
+            "Uniform Value | Warning"
+        )
+    }
+
+    pub fn set_ivec2(&mut self, v:[i32;2]) {
+        match self { Self::Int32Vec2(value,_) => { *value = v; }, _ => self.type_mismatch("ivec2") }
+    }
+    pub fn set_ivec3(&mut self, v:[i32;3]) {
+        match self { Self::Int32Vec3(value,_) => { *value = v; }, _ => self.type_mismatch("ivec3") }
+    }
+    pub fn set_ivec4(&mut self, v:[i32;4]) {
+        match self { Self::Int32Vec4(value,_) => { *value = v; }, _ => self.type_mismatch("ivec4") }
+    }
+    pub fn set_uvec2(&mut self, v:[u32;2]) {
+        match self { Self::UInt32Vec2(value,_) => { *value = v; }, _ => self.type_mismatch("uvec2") }
+    }
+    pub fn set_uvec3(&mut self, v:[u32;3]) {
+        match self { Self::UInt32Vec3(value,_) => { *value = v; }, _ => self.type_mismatch("uvec3") }
+    }
+    pub fn set_uvec4(&mut self, v:[u32;4]) {
+        match self { Self::UInt32Vec4(value,_) => { *value = v; }, _ => self.type_mismatch("uvec4") }
+    }
+    pub fn set_bvec2(&mut self, v:[bool;2]) {
+        match self { Self::BoolVec2(value,_) => { *value = v; }, _ => self.type_mismatch("bvec2") }
+    }
+    pub fn set_bvec3(&mut self, v:[bool;3]) {
+        match self { Self::BoolVec3(value,_) => { *value = v; }, _ => self.type_mismatch("bvec3") }
+    }
+    pub fn set_bvec4(&mut self, v:[bool;4]) {
+        match self { Self::BoolVec4(value,_) => { *value = v; }, _ => self.type_mismatch("bvec4") }
+    }
+    pub fn set_dvec2(&mut self, v:[f64;2]) {
+        match self { Self::Float64Vec2(value,_) => { *value = v; }, _ => self.type_mismatch("dvec2") }
+    }
+    pub fn set_dvec3(&mut self, v:[f64;3]) {
+        match self { Self::Float64Vec3(value,_) => { *value = v; }, _ => self.type_mismatch("dvec3") }
+    }
+    pub fn set_dvec4(&mut self, v:[f64;4]) {
+        match self { Self::Float64Vec4(value,_) => { *value = v; }, _ => self.type_mismatch("dvec4") }
+    }
+
+    /// Sets a mat2 or non-square matrix from column major values
+    pub fn set_matrix_f32(&mut self, v:&[f32]) {
+        match self {
+            Self::Float32Matrix( ( shape, value ), _ ) if shape.value_count() == v.len() => {
+                value.copy_from_slice( v );
+            },
+            _ => self.type_mismatch( &format!( "{} f32 matrix values", v.len() ) ),
+        }
+    }
+
+    pub fn set_matrix2x2(&mut self, v:[f32;4]) { self.set_matrix_f32( &v ) }
+
+    /// Sets a double matrix from column major values
+    pub fn set_matrix_f64(&mut self, v:&[f64]) {
+        match self {
+            Self::Float64Matrix( ( shape, value ), _ ) if shape.value_count() == v.len() => {
+                value.copy_from_slice( v );
+            },
+            _ => self.type_mismatch( &format!( "{} f64 matrix values", v.len() ) ),
+        }
+    }
+
+    /// Sets the texture of a sampler uniform other than sampler2D.
+    /// The texture's target has to match the sampler type.
+    pub fn set_sampler_texture(&mut self, texture:Rc<dyn TextureObject>) {
+        match self {
+            Self::Sampler( ( kind, value, _ ), _ ) if kind.texture_target() == texture.target() => {
+                *value = Some( texture );
+            },
+            _ => self.type_mismatch( &format!( "Texture with target {:#06X}", texture.target() ) ),
+        }
+    }
+
+    /// Sets the texture unit of any sampler or the image unit of an image uniform
+    pub fn set_texture_unit(&mut self, unit:GLint) {
+        match self {
+            Self::Sampler2D( ( _, sampler ), _ )   |
+            Self::Sampler( ( _, _, sampler ), _ ) => { sampler.set_id( unit ) },
+            Self::Image( ( _, binding ), _ )      => { binding.set_unit( unit as GLuint ) },
+            _ => self.type_mismatch( "texture unit" ),
+        }
+    }
+
+    /// Samples the texture of any sampler with a sampler object,
+    /// None goes back to the parameters of the texture itself
+    pub fn set_sampler_object(&mut self, object:Option<Rc<SamplerObject>>) {
+        if let Some( object ) = object.as_ref() {
+            if !self.accepts_sampler_object( object ) {
+                log(
+                    &format!("Attempted to assign a depth compare sampler object to Uniform of type {}!", self.type_name()),
+                    "Uniform Value | Warning"
+                );
+                return;
+            }
+        }
+        match self {
+            Self::Sampler2D( ( _, sampler ), _ )   |
+            Self::Sampler( ( _, _, sampler ), _ ) => { sampler.set_object( object ) },
+            _ => self.type_mismatch( "sampler object" ),
+        }
+    }
+
+    /// Sampler object of any sampler, None for every other uniform
+    pub fn sampler_object(&self) -> Option<Rc<SamplerObject>> {
+        match self {
+            Self::Sampler2D( ( _, sampler ), _ )   |
+            Self::Sampler( ( _, _, sampler ), _ ) => sampler.object().cloned(),
+            _ => None,
+        }
+    }
+
+    /// True for samplers whose sampler object was explicitly set to None
+    pub fn clears_sampler_object(&self) -> bool {
+        match self {
+            Self::Sampler2D( ( _, sampler ), _ )   |
+            Self::Sampler( ( _, _, sampler ), _ ) => sampler.clears_object(),
+            _ => false,
+        }
+    }
+
+    /// Sampler objects that compare depth only give defined results on shadow samplers,
+    /// every other sampler object goes on any sampler
+    pub fn accepts_sampler_object(&self, object:&SamplerObject) -> bool {
+        match self {
+            Self::Sampler2D(_, _) => object.description().depth_compare().is_none(),
+            Self::Sampler( ( kind, _, _ ), _ ) => kind.is_shadow() || object.description().depth_compare().is_none(),
+            _ => false,
+        }
+    }
+
+    /// Binds a texture to an image uniform, read and written as format
+    pub fn set_image(&mut self, texture:Rc<dyn TextureObject>, access:ImageAccess, format:GLenum) {
+        match self {
+            Self::Image( ( value, binding ), _ ) => {
+                *value = Some( texture );
+                binding.set_access( access );
+                binding.set_format( format );
+            },
+            _ => self.type_mismatch( "Image" ),
+        }
+    }
+
+    pub fn set_image_binding(&mut self, new_binding:ImageBinding) {
+        match self {
+            Self::Image( ( _, binding ), _ ) => { *binding = new_binding },
+            _ => self.type_mismatch( "Image Binding" ),
+        }
+    }
+
+    /// Finds the uniform at a location, searching array elements and struct members
+    pub fn find_location( &self, location:GLint ) -> Option<&Self> {
+        match self {
+            Self::Array( elements ) => elements.iter().find_map( |e| e.find_location( location ) ),
+            Self::Struct( members ) => members.iter().find_map( |( _, m )| m.find_location( location ) ),
+            _ => if self.location() == location && location >= 0 { Some( self ) } else { None },
+        }
+    }
+
+    pub fn find_location_mut( &mut self, location:GLint ) -> Option<&mut Self> {
+        match self {
+            Self::Array( elements ) => elements.iter_mut().find_map( |e| e.find_location_mut( location ) ),
+            Self::Struct( members ) => members.iter_mut().find_map( |( _, m )| m.find_location_mut( location ) ),
+            _ => if self.location() == location && location >= 0 { Some( self ) } else { None },
+        }
+    }
+
+    /// Number of elements if this is an array uniform, otherwise 1
+    pub fn element_count(&self) -> usize {
+        match self {
+            Self::Array( elements ) => elements.len(),
+            _ => 1,
+        }
+    }
+
+    pub fn get_element( &self, index:usize ) -> Result<&Self, Error> {
+        match self {
+            Self::Array( elements ) => elements.get( index ).ok_or_else( || Error::UniformNotFound(
+                format!( "Element {} is out of bounds for array of {} elements!", index, elements.len() )
+            ) ),
+            _ => Err( Error::UniformNotFound(
+                format!( "Attempted to index Uniform of type {}!", self.type_name() )
+            ) ),
+        }
+    }
+
+    pub fn get_element_mut( &mut self, index:usize ) -> Result<&mut Self, Error> {
+        let type_name = self.type_name().to_owned();
+        match self {
+            Self::Array( elements ) => {
+                let len = elements.len();
+                elements.get_mut( index ).ok_or_else( || Error::UniformNotFound(
+                    format!( "Element {} is out of bounds for array of {} elements!", index, len )
+                ) )
+            },
+            _ => Err( Error::UniformNotFound(
+                format!( "Attempted to index Uniform of type {}!", type_name )
+            ) ),
+        }
+    }
+
+    pub fn get_member( &self, name:&str ) -> Result<&Self, Error> {
+        match self {
+            Self::Struct( members ) => members.iter().find( |( n, _ )| n == name )
+                .map( |( _, m )| m )
+                .ok_or_else( || Error::UniformNotFound( format!( "Struct has no member \"{}\"!", name ) ) ),
+            _ => Err( Error::UniformNotFound(
+                format!( "Attempted to get member \"{}\" of Uniform of type {}!", name, self.type_name() )
+            ) ),
+        }
+    }
+
+    pub fn get_member_mut( &mut self, name:&str ) -> Result<&mut Self, Error> {
+        let type_name = self.type_name().to_owned();
+        match self {
+            Self::Struct( members ) => members.iter_mut().find( |( n, _ )| n == name )
+                .map( |( _, m )| m )
+                .ok_or_else( || Error::UniformNotFound( format!( "Struct has no member \"{}\"!", name ) ) ),
+            _ => Err( Error::UniformNotFound(
+                format!( "Attempted to get member \"{}\" of Uniform of type {}!", name, type_name )
+            ) ),
+        }
+    }
+
+    /// Finds a nested uniform by a path relative to this one, like `[2].color`
+    pub fn get( &self, path:&str ) -> Result<&Self, Error> {
+        let path = parse_relative_path( path )?;
+        self.get_path( &path )
+    }
+
+    pub fn get_mut( &mut self, path:&str ) -> Result<&mut Self, Error> {
+        let path = parse_relative_path( path )?;
+        self.get_path_mut( &path )
+    }
+
+    pub fn get_path( &self, path:&[UniformPathSegment] ) -> Result<&Self, Error> {
+        let mut current = self;
+        for segment in path.iter() {
+            current = match segment {
+                UniformPathSegment::Element( index ) => current.get_element( *index )?,
+                UniformPathSegment::Member( name )   => current.get_member( name )?,
+            };
+        }
+        Ok( current )
+    }
+
+    pub fn get_path_mut( &mut self, path:&[UniformPathSegment] ) -> Result<&mut Self, Error> {
+        let mut current = self;
+        for segment in path.iter() {
+            current = match segment {
+                UniformPathSegment::Element( index ) => current.get_element_mut( *index )?,
+                UniformPathSegment::Member( name )   => current.get_member_mut( name )?,
+            };
+        }
+        Ok( current )
+    }
+
+    /// Places a reflected uniform in the array/struct tree, creating the levels it needs
+    fn insert_at_path( &mut self, path:&[UniformPathSegment], value:Uniform ) {
+        let ( segment, rest ) = match path.split_first() {
+            Some( split ) => split,
+            None => { *self = value; return; }
+        };
+        match segment {
+            UniformPathSegment::Element( index ) => {
+                if !matches!( self, Self::Array(_) ) { *self = Self::Array( Vec::new() ); }
+                if let Self::Array( elements ) = self {
+                    if elements.len() <= *index { elements.resize( *index + 1, Self::None ); }
+                    elements[*index].insert_at_path( rest, value );
+                }
+            },
+            UniformPathSegment::Member( name ) => {
+                if !matches!( self, Self::Struct(_) ) { *self = Self::Struct( Vec::new() ); }
+                if let Self::Struct( members ) = self {
+                    let idx = match members.iter().position( |( n, _ )| n == name ) {
+                        Some( idx ) => idx,
+                        None => {
+                            members.push( ( name.clone(), Self::None ) );
+                            members.len() - 1
+                        }
+                    };
+                    members[idx].1.insert_at_path( rest, value );
+                }
+            },
+        }
+    }
+
+    /// Copies values from a uniform of the same shape, keeping this uniform's locations.
+    /// Array elements are copied up to the shorter length and struct members by name.
+    /// Returns false if the types don't match.
+    pub fn copy_value_from( &mut self, other:&Self ) -> bool {
+        match ( self, other ) {
+            ( Self::Array( elements ), Self::Array( other_elements ) ) => {
+                let mut matched = true;
+                for ( element, other_element ) in elements.iter_mut().zip( other_elements.iter() ) {
+                    matched &= element.copy_value_from( other_element );
+                }
+                matched
+            },
+            ( Self::Struct( members ), Self::Struct( other_members ) ) => {
+                let mut matched = true;
+                for ( name, member ) in members.iter_mut() {
+                    match other_members.iter().find( |( n, _ )| n == name ) {
+                        Some( ( _, other_member ) ) => matched &= member.copy_value_from( other_member ),
+                        None => {},
+                    }
+                }
+                matched
+            },
+            ( this, other ) => {
+                if this.type_name() != other.type_name() { return false; }
+                let location = this.location();
+                // a texture set without a sampler object keeps the one of this slot,
+                // unless the object was set to None to go back to the texture's own parameters
+                let object = this.sampler_object();
+                *this = other.clone();
+                this.set_location( location );
+                if this.sampler_object().is_none() && !other.clears_sampler_object() && object.is_some() {
+                    this.set_sampler_object( object );
+                }
+                true
+            },
+        }
+    }
+
+    /// Sets array elements from the start of values with the given setter.
+    /// Values past the end of the array are ignored with a warning.
+    pub fn set_elements<T:Clone, F:Fn( &mut Self, T )>( &mut self, values:&[T], set:F ) {
+        match self {
+            Self::Array( elements ) => {
+                if values.len() > elements.len() {
+                    log(
+                        &format!(
+                            "Attempted to assign {} values to array of {} elements! Extra values are ignored.",
+                            values.len(), elements.len()
+                        ),
+                        "Uniform Value | Warning"
+                    );
+                }
+                for ( element, value ) in elements.iter_mut().zip( values.iter() ) {
+                    set( element, value.clone() );
+                }
+            },
+            _ => {
+                log(
+                    &format!("Attempted to assign array to Uniform of type {}!", self.type_name()),
+                    "Uniform Value | Warning"
+                )
+            }
+        }
+    }
+
+    pub fn set_f32_array(&mut self, v:&[f32])                { self.set_elements( v, Self::set_f32 ) }
+    pub fn set_vector2_array(&mut self, v:&[Vector2])        { self.set_elements( v, Self::set_vector2 ) }
+    pub fn set_vector3_array(&mut self, v:&[Vector3])        { self.set_elements( v, Self::set_vector3 ) }
+    pub fn set_vector4_array(&mut self, v:&[Vector4])        { self.set_elements( v, Self::set_vector4 ) }
+    pub fn set_rgb_array(&mut self, v:&[color::RGB])         { self.set_elements( v, Self::set_rgb ) }
+    pub fn set_rgba_array(&mut self, v:&[color::RGB])        { self.set_elements( v, Self::set_rgba ) }
+    pub fn set_f64_array(&mut self, v:&[f64])                { self.set_elements( v, Self::set_f64 ) }
+    pub fn set_i32_array(&mut self, v:&[i32])                { self.set_elements( v, Self::set_i32 ) }
+    pub fn set_u32_array(&mut self, v:&[u32])                { self.set_elements( v, Self::set_u32 ) }
+    pub fn set_bool_array(&mut self, v:&[bool])              { self.set_elements( v, Self::set_bool ) }
+    pub fn set_matrix3x3_array(&mut self, v:&[Matrix3x3])    { self.set_elements( v, Self::set_matrix3x3 ) }
+    pub fn set_matrix4x4_array(&mut self, v:&[Matrix4x4])    { self.set_elements( v, Self::set_matrix4x4 ) }
+
+    /// Binds the textures of this sampler, or the samplers inside it,
+    /// to units from the allocator and points the samplers at them
+    pub fn bind_textures( &mut self, program:&ShaderProgram, units:&mut TextureUnitAllocator ) {
+        let ( target, handle, sampler, location ) = match self {
+            Self::Sampler2D( ( texture, sampler ), location ) =>
+                ( gl::TEXTURE_2D, texture.handle(), sampler, *location ),
+            Self::Sampler( ( kind, texture, sampler ), location ) =>
+                ( kind.texture_target(), texture.as_ref().map_or( 0, |t| t.handle() ), sampler, *location ),
+            Self::Array( elements ) => {
+                for element in elements.iter_mut() { element.bind_textures( program, units ); }
+                return;
+            },
+            Self::Struct( members ) => {
+                for ( _, member ) in members.iter_mut() { member.bind_textures( program, units ); }
+                return;
+            },
+            _ => return,
+        };
+        match units.allocate( target, handle, sampler.object_handle() ) {
+            Some( unit ) => {
+                sampler.set_id( unit );
+                program.set_sampler_unit( location, unit );
+            },
+            None => {},
+        }
+    }
+
     pub fn send_if_dirty(&self, dirty:bool) {
-        // some uniforms need to be sent regardless of if they're dirty or not
         match self {
-            Self::Sampler2D(_, _) => { self.send_to_gl() }
+            // samplers are sent by bind_textures when their unit changes
+            Self::Sampler2D(_, _) |
+            Self::Sampler(_, _)   => {},
+            // image units can be rebound by anything in between draws
+            Self::Image(_, _)     => { self.send_to_gl() }
+            Self::Array( elements ) => for element in elements.iter() {
+                element.send_if_dirty( dirty );
+            },
+            Self::Struct( members ) => for ( _, member ) in members.iter() {
+                member.send_if_dirty( dirty );
+            },
             _ => {
                 if dirty { self.send_to_gl() }
             }
@@ -356,19 +993,82 @@ impl Uniform {
                 gl::Uniform1ui( *loc, *v );
             },
             Uniform::Bool       (v, loc) => unsafe{
-                let val = if *v { 1 } else { 0 };
-                gl::Uniform1ui( *loc, val );
+                // bool uniforms can only be set with the i and f variants
+                gl::Uniform1i( *loc, *v as GLint );
+            },
+            Uniform::Int32Vec2  (v, loc) => unsafe{ gl::Uniform2iv( *loc, 1, v.as_ptr() ); },
+            Uniform::Int32Vec3  (v, loc) => unsafe{ gl::Uniform3iv( *loc, 1, v.as_ptr() ); },
+            Uniform::Int32Vec4  (v, loc) => unsafe{ gl::Uniform4iv( *loc, 1, v.as_ptr() ); },
+            Uniform::UInt32Vec2 (v, loc) => unsafe{ gl::Uniform2uiv( *loc, 1, v.as_ptr() ); },
+            Uniform::UInt32Vec3 (v, loc) => unsafe{ gl::Uniform3uiv( *loc, 1, v.as_ptr() ); },
+            Uniform::UInt32Vec4 (v, loc) => unsafe{ gl::Uniform4uiv( *loc, 1, v.as_ptr() ); },
+            Uniform::BoolVec2   (v, loc) => unsafe{
+                gl::Uniform2i( *loc, v[0] as GLint, v[1] as GLint );
             },
+            Uniform::BoolVec3   (v, loc) => unsafe{
+                gl::Uniform3i( *loc, v[0] as GLint, v[1] as GLint, v[2] as GLint );
+            },
+            Uniform::BoolVec4   (v, loc) => unsafe{
+                gl::Uniform4i( *loc, v[0] as GLint, v[1] as GLint, v[2] as GLint, v[3] as GLint );
+            },
+            Uniform::Float64Vec2(v, loc) => unsafe{ gl::Uniform2dv( *loc, 1, v.as_ptr() ); },
+            Uniform::Float64Vec3(v, loc) => unsafe{ gl::Uniform3dv( *loc, 1, v.as_ptr() ); },
+            Uniform::Float64Vec4(v, loc) => unsafe{ gl::Uniform4dv( *loc, 1, v.as_ptr() ); },
             Uniform::Float32Mat3(v, loc) => unsafe{
                 gl::UniformMatrix3fv( *loc, 1, gl::FALSE, v.as_ptr() );
             },
             Uniform::Float32Mat4(v, loc) => unsafe{
                 gl::UniformMatrix4fv( *loc, 1, gl::FALSE, v.as_ptr() );
             },
+            Uniform::Float32Matrix( ( shape, v ), loc ) => unsafe{
+                let f = match ( shape.columns(), shape.rows() ) {
+                    ( 2, 2 ) => gl::UniformMatrix2fv,
+                    ( 2, 3 ) => gl::UniformMatrix2x3fv,
+                    ( 2, 4 ) => gl::UniformMatrix2x4fv,
+                    ( 3, 2 ) => gl::UniformMatrix3x2fv,
+                    ( 3, 3 ) => gl::UniformMatrix3fv,
+                    ( 3, 4 ) => gl::UniformMatrix3x4fv,
+                    ( 4, 2 ) => gl::UniformMatrix4x2fv,
+                    ( 4, 3 ) => gl::UniformMatrix4x3fv,
+                    _        => gl::UniformMatrix4fv,
+                };
+                f( *loc, 1, gl::FALSE, v.as_ptr() );
+            },
+            Uniform::Float64Matrix( ( shape, v ), loc ) => unsafe{
+                let f = match ( shape.columns(), shape.rows() ) {
+                    ( 2, 2 ) => gl::UniformMatrix2dv,
+                    ( 2, 3 ) => gl::UniformMatrix2x3dv,
+                    ( 2, 4 ) => gl::UniformMatrix2x4dv,
+                    ( 3, 2 ) => gl::UniformMatrix3x2dv,
+                    ( 3, 3 ) => gl::UniformMatrix3dv,
+                    ( 3, 4 ) => gl::UniformMatrix3x4dv,
+                    ( 4, 2 ) => gl::UniformMatrix4x2dv,
+                    ( 4, 3 ) => gl::UniformMatrix4x3dv,
+                    _        => gl::UniformMatrix4dv,
+                };
+                f( *loc, 1, gl::FALSE, v.as_ptr() );
+            },
+            // the textures themselves are bound by bind_textures
             Uniform::Sampler2D  (v, loc) => unsafe{
-                v.0.use_texture( &v.1, *loc );
                 gl::Uniform1i( *loc, *v.1.id() );
             },
+            Uniform::Sampler( ( _, _, sampler ), loc ) => unsafe{
+                gl::Uniform1i( *loc, *sampler.id() );
+            },
+            Uniform::Image( ( texture, binding ), loc ) => unsafe{
+                match texture {
+                    Some( texture ) => binding.bind( texture.as_ref() ),
+                    None => {},
+                }
+                gl::Uniform1i( *loc, binding.unit() as GLint );
+            },
+            // elements the linker optimized out stay None to keep the indices
+            Uniform::Array( elements ) => for element in elements.iter() {
+                if !matches!( element, Uniform::None ) { element.send_to_gl(); }
+            },
+            Uniform::Struct( members ) => for ( _, member ) in members.iter() {
+                member.send_to_gl();
+            },
             _ => {
                 log(
                     &format!( "Sent no data to OpenGL." ),
@@ -390,7 +1090,48 @@ impl Uniform {
             Uniform::Bool       (_, _) => "bool",
             Uniform::Float32Mat3(_, _) => "f32 Matrix3x3",
             Uniform::Float32Mat4(_, _) => "f32 Matrix4x4",
+            Uniform::Int32Vec2  (_, _) => "i32 Vector2",
+            Uniform::Int32Vec3  (_, _) => "i32 Vector3",
+            Uniform::Int32Vec4  (_, _) => "i32 Vector4",
+            Uniform::UInt32Vec2 (_, _) => "u32 Vector2",
+            Uniform::UInt32Vec3 (_, _) => "u32 Vector3",
+            Uniform::UInt32Vec4 (_, _) => "u32 Vector4",
+            Uniform::BoolVec2   (_, _) => "bool Vector2",
+            Uniform::BoolVec3   (_, _) => "bool Vector3",
+            Uniform::BoolVec4   (_, _) => "bool Vector4",
+            Uniform::Float64Vec2(_, _) => "f64 Vector2",
+            Uniform::Float64Vec3(_, _) => "f64 Vector3",
+            Uniform::Float64Vec4(_, _) => "f64 Vector4",
+            Uniform::Float32Matrix( ( shape, _ ), _ ) => match ( shape.columns(), shape.rows() ) {
+                ( 2, 2 ) => "f32 Matrix2x2",
+                ( 2, 3 ) => "f32 Matrix2x3",
+                ( 2, 4 ) => "f32 Matrix2x4",
+                ( 3, 2 ) => "f32 Matrix3x2",
+                // same names as Float32Mat3 and Float32Mat4, the values are interchangeable
+                ( 3, 3 ) => "f32 Matrix3x3",
+                ( 3, 4 ) => "f32 Matrix3x4",
+                ( 4, 2 ) => "f32 Matrix4x2",
+                ( 4, 3 ) => "f32 Matrix4x3",
+                ( 4, 4 ) => "f32 Matrix4x4",
+                _        => "f32 Matrix",
+            },
+            Uniform::Float64Matrix( ( shape, _ ), _ ) => match ( shape.columns(), shape.rows() ) {
+                ( 2, 2 ) => "f64 Matrix2x2",
+                ( 3, 3 ) => "f64 Matrix3x3",
+                ( 4, 4 ) => "f64 Matrix4x4",
+                ( 2, 3 ) => "f64 Matrix2x3",
+                ( 2, 4 ) => "f64 Matrix2x4",
+                ( 3, 2 ) => "f64 Matrix3x2",
+                ( 3, 4 ) => "f64 Matrix3x4",
+                ( 4, 2 ) => "f64 Matrix4x2",
+                ( 4, 3 ) => "f64 Matrix4x3",
+                _        => "f64 Matrix",
+            },
             Uniform::Sampler2D  (_, _) => "Sampler2D",
+            Uniform::Sampler( ( kind, _, _ ), _ ) => kind.msg(),
+            Uniform::Image      (_, _) => "Image",
+            Uniform::Array      (_)    => "Array",
+            Uniform::Struct     (_)    => "Struct",
             Uniform::None              => "None",
         }
     }
@@ -407,7 +1148,36 @@ impl Uniform {
             Uniform::Bool       (v, _)      => format!("{}", v), 
             Uniform::Float32Mat3(v, _) => format!("{:?}", v.as_array()),
             Uniform::Float32Mat4(v, _) => format!("{:?}", v.as_array()),
+            Uniform::Int32Vec2  (v, _) => format!("{:?}", v),
+            Uniform::Int32Vec3  (v, _) => format!("{:?}", v),
+            Uniform::Int32Vec4  (v, _) => format!("{:?}", v),
+            Uniform::UInt32Vec2 (v, _) => format!("{:?}", v),
+            Uniform::UInt32Vec3 (v, _) => format!("{:?}", v),
+            Uniform::UInt32Vec4 (v, _) => format!("{:?}", v),
+            Uniform::BoolVec2   (v, _) => format!("{:?}", v),
+            Uniform::BoolVec3   (v, _) => format!("{:?}", v),
+            Uniform::BoolVec4   (v, _) => format!("{:?}", v),
+            Uniform::Float64Vec2(v, _) => format!("{:.3?}", v),
+            Uniform::Float64Vec3(v, _) => format!("{:.3?}", v),
+            Uniform::Float64Vec4(v, _) => format!("{:.3?}", v),
+            Uniform::Float32Matrix( ( _, v ), _ ) => format!("{:?}", v),
+            Uniform::Float64Matrix( ( _, v ), _ ) => format!("{:?}", v),
             Uniform::Sampler2D  (v, _) => format!("{} {}", v.0, v.1),
+            Uniform::Sampler( ( _, texture, sampler ), _ ) => match texture {
+                Some( texture ) => format!("Texture {} {}", texture.handle(), sampler),
+                None => format!("No Texture {}", sampler),
+            },
+            Uniform::Image( ( texture, binding ), _ ) => match texture {
+                Some( texture ) => format!("Texture {} {}", texture.handle(), binding),
+                None => format!("No Texture {}", binding),
+            },
+            Uniform::Array( elements ) => format!( "[{}]",
+                elements.iter().map( |e| e.format_data() ).collect::<Vec<String>>().join(", ")
+            ),
+            Uniform::Struct( members ) => format!( "{{ {} }}",
+                members.iter().map( |( name, m )| format!( "{}: {}", name, m.format_data() ) )
+                    .collect::<Vec<String>>().join(", ")
+            ),
             Uniform::None => "None".to_owned(),
         }
     }
@@ -420,6 +1190,44 @@ impl fmt::Display for Uniform {
     }
 }
 
+impl Index<usize> for Uniform {
+    type Output = Uniform;
+    fn index( &self, index:usize ) -> &Self::Output {
+        match self.get_element( index ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
+impl IndexMut<usize> for Uniform {
+    fn index_mut( &mut self, index:usize ) -> &mut Self::Output {
+        match self.get_element_mut( index ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
+impl Index<&str> for Uniform {
+    type Output = Uniform;
+    fn index( &self, path:&str ) -> &Self::Output {
+        match self.get( path ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
+impl IndexMut<&str> for Uniform {
+    fn index_mut( &mut self, path:&str ) -> &mut Self::Output {
+        match self.get_mut( path ) {
+            Ok(res) => res,
+            Err(e) => panic!( "{}", e ),
+        }
+    }
+}
+
 pub struct UniformBlock {
     ubo:  GLuint,
     size: usize,
@@ -516,3 +1324,240 @@ impl UniformBlock {
     }
 
 }
+
+pub struct StorageBuffer {
+    ssbo:  GLuint,
+    size:  usize,
+    usage: BufferUsage,
+    buffer_points: Vec<GLuint>,
+}
+
+impl StorageBuffer {
+    /// Allocates size bytes, filled with data when it's given
+    pub fn new( data:Option< &[u8] >, size:usize, usage:BufferUsage ) -> Result<Self, Error> {
+        if let Some( data ) = data {
+            if data.len() != size {
+                return Err( Error::BufferSize( format!(
+                    "Storage Buffer data is {} bytes but the buffer is {} bytes!", data.len(), size
+                ) ) );
+            }
+        }
+        let mut ssbo = 0;
+        unsafe {
+            gl::GenBuffers( 1, &mut ssbo );
+        }
+        let buffer = Self { ssbo, size, usage, buffer_points:Vec::new() };
+        buffer.allocate( data );
+        Ok( buffer )
+    }
+
+    fn allocate( &self, data:Option< &[u8] > ) {
+        unsafe {
+            let data_ptr = match data {
+                Some(data) => data.as_ptr() as *const GLvoid,
+                None => core::ptr::null() as *const GLvoid,
+            };
+
+            gl::BindBuffer( gl::SHADER_STORAGE_BUFFER, self.ssbo );
+            gl::BufferData( gl::SHADER_STORAGE_BUFFER,
+                self.size as GLsizeiptr,
+                data_ptr,
+                self.usage.as_glenum()
+            );
+        }
+    }
+
+    pub fn handle(&self) -> GLuint      { self.ssbo  }
+    pub fn size(&self)   -> usize       { self.size  }
+    pub fn usage(&self)  -> BufferUsage { self.usage }
+
+    pub fn use_buffer(&self) {
+        unsafe { gl::BindBuffer( gl::SHADER_STORAGE_BUFFER, self.ssbo ) }
+    }
+
+    pub fn bind_to_buffer_point( &mut self, buffer_point:GLuint ) {
+        unsafe {
+            gl::BindBufferBase(
+                gl::SHADER_STORAGE_BUFFER,
+                buffer_point,
+                self.ssbo
+            );
+        }
+        if !self.buffer_points.contains( &buffer_point ) {
+            self.buffer_points.push( buffer_point )
+        } else {
+            log(
+                &format!(
+                    "Attempted to bind Storage Buffer \"{}\" to point \"{}\" but it's already bound there!",
+                    self.ssbo, buffer_point
+                ),
+                "Storage Buffer"
+            )
+        }
+    }
+
+    pub fn unbind_from_buffer_point( &mut self, buffer_point:GLuint ) {
+        if self.buffer_points.contains( &buffer_point ) {
+            self.buffer_points.retain( |x| *x != buffer_point );
+            self.buffer_points.shrink_to_fit();
+        } else {
+            log(
+                &format!(
+                    "Attempted to unbind Storage Buffer \"{}\" from point \"{}\" but it was never bound there!",
+                    self.ssbo, buffer_point
+                ),
+                "Storage Buffer"
+            );
+            return;
+        }
+        unsafe {
+            gl::BindBufferBase(
+                gl::SHADER_STORAGE_BUFFER,
+                buffer_point,
+                0
+            );
+        }
+    }
+
+    /// Reallocates the buffer with a new size.
+    /// Existing data is kept up to the smaller of the two sizes.
+    pub fn resize( &mut self, new_size:usize ) {
+        if new_size == self.size { return; }
+
+        let kept = self.read_data_slice( 0, self.size.min( new_size ) );
+        self.size = new_size;
+        self.allocate( None );
+        self.set_data_slice( &kept, 0 );
+
+        // a reallocated buffer has to be rebound to its buffer points
+        for buffer_point in self.buffer_points.iter() {
+            unsafe {
+                gl::BindBufferBase(
+                    gl::SHADER_STORAGE_BUFFER,
+                    *buffer_point,
+                    self.ssbo
+                );
+            }
+        }
+
+        log(
+            &format!( "Resized Storage Buffer \"{}\" to {} bytes.", self.ssbo, new_size ),
+            "Storage Buffer"
+        );
+    }
+
+    /// Resizes the buffer only if it's smaller than the required size.
+    pub fn reserve( &mut self, required_size:usize ) {
+        if required_size > self.size { self.resize( required_size ) }
+    }
+
+    pub fn set_data( &self, data:&[u8] ) {
+        self.set_data_slice( &data[..self.size.min( data.len() )], 0 )
+    }
+
+    pub fn set_data_slice( &self, data:&[u8], offset:usize ) {
+        if offset + data.len() > self.size {
+            log(
+                &format!(
+                    "Attempted to write {} bytes at offset {} to Storage Buffer \"{}\" of size {}!",
+                    data.len(), offset, self.ssbo, self.size
+                ),
+                "Storage Buffer | Warning"
+            );
+            return;
+        }
+        unsafe {
+            gl::BindBuffer( gl::SHADER_STORAGE_BUFFER, self.ssbo );
+            gl::BufferSubData(
+                gl::SHADER_STORAGE_BUFFER, offset as GLintptr,
+                data.len() as GLsizeiptr,
+                data.as_ptr() as *const GLvoid
+            )
+        }
+    }
+
+    pub fn read_data( &self ) -> Vec<u8> {
+        self.read_data_slice( 0, self.size )
+    }
+
+    pub fn read_data_slice( &self, offset:usize, len:usize ) -> Vec<u8> {
+        let len = len.min( self.size.saturating_sub( offset ) );
+        let mut buffer = vec![0u8;len];
+        if len == 0 { return buffer; }
+        unsafe {
+            gl::BindBuffer( gl::SHADER_STORAGE_BUFFER, self.ssbo );
+            gl::GetBufferSubData(
+                gl::SHADER_STORAGE_BUFFER, offset as GLintptr,
+                len as GLsizeiptr,
+                buffer.as_mut_ptr() as *mut GLvoid
+            );
+        }
+        buffer
+    }
+
+}
+
+impl Drop for StorageBuffer {
+    fn drop( &mut self ) {
+        unsafe { gl::DeleteBuffers( 1, &self.ssbo ) }
+    }
+}
+
+impl fmt::Display for StorageBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Storage Buffer {} | size (bytes): {} usage: {}",
+            self.handle(), self.size(), self.usage()
+        )
+    }
+}
+
+/// Hint to the driver about how a buffer's data store will be accessed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BufferUsage {
+    StreamDraw,
+    StreamRead,
+    StreamCopy,
+    StaticDraw,
+    StaticRead,
+    StaticCopy,
+    DynamicDraw,
+    DynamicRead,
+    DynamicCopy,
+}
+
+impl BufferUsage {
+
+    pub fn as_glenum( &self ) -> GLenum {
+        match self {
+            Self::StreamDraw  => gl::STREAM_DRAW  ,
+            Self::StreamRead  => gl::STREAM_READ  ,
+            Self::StreamCopy  => gl::STREAM_COPY  ,
+            Self::StaticDraw  => gl::STATIC_DRAW  ,
+            Self::StaticRead  => gl::STATIC_READ  ,
+            Self::StaticCopy  => gl::STATIC_COPY  ,
+            Self::DynamicDraw => gl::DYNAMIC_DRAW ,
+            Self::DynamicRead => gl::DYNAMIC_READ ,
+            Self::DynamicCopy => gl::DYNAMIC_COPY ,
+        }
+    }
+
+    pub fn msg(&self) -> &str {
+        match self {
+            Self::StreamDraw  => "Stream Draw"  ,
+            Self::StreamRead  => "Stream Read"  ,
+            Self::StreamCopy  => "Stream Copy"  ,
+            Self::StaticDraw  => "Static Draw"  ,
+            Self::StaticRead  => "Static Read"  ,
+            Self::StaticCopy  => "Static Copy"  ,
+            Self::DynamicDraw => "Dynamic Draw" ,
+            Self::DynamicRead => "Dynamic Read" ,
+            Self::DynamicCopy => "Dynamic Copy" ,
+        }
+    }
+}
+
+impl fmt::Display for BufferUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "{}", self.msg() )
+    }
+}
@@ -0,0 +1,149 @@
+use std::{ cell::RefCell, collections::HashMap };
+use core::fmt;
+use crate::{ Rc, debugging::{ Error, log } };
+use super::{
+    ShaderProgram,
+    shader::program_from_source,
+    preprocessor::{ ShaderParseOptions, parse_keywords },
+};
+
+/// Compiles permutations of a .shader file from the keywords
+/// it declares with `#pragma multi_compile`.
+/// Each permutation is compiled once and cached.
+pub struct ShaderVariants {
+    name:     String,
+    source:   RefCell<String>,
+    options:  ShaderParseOptions,
+    keywords: RefCell<Vec<String>>,
+    cache:    RefCell<HashMap<u32, Rc<ShaderProgram>>>,
+}
+
+/// Keywords are stored as bits in the cache key
+pub const MAX_SHADER_KEYWORDS:usize = 32;
+
+impl ShaderVariants {
+    pub fn new( name:&str, source:String, options:ShaderParseOptions ) -> Result<Self, Error> {
+        let keywords = Self::parse_keywords( name, &source )?;
+        Ok( Self {
+            name: name.to_owned(),
+            source: RefCell::new( source ), options,
+            keywords: RefCell::new( keywords ),
+            cache: RefCell::new( HashMap::new() )
+        } )
+    }
+
+    fn parse_keywords( name:&str, source:&str ) -> Result<Vec<String>, Error> {
+        let keywords = parse_keywords( source );
+        if keywords.len() > MAX_SHADER_KEYWORDS {
+            return Err( Error::ShaderParse(
+                format!( "Shader \"{}\" declares {} keywords, maximum is {}!",
+                    name, keywords.len(), MAX_SHADER_KEYWORDS
+                )
+            ) );
+        }
+        Ok( keywords )
+    }
+
+    pub fn name(&self)     -> &str        { &self.name                     }
+    pub fn keywords(&self) -> Vec<String> { self.keywords.borrow().clone() }
+
+    pub fn has_keyword( &self, keyword:&str ) -> bool {
+        self.keywords.borrow().iter().any( |k| k == keyword )
+    }
+
+    pub fn compiled_variant_count(&self) -> usize { self.cache.borrow().len() }
+
+    /// Returns the program compiled with the given keywords defined,
+    /// compiling it first if it isn't cached yet.
+    /// Keywords the shader doesn't declare are ignored.
+    pub fn get_variant( &self, enabled:&[&str] ) -> Result<Rc<ShaderProgram>, Error> {
+        let key = self.keyword_mask( enabled );
+
+        match self.cache.borrow().get( &key ) {
+            Some( program ) => return Ok( program.clone() ),
+            None => {},
+        }
+
+        let program = self.compile( &self.source.borrow(), &self.keywords.borrow(), key )?;
+        self.cache.borrow_mut().insert( key, program.clone() );
+        Ok( program )
+    }
+
+    /// Recompiles every cached variant from new source and swaps them in place,
+    /// so materials using them pick up the change.
+    /// If any variant fails to compile nothing is replaced.
+    pub fn reload( &self, source:String ) -> Result<(), Error> {
+        let keywords = Self::parse_keywords( &self.name, &source )?;
+
+        // existing program, its key with the new keywords, replacement
+        let mut rebuilt:Vec<( Rc<ShaderProgram>, u32, Rc<ShaderProgram> )> = Vec::new();
+        {
+            let old_keywords = self.keywords.borrow();
+            for ( old_key, program ) in self.cache.borrow().iter() {
+                let mut key = 0;
+                for ( idx, keyword ) in old_keywords.iter().enumerate() {
+                    if old_key & ( 1 << idx ) == 0 { continue; }
+                    match keywords.iter().position( |k| k == keyword ) {
+                        Some( new_idx ) => key |= 1 << new_idx,
+                        None => {},
+                    }
+                }
+                rebuilt.push( ( program.clone(), key, self.compile( &source, &keywords, key )? ) );
+            }
+        }
+
+        let mut cache = HashMap::with_capacity( rebuilt.len() );
+        for ( program, key, replacement ) in rebuilt.into_iter() {
+            program.replace_with( &replacement );
+            cache.entry( key ).or_insert( program );
+        }
+
+        *self.source.borrow_mut()   = source;
+        *self.keywords.borrow_mut() = keywords;
+        *self.cache.borrow_mut()    = cache;
+        Ok(())
+    }
+
+    fn compile( &self, source:&str, keywords:&[String], key:u32 ) -> Result<Rc<ShaderProgram>, Error> {
+        let mut options = self.options.clone();
+        let mut defined:Vec<&str> = Vec::new();
+        for ( idx, keyword ) in keywords.iter().enumerate() {
+            if key & ( 1 << idx ) != 0 {
+                options.set_define( keyword, "" );
+                defined.push( keyword );
+            }
+        }
+
+        let program = program_from_source( source, &options )?;
+
+        log(
+            &format!( "Compiled variant of \"{}\" with keywords [{}].", self.name, defined.join(", ") ),
+            "Shader Variants"
+        );
+
+        Ok( program )
+    }
+
+    fn keyword_mask( &self, enabled:&[&str] ) -> u32 {
+        let keywords = self.keywords.borrow();
+        let mut mask = 0;
+        for keyword in enabled.iter() {
+            match keywords.iter().position( |k| k == keyword ) {
+                Some( idx ) => mask |= 1 << idx,
+                None => log(
+                    &format!( "Shader \"{}\" has no keyword \"{}\"!", self.name, keyword ),
+                    "Shader Variants | Warning"
+                ),
+            }
+        }
+        mask
+    }
+}
+
+impl fmt::Display for ShaderVariants {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!( f, "Shader Variants \"{}\" | Keywords: [{}] Compiled: {}",
+            self.name(), self.keywords.borrow().join(", "), self.compiled_variant_count()
+        )
+    }
+}
@@ -8,6 +8,9 @@ pub struct Input {
 
     pub speed_up: bool,
 
+    /// Check for changed assets right away instead of waiting for the next poll
+    pub reload: bool,
+
     mouse_pos:   Vector2,
     mouse_delta: Vector2,
 
@@ -23,6 +26,8 @@ impl Input {
 
             speed_up: false,
 
+            reload: false,
+
             mouse_pos:   Vector2::new_zero(),
             mouse_delta: Vector2::new_zero(),
 
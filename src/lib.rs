@@ -0,0 +1,6 @@
+//! Asset tools that don't need a GL context,
+//! shared with the binaries in src/bin
+
+extern crate image;
+
+pub mod bcn;
@@ -6,6 +6,7 @@ use debugging::print_start;
 
 #[allow(unused_imports)]
 use gl::types::*;
+#[allow(unused_imports)]
 use graphics::Texture;
 #[allow(unused_imports)]
 use texture::Sampler;
@@ -26,7 +27,8 @@ pub use time::Time;
 pub use input::Input;
 pub use transform::Transform;
 #[allow(unused_imports)]
-use graphics::{ Camera, camera, Material, texture, UniformBlock,
+use graphics::{ Camera, camera, Material, texture, UniformBlock, UniformRingBuffer, Skybox,
+    ring_buffer::{ ObjectData, DEFAULT_FRAMES_IN_FLIGHT },
     light::{ DirectionalLight, PointLight, SpotLight, Lights }
 };
 
@@ -82,9 +84,21 @@ fn main() {
     let sdl_timer = sdl.timer().unwrap();
     let mut timer = Time::new();
 
+    // NOTE: skybox, the clear and fog colors come from it
+    graphics::cubemap::set_seamless_filtering( true );
+    let mut skybox = Skybox::new(
+        resources::load_cubemap( "sky", None ).unwrap(),
+        resources::load_shader_program( "skybox" ).unwrap()
+    ).unwrap();
+
+    // NOTE: image-based lighting from the sky, for materials that opt in
+    graphics::ibl::set_environment( Some(
+        resources::load_environment_lighting( skybox.cubemap(), None ).unwrap()
+    ) );
+
     // NOTE: clear color
     // let clear_color = color::RGB::from_hex("#a98bc4").unwrap();
-    let clear_color = color::RGB::new_black();
+    let clear_color = skybox.fog_color();
     graphics::set_clear_color( &clear_color );
     graphics::update_viewport( &program_info.dimensions );
 
@@ -122,33 +136,34 @@ fn main() {
         Vector3::new( 50.0, 1.0, 50.0 )
     );
 
-    let floor_texture = resources::load_texture( "brickwall.jpg", None ).unwrap();
-
-    // NOTE: Shaders loaded here!
-    let blinn_phong = graphics::null_shader();
-
-    // NOTE: Materials created here!
-    let mut suzanne_material = Material::new( blinn_phong.clone() );
-    suzanne_material["specular_sampler"].set_texture2d( Texture::new_color_texture(
-        color::RGB::new_white() * 0.5
-    ) );
-    let model_loc = suzanne_material.get_uniform_location("model");
-    let normal_loc = suzanne_material.get_uniform_location( "normal_mat" );
-    suzanne_material["glossiness"].set_f32( 64.0 );
-    suzanne_material["use_vertex_color"].set_bool(true);
-
-    let mut floor_material = Material::new( blinn_phong.clone() );
-    let floor_mat = floor_transform.as_matrix();
-    floor_material["albedo_sampler"].set_texture2d( floor_texture.clone() );
-    floor_material["albedo_sampler_scaler"].set_vector2( Vector2::new_one() * 25.0 );
-    floor_material[normal_loc].set_matrix3x3( Matrix3x3::new_normal_matrix( &floor_mat ).unwrap() );
-    floor_material[model_loc].set_matrix4x4( floor_mat );
+    // NOTE: Materials loaded here!
+    let mut suzanne_material = resources::load_material( "suzanne" ).unwrap();
+    let mut floor_material   = resources::load_material( "floor" ).unwrap();
+    let floor_data = ObjectData::new( &floor_transform.as_matrix() );
+
+    // NOTE: PBR material from glTF style properties, lit by the sky
+    let sphere = resources::load_meshes( "sphere.gltf" ).unwrap();
+    let mut sphere_material = {
+        let mut properties = graphics::MetallicRoughness::default();
+        properties.set_base_color( Vector4::new( 0.9, 0.6, 0.2, 1.0 ) );
+        properties.set_roughness( 0.3 );
+        let mut material = Material::new_metallic_roughness(
+            resources::load_shader_variants( "pbr" ).unwrap(), &[], &properties
+        ).unwrap();
+        material.set_environment_lighting( true ).unwrap();
+        material
+    };
+    let sphere_transform = Transform::new(
+        Vector3::new_up() * 3.0,
+        Quaternion::new_identity(),
+        Vector3::new_one()
+    );
+    let sphere_data = ObjectData::new( &sphere_transform.as_matrix() );
 
     let mut matrices_block = UniformBlock::new( None, 128 );
     let projection = camera.new_projection().to_le_bytes();
     matrices_block.set_data_slice( &projection, 64 );
     matrices_block.bind_to_buffer_point( 0 );
-    blinn_phong.bind_uniform_block_by_name( "Matrices", 0 );
 
     // NOTE: lights created here!
     let mut light = Lights::new();
@@ -176,7 +191,6 @@ fn main() {
 
     let mut lights_block = UniformBlock::new( Some( light.as_bytes() ), light.size() );
     lights_block.bind_to_buffer_point( 1 );
-    blinn_phong.bind_uniform_block_by_name( "Lights", 1 );
 
     let mut data_block = UniformBlock::new( None, 40 );
     let fog_color = clear_color.as_vector4();
@@ -184,11 +198,13 @@ fn main() {
     data_block.set_data_slice( &camera.near_clip().to_le_bytes(), 32 );
     data_block.set_data_slice( &camera.far_clip().to_le_bytes(), 36 );
     data_block.bind_to_buffer_point( 2 );
-    blinn_phong.bind_uniform_block_by_name( "Data", 2 );
+
+    // NOTE: per-draw object data
+    let mut object_ring = UniformRingBuffer::new( 64 * 1024, DEFAULT_FRAMES_IN_FLIGHT );
 
     let mut cam_yaw = AngleAxis::new( -180.0f32.to_radians(), Vector3::new_up() );
     let mut cam_pitch = AngleAxis::new( 0.0, Vector3::new_right() );
-    let mut _depth_test = graphics::DepthTest::initialize();
+    let mut depth_test = graphics::DepthTest::initialize();
     loop {
 
         // UPDATE -------------------------------------------------------------------------------
@@ -219,6 +235,18 @@ fn main() {
 
         input.set_mouse_delta( mouse - last_mouse );
 
+        let reloaded = if input.reload {
+            input.reload = false;
+            resources::reload_changed_assets()
+        } else {
+            resources::poll_hot_reload()
+        };
+        if reloaded > 0 {
+            suzanne_material.refresh_shader();
+            floor_material.refresh_shader();
+            sphere_material.refresh_shader();
+        }
+
         cam_yaw   += -input.mouse_delta()[0].to_radians() * timer.unscaled_delta_time() * mouse_sensitivity;
         cam_pitch += input.mouse_delta()[1].to_radians()  * timer.unscaled_delta_time() * mouse_sensitivity;
         cam_pitch.clamp_to( -1.309, 1.309 );
@@ -269,12 +297,15 @@ fn main() {
 
         // RENDER -------------------------------------------------------------------------------
         graphics::clear_screen( gl::DEPTH_BUFFER_BIT );
+        object_ring.begin_frame();
         {
 
             suzanne_material.use_shader();
+            suzanne_material.send_all_uniforms_to_gl();
             let mut rot_aa = AngleAxis::new( 0.0, Vector3::new_up() );
             let mut angle_modifier = 0.0;
             let mut dist = 3.0;
+            let mut suzanne_block = graphics::PropertyBlock::new();
             for i in 0..80 {
                 
                 let rot = Quaternion::from_angle_axis( rot_aa ).normal();
@@ -298,18 +329,31 @@ fn main() {
                     _ => {}
                 };
                 
-                let model_mat = transform.as_matrix();
-
-                suzanne_material[normal_loc].set_matrix3x3( Matrix3x3::new_normal_matrix( &model_mat ).unwrap() );
-                suzanne_material[model_loc].set_matrix4x4( model_mat );
-                suzanne_material.send_all_uniforms_to_gl();
+                let object_data = ObjectData::new( &transform.as_matrix() );
+                // the ring logs when it's out of space, drawing would reuse the last object's data
+                if object_ring.push( object_data.as_bytes(), 3 ).is_none() { continue; }
+                suzanne_block.set_f32( "glossiness", 8.0 + ( i % 4 ) as f32 * 40.0 );
+                suzanne_material.use_material_with( &[ &suzanne_block ] );
                 for mesh in suzanne.iter() { mesh.render(); }
             }
 
+            floor_material.use_shader();
             floor_material.send_all_uniforms_to_gl();
-            floor[0].render();
+            if object_ring.push( floor_data.as_bytes(), 3 ).is_some() {
+                floor[0].render();
+            }
 
-        } window.gl_swap_window();
+            sphere_material.use_shader();
+            sphere_material.send_all_uniforms_to_gl();
+            if object_ring.push( sphere_data.as_bytes(), 3 ).is_some() {
+                for mesh in sphere.iter() { mesh.render(); }
+            }
+
+            skybox.render( &mut depth_test );
+
+        }
+        object_ring.end_frame();
+        window.gl_swap_window();
 
     }
 
@@ -369,6 +413,9 @@ fn process_input( input:&mut Input, key_code:Option<Keycode>, is_down:bool ) {
         Keycode::Escape => {
             if is_down { input.quit_game(); }
         },
+        Keycode::F5 => {
+            if is_down { input.reload = true; }
+        },
         _ => {}
     };
 }
@@ -0,0 +1,251 @@
+use std::{
+    fs, path::PathBuf,
+    cell::RefCell,
+    rc::Weak,
+    time::{ Duration, Instant, SystemTime },
+};
+use crate::{
+    graphics::{
+        ShaderProgram, ShaderVariants, Texture, Cubemap, Mesh,
+        ibl::EnvironmentLighting,
+        texture::delete_textures,
+        cubemap::delete_cubemaps,
+        shader::program_from_source,
+        preprocessor::ShaderParseOptions,
+    },
+    Rc, debugging::{ Error, log }
+};
+
+/// How often poll_hot_reload checks modification times
+pub const HOT_RELOAD_POLL_INTERVAL:Duration = Duration::from_millis( 500 );
+
+/// Include files a shader pulled in, filled in by its include loader
+pub type IncludeList = Rc<RefCell<Vec<PathBuf>>>;
+
+/// Keeps track of the files behind loaded assets
+/// and rebuilds assets in place when their files change.
+struct HotReloader {
+    assets: Vec<WatchedAsset>,
+    last_poll: Option<Instant>,
+}
+
+struct WatchedAsset {
+    path: PathBuf,
+    includes: Option<IncludeList>,
+    modified: Vec<( PathBuf, Option<SystemTime> )>,
+    kind: WatchedKind,
+}
+
+enum WatchedKind {
+    ShaderProgram( Weak<ShaderProgram>, ShaderParseOptions ),
+    ShaderVariants( Weak<ShaderVariants> ),
+    Texture( Weak<Texture> ),
+    Cubemap( Weak<Cubemap>, Vec<PathBuf> ),
+    Meshes( Vec<Weak<Mesh>> ),
+    EnvironmentLighting( Weak<EnvironmentLighting> ),
+}
+
+thread_local!(
+    static HOT_RELOADER: RefCell<HotReloader> = RefCell::new(
+        HotReloader { assets: Vec::new(), last_poll: None }
+    );
+);
+
+pub fn watch_shader_program(
+    path:&PathBuf, program:&Rc<ShaderProgram>,
+    options:&ShaderParseOptions, includes:IncludeList
+) {
+    watch( path, Some( includes ), WatchedKind::ShaderProgram( Rc::downgrade( program ), options.clone() ) );
+}
+
+pub fn watch_shader_variants( path:&PathBuf, variants:&Rc<ShaderVariants>, includes:IncludeList ) {
+    watch( path, Some( includes ), WatchedKind::ShaderVariants( Rc::downgrade( variants ) ) );
+}
+
+pub fn watch_texture( path:&PathBuf, texture:&Rc<Texture> ) {
+    watch( path, None, WatchedKind::Texture( Rc::downgrade( texture ) ) );
+}
+
+/// Watches all six faces, reloading the whole cubemap when any of them changes
+pub fn watch_cubemap( faces:&[PathBuf], cubemap:&Rc<Cubemap> ) {
+    let path = match faces.first() { Some( path ) => path, None => return };
+    let modified = faces.iter().map( |face| ( face.clone(), modified_time( face ) ) ).collect();
+    HOT_RELOADER.with( |reloader| reloader.borrow_mut().assets.push( WatchedAsset {
+        path: path.clone(), includes: None, modified,
+        kind: WatchedKind::Cubemap( Rc::downgrade( cubemap ), faces.to_vec() )
+    } ) );
+}
+
+/// Watches the environment cubemap's and the generation shaders' files,
+/// regenerating the maps after the cubemap or shaders reloaded when any of them changes
+pub fn watch_environment_lighting( lighting:&Rc<EnvironmentLighting> ) {
+    let sources = lighting.sources();
+    let path = match sources.first() { Some( path ) => path, None => return };
+    let modified = sources.iter().map( |source| ( source.clone(), modified_time( source ) ) ).collect();
+    HOT_RELOADER.with( |reloader| reloader.borrow_mut().assets.push( WatchedAsset {
+        path: path.clone(), includes: None, modified,
+        kind: WatchedKind::EnvironmentLighting( Rc::downgrade( lighting ) )
+    } ) );
+}
+
+pub fn watch_meshes( path:&PathBuf, meshes:&[Rc<Mesh>] ) {
+    watch( path, None, WatchedKind::Meshes( meshes.iter().map( |m| Rc::downgrade( m ) ).collect() ) );
+}
+
+fn watch( path:&PathBuf, includes:Option<IncludeList>, kind:WatchedKind ) {
+    let modified = vec![ ( path.clone(), modified_time( path ) ) ];
+    HOT_RELOADER.with( |reloader| reloader.borrow_mut().assets.push(
+        WatchedAsset { path: path.clone(), includes, modified, kind }
+    ) );
+}
+
+/// Checks for changed files at most once every HOT_RELOAD_POLL_INTERVAL.
+/// Meant to be called every frame. Returns how many assets were reloaded.
+pub fn poll_hot_reload() -> usize {
+    let due = HOT_RELOADER.with( |reloader| match reloader.borrow().last_poll {
+        Some( last_poll ) => last_poll.elapsed() >= HOT_RELOAD_POLL_INTERVAL,
+        None => true,
+    } );
+    if due { reload_changed_assets() } else { 0 }
+}
+
+/// Reloads every asset whose files changed since they were loaded.
+/// Assets that fail to reload keep their previous version.
+/// Returns how many assets were reloaded.
+pub fn reload_changed_assets() -> usize {
+    // take the list so reloading can register include files without re-borrowing
+    let mut assets = HOT_RELOADER.with( |reloader| {
+        let mut reloader = reloader.borrow_mut();
+        reloader.last_poll = Some( Instant::now() );
+        core::mem::take( &mut reloader.assets )
+    } );
+
+    assets.retain( |asset| asset.is_alive() );
+
+    let mut reloaded = 0;
+    for asset in assets.iter_mut() {
+        asset.track_includes();
+        if !asset.check_modified() { continue; }
+
+        match asset.reload() {
+            Ok(()) => {
+                reloaded += 1;
+                log(
+                    &format!( "Reloaded {}", asset.path.display() ),
+                    "Hot Reload"
+                );
+            },
+            Err( e ) => log(
+                &format!( "Failed to reload {}, keeping previous version!\n   {}", asset.path.display(), e.msg() ),
+                "Hot Reload | Error"
+            ),
+        }
+        // pick up includes added by the new version
+        asset.track_includes();
+    }
+
+    HOT_RELOADER.with( |reloader| {
+        let mut reloader = reloader.borrow_mut();
+        // assets loaded while reloading were pushed onto the empty list
+        assets.append( &mut reloader.assets );
+        reloader.assets = assets;
+    } );
+
+    reloaded
+}
+
+impl WatchedAsset {
+
+    fn is_alive( &self ) -> bool {
+        match &self.kind {
+            WatchedKind::ShaderProgram( program, _ ) => program.strong_count() > 0,
+            WatchedKind::ShaderVariants( variants )  => variants.strong_count() > 0,
+            WatchedKind::Texture( texture )          => texture.strong_count() > 0,
+            WatchedKind::Cubemap( cubemap, _ )       => cubemap.strong_count() > 0,
+            WatchedKind::Meshes( meshes ) => meshes.iter().any( |mesh| mesh.strong_count() > 0 ),
+            WatchedKind::EnvironmentLighting( lighting ) => lighting.strong_count() > 0,
+        }
+    }
+
+    fn track_includes( &mut self ) {
+        let includes = match &self.includes {
+            Some( includes ) => includes.borrow(),
+            None => return,
+        };
+        for include in includes.iter() {
+            if !self.modified.iter().any( |( path, _ )| path == include ) {
+                self.modified.push( ( include.clone(), modified_time( include ) ) );
+            }
+        }
+    }
+
+    /// Updates the stored modification times, returns true if any file changed
+    fn check_modified( &mut self ) -> bool {
+        let mut changed = false;
+        for ( path, last_modified ) in self.modified.iter_mut() {
+            let modified = modified_time( path );
+            // missing files are usually mid-save, check again next time
+            if modified.is_none() { continue; }
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn reload( &self ) -> Result<(), Error> {
+        match &self.kind {
+            WatchedKind::ShaderProgram( program, options ) => {
+                let program = match program.upgrade() { Some( p ) => p, None => return Ok(()) };
+                let replacement = program_from_source( &super::load_string_path( &self.path )?, options )?;
+                program.replace_with( &replacement );
+            },
+            WatchedKind::ShaderVariants( variants ) => {
+                let variants = match variants.upgrade() { Some( v ) => v, None => return Ok(()) };
+                variants.reload( super::load_string_path( &self.path )? )?;
+            },
+            WatchedKind::Texture( texture ) => {
+                let texture = match texture.upgrade() { Some( t ) => t, None => return Ok(()) };
+                let replacement = super::build_texture( &self.path, *texture.options() )?;
+                texture.replace_with( &replacement );
+                unsafe { delete_textures( vec![ replacement ] ); }
+            },
+            WatchedKind::Cubemap( cubemap, faces ) => {
+                let cubemap = match cubemap.upgrade() { Some( c ) => c, None => return Ok(()) };
+                let replacement = super::build_cubemap( faces, *cubemap.options() )?;
+                cubemap.replace_with( &replacement );
+                unsafe { delete_cubemaps( vec![ replacement ] ); }
+            },
+            WatchedKind::Meshes( meshes ) => {
+                let replacements = super::build_meshes( &self.path )?;
+                if replacements.len() != meshes.len() {
+                    let count = replacements.len();
+                    Mesh::delete_meshes( replacements );
+                    return Err( Error::ResourcesReadFile( format!(
+                        "Expected {} meshes but the file now contains {}!",
+                        meshes.len(), count
+                    ) ) );
+                }
+                for ( mesh, replacement ) in meshes.iter().zip( replacements.iter() ) {
+                    match mesh.upgrade() {
+                        Some( mesh ) => mesh.replace_with( replacement ),
+                        None => {},
+                    }
+                }
+                Mesh::delete_meshes( replacements );
+            },
+            WatchedKind::EnvironmentLighting( lighting ) => {
+                // watched after the cubemap and shaders it's made from, so they've already reloaded
+                let lighting = match lighting.upgrade() { Some( l ) => l, None => return Ok(()) };
+                lighting.regenerate()?;
+            },
+        }
+        Ok(())
+    }
+
+}
+
+fn modified_time( path:&PathBuf ) -> Option<SystemTime> {
+    fs::metadata( path ).and_then( |metadata| metadata.modified() ).ok()
+}
@@ -0,0 +1,426 @@
+//! Text format for materials, found in resources/materials.
+//!
+//! ```text
+//! # comments start with '#'
+//! shader   blinn-phong
+//! keywords VERTEX_COLOR NORMAL_MAP
+//! environment_lighting
+//!
+//! glossiness            = float 64.0
+//! albedo_sampler_scaler = vec2 25.0 25.0
+//! lights[2].color       = rgb 1.0 0.5 0.2
+//! albedo_sampler        = texture brickwall.jpg wrapping=clamp_to_edge mag_filtering=nearest
+//! specular_sampler      = color_texture 127 127 127
+//! normal_sampler        = texture brickwall_normal.png
+//! normal_strength       = float 1.0
+//! ```
+//!
+//! Uniforms are named like they're looked up on a Material and
+//! values are written with their GLSL type, which has to match the shader's.
+//! Matrices are column major. Colours are written as `rgb`/`rgba`
+//! and end up in vec3/vec4 uniforms.
+//! Textures are paths relative to resources/textures, followed by
+//! `wrapping`, `wrapping_x`, `wrapping_y`, `min_filtering`, `mag_filtering`,
+//! `border=r,g,b` and `srgb=true` options.
+//! `srgb=true` is for the base color and emissive maps of the pbr shader,
+//! blinn-phong lights colors as they're stored and expects it off.
+//! Other sampler kinds and image uniforms aren't written,
+//! saving fails if one of them has a texture set.
+//! `environment_lighting` opts in to image-based lighting and enables
+//! the shader's ENVIRONMENT_LIGHTING keyword,
+//! the environment's uniforms aren't written to the file.
+
+use fmath::types::*;
+use std::{ cell::RefCell, collections::HashMap, rc::Weak };
+use crate::{
+    graphics::{
+        Material, Uniform, Sampler, Texture, ShaderVariants, ibl,
+        uniform::MatrixShape,
+        texture::{ self, TextureOptions, TextureWrapping, TextureFiltering, MipmapFiltering },
+    },
+    Rc, debugging::Error
+};
+
+/// Shaders and textures already loaded by material files.
+/// Materials naming the same shader share its variants and hot reload watch,
+/// materials naming the same texture with the same options share the texture.
+struct MaterialAssets {
+    shaders:  HashMap<String, Weak<ShaderVariants>>,
+    textures: HashMap<String, Weak<Texture>>,
+}
+
+thread_local!(
+    static MATERIAL_ASSETS: RefCell<MaterialAssets> = RefCell::new(
+        MaterialAssets { shaders: HashMap::new(), textures: HashMap::new() }
+    );
+);
+
+fn cached_shader_variants( shader:&str ) -> Result<Rc<ShaderVariants>, Error> {
+    let cached = MATERIAL_ASSETS.with( |assets| assets.borrow().shaders.get( shader ).and_then( |v| v.upgrade() ) );
+    match cached {
+        Some( variants ) => Ok( variants ),
+        None => {
+            let variants = super::load_shader_variants( shader )?;
+            MATERIAL_ASSETS.with( |assets| assets.borrow_mut().shaders.insert(
+                shader.to_owned(), Rc::downgrade( &variants )
+            ) );
+            Ok( variants )
+        },
+    }
+}
+
+/// Texture for a key in the material text, built by load if it isn't loaded already
+fn cached_texture(
+    key:String, load:impl FnOnce() -> Result<Rc<Texture>, String>
+) -> Result<Rc<Texture>, String> {
+    let cached = MATERIAL_ASSETS.with( |assets| assets.borrow().textures.get( &key ).and_then( |t| t.upgrade() ) );
+    match cached {
+        Some( texture ) => Ok( texture ),
+        None => {
+            let texture = load()?;
+            MATERIAL_ASSETS.with( |assets| assets.borrow_mut().textures.insert( key, Rc::downgrade( &texture ) ) );
+            Ok( texture )
+        },
+    }
+}
+
+/// Builds a material from the text of a .material file.
+/// source_name is used in error messages.
+pub fn parse_material( src:&str, source_name:&str ) -> Result<Material, Error> {
+    let lines:Vec<( usize, &str )> = src.lines().enumerate()
+        .map( |( idx, line )| ( idx + 1, strip_comment( line ).trim() ) )
+        .filter( |( _, line )| !line.is_empty() )
+        .collect();
+    let error = |line:usize, msg:String| Error::MaterialParse( format!( "{}:{}: {}", source_name, line, msg ) );
+
+    // shader and keywords first, uniforms need the shader to exist
+    let mut shader:Option<&str> = None;
+    let mut keywords:Vec<&str>  = Vec::new();
+    let mut environment_lighting = false;
+    for ( line_number, line ) in lines.iter() {
+        if line.contains('=') { continue; }
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some( "shader" ) => match ( words.next(), shader ) {
+                ( Some(_), Some(_) ) => return Err( error( *line_number, "Shader is set more than once!".to_owned() ) ),
+                ( Some( name ), None ) => shader = Some( name ),
+                ( None, _ ) => return Err( error( *line_number, "Expected a shader name!".to_owned() ) ),
+            },
+            Some( "keywords" ) => keywords.extend( words ),
+            Some( "environment_lighting" ) => environment_lighting = true,
+            Some( other ) => return Err( error( *line_number, format!( "Unrecognized line \"{}\"!", other ) ) ),
+            None => {},
+        }
+    }
+    let shader = shader.ok_or_else( || Error::MaterialParse(
+        format!( "{}: Material doesn't name a shader!", source_name )
+    ) )?;
+
+    let variants = cached_shader_variants( shader )?;
+    for keyword in keywords.iter() {
+        if !variants.has_keyword( keyword ) {
+            return Err( Error::MaterialParse( format!(
+                "{}: Shader \"{}\" has no keyword \"{}\"!", source_name, shader, keyword
+            ) ) );
+        }
+    }
+    let mut material = Material::from_variants( variants, &keywords )?;
+    // listing the keyword opts in as well, so it isn't turned back off
+    let environment_lighting = environment_lighting || keywords.contains( &ibl::ENVIRONMENT_LIGHTING );
+    material.set_environment_lighting( environment_lighting )?;
+
+    for ( line_number, line ) in lines.iter() {
+        let ( name, value ) = match line.split_once('=') {
+            Some( ( name, value ) ) => ( name.trim(), value.trim() ),
+            None => continue,
+        };
+        let value = parse_value( value ).map_err( |msg| error( *line_number, msg ) )?;
+        let uniform = material.get_uniform_mut_by_name( name )
+            .map_err( |_| error( *line_number, format!( "Unknown uniform \"{}\"!", name ) ) )?;
+        if !uniform.copy_value_from( &value ) {
+            return Err( error( *line_number, format!(
+                "Uniform \"{}\" is {} but was given {}!",
+                name, uniform.type_name(), value.type_name()
+            ) ) );
+        }
+    }
+
+    Ok( material )
+}
+
+/// Writes a material in the .material format.
+/// Only materials made from shader variants can be written,
+/// plain shader programs don't know which file they came from.
+pub fn write_material( material:&Material ) -> Result<String, Error> {
+    let variants = material.variants().ok_or_else( || Error::ResourcesWriteFile(
+        "Only materials created from shader variants can be saved!".to_owned()
+    ) )?;
+
+    let mut buffer = format!( "shader {}\n", variants.name() );
+    if !material.keywords().is_empty() {
+        buffer.push_str( &format!( "keywords {}\n", material.keywords().join(" ") ) );
+    }
+    if material.uses_environment_lighting() {
+        buffer.push_str( "environment_lighting\n" );
+    }
+    buffer.push('\n');
+
+    for ( name, uniform ) in material.uniform_names().iter().zip( material.uniforms().iter() ) {
+        if material.uses_environment_lighting() && ibl::ENVIRONMENT_UNIFORMS.contains( &name.as_str() ) {
+            continue;
+        }
+        write_uniform( &mut buffer, name, uniform )?;
+    }
+    Ok( buffer )
+}
+
+fn write_uniform( buffer:&mut String, path:&str, uniform:&Uniform ) -> Result<(), Error> {
+    match uniform {
+        Uniform::Array( elements ) => for ( idx, element ) in elements.iter().enumerate() {
+            write_uniform( buffer, &format!( "{}[{}]", path, idx ), element )?;
+        },
+        Uniform::Struct( members ) => for ( name, member ) in members.iter() {
+            write_uniform( buffer, &format!( "{}.{}", path, name ), member )?;
+        },
+        _ => match format_value( uniform ) {
+            Some( value ) => buffer.push_str( &format!( "{} = {}\n", path, value? ) ),
+            // set by the engine, not the material
+            None => {},
+        },
+    }
+    Ok(())
+}
+
+fn format_value( uniform:&Uniform ) -> Option<Result<String, Error>> {
+    let floats = |v:&[f32]| v.iter().map( |f| format!( "{:?}", f ) ).collect::<Vec<String>>().join(" ");
+    let doubles = |v:&[f64]| v.iter().map( |f| format!( "{:?}", f ) ).collect::<Vec<String>>().join(" ");
+    let values = |v:&[String]| v.join(" ");
+    let value = match uniform {
+        Uniform::Float32( v, _ )     => format!( "float {:?}", v ),
+        Uniform::Float32Vec2( v, _ ) => format!( "vec2 {}", floats( &[ v[0], v[1] ] ) ),
+        Uniform::Float32Vec3( v, _ ) => format!( "vec3 {}", floats( &[ v[0], v[1], v[2] ] ) ),
+        Uniform::Float32Vec4( v, _ ) => format!( "vec4 {}", floats( &[ v[0], v[1], v[2], v[3] ] ) ),
+        Uniform::Float64( v, _ )     => format!( "double {:?}", v ),
+        Uniform::Int32( v, _ )       => format!( "int {}", v ),
+        Uniform::UInt32( v, _ )      => format!( "uint {}", v ),
+        Uniform::Bool( v, _ )        => format!( "bool {}", v ),
+        Uniform::Int32Vec2( v, _ )   => format!( "ivec2 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::Int32Vec3( v, _ )   => format!( "ivec3 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::Int32Vec4( v, _ )   => format!( "ivec4 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::UInt32Vec2( v, _ )  => format!( "uvec2 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::UInt32Vec3( v, _ )  => format!( "uvec3 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::UInt32Vec4( v, _ )  => format!( "uvec4 {}", values( &v.map( |i| i.to_string() ) ) ),
+        Uniform::BoolVec2( v, _ )    => format!( "bvec2 {}", values( &v.map( |b| b.to_string() ) ) ),
+        Uniform::BoolVec3( v, _ )    => format!( "bvec3 {}", values( &v.map( |b| b.to_string() ) ) ),
+        Uniform::BoolVec4( v, _ )    => format!( "bvec4 {}", values( &v.map( |b| b.to_string() ) ) ),
+        Uniform::Float64Vec2( v, _ ) => format!( "dvec2 {}", doubles( v ) ),
+        Uniform::Float64Vec3( v, _ ) => format!( "dvec3 {}", doubles( v ) ),
+        Uniform::Float64Vec4( v, _ ) => format!( "dvec4 {}", doubles( v ) ),
+        Uniform::Float32Mat3( v, _ ) => format!( "mat3 {}", floats( v.as_array() ) ),
+        Uniform::Float32Mat4( v, _ ) => format!( "mat4 {}", floats( v.as_array() ) ),
+        Uniform::Float32Matrix( ( shape, v ), _ ) => format!( "{} {}", matrix_type_name( "mat", shape ), floats( v ) ),
+        Uniform::Float64Matrix( ( shape, v ), _ ) => format!( "{} {}", matrix_type_name( "dmat", shape ), doubles( v ) ),
+        Uniform::Sampler2D( ( texture, _ ), _ ) => {
+            if Rc::ptr_eq( texture, &texture::null_texture() ) {
+                "null_texture".to_owned()
+            } else if let Some( local_path ) = texture.local_path() {
+                format!( "texture {}{}", local_path, format_texture_options( texture.options() ) )
+            } else if let Some( color ) = texture.solid_color() {
+                let [ r, g, b ] = color.as_array_rgb();
+                format!( "color_texture {} {} {}", r, g, b )
+            } else {
+                return Some( Err( Error::ResourcesWriteFile( format!(
+                    "{} wasn't loaded from a file and can't be saved!", texture
+                ) ) ) );
+            }
+        },
+        // other sampler kinds and images only have textures built at runtime,
+        // which can't be written, unset ones keep their default when loaded
+        Uniform::Sampler( ( kind, Some( texture ), _ ), _ ) => return Some( Err( Error::ResourcesWriteFile( format!(
+            "{} texture {} can't be saved, only sampler2D textures can!", kind.msg(), texture.handle()
+        ) ) ) ),
+        Uniform::Image( ( Some( texture ), _ ), _ ) => return Some( Err( Error::ResourcesWriteFile( format!(
+            "Image texture {} can't be saved, only sampler2D textures can!", texture.handle()
+        ) ) ) ),
+        _ => return None,
+    };
+    Some( Ok( value ) )
+}
+
+fn matrix_type_name( prefix:&str, shape:&MatrixShape ) -> String {
+    if shape.columns() == shape.rows() { format!( "{}{}", prefix, shape.columns() ) }
+    else { format!( "{}{}", prefix, shape ) }
+}
+
+/// Options that differ from TextureOptions::default
+fn format_texture_options( options:&TextureOptions ) -> String {
+    let default = TextureOptions::default();
+    let mut buffer = String::new();
+    if options.wrapping_x().name() == options.wrapping_y().name() {
+        if options.wrapping_x().name() != default.wrapping_x().name() {
+            buffer.push_str( &format!( " wrapping={}", options.wrapping_x().name() ) );
+        }
+    } else {
+        buffer.push_str( &format!(
+            " wrapping_x={} wrapping_y={}",
+            options.wrapping_x().name(), options.wrapping_y().name()
+        ) );
+    }
+    if options.min_filtering().name() != default.min_filtering().name() {
+        buffer.push_str( &format!( " min_filtering={}", options.min_filtering().name() ) );
+    }
+    if options.mag_filtering().name() != default.mag_filtering().name() {
+        buffer.push_str( &format!( " mag_filtering={}", options.mag_filtering().name() ) );
+    }
+    match options.border_color() {
+        Some( color ) => {
+            let [ r, g, b ] = color.as_array_rgb();
+            buffer.push_str( &format!( " border={},{},{}", r, g, b ) );
+        },
+        None => {},
+    }
+    if options.srgb() {
+        buffer.push_str( " srgb=true" );
+    }
+    buffer
+}
+
+fn strip_comment( line:&str ) -> &str {
+    match line.find('#') {
+        Some( idx ) => &line[..idx],
+        None => line,
+    }
+}
+
+/// Parses `type values...` into a uniform without a location
+fn parse_value( src:&str ) -> Result<Uniform, String> {
+    let mut words = src.split_whitespace();
+    let kind = words.next().ok_or_else( || "Expected a value!".to_owned() )?;
+    let words:Vec<&str> = words.collect();
+    let uniform = match kind {
+        "float"  => Uniform::Float32( parse_array::<f32, 1>( kind, &words )?[0], -1 ),
+        "double" => Uniform::Float64( parse_array::<f64, 1>( kind, &words )?[0], -1 ),
+        "int"    => Uniform::Int32  ( parse_array::<i32, 1>( kind, &words )?[0], -1 ),
+        "uint"   => Uniform::UInt32 ( parse_array::<u32, 1>( kind, &words )?[0], -1 ),
+        "bool"   => Uniform::Bool   ( parse_array::<bool, 1>( kind, &words )?[0], -1 ),
+
+        "vec2" => {
+            let [ x, y ] = parse_array::<f32, 2>( kind, &words )?;
+            Uniform::Float32Vec2( Vector2::new( x, y ), -1 )
+        },
+        "vec3" | "rgb" => {
+            let [ x, y, z ] = parse_array::<f32, 3>( kind, &words )?;
+            Uniform::Float32Vec3( Vector3::new( x, y, z ), -1 )
+        },
+        "vec4" | "rgba" => {
+            let [ x, y, z, w ] = parse_array::<f32, 4>( kind, &words )?;
+            Uniform::Float32Vec4( Vector4::new( x, y, z, w ), -1 )
+        },
+
+        "ivec2" => Uniform::Int32Vec2 ( parse_array( kind, &words )?, -1 ),
+        "ivec3" => Uniform::Int32Vec3 ( parse_array( kind, &words )?, -1 ),
+        "ivec4" => Uniform::Int32Vec4 ( parse_array( kind, &words )?, -1 ),
+        "uvec2" => Uniform::UInt32Vec2( parse_array( kind, &words )?, -1 ),
+        "uvec3" => Uniform::UInt32Vec3( parse_array( kind, &words )?, -1 ),
+        "uvec4" => Uniform::UInt32Vec4( parse_array( kind, &words )?, -1 ),
+        "bvec2" => Uniform::BoolVec2  ( parse_array( kind, &words )?, -1 ),
+        "bvec3" => Uniform::BoolVec3  ( parse_array( kind, &words )?, -1 ),
+        "bvec4" => Uniform::BoolVec4  ( parse_array( kind, &words )?, -1 ),
+        "dvec2" => Uniform::Float64Vec2( parse_array( kind, &words )?, -1 ),
+        "dvec3" => Uniform::Float64Vec3( parse_array( kind, &words )?, -1 ),
+        "dvec4" => Uniform::Float64Vec4( parse_array( kind, &words )?, -1 ),
+
+        "texture"       => parse_texture( &words )?,
+        "color_texture" => {
+            let [ r, g, b ] = parse_array::<u8, 3>( kind, &words )?;
+            let texture = cached_texture( format!( "color_texture {} {} {}", r, g, b ), || Ok(
+                Texture::new_color_texture( color::RGB::new( r, g, b ) )
+            ) )?;
+            Uniform::Sampler2D( ( texture, Sampler::empty() ), -1 )
+        },
+        "null_texture" => Uniform::Sampler2D( ( texture::null_texture(), Sampler::empty() ), -1 ),
+
+        _ => match parse_matrix_type( kind ) {
+            // mat3 and mat4 too, copy_value_from takes them for Matrix3x3 and Matrix4x4 uniforms
+            Some( ( false, shape ) ) => {
+                let values = parse_vec::<f32>( kind, &words, shape.value_count() )?;
+                Uniform::Float32Matrix( ( shape, values ), -1 )
+            },
+            Some( ( true, shape ) ) => {
+                let values = parse_vec::<f64>( kind, &words, shape.value_count() )?;
+                Uniform::Float64Matrix( ( shape, values ), -1 )
+            },
+            None => return Err( format!( "Unrecognized type \"{}\"!", kind ) ),
+        },
+    };
+    Ok( uniform )
+}
+
+/// Parses matN, matCxR, dmatN and dmatCxR, returns true for double matrices
+fn parse_matrix_type( kind:&str ) -> Option<( bool, MatrixShape )> {
+    let ( double, dimensions ) = match kind.strip_prefix("dmat") {
+        Some( dimensions ) => ( true, dimensions ),
+        None => ( false, kind.strip_prefix("mat")? ),
+    };
+    let parse_dimension = |s:&str| match s.parse::<u8>() {
+        Ok( n ) if ( 2..=4 ).contains( &n ) => Some( n ),
+        _ => None,
+    };
+    let shape = match dimensions.split_once('x') {
+        Some( ( columns, rows ) ) => MatrixShape::new( parse_dimension( columns )?, parse_dimension( rows )? ),
+        None => {
+            let n = parse_dimension( dimensions )?;
+            MatrixShape::new( n, n )
+        },
+    };
+    Some( ( double, shape ) )
+}
+
+fn parse_texture( words:&[&str] ) -> Result<Uniform, String> {
+    let ( local_path, options_words ) = match words.split_first() {
+        Some( split ) => split,
+        None => return Err( "Expected a texture path!".to_owned() ),
+    };
+
+    let mut options = TextureOptions::default();
+    for word in options_words.iter() {
+        let ( key, value ) = word.split_once('=')
+            .ok_or_else( || format!( "Expected texture option \"name=value\" but found \"{}\"!", word ) )?;
+        let invalid = || format!( "\"{}\" is not a valid value for texture option \"{}\"!", value, key );
+        match key {
+            "wrapping"   => options.set_wrapping( TextureWrapping::from_name( value ).ok_or_else( invalid )? ),
+            "wrapping_x" => options.set_wrapping_x( TextureWrapping::from_name( value ).ok_or_else( invalid )? ),
+            "wrapping_y" => options.set_wrapping_y( TextureWrapping::from_name( value ).ok_or_else( invalid )? ),
+            "min_filtering" => options.set_min_filtering( MipmapFiltering::from_name( value ).ok_or_else( invalid )? ),
+            "mag_filtering" => options.set_mag_filtering( TextureFiltering::from_name( value ).ok_or_else( invalid )? ),
+            "border" => {
+                let channels:Vec<&str> = value.split(',').collect();
+                let [ r, g, b ] = parse_array::<u8, 3>( key, &channels ).map_err( |_| invalid() )?;
+                options.set_border_color( color::RGB::new( r, g, b ) );
+            },
+            "srgb" => options.set_srgb( value.parse::<bool>().map_err( |_| invalid() )? ),
+            _ => return Err( format!( "Unrecognized texture option \"{}\"!", key ) ),
+        }
+    }
+
+    let key = format!( "texture {}{}", local_path, format_texture_options( &options ) );
+    let texture = cached_texture( key, || super::load_texture( local_path, Some( options ) )
+        .map_err( |e| e.msg().to_owned() ) )?;
+    Ok( Uniform::Sampler2D( ( texture, Sampler::empty() ), -1 ) )
+}
+
+fn parse_vec<T:core::str::FromStr>( kind:&str, words:&[&str], count:usize ) -> Result<Vec<T>, String> {
+    if words.len() != count {
+        return Err( format!( "{} takes {} values but was given {}!", kind, count, words.len() ) );
+    }
+    words.iter().map( |word| word.parse::<T>()
+        .map_err( |_| format!( "\"{}\" is not a valid {} value!", word, kind ) )
+    ).collect()
+}
+
+fn parse_array<T:core::str::FromStr + Copy + Default, const N:usize>( kind:&str, words:&[&str] ) -> Result<[T;N], String> {
+    let values = parse_vec::<T>( kind, words, N )?;
+    let mut array = [T::default();N];
+    array.copy_from_slice( &values );
+    Ok( array )
+}
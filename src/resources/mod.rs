@@ -1,20 +1,33 @@
 extern crate wavefront_obj;
 extern crate rs_gltf;
+use gl::types::*;
 #[allow(unused_imports)]
 use std::{
     env, fs,
     path::{ PathBuf, Path },
+    cell::RefCell,
 };
 use crate::{
     graphics::{
-        *, texture::{ TextureOptions, ImageGL },
-        shader::shader_parser,
+        *, texture::{ TextureOptions, TextureWrapping, ImageGL },
+        compressed::CompressedImage,
+        cubemap::CUBEMAP_FACES,
+        shader::{ program_from_source, parse_stages, ShaderKind, ALL_SHADER_KINDS },
+        spirv::SpirvOptions,
+        preprocessor::ShaderParseOptions,
     }, Rc, cstr::*, debugging::Error
 };
 
 mod image_loader;
 pub use image_loader::DynamicImage;
 
+mod texture_container;
+
+pub mod hot_reload;
+pub use hot_reload::{ poll_hot_reload, reload_changed_assets };
+
+pub mod material_file;
+
 static mut RESOURCES_PATH:String = String::new();
 fn get_resources_path() -> PathBuf {
     unsafe { PathBuf::from( &RESOURCES_PATH ) }
@@ -60,8 +73,14 @@ pub fn load_program_info() -> Result<super::ProgramInfo, Error> {
 }
 
 pub fn load_meshes( local_path:&str ) -> Result< Vec<Rc<Mesh>>, Error > {
-
     let path = resource_path_from_local_path( &format!( "models/{}", local_path ) );
+    let meshes = build_meshes( &path )?;
+    hot_reload::watch_meshes( &path, &meshes );
+    Ok( meshes )
+}
+
+fn build_meshes( path:&PathBuf ) -> Result< Vec<Rc<Mesh>>, Error > {
+
     // determine file type
     match path.extension() {
         Some(ext) => {
@@ -73,13 +92,13 @@ pub fn load_meshes( local_path:&str ) -> Result< Vec<Rc<Mesh>>, Error > {
             };
             match ext_str {
                 OBJ_EXT => {
-                    let raw = load_string_path( &path )?;
+                    let raw = load_string_path( path )?;
                     let mesh_objs = wavefront_obj::parse_obj( raw )
                         .map_err( |e| Error::OBJParse( e.msg() ) )?;
                     Ok( Mesh::from_obj( mesh_objs ) )
                 }
                 GLTF_JSON_EXT => {
-                    let raw = load_string_path( &path )?;
+                    let raw = load_string_path( path )?;
                     let gltf = rs_gltf::parse_into_gltf( &raw )
                         .map_err( |e| Error::GLTFJsonError( e.msg().to_owned() ) )?;
                     Ok( Mesh::from_gltf( gltf )? )
@@ -101,18 +120,204 @@ pub fn load_texture( local_path:&str, options:Option<TextureOptions> ) -> Result
         Some(options) => options,
         None => TextureOptions::default(),
     };
-    load_texture_path(
+    let texture = load_texture_path(
         &resource_path_from_local_path( &format!( "textures/{}", local_path ) ),
         options
-    )
+    )?;
+    texture.set_local_path( local_path );
+    Ok( texture )
 }
 
 pub fn load_texture_path( path:&PathBuf, options:TextureOptions ) -> Result<Rc<Texture>, Error> {
+    let texture = build_texture( path, options )?;
+    hot_reload::watch_texture( path, &texture );
+    Ok( texture )
+}
+
+fn build_texture( path:&PathBuf, options:TextureOptions ) -> Result<Rc<Texture>, Error> {
+    if is_compressed_texture_path( path ) {
+        return Texture::from_compressed( &load_compressed_image_path( path )?, options );
+    }
     let dynamic_image = load_image_path( path )?;
     let gl_image = ImageGL::from_dynamic_image( dynamic_image )?;
     Ok( Texture::new( gl_image, options ) )
 }
 
+/// Loads a cubemap from a folder in resources/textures
+/// holding one image per face, named after CubemapFace::file_stem,
+/// like `textures/sky/right.png` and `textures/sky/left.png`,
+/// or from a single .dds or .ktx2 cubemap file like `textures/sky.ktx2`
+pub fn load_cubemap( local_path:&str, options:Option<TextureOptions> ) -> Result<Rc<Cubemap>, Error> {
+    let folder = resource_path_from_local_path( &format!( "textures/{}", local_path ) );
+    if is_compressed_texture_path( &folder ) {
+        return load_cubemap_paths( vec![ folder ], options.unwrap_or( TextureOptions::default() ) );
+    }
+    let entries = fs::read_dir( &folder )
+        .map_err( |e| Error::ResourcesReadFile(
+            format!( "{} at {:?}", e, folder.to_str().unwrap().replace("\\", "/") )
+        ) )?;
+    let files:Vec<PathBuf> = entries.filter_map( |entry| entry.ok() )
+        .map( |entry| entry.path() )
+        .filter( |path| path.is_file() )
+        .collect();
+
+    let mut faces = Vec::with_capacity( 6 );
+    for face in CUBEMAP_FACES.iter() {
+        let path = files.iter().find(
+            |path| path.file_stem().and_then( |stem| stem.to_str() ) == Some( face.file_stem() )
+        ).ok_or( Error::ResourcesReadFile( format!(
+            "Cubemap \"textures/{}\" has no image for face {}!", local_path, face
+        ) ) )?;
+        faces.push( path.clone() );
+    }
+    load_cubemap_paths( faces, options.unwrap_or( TextureOptions::default() ) )
+}
+
+/// Loads a cubemap from six images in resources/textures, in CUBEMAP_FACES order
+pub fn load_cubemap_faces( local_paths:[&str;6], options:Option<TextureOptions> ) -> Result<Rc<Cubemap>, Error> {
+    let faces = local_paths.iter()
+        .map( |local_path| resource_path_from_local_path( &format!( "textures/{}", local_path ) ) )
+        .collect();
+    load_cubemap_paths( faces, options.unwrap_or( TextureOptions::default() ) )
+}
+
+pub fn load_cubemap_paths( faces:Vec<PathBuf>, options:TextureOptions ) -> Result<Rc<Cubemap>, Error> {
+    let cubemap = build_cubemap( &faces, options )?;
+    hot_reload::watch_cubemap( &faces, &cubemap );
+    cubemap.set_sources( faces );
+    Ok( cubemap )
+}
+
+fn build_cubemap( faces:&[PathBuf], options:TextureOptions ) -> Result<Rc<Cubemap>, Error> {
+    if let [ path ] = faces {
+        if is_compressed_texture_path( path ) {
+            return Cubemap::from_compressed( &load_compressed_image_path( path )?, options );
+        }
+    }
+    let mut images = Vec::with_capacity( 6 );
+    for path in faces.iter() {
+        images.push( ImageGL::from_dynamic_image_unflipped( load_image_path( path )? )? );
+    }
+    let images:[ImageGL;6] = images.try_into().map_err( |images:Vec<ImageGL>| Error::TextureInvalidImage(
+        format!( "A cubemap needs 6 faces, got {}!", images.len() )
+    ) )?;
+    Cubemap::new( images, options )
+}
+
+/// Loads a cubemap array from a .dds or .ktx2 file in resources/textures,
+/// sampled as samplerCubeArray.
+/// 2D arrays load with load_texture_array as a Texture2DArray.
+pub fn load_compressed_array( local_path:&str, options:Option<TextureOptions> ) -> Result<Rc<CompressedTexture>, Error> {
+    let path = resource_path_from_local_path( &format!( "textures/{}", local_path ) );
+    let image = load_compressed_image_path( &path )?;
+    match ( image.is_array(), image.is_cubemap() ) {
+        ( true, true ) => {},
+        ( true, false ) => return Err( Error::TextureInvalidImage( format!(
+            "\"textures/{}\" is a 2D array, load it with load_texture_array instead!", local_path
+        ) ) ),
+        _ => return Err( Error::TextureInvalidImage( format!(
+            "\"textures/{}\" isn't an array, load it with load_texture or load_cubemap instead!", local_path
+        ) ) ),
+    }
+    CompressedTexture::new( &image, options.unwrap_or( TextureOptions::default() ) )
+}
+
+/// Loads same-sized images from resources/textures as the layers of a texture array,
+/// in order, or a whole array from a single .dds or .ktx2 file
+pub fn load_texture_array( local_paths:&[&str], options:Option<TextureOptions> ) -> Result<Rc<Texture2DArray>, Error> {
+    let options = options.unwrap_or( TextureOptions::default() );
+    let paths:Vec<PathBuf> = local_paths.iter()
+        .map( |local_path| resource_path_from_local_path( &format!( "textures/{}", local_path ) ) )
+        .collect();
+    if let [ path ] = paths.as_slice() {
+        if is_compressed_texture_path( path ) {
+            return Texture2DArray::from_compressed( &load_compressed_image_path( path )?, options );
+        }
+    }
+    let mut layers = Vec::with_capacity( paths.len() );
+    for path in paths.iter() {
+        layers.push( ImageGL::from_dynamic_image( load_image_path( path )? )? );
+    }
+    Texture2DArray::new( layers, options )
+}
+
+/// Loads same-sized images from resources/textures as the slices of a 3D texture,
+/// the first image is the slice at depth 0
+pub fn load_texture3d( local_paths:&[&str], options:Option<TextureOptions> ) -> Result<Rc<Texture3D>, Error> {
+    let mut slices = Vec::with_capacity( local_paths.len() );
+    for local_path in local_paths.iter() {
+        let path = resource_path_from_local_path( &format!( "textures/{}", local_path ) );
+        slices.push( ImageGL::from_dynamic_image( load_image_path( &path )? )? );
+    }
+    Texture3D::from_slices( slices, options.unwrap_or( TextureOptions::default() ) )
+}
+
+/// Reads a block compressed .dds or .ktx2 file, mip levels and all
+pub fn load_compressed_image_path( path:&PathBuf ) -> Result<CompressedImage, Error> {
+    let bytes = load_bytes_path( path )?;
+    let source_name = path.to_str().unwrap_or_default().replace( "\\", "/" );
+    match path.extension().and_then( |ext| ext.to_str() ) {
+        Some( DDS_EXT )  => texture_container::parse_dds( &bytes, &source_name ),
+        Some( KTX2_EXT ) => texture_container::parse_ktx2( &bytes, &source_name ),
+        _ => Err( Error::ResourcesUnrecognizedFileExt( format!(
+            "\"{}\" isn't a .dds or .ktx2 file!", source_name
+        ) ) ),
+    }
+}
+
+fn is_compressed_texture_path( path:&Path ) -> bool {
+    matches!( path.extension().and_then( |ext| ext.to_str() ), Some( DDS_EXT ) | Some( KTX2_EXT ) )
+}
+
+/// Loads an equirectangular image from resources/textures, like an .hdr sky,
+/// and renders it into a half float cubemap with faces of the given size
+pub fn load_equirectangular_cubemap(
+    local_path:&str, size:GLint, options:Option<TextureOptions>
+) -> Result<Rc<Cubemap>, Error> {
+    let mut source_options = TextureOptions::default();
+    source_options.set_wrapping_y( TextureWrapping::ClampToEdge );
+    let path = resource_path_from_local_path( &format!( "textures/{}", local_path ) );
+    let source = build_texture( &path, source_options )?;
+    let cubemap = Cubemap::from_equirectangular(
+        &source, size, gl::RGBA16F,
+        load_shader_program( "equirectangular_to_cubemap" )?,
+        options.unwrap_or( TextureOptions::default() )
+    );
+    unsafe { texture::delete_textures( vec![ source ] ); }
+    let cubemap = cubemap?;
+    cubemap.set_sources( vec![ path ] );
+    Ok( cubemap )
+}
+
+/// Image-based lighting for an environment cubemap,
+/// generated with the shaders in resources/shaders or loaded from the ibl cache.
+/// The maps are keyed on the files the cubemap and the shaders were loaded from,
+/// and regenerated when any of them changes while hot reloading.
+pub fn load_environment_lighting(
+    environment:&Rc<Cubemap>, settings:Option<ibl::EnvironmentSettings>
+) -> Result<Rc<EnvironmentLighting>, Error> {
+    let mut sources = Vec::new();
+    let mut load = |local_path:&str| -> Result<Rc<ShaderProgram>, Error> {
+        let mut path = resource_path_from_local_path( &format!( "shaders/{}", local_path ) );
+        path.set_extension("shader");
+        let mut options = shader_parse_options();
+        options.set_source_name( &format!( "shaders/{}.shader", local_path ) );
+        let ( program, includes ) = load_watched_shader_program( &path, &options )?;
+        sources.push( path );
+        sources.extend( includes );
+        Ok( program )
+    };
+    let irradiance = load( "irradiance_convolution" )?;
+    let prefilter  = load( "prefilter_environment" )?;
+    let brdf_lut   = load( "brdf_lut" )?;
+    let shaders = ibl::EnvironmentShaders { irradiance, prefilter, brdf_lut, sources };
+    let lighting = Rc::new( EnvironmentLighting::load_or_generate(
+        environment, settings.unwrap_or( ibl::EnvironmentSettings::default() ), shaders
+    )? );
+    hot_reload::watch_environment_lighting( &lighting );
+    Ok( lighting )
+}
+
 pub fn load_image( local_path:&str ) -> Result<DynamicImage, Error> {
     load_image_path( &resource_path_from_local_path(local_path) )
 }
@@ -120,14 +325,161 @@ pub fn load_image( local_path:&str ) -> Result<DynamicImage, Error> {
 pub fn load_image_path( path:&PathBuf ) -> Result<DynamicImage, Error> { image_loader::load_image(path) }
 
 pub fn load_shader_program( local_path:&str ) -> Result<Rc<ShaderProgram>, Error> {
+    load_shader_program_with_defines( local_path, &[] )
+}
+
+/// Loads a shader program with extra #defines inserted after #version in every stage
+pub fn load_shader_program_with_defines(
+    local_path:&str, defines:&[( &str, &str )]
+) -> Result<Rc<ShaderProgram>, Error> {
+    let mut path = resource_path_from_local_path( &format!( "shaders/{}", local_path ) );
+    path.set_extension("shader");
+    let mut options = shader_parse_options();
+    options.set_source_name( &format!( "shaders/{}.shader", local_path ) );
+    for ( name, value ) in defines.iter() {
+        options.set_define( name, value );
+    }
+    load_shader_program_path( &path, &options )
+}
+
+/// Loads a shader program and watches it and its includes for hot reloading.
+pub fn load_shader_program_path( path:&PathBuf, options:&ShaderParseOptions ) -> Result<Rc<ShaderProgram>, Error> {
+    Ok( load_watched_shader_program( path, options )?.0 )
+}
+
+/// Like load_shader_program_path, also returning the files the program included
+fn load_watched_shader_program(
+    path:&PathBuf, options:&ShaderParseOptions
+) -> Result<( Rc<ShaderProgram>, Vec<PathBuf> ), Error> {
+    let includes = hot_reload::IncludeList::default();
+    let options = tracked_shader_parse_options( options, &includes );
+    let program = program_from_source( &load_string_path(path)?, &options )?;
+    let included = includes.borrow().clone();
+    hot_reload::watch_shader_program( path, &program, &options, includes );
+    Ok( ( program, included ) )
+}
+
+/// Loads a .shader file as a separable program for use in a ProgramPipeline.
+/// The file may contain any subset of stages, like just a #fragment stage.
+pub fn load_separable_program( local_path:&str ) -> Result<Rc<ShaderProgram>, Error> {
+    let mut path = resource_path_from_local_path( &format!( "shaders/{}", local_path ) );
+    path.set_extension("shader");
+    let mut options = shader_parse_options();
+    options.set_source_name( &format!( "shaders/{}.shader", local_path ) );
+    options.set_separable( true );
+    load_shader_program_path( &path, &options )
+}
+
+/// Loads a shader whose keyword permutations are compiled on demand
+pub fn load_shader_variants( local_path:&str ) -> Result<Rc<ShaderVariants>, Error> {
     let mut path = resource_path_from_local_path( &format!( "shaders/{}", local_path ) );
     path.set_extension("shader");
-    load_shader_program_path(&path)
+    let mut options = shader_parse_options();
+    options.set_source_name( &format!( "shaders/{}.shader", local_path ) );
+
+    let includes = hot_reload::IncludeList::default();
+    let options = tracked_shader_parse_options( &options, &includes );
+    let variants = Rc::new( ShaderVariants::new(
+        local_path, load_string_path( &path )?, options
+    )? );
+    hot_reload::watch_shader_variants( &path, &variants, includes );
+    Ok( variants )
+}
+
+/// Parse options that resolve #include paths relative to resources/shaders.
+/// Include paths are reported relative to resources/shaders in compile errors.
+pub fn shader_parse_options() -> ShaderParseOptions {
+    let mut options = ShaderParseOptions::default();
+    options.set_include_loader( Rc::new(
+        |include_path:&str| load_string( &format!( "shaders/{}", include_path ) )
+    ) );
+    options
 }
 
-pub fn load_shader_program_path( path:&PathBuf ) -> Result<Rc<ShaderProgram>, Error> {
-    let shader_source = shader_parser( &load_string_path(path)? )?;
-    ShaderProgram::from_shaders( &shader_source )
+/// Copy of the options whose include loader also records
+/// the resources/shaders path of every file it loads
+fn tracked_shader_parse_options(
+    options:&ShaderParseOptions, includes:&hot_reload::IncludeList
+) -> ShaderParseOptions {
+    let mut options = options.clone();
+    let loader = match options.include_loader() {
+        Some( loader ) => loader.clone(),
+        None => return options,
+    };
+    let includes = includes.clone();
+    options.set_include_loader( Rc::new( move |include_path:&str| {
+        let path = resource_path_from_local_path( &format!( "shaders/{}", include_path ) );
+        if !includes.borrow().contains( &path ) {
+            includes.borrow_mut().push( path );
+        }
+        loader( include_path )
+    } ) );
+    options
+}
+
+pub fn load_compute_program( local_path:&str ) -> Result<ComputeProgram, Error> {
+    let mut path = resource_path_from_local_path( &format!( "shaders/{}", local_path ) );
+    path.set_extension("shader");
+    let mut options = shader_parse_options();
+    options.set_source_name( &format!( "shaders/{}.shader", local_path ) );
+    load_compute_program_path( &path, &options )
+}
+
+/// Loads a compute program and watches it and its includes for hot reloading.
+pub fn load_compute_program_path( path:&PathBuf, options:&ShaderParseOptions ) -> Result<ComputeProgram, Error> {
+    let includes = hot_reload::IncludeList::default();
+    let options = tracked_shader_parse_options( options, &includes );
+    let program = ComputeProgram::from_stages( &parse_stages( &load_string_path(path)?, &options )? )?;
+    hot_reload::watch_shader_program( path, program.program(), &options, includes );
+    Ok( program )
+}
+
+/// Loads a program from SPIR-V modules named after their stage,
+/// like `shaders/name.vert.spv` and `shaders/name.frag.spv`.
+/// Uniforms may have no names, see spirv::specialize.
+pub fn load_spirv_program( local_path:&str ) -> Result<Rc<ShaderProgram>, Error> {
+    load_spirv_program_with_options( local_path, &SpirvOptions::default() )
+}
+
+/// Like load_spirv_program, specializing every stage with the same options
+pub fn load_spirv_program_with_options(
+    local_path:&str, options:&SpirvOptions
+) -> Result<Rc<ShaderProgram>, Error> {
+    let mut shaders = Vec::new();
+    for kind in ALL_SHADER_KINDS.iter() {
+        let path = resource_path_from_local_path(
+            &format!( "shaders/{}.{}.{}", local_path, kind.spirv_extension(), SPIRV_EXT )
+        );
+        if !path.is_file() { continue; }
+        shaders.push( load_spirv_shader_path( &path, *kind, options )? );
+    }
+    if shaders.is_empty() {
+        return Err( Error::ResourcesReadFile(
+            format!( "No SPIR-V stages found for \"shaders/{}\"!", local_path )
+        ) );
+    }
+    ShaderProgram::from_shaders( &shaders )
+}
+
+pub fn load_spirv_shader_path( path:&PathBuf, kind:ShaderKind, options:&SpirvOptions ) -> Result<Shader, Error> {
+    Shader::from_spirv( &load_bytes_path( path )?, kind, options )
+}
+
+/// Loads resources/materials/{local_path}.material
+pub fn load_material( local_path:&str ) -> Result<Material, Error> {
+    let mut path = resource_path_from_local_path( &format!( "materials/{}", local_path ) );
+    path.set_extension( MATERIAL_EXT );
+    material_file::parse_material(
+        &load_string_path( &path )?,
+        &format!( "materials/{}.{}", local_path, MATERIAL_EXT )
+    )
+}
+
+/// Writes a material to resources/materials/{local_path}.material
+pub fn save_material( material:&Material, local_path:&str ) -> Result<(), Error> {
+    let mut path = resource_path_from_local_path( &format!( "materials/{}", local_path ) );
+    path.set_extension( MATERIAL_EXT );
+    save_bytes_path( &path, material_file::write_material( material )?.as_bytes() )
 }
 
 pub fn load_cstring( local_path:&str ) -> Result<CString, Error> {
@@ -169,6 +521,28 @@ pub fn load_bytes_path( path:&PathBuf ) -> Result<Vec<u8>, Error> {
         ) )
 }
 
+pub fn save_bytes_path( path:&PathBuf, bytes:&[u8] ) -> Result<(), Error> {
+    let map_err = |e:std::io::Error| Error::ResourcesWriteFile(
+        format!( "{} at {:?}", e, path.to_str().unwrap().replace("\\", "/") )
+    );
+    match path.parent() {
+        Some( parent ) => fs::create_dir_all( parent ).map_err( map_err )?,
+        None => {},
+    }
+    fs::write( path, bytes ).map_err( map_err )
+}
+
+/// Path inside the cache directory next to the resources directory
+pub fn cache_path_from_local_path( local_path:&str ) -> PathBuf {
+    let mut full_path = get_resources_path();
+    full_path.pop();
+    full_path.push( "cache" );
+    for part in local_path.split(LOCAL_SEPARATOR) {
+        full_path.push( part );
+    }
+    full_path
+}
+
 const LOCAL_SEPARATOR:char = '/';
 fn resource_path_from_local_path( local_path:&str ) -> PathBuf {
     let mut full_path = get_resources_path();
@@ -200,3 +574,7 @@ pub fn initialize() {
 // recognized file extensions
 const OBJ_EXT:&str = "obj";
 const GLTF_JSON_EXT:&str = "gltf";
+const SPIRV_EXT:&str = "spv";
+const MATERIAL_EXT:&str = "material";
+const DDS_EXT:&str = "dds";
+const KTX2_EXT:&str = "ktx2";
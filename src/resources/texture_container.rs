@@ -0,0 +1,475 @@
+//! Readers for DDS and KTX2 files holding block compressed textures.
+//!
+//! Only the layouts GL can upload as they are are read:
+//! BCn data in DDS files, BCn and ETC2/EAC data in KTX2 files,
+//! 2D textures, cubemaps and arrays of either with any number of mip levels.
+//! Uncompressed, supercompressed and 3D files are rejected with an error.
+//!
+//! Block compressed rows can't be flipped on upload the way PNG rows are,
+//! so 2D textures are expected bottom row first, the way bake_textures writes them.
+//! DDS files don't record which way they're stored, KTX2 files that say they're
+//! stored top row first in KTXorientation, or don't say at all, log a warning.
+
+use gl::types::*;
+use crate::{
+    graphics::compressed::{ CompressedImage, CompressedFormat },
+    debugging::{ Error, log },
+};
+
+const DDS_MAGIC:&[u8;4] = b"DDS ";
+// magic + DDS_HEADER
+const DDS_HEADER_SIZE:usize = 128;
+const DDS_DX10_HEADER_SIZE:usize = 20;
+const DDPF_FOURCC:u32 = 0x4;
+const DDSCAPS2_CUBEMAP:u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES:u32 = 0xFC00;
+const DDSCAPS2_VOLUME:u32 = 0x200000;
+const DDS_RESOURCE_MISC_TEXTURECUBE:u32 = 0x4;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D:u32 = 3;
+
+const KTX2_IDENTIFIER:[u8;12] = [ 0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A ];
+// identifier + header + index, the level index follows
+const KTX2_LEVEL_INDEX_OFFSET:usize = 80;
+const KTX2_LEVEL_INDEX_SIZE:usize = 24;
+const KTX2_ORIENTATION_KEY:&[u8] = b"KTXorientation";
+
+/// Reads a .dds file, source_name is used in errors
+pub fn parse_dds( data:&[u8], source_name:&str ) -> Result<CompressedImage, Error> {
+    let invalid = |msg:&str| Error::TextureInvalidImage( format!( "\"{}\" {}", source_name, msg ) );
+    if data.len() < DDS_HEADER_SIZE || &data[0..4] != DDS_MAGIC {
+        return Err( invalid( "isn't a DDS file!" ) );
+    }
+
+    let height    = read_u32( data, 12 )?;
+    let width     = read_u32( data, 16 )?;
+    let mip_count = read_u32( data, 28 )?.max( 1 );
+    let pf_flags  = read_u32( data, 80 )?;
+    let four_cc   = &data[84..88];
+    let caps2     = read_u32( data, 112 )?;
+
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err( invalid( "is a volume texture, only 2D textures and cubemaps can be loaded!" ) );
+    }
+    if pf_flags & DDPF_FOURCC == 0 {
+        return Err( invalid( "isn't block compressed, save it as BCn or load a PNG instead!" ) );
+    }
+
+    let mut data_offset = DDS_HEADER_SIZE;
+    let ( format, srgb, cubemap, array_size ) = if four_cc == b"DX10" {
+        if data.len() < DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE {
+            return Err( invalid( "ends inside its DX10 header!" ) );
+        }
+        let dxgi_format = read_u32( data, 128 )?;
+        let dimension   = read_u32( data, 132 )?;
+        let misc_flags  = read_u32( data, 136 )?;
+        let array_size  = read_u32( data, 140 )?.max( 1 );
+        data_offset += DDS_DX10_HEADER_SIZE;
+
+        if dimension != D3D10_RESOURCE_DIMENSION_TEXTURE2D {
+            return Err( invalid( "isn't a 2D texture, only 2D textures and cubemaps can be loaded!" ) );
+        }
+        let ( format, srgb ) = dxgi_format_to_compressed( dxgi_format )
+            .ok_or_else( || invalid( &format!( "has DXGI format {}, which isn't a supported block compressed format!", dxgi_format ) ) )?;
+        ( format, srgb, misc_flags & DDS_RESOURCE_MISC_TEXTURECUBE != 0, array_size )
+    } else {
+        let format = match four_cc {
+            b"DXT1"           => CompressedFormat::Bc1Rgba,
+            b"DXT2" | b"DXT3" => CompressedFormat::Bc2,
+            b"DXT4" | b"DXT5" => CompressedFormat::Bc3,
+            b"ATI1" | b"BC4U" => CompressedFormat::Bc4,
+            b"BC4S"           => CompressedFormat::Bc4Signed,
+            b"ATI2" | b"BC5U" => CompressedFormat::Bc5,
+            b"BC5S"           => CompressedFormat::Bc5Signed,
+            _ => return Err( invalid( &format!(
+                "has FourCC \"{}\", which isn't a supported block compressed format!",
+                String::from_utf8_lossy( four_cc )
+            ) ) ),
+        };
+        ( format, false, caps2 & DDSCAPS2_CUBEMAP != 0, 1 )
+    };
+
+    check_level_count( width, height, mip_count, source_name )?;
+    if cubemap && four_cc != b"DX10" && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+        return Err( invalid( "is a cubemap missing some of its faces!" ) );
+    }
+
+    let mut image = CompressedImage {
+        width: width as GLint, height: height as GLint,
+        layers: if array_size > 1 { array_size as GLint } else { 0 },
+        faces: if cubemap { 6 } else { 1 },
+        format, srgb,
+        levels: vec![ Vec::new(); mip_count as usize ],
+    };
+
+    // DDS stores every image's whole mip chain in turn,
+    // GL takes every image of one level at a time
+    let mut offset = data_offset;
+    for _ in 0..image.image_count() {
+        for level in 0..image.levels.len() {
+            let size = image.image_byte_size( level );
+            let bytes = data.get( offset..offset + size )
+                .ok_or_else( || invalid( &format!( "ends inside mip level {}!", level ) ) )?;
+            image.levels[level].extend_from_slice( bytes );
+            offset += size;
+        }
+    }
+
+    image.validate( source_name )?;
+    Ok( image )
+}
+
+/// Reads a .ktx2 file, source_name is used in errors
+pub fn parse_ktx2( data:&[u8], source_name:&str ) -> Result<CompressedImage, Error> {
+    let invalid = |msg:&str| Error::TextureInvalidImage( format!( "\"{}\" {}", source_name, msg ) );
+    if data.len() < KTX2_LEVEL_INDEX_OFFSET || data[0..12] != KTX2_IDENTIFIER {
+        return Err( invalid( "isn't a KTX2 file!" ) );
+    }
+
+    let vk_format        = read_u32( data, 12 )?;
+    let width            = read_u32( data, 20 )?;
+    let height           = read_u32( data, 24 )?;
+    let depth            = read_u32( data, 28 )?;
+    let layer_count      = read_u32( data, 32 )?;
+    let face_count       = read_u32( data, 36 )?;
+    let level_count      = read_u32( data, 40 )?;
+    let supercompression = read_u32( data, 44 )?;
+    let kvd_offset       = read_u32( data, 56 )? as usize;
+    let kvd_length       = read_u32( data, 60 )? as usize;
+
+    if supercompression != 0 {
+        return Err( invalid( &format!(
+            "uses supercompression scheme {}, only files without supercompression can be loaded!",
+            supercompression
+        ) ) );
+    }
+    if depth > 0 || height == 0 {
+        return Err( invalid( "isn't a 2D texture, only 2D textures and cubemaps can be loaded!" ) );
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err( invalid( &format!( "has {} faces, it needs 1 or 6!", face_count ) ) );
+    }
+    let ( format, srgb ) = vk_format_to_compressed( vk_format )
+        .ok_or_else( || invalid( &format!( "has VkFormat {}, which isn't a supported block compressed format!", vk_format ) ) )?;
+    if face_count == 1 {
+        let key_values = data.get( kvd_offset..kvd_offset.saturating_add( kvd_length ) )
+            .ok_or_else( || invalid( "ends inside its key/value data!" ) )?;
+        // without the key KTX2 files are top row first, the second letter is the y axis
+        let bottom_up = find_key_value( key_values, KTX2_ORIENTATION_KEY )
+            .is_some_and( |value| value.get( 1 ) == Some( &b'u' ) );
+        if !bottom_up {
+            log(
+                &format!( "\"{}\" is stored top row first and will be upside down, rebake it with bake_textures.", source_name ),
+                "Texture | Warning"
+            );
+        }
+    }
+    check_level_count( width, height, level_count, source_name )?;
+    if level_count == 0 {
+        log(
+            &format!( "\"{}\" asks for its mipmaps to be generated, compressed textures can't be, using level 0 only.", source_name ),
+            "Texture | Warning"
+        );
+    }
+
+    let mut image = CompressedImage {
+        width: width as GLint, height: height as GLint,
+        layers: layer_count as GLint,
+        faces: face_count as GLint,
+        format, srgb,
+        levels: Vec::with_capacity( level_count.max( 1 ) as usize ),
+    };
+
+    // each level already holds every layer and face in the order GL takes them
+    for level in 0..level_count.max( 1 ) as usize {
+        let entry = KTX2_LEVEL_INDEX_OFFSET + level * KTX2_LEVEL_INDEX_SIZE;
+        let offset = read_u64( data, entry )? as usize;
+        let length = read_u64( data, entry + 8 )? as usize;
+        let bytes = data.get( offset..offset.saturating_add( length ) )
+            .ok_or_else( || invalid( &format!( "ends inside mip level {}!", level ) ) )?;
+        image.levels.push( bytes.to_vec() );
+    }
+
+    image.validate( source_name )?;
+    Ok( image )
+}
+
+/// Rejects more mip levels than halving the larger side down to 1 pixel gives,
+/// before they're allocated or a level shifts the size by 32 bits or more
+fn check_level_count( width:u32, height:u32, level_count:u32, source_name:&str ) -> Result<(), Error> {
+    let max_level_count = u32::BITS - width.max( height ).max( 1 ).leading_zeros();
+    if level_count > max_level_count {
+        return Err( Error::TextureInvalidImage( format!(
+            "\"{}\" has {} mip levels, a {}x{} texture can't have more than {}!",
+            source_name, level_count, width, height, max_level_count
+        ) ) );
+    }
+    Ok(())
+}
+
+/// Value of a key in KTX2 key/value data, None if it isn't there
+fn find_key_value<'a>( mut key_values:&'a [u8], key:&[u8] ) -> Option<&'a [u8]> {
+    while key_values.len() >= 4 {
+        let length = u32::from_le_bytes( [ key_values[0], key_values[1], key_values[2], key_values[3] ] ) as usize;
+        let entry = key_values.get( 4..4 + length )?;
+        if let Some( value ) = entry.strip_prefix( key ).and_then( |rest| rest.strip_prefix( &[0] ) ) {
+            return Some( value.strip_suffix( &[0] ).unwrap_or( value ) );
+        }
+        // entries are padded to 4 bytes
+        key_values = key_values.get( 4 + length.div_ceil( 4 ) * 4.. )?;
+    }
+    None
+}
+
+fn dxgi_format_to_compressed( dxgi_format:u32 ) -> Option<( CompressedFormat, bool )> {
+    Some( match dxgi_format {
+        71 => ( CompressedFormat::Bc1Rgba,      false ),
+        72 => ( CompressedFormat::Bc1Rgba,      true  ),
+        74 => ( CompressedFormat::Bc2,          false ),
+        75 => ( CompressedFormat::Bc2,          true  ),
+        77 => ( CompressedFormat::Bc3,          false ),
+        78 => ( CompressedFormat::Bc3,          true  ),
+        80 => ( CompressedFormat::Bc4,          false ),
+        81 => ( CompressedFormat::Bc4Signed,    false ),
+        83 => ( CompressedFormat::Bc5,          false ),
+        84 => ( CompressedFormat::Bc5Signed,    false ),
+        95 => ( CompressedFormat::Bc6hUnsigned, false ),
+        96 => ( CompressedFormat::Bc6hSigned,   false ),
+        98 => ( CompressedFormat::Bc7,          false ),
+        99 => ( CompressedFormat::Bc7,          true  ),
+        _ => return None,
+    } )
+}
+
+fn vk_format_to_compressed( vk_format:u32 ) -> Option<( CompressedFormat, bool )> {
+    Some( match vk_format {
+        131 => ( CompressedFormat::Bc1Rgb,        false ),
+        132 => ( CompressedFormat::Bc1Rgb,        true  ),
+        133 => ( CompressedFormat::Bc1Rgba,       false ),
+        134 => ( CompressedFormat::Bc1Rgba,       true  ),
+        135 => ( CompressedFormat::Bc2,           false ),
+        136 => ( CompressedFormat::Bc2,           true  ),
+        137 => ( CompressedFormat::Bc3,           false ),
+        138 => ( CompressedFormat::Bc3,           true  ),
+        139 => ( CompressedFormat::Bc4,           false ),
+        140 => ( CompressedFormat::Bc4Signed,     false ),
+        141 => ( CompressedFormat::Bc5,           false ),
+        142 => ( CompressedFormat::Bc5Signed,     false ),
+        143 => ( CompressedFormat::Bc6hUnsigned,  false ),
+        144 => ( CompressedFormat::Bc6hSigned,    false ),
+        145 => ( CompressedFormat::Bc7,           false ),
+        146 => ( CompressedFormat::Bc7,           true  ),
+        147 => ( CompressedFormat::Etc2Rgb,       false ),
+        148 => ( CompressedFormat::Etc2Rgb,       true  ),
+        149 => ( CompressedFormat::Etc2RgbA1,     false ),
+        150 => ( CompressedFormat::Etc2RgbA1,     true  ),
+        151 => ( CompressedFormat::Etc2Rgba,      false ),
+        152 => ( CompressedFormat::Etc2Rgba,      true  ),
+        153 => ( CompressedFormat::EacR11,        false ),
+        154 => ( CompressedFormat::EacR11Signed,  false ),
+        155 => ( CompressedFormat::EacRg11,       false ),
+        156 => ( CompressedFormat::EacRg11Signed, false ),
+        _ => return None,
+    } )
+}
+
+fn read_u32( data:&[u8], offset:usize ) -> Result<u32, Error> {
+    match data.get( offset..offset + 4 ) {
+        Some( bytes ) => Ok( u32::from_le_bytes( [ bytes[0], bytes[1], bytes[2], bytes[3] ] ) ),
+        None => Err( Error::TextureInvalidImage( format!( "Texture file ends before byte {}!", offset + 4 ) ) ),
+    }
+}
+
+fn read_u64( data:&[u8], offset:usize ) -> Result<u64, Error> {
+    let low  = read_u32( data, offset )? as u64;
+    let high = read_u32( data, offset + 4 )? as u64;
+    Ok( low | ( high << 32 ) )
+}
+
+// shared with the bcn tests, the lib's cfg(test) items aren't built for this crate
+#[cfg(test)]
+#[path = "../bcn/test_image.rs"]
+mod test_image;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opengl::bcn::{ self, BakeOptions, BcFormat };
+    use super::test_image::test_image;
+
+    fn baked( format:BcFormat, srgb:bool ) -> bcn::BakedTexture {
+        let mut options = BakeOptions::default();
+        options.set_format( format );
+        options.set_srgb( srgb );
+        bcn::bake( &test_image(), &options )
+    }
+
+    fn check( image:&CompressedImage, baked:&bcn::BakedTexture, format:CompressedFormat ) {
+        assert_eq!( ( image.width, image.height ), ( 12, 8 ) );
+        assert_eq!( ( image.layers, image.faces ), ( 0, 1 ) );
+        assert_eq!( image.format, format );
+        assert_eq!( image.srgb, baked.srgb );
+        assert_eq!( image.levels, baked.levels );
+    }
+
+    const FORMATS:[( BcFormat, CompressedFormat );5] = [
+        ( BcFormat::Bc1, CompressedFormat::Bc1Rgba ), ( BcFormat::Bc3, CompressedFormat::Bc3 ),
+        ( BcFormat::Bc4, CompressedFormat::Bc4 ), ( BcFormat::Bc5, CompressedFormat::Bc5 ),
+        ( BcFormat::Bc7, CompressedFormat::Bc7 ),
+    ];
+
+    #[test]
+    fn baked_dds_round_trips() {
+        for ( format, expected ) in FORMATS {
+            for srgb in [ false, true ] {
+                let baked = baked( format, srgb );
+                let image = parse_dds( &bcn::write_dds( &baked ), "test.dds" ).unwrap();
+                check( &image, &baked, expected );
+            }
+        }
+    }
+
+    fn push_u32s( bytes:&mut Vec<u8>, values:&[u32] ) {
+        for value in values.iter() { bytes.extend_from_slice( &value.to_le_bytes() ); }
+    }
+
+    /// DDS header with a FourCC, the DX10 header when it's given and data_size bytes of ( index % 251 )
+    fn dds( four_cc:&[u8;4], width:u32, height:u32, mips:u32, caps2:u32, dx10:Option<[u32;5]>, data_size:usize ) -> Vec<u8> {
+        let mut bytes = DDS_MAGIC.to_vec();
+        push_u32s( &mut bytes, &[ 124, 0, height, width, 0, 0, mips ] );
+        push_u32s( &mut bytes, &[0;11] );
+        push_u32s( &mut bytes, &[ 32, DDPF_FOURCC ] );
+        bytes.extend_from_slice( four_cc );
+        push_u32s( &mut bytes, &[0;5] );
+        push_u32s( &mut bytes, &[ 0, caps2, 0, 0, 0 ] );
+        if let Some( dx10 ) = dx10 { push_u32s( &mut bytes, &dx10 ); }
+        bytes.extend( ( 0..data_size ).map( |index| ( index % 251 ) as u8 ) );
+        bytes
+    }
+
+    /// KTX2 file with one face and the given level sizes, largest first
+    fn ktx2( vk_format:u32, width:u32, height:u32, levels:&[usize], key_values:&[u8] ) -> Vec<u8> {
+        let kvd_offset = KTX2_LEVEL_INDEX_OFFSET + levels.len() * KTX2_LEVEL_INDEX_SIZE;
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        push_u32s( &mut bytes, &[ vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0 ] );
+        push_u32s( &mut bytes, &[ 0, 0, kvd_offset as u32, key_values.len() as u32 ] );
+        bytes.extend_from_slice( &[0;16] );
+        let mut offset = kvd_offset + key_values.len();
+        for size in levels.iter() {
+            for value in [ offset as u64, *size as u64, *size as u64 ] {
+                bytes.extend_from_slice( &value.to_le_bytes() );
+            }
+            offset += size;
+        }
+        bytes.extend_from_slice( key_values );
+        for ( level, size ) in levels.iter().enumerate() {
+            bytes.resize( bytes.len() + size, level as u8 );
+        }
+        bytes
+    }
+
+    fn key_value( key:&str, value:&str ) -> Vec<u8> {
+        let length = key.len() + value.len() + 2;
+        let mut bytes = ( length as u32 ).to_le_bytes().to_vec();
+        bytes.extend_from_slice( key.as_bytes() );
+        bytes.push( 0 );
+        bytes.extend_from_slice( value.as_bytes() );
+        bytes.push( 0 );
+        bytes.resize( 4 + length.div_ceil( 4 ) * 4, 0 );
+        bytes
+    }
+
+    #[test]
+    fn dds_legacy_four_cc() {
+        let image = parse_dds( &dds( b"DXT1", 8, 6, 3, 0, None, 32 + 8 + 8 ), "test.dds" ).unwrap();
+        assert_eq!( ( image.width, image.height, image.layers, image.faces ), ( 8, 6, 0, 1 ) );
+        assert_eq!( image.format, CompressedFormat::Bc1Rgba );
+        assert!( !image.srgb );
+        assert_eq!( image.levels.iter().map( Vec::len ).collect::<Vec<_>>(), [ 32, 8, 8 ] );
+        assert_eq!( image.levels[1][0], 32 );
+    }
+
+    #[test]
+    fn dds_cubemap_levels_hold_every_face() {
+        // BC7 sRGB, 2D, cubemap, 1 cube, 8x8 with 2 levels
+        let dx10 = [ 99, D3D10_RESOURCE_DIMENSION_TEXTURE2D, DDS_RESOURCE_MISC_TEXTURECUBE, 1, 0 ];
+        let bytes = dds( b"DX10", 8, 8, 2, 0, Some( dx10 ), 6 * ( 64 + 16 ) );
+        let image = parse_dds( &bytes, "test.dds" ).unwrap();
+        assert_eq!( ( image.faces, image.layers ), ( 6, 0 ) );
+        assert_eq!( image.format, CompressedFormat::Bc7 );
+        assert!( image.srgb );
+        assert_eq!( ( image.levels[0].len(), image.levels[1].len() ), ( 6 * 64, 6 * 16 ) );
+        // the file holds each face's mip chain in turn, the second face starts after the first one's
+        let data = &bytes[DDS_HEADER_SIZE + DDS_DX10_HEADER_SIZE..];
+        assert_eq!( image.levels[0][64..128], data[80..144] );
+        assert_eq!( image.levels[1][0..16], data[64..80] );
+    }
+
+    #[test]
+    fn dds_rejects_what_it_cant_load() {
+        let mut bad_magic = dds( b"DXT5", 4, 4, 1, 0, None, 16 );
+        bad_magic[0] = b'X';
+        assert!( parse_dds( &bad_magic, "test.dds" ).is_err() );
+        assert!( parse_dds( &dds( b"DXT5", 4, 4, 1, DDSCAPS2_VOLUME, None, 16 ), "test.dds" ).is_err() );
+        assert!( parse_dds( &dds( b"DXT5", 4, 4, 1, 0, None, 15 ), "test.dds" ).is_err() );
+        assert!( parse_dds( &dds( b"RGBG", 4, 4, 1, 0, None, 16 ), "test.dds" ).is_err() );
+        // a cubemap with only +X
+        assert!( parse_dds( &dds( b"DXT1", 4, 4, 1, DDSCAPS2_CUBEMAP | 0x400, None, 8 ), "test.dds" ).is_err() );
+    }
+
+    #[test]
+    fn rejects_more_levels_than_the_size_has() {
+        // 8x6 halves down to 1x1 in 4 levels
+        assert!( parse_dds( &dds( b"DXT1", 8, 6, 4, 0, None, 32 + 8 + 8 + 8 ), "test.dds" ).is_ok() );
+        assert!( parse_dds( &dds( b"DXT1", 8, 6, 5, 0, None, 32 + 8 + 8 + 8 + 8 ), "test.dds" ).is_err() );
+        assert!( parse_dds( &dds( b"DXT1", 8, 6, u32::MAX, 0, None, 32 ), "test.dds" ).is_err() );
+        let mut ktx2_levels = ktx2( 139, 4, 4, &[ 8 ], &[] );
+        ktx2_levels[40..44].copy_from_slice( &u32::MAX.to_le_bytes() );
+        assert!( parse_ktx2( &ktx2_levels, "test.ktx2" ).is_err() );
+    }
+
+    #[test]
+    fn ktx2_header() {
+        let key_values = key_value( "KTXorientation", "ru" );
+        // BC4, 8x8 with 3 levels
+        let image = parse_ktx2( &ktx2( 139, 8, 8, &[ 32, 8, 8 ], &key_values ), "test.ktx2" ).unwrap();
+        assert_eq!( ( image.width, image.height, image.layers, image.faces ), ( 8, 8, 0, 1 ) );
+        assert_eq!( image.format, CompressedFormat::Bc4 );
+        assert!( !image.srgb );
+        assert_eq!( image.levels, [ vec![ 0; 32 ], vec![ 1; 8 ], vec![ 2; 8 ] ] );
+    }
+
+    #[test]
+    fn ktx2_rejects_what_it_cant_load() {
+        let mut supercompressed = ktx2( 139, 4, 4, &[ 8 ], &[] );
+        supercompressed[44] = 2;
+        assert!( parse_ktx2( &supercompressed, "test.ktx2" ).is_err() );
+        // R8G8B8A8_UNORM
+        assert!( parse_ktx2( &ktx2( 37, 4, 4, &[ 64 ], &[] ), "test.ktx2" ).is_err() );
+        let mut truncated = ktx2( 139, 4, 4, &[ 8 ], &[] );
+        truncated.pop();
+        assert!( parse_ktx2( &truncated, "test.ktx2" ).is_err() );
+        // level sizes that don't match the size in the header
+        assert!( parse_ktx2( &ktx2( 139, 8, 8, &[ 8 ], &[] ), "test.ktx2" ).is_err() );
+    }
+
+    #[test]
+    fn ktx2_key_values() {
+        let mut key_values = key_value( "KTXwriter", "bake_textures" );
+        key_values.extend( key_value( "KTXorientation", "rd" ) );
+        assert_eq!( find_key_value( &key_values, KTX2_ORIENTATION_KEY ), Some( &b"rd"[..] ) );
+        assert_eq!( find_key_value( &key_values, b"KTXwriter" ), Some( &b"bake_textures"[..] ) );
+        assert_eq!( find_key_value( &key_values, b"KTX" ), None );
+        assert_eq!( find_key_value( &key_values[..key_values.len() - 4], KTX2_ORIENTATION_KEY ), None );
+    }
+
+    #[test]
+    fn baked_ktx2_round_trips() {
+        for ( format, expected ) in FORMATS {
+            for srgb in [ false, true ] {
+                let baked = baked( format, srgb );
+                let image = parse_ktx2( &bcn::write_ktx2( &baked ), "test.ktx2" ).unwrap();
+                check( &image, &baked, expected );
+            }
+        }
+    }
+}